@@ -196,6 +196,14 @@ async fn main() -> Result<(), std::io::Error> {
                 .value_parser(value_parser!(String)) // TODO: check if database exists
                 .action(ArgAction::Set),
         )
+        .arg(
+            Arg::new("event-log")
+                .long("event-log")
+                .value_name("FILE")
+                .help("Append node events as newline-delimited JSON to FILE")
+                .value_parser(value_parser!(String))
+                .action(ArgAction::Set),
+        )
         .arg(
             Arg::new("cla")
                 .short('C')
@@ -411,6 +419,10 @@ Tag 255 takes 5 arguments and is interpreted as address. Usage: -S 255:'Samplest
         }
     }
 
+    if let Some(event_log) = matches.get_one::<String>("event-log") {
+        cfg.event_log = Some(std::path::PathBuf::from(event_log));
+    }
+
     if let Some(clas) = matches.get_many::<String>("cla") {
         for cla in clas {
             let mut cla_split: Vec<&str> = cla.split(':').collect();
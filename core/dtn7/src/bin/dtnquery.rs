@@ -34,14 +34,30 @@ enum Commands {
         #[clap(short, long)]
         addr: Option<String>,
     },
-    /// List bundles status in store
-    Store,
+    /// List bundles status in store or maintain the store
+    Store {
+        #[clap(subcommand)]
+        cmd: Option<StoreCommands>,
+    },
+    /// List status reports received for a bundle
+    Reports {
+        /// Bundle ID, e.g., dtn://node1/-683555464000-0
+        bid: String,
+    },
     /// General dtnd info
     Info,
     /// Local node id
     Nodeid,
 }
 
+#[derive(Subcommand, Debug)]
+enum StoreCommands {
+    /// Check store for orphaned, corrupt and expired entries
+    Check,
+    /// Remove orphaned, corrupt and expired entries and compact the store
+    Repair,
+}
+
 fn main() {
     let args = Args::parse();
     let port = if let Ok(env_port) = std::env::var("DTN_WEB_PORT") {
@@ -87,10 +103,30 @@ fn main() {
                 format!("http://{}:{}/status/bundles", localhost, port)
             }
         }
-        Commands::Store => {
+        Commands::Store { cmd: None } => {
             println!("Listing of bundles status in store:");
             format!("http://{}:{}/status/store", localhost, port)
         }
+        Commands::Store {
+            cmd: Some(StoreCommands::Check),
+        } => {
+            println!("Store check:");
+            format!("http://{}:{}/store/check", localhost, port)
+        }
+        Commands::Store {
+            cmd: Some(StoreCommands::Repair),
+        } => {
+            println!("Store repair:");
+            format!("http://{}:{}/store/repair", localhost, port)
+        }
+        Commands::Reports { bid } => {
+            println!("Status reports for {}:", bid);
+            let bid: String = url::form_urlencoded::byte_serialize(bid.as_bytes()).collect();
+            format!(
+                "http://{}:{}/status/bundle/{}/reports",
+                localhost, port, bid
+            )
+        }
         Commands::Info => {
             println!("Daemon info:");
             format!("http://{}:{}/status/info", localhost, port)
@@ -100,7 +136,19 @@ fn main() {
             format!("http://{}:{}/status/nodeid", localhost, port)
         }
     };
-    let res = attohttpc::get(url)
+    // repairing changes the store and is only available via POST
+    let repair = matches!(
+        args.cmd,
+        Commands::Store {
+            cmd: Some(StoreCommands::Repair)
+        }
+    );
+    let req = if repair {
+        attohttpc::post(url)
+    } else {
+        attohttpc::get(url)
+    };
+    let res = req
         .send()
         .expect("error connecting to local dtnd")
         .text()
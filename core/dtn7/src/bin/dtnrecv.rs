@@ -1,5 +1,6 @@
 use bp7::*;
 use clap::Parser;
+use dtn7::core::transfer::TransferProgress;
 use std::convert::TryFrom;
 use std::fs;
 use std::io::prelude::*;
@@ -22,25 +23,33 @@ struct Args {
     verbose: bool,
 
     /// Specify local endpoint, e.g. 'incoming', or a group endpoint 'dtn://helpers/~incoming'
-    #[clap(short, long, required_unless_present_any = ["bid", "delete", "register", "unregister"], conflicts_with_all = ["bid", "delete", "register", "unregister"])]
+    #[clap(short, long, required_unless_present_any = ["bid", "delete", "register", "unregister", "transfer", "transfers"], conflicts_with_all = ["bid", "delete", "register", "unregister", "transfer", "transfers"])]
     endpoint: Option<String>,
 
     /// Register a local endpoint, e.g. 'incoming', or a group endpoint 'dtn://helpers/~incoming'
-    #[clap(short, long, required_unless_present_any = ["bid", "delete", "endpoint", "unregister"], conflicts_with_all = ["bid", "delete", "endpoint", "unregister"], value_name = "ENDPOINT")]
+    #[clap(short, long, required_unless_present_any = ["bid", "delete", "endpoint", "unregister", "transfer", "transfers"], conflicts_with_all = ["bid", "delete", "endpoint", "unregister", "transfer", "transfers"], value_name = "ENDPOINT")]
     register: Option<String>,
 
     /// Unregister a local endpoint, e.g. 'incoming', or a group endpoint 'dtn://helpers/~incoming'
-    #[clap(short, long, required_unless_present_any = ["bid", "delete", "endpoint", "register"], conflicts_with_all = ["bid", "delete", "endpoint", "register"], value_name = "ENDPOINT")]
+    #[clap(short, long, required_unless_present_any = ["bid", "delete", "endpoint", "register", "transfer", "transfers"], conflicts_with_all = ["bid", "delete", "endpoint", "register", "transfer", "transfers"], value_name = "ENDPOINT")]
     unregister: Option<String>,
 
     /// Download any bundle by its ID
-    #[clap(short, long, required_unless_present_any = ["endpoint", "delete", "register", "unregister"], conflicts_with_all = ["endpoint", "delete", "register", "unregister"])]
+    #[clap(short, long, required_unless_present_any = ["endpoint", "delete", "register", "unregister", "transfer", "transfers"], conflicts_with_all = ["endpoint", "delete", "register", "unregister", "transfer", "transfers"])]
     bid: Option<String>,
 
     /// Delete any bundle by its ID
-    #[clap(short, long, required_unless_present_any = ["endpoint", "bid", "register", "unregister"], conflicts_with_all = ["endpoint", "bid", "register", "unregister"],  value_name = "BID")]
+    #[clap(short, long, required_unless_present_any = ["endpoint", "bid", "register", "unregister", "transfer", "transfers"], conflicts_with_all = ["endpoint", "bid", "register", "unregister", "transfer", "transfers"],  value_name = "BID")]
     delete: Option<String>,
 
+    /// Wait for a transfer of a large ADU to complete while showing its progress, then download it
+    #[clap(short, long, required_unless_present_any = ["endpoint", "bid", "delete", "register", "unregister", "transfers"], conflicts_with_all = ["endpoint", "bid", "delete", "register", "unregister", "transfers"], value_name = "TRANSFER")]
+    transfer: Option<String>,
+
+    /// List the progress of incoming transfers
+    #[clap(long, required_unless_present_any = ["endpoint", "bid", "delete", "register", "unregister", "transfer"], conflicts_with_all = ["endpoint", "bid", "delete", "register", "unregister", "transfer"])]
+    transfers: bool,
+
     /// Write bundle payload to file instead of stdout
     #[clap(short, long)]
     outfile: Option<String>,
@@ -69,6 +78,69 @@ fn write_bytes(data: &[u8], possible_file: Option<String>, verbose: bool) {
             .expect("Error writing binary.");
     }
 }
+fn transfer_progress(base_url: &str) -> Vec<TransferProgress> {
+    let res = attohttpc::get(format!("{}/transfers", base_url))
+        .send()
+        .expect("error connecting to local dtnd");
+    serde_json::from_str(&res.text().unwrap()).expect("invalid transfer progress")
+}
+
+fn print_progress(t: &TransferProgress) {
+    let total = |n: Option<u64>| n.map_or("?".to_string(), |n| n.to_string());
+    eprintln!(
+        "{} {} -> {} {}: {}/{} chunks, {}/{} bytes{}",
+        t.id,
+        t.src,
+        t.dst,
+        t.name.as_deref().unwrap_or("-"),
+        t.chunks,
+        total(t.total_chunks),
+        t.bytes,
+        total(t.total_bytes),
+        if t.complete { ", complete" } else { "" }
+    );
+}
+
+/// Waits for a transfer to complete, downloads its ADU and removes it from dtnd.
+fn receive_transfer(base_url: &str, id: &str, possible_file: Option<String>, verbose: bool) {
+    let src = loop {
+        let Some(progress) = transfer_progress(base_url).into_iter().find(|t| t.id == id) else {
+            eprintln!("Unknown transfer {}", id);
+            process::exit(23);
+        };
+        print_progress(&progress);
+        if progress.complete {
+            break progress.src;
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    };
+    let src: String = url::form_urlencoded::byte_serialize(src.as_bytes()).collect();
+    let query = format!("id={}&src={}", id, src);
+    let res = attohttpc::get(format!("{}/transfers/download?{}", base_url, query))
+        .send()
+        .expect("error connecting to local dtnd");
+    if !res.is_success() {
+        eprintln!("Unexpected response from server! {:?}", res);
+        process::exit(23);
+    }
+    let written = if let Some(outfile) = possible_file {
+        if verbose {
+            println!("Writing to {}", outfile);
+        }
+        let file = fs::File::create(outfile).expect("Unable to write file");
+        res.write_to(file).expect("Unable to write file")
+    } else {
+        res.write_to(std::io::stdout())
+            .expect("Error writing binary.")
+    };
+    if verbose {
+        eprintln!("Wrote {} bytes", written);
+    }
+    attohttpc::get(format!("{}/transfers/delete?{}", base_url, query))
+        .send()
+        .expect("error connecting to local dtnd");
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -78,6 +150,16 @@ fn main() {
         args.port.to_string()
     };
     let localhost = if args.ipv6 { "[::1]" } else { "127.0.0.1" };
+    let base_url = format!("http://{}:{}", localhost, port);
+    if args.transfers {
+        for t in transfer_progress(&base_url) {
+            print_progress(&t);
+        }
+        process::exit(0);
+    } else if let Some(transfer) = args.transfer {
+        receive_transfer(&base_url, &transfer, args.outfile, args.verbose);
+        process::exit(0);
+    }
     let local_url = if let Some(endpoint) = args.endpoint {
         format!("http://{}:{}/endpoint?{}", localhost, port, endpoint)
     } else if args.delete.is_some() {
@@ -120,14 +202,14 @@ fn main() {
         process::exit(23);
     }
     let buf: Vec<u8> = res.bytes().expect("No bundle bytes received");
-    if args.delete.is_some() {
-        println!("Deleted bundle {}", args.delete.unwrap());
+    if let Some(delete) = args.delete {
+        println!("Deleted bundle {}", delete);
         process::exit(0);
-    } else if args.register.is_some() {
-        println!("Registered endpoint {}", args.register.unwrap());
+    } else if let Some(register) = args.register {
+        println!("Registered endpoint {}", register);
         process::exit(0);
-    } else if args.unregister.is_some() {
-        println!("Unregistered endpoint {}", args.unregister.unwrap());
+    } else if let Some(unregister) = args.unregister {
+        println!("Unregistered endpoint {}", unregister);
         process::exit(0);
     } else if buf.len() > 50 {
         // TODO: very arbitrary number, should check return code
@@ -2,8 +2,10 @@ use bp7::bundle::*;
 use bp7::flags::{BundleControlFlags, BundleValidation};
 use bp7::*;
 use clap::Parser;
+use dtn7::core::transfer::{new_transfer_id, UploadStatus};
 use dtn7_plus::client::DtnClient;
 use std::io;
+use std::io::SeekFrom;
 use std::{convert::TryInto, io::prelude::*};
 
 /// A simple Bundle Protocol 7 Send Utility for Delay Tolerant Networking
@@ -41,6 +43,84 @@ struct Args {
     /// Bundle lifetime in seconds (default = 3600)
     #[clap(short, long, default_value_t = 3600)]
     lifetime: u64,
+
+    /// Stream the data to dtnd, which splits it into bundles of at most this many bytes
+    #[clap(short, long, value_name = "BYTES", conflicts_with_all = ["dryrun", "sender"])]
+    chunk_size: Option<usize>,
+
+    /// Resume an interrupted streaming transfer of INFILE
+    #[clap(long, value_name = "TRANSFER", requires = "infile", conflicts_with_all = ["dryrun", "sender"])]
+    resume: Option<String>,
+}
+
+/// Request body read until EOF, sent with chunked encoding.
+struct ReaderBody<R>(R);
+
+impl<R: Read> attohttpc::body::Body for ReaderBody<R> {
+    fn kind(&mut self) -> io::Result<attohttpc::body::BodyKind> {
+        Ok(attohttpc::body::BodyKind::Chunked)
+    }
+    fn write<W: Write>(&mut self, mut writer: W) -> io::Result<()> {
+        io::copy(&mut self.0, &mut writer)?;
+        Ok(())
+    }
+}
+
+/// Streams the ADU to dtnd, which sends it as a sequence of bundles.
+fn send_stream(args: &Args, base_url: &str) {
+    let (transfer, offset) = if let Some(transfer) = &args.resume {
+        let res = attohttpc::get(format!("{}/send/stream", base_url))
+            .param("transfer", transfer)
+            .send()
+            .expect("error connecting to local dtnd");
+        if !res.is_success() {
+            eprintln!("Transfer {} can not be resumed", transfer);
+            std::process::exit(23);
+        }
+        let status: UploadStatus =
+            serde_json::from_str(&res.text().unwrap()).expect("invalid transfer status");
+        (transfer.clone(), status.offset)
+    } else {
+        (new_transfer_id(), 0)
+    };
+    println!("Transfer-Id: {}", transfer);
+
+    let mut req = attohttpc::post(format!("{}/send/stream", base_url))
+        .param("dst", &args.receiver)
+        .param("lifetime", format!("{}s", args.lifetime))
+        .param("transfer", &transfer)
+        .param("offset", offset);
+    if let Some(chunk_size) = args.chunk_size {
+        req = req.param("chunk_size", chunk_size);
+    }
+    let res = if let Some(infile) = &args.infile {
+        if let Some(name) = std::path::Path::new(infile).file_name() {
+            req = req.param("name", name.to_string_lossy());
+        }
+        let mut f = std::fs::File::open(infile).expect("Error accessing file.");
+        f.seek(SeekFrom::Start(offset))
+            .expect("Error seeking in file.");
+        if args.verbose {
+            println!("Streaming {} from offset {}", infile, offset);
+        }
+        req.body(ReaderBody(f)).send()
+    } else {
+        req.body(ReaderBody(io::stdin())).send()
+    };
+    match res {
+        Ok(res) if res.is_success() => println!("Result: {}", res.text().unwrap()),
+        Ok(res) => {
+            eprintln!("Error: {}", res.text().unwrap_or_default());
+            std::process::exit(23);
+        }
+        Err(err) => {
+            eprintln!(
+                "Error streaming to dtnd, resume with --resume {}: {}",
+                transfer, err
+            );
+            std::process::exit(23);
+        }
+    }
 }
 
 fn main() {
@@ -55,6 +135,10 @@ fn main() {
         localhost.into(),
         port.parse::<u16>().expect("invalid port number"),
     );
+    if args.chunk_size.is_some() || args.resume.is_some() {
+        send_stream(&args, &format!("http://{}:{}", localhost, port));
+        return;
+    }
     let sender: EndpointID = args
         .sender
         .unwrap_or_else(|| {
@@ -0,0 +1,199 @@
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use dtn7::core::store::archive::{import_store, migrate_store, ArchiveSummary};
+use dtn7::core::store::{bundle_stores, BundleStore, BundleStoresEnum};
+#[cfg(feature = "store_encryption")]
+use dtn7::core::store::{EncryptedBundleStore, StoreKeys};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// A simple Bundle Protocol 7 Store Utility for Delay Tolerant Networking
+///
+/// Works on the store of a stopped dtnd, e.g., to switch the store backend or to seed a new node.
+#[derive(Parser, Debug)]
+#[clap(version, author, long_about = None)]
+struct Args {
+    #[clap(subcommand)]
+    cmd: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Export all bundles of a store into an archive
+    Export {
+        /// Store backend
+        #[clap(short = 'D', long)]
+        db: String,
+        /// Working directory of the store
+        #[clap(short, long)]
+        workdir: PathBuf,
+        /// Node ID noted in the archive
+        #[clap(short, long, default_value = "")]
+        nodeid: String,
+        /// Key file of an encrypted store
+        #[clap(short, long)]
+        key_file: Option<PathBuf>,
+        /// Archive file to write
+        archive: PathBuf,
+    },
+    /// Import all bundles of an archive into a store
+    Import {
+        /// Store backend
+        #[clap(short = 'D', long)]
+        db: String,
+        /// Working directory of the store
+        #[clap(short, long)]
+        workdir: PathBuf,
+        /// Key file of an encrypted store
+        #[clap(short, long)]
+        key_file: Option<PathBuf>,
+        /// Archive file to read
+        archive: PathBuf,
+    },
+    /// Copy all bundles from one store backend to another
+    Migrate {
+        /// Source store backend
+        #[clap(long)]
+        from: String,
+        /// Target store backend
+        #[clap(long)]
+        to: String,
+        /// Working directory of the source store
+        #[clap(short, long)]
+        workdir: PathBuf,
+        /// Working directory of the target store (default = same as source)
+        #[clap(short, long)]
+        target_workdir: Option<PathBuf>,
+        /// Key file of the source store if encrypted
+        #[clap(short, long)]
+        key_file: Option<PathBuf>,
+        /// Key file to encrypt the target store with
+        #[clap(long)]
+        target_key_file: Option<PathBuf>,
+    },
+    #[cfg(feature = "store_encryption")]
+    /// Encrypt a store or reseal it with the first key of the key file
+    Rekey {
+        /// Store backend
+        #[clap(short = 'D', long)]
+        db: String,
+        /// Working directory of the store
+        #[clap(short, long)]
+        workdir: PathBuf,
+        /// Key file, the first key is used for sealing
+        #[clap(short, long)]
+        key_file: PathBuf,
+    },
+    #[cfg(feature = "store_encryption")]
+    /// Print a new random store key entry
+    Keygen {
+        /// Key ID
+        #[clap(default_value_t = 1)]
+        id: u32,
+    },
+}
+
+/// Validates the backend and points the store to `workdir`.
+fn prepare_store(db: &str, workdir: &Path) -> Result<()> {
+    if db == "mem" {
+        bail!("mem store is not persistent");
+    }
+    if !bundle_stores().contains(&db) {
+        bail!(
+            "unknown store backend {}, available: {}",
+            db,
+            bundle_stores().join(", ")
+        );
+    }
+    std::fs::create_dir_all(workdir)?;
+    dtn7::CONFIG.lock().workdir = workdir.to_path_buf();
+    Ok(())
+}
+
+fn open_store(db: &str, workdir: &Path, key_file: Option<&Path>) -> Result<BundleStoresEnum> {
+    prepare_store(db, workdir)?;
+    dtn7::core::store::open(db, key_file)
+}
+
+/// Opens a store that is only read from, encrypted stores are left as they are.
+fn open_source(db: &str, workdir: &Path, key_file: Option<&Path>) -> Result<BundleStoresEnum> {
+    prepare_store(db, workdir)?;
+    dtn7::core::store::open_read_only(db, key_file)
+}
+
+fn print_summary(action: &str, summary: &ArchiveSummary) {
+    println!(
+        "{} {} bundles and {} tombstones, skipped {}",
+        action, summary.bundles, summary.tombstones, summary.skipped
+    );
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    match args.cmd {
+        Commands::Export {
+            db,
+            workdir,
+            nodeid,
+            key_file,
+            archive,
+        } => {
+            let store = open_source(&db, &workdir, key_file.as_deref())?;
+            let mut out = BufWriter::new(File::create(&archive)?);
+            let summary = store.export(&mut out, &nodeid)?;
+            print_summary("Exported", &summary);
+        }
+        Commands::Import {
+            db,
+            workdir,
+            key_file,
+            archive,
+        } => {
+            let mut store = open_store(&db, &workdir, key_file.as_deref())?;
+            let mut input = BufReader::new(File::open(&archive)?);
+            let (header, summary) = import_store(&mut store, &mut input)?;
+            println!("Archive of {} created at {}", header.node, header.created);
+            print_summary("Imported", &summary);
+        }
+        Commands::Migrate {
+            from,
+            to,
+            workdir,
+            target_workdir,
+            key_file,
+            target_key_file,
+        } => {
+            let target_workdir = target_workdir.unwrap_or_else(|| workdir.clone());
+            if from == to && target_workdir == workdir {
+                bail!("source and target store are the same");
+            }
+            let source = open_source(&from, &workdir, key_file.as_deref())?;
+            let mut target = open_store(&to, &target_workdir, target_key_file.as_deref())?;
+            let summary = migrate_store(&source, &mut target)?;
+            print_summary("Migrated", &summary);
+        }
+        #[cfg(feature = "store_encryption")]
+        Commands::Rekey {
+            db,
+            workdir,
+            key_file,
+        } => {
+            let keys = StoreKeys::from_file(&key_file)?;
+            prepare_store(&db, &workdir)?;
+            let inner = dtn7::core::store::new(&db);
+            let mut store = EncryptedBundleStore::new(inner, keys)?;
+            let resealed = store.rotate()?;
+            println!(
+                "Resealed {} bundles with store key {}",
+                resealed,
+                store.keys().current()
+            );
+        }
+        #[cfg(feature = "store_encryption")]
+        Commands::Keygen { id } => {
+            println!("{}", StoreKeys::generate_entry(id)?);
+        }
+    }
+    Ok(())
+}
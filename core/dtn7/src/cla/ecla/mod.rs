@@ -1,3 +1,4 @@
+use crate::core::encoding::Encoding;
 use async_trait::async_trait;
 use bp7::EndpointID;
 use enum_dispatch::enum_dispatch;
@@ -24,27 +25,67 @@ pub mod ws_client;
 
 */
 
+/// Binary data as base64 string in human readable encodings (JSON) and as byte string otherwise (CBOR).
 mod base64 {
     use base64::prelude::*;
 
-    use serde::{Deserialize, Serialize};
+    use serde::de::{self, SeqAccess, Visitor};
     use serde::{Deserializer, Serializer};
+    use std::fmt;
 
     // TODO: Uses a extra allocation at the moment. Might be worth investigating a allocation-less solution in the future.
 
     pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
-        let base64 = BASE64_STANDARD.encode(v);
-        String::serialize(&base64, s)
+        if s.is_human_readable() {
+            s.serialize_str(&BASE64_STANDARD.encode(v))
+        } else {
+            s.serialize_bytes(v)
+        }
     }
 
+    struct DataVisitor;
+
+    impl<'de> Visitor<'de> for DataVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("base64 string or byte string")
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            BASE64_STANDARD.decode(v.as_bytes()).map_err(E::custom)
+        }
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()? {
+                v.push(b);
+            }
+            Ok(v)
+        }
+    }
+
+    // Packets are internally tagged, so the deserializer can not tell which encoding is used
+    // and any representation is accepted.
     pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
-        let base64 = String::deserialize(d)?;
-        BASE64_STANDARD
-            .decode(base64.as_bytes())
-            .map_err(serde::de::Error::custom)
+        d.deserialize_any(DataVisitor)
     }
 }
 
+/// Version of the ECLA protocol spoken by dtnd.
+///
+/// Modules that do not state a version in their `Register` packet are treated as version 1,
+/// which has no capabilities, transfer acknowledgements or flow control.
+pub const ECLA_PROTOCOL_VERSION: u32 = 2;
+
+fn legacy_version() -> u32 {
+    1
+}
+
 /// The variant of Packets that can be sent or received. The resulting JSON will have
 /// a field called type that encodes the selected variant.
 #[derive(Serialize, Deserialize, Clone)]
@@ -62,6 +103,25 @@ pub enum Packet {
     Registered(Registered),
     /// Packet that contains a error message if a error happens.
     Error(Error),
+    /// Packet that reports the outcome of a ForwardData transfer requested by dtnd.
+    TransferAck(TransferAck),
+    /// Packet that grants dtnd additional ForwardData transfers if flow control is enabled.
+    Credit(Credit),
+}
+
+/// Optional protocol features a module can request on registration.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The module answers each ForwardData packet carrying a `transfer_id` with a TransferAck.
+    #[serde(default)]
+    pub transfer_ack: bool,
+    /// Number of ForwardData packets dtnd may send before it has to wait for Credit packets.
+    /// Flow control is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credits: Option<u32>,
+    /// Largest encoded bundle in bytes the module can transmit, larger bundles are not passed to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bundle_size: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -77,6 +137,12 @@ pub struct Beacon {
 pub struct Registered {
     pub eid: EndpointID,
     pub nodeid: String,
+    /// Negotiated protocol version.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    /// Capabilities dtnd agreed to use with this module.
+    #[serde(default)]
+    pub capabilities: Capabilities,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -98,6 +164,41 @@ pub struct Register {
     ///
     /// Example: For mtcp this would be the listening port on which it accepts connections and data.
     pub port: Option<u16>,
+    /// Highest protocol version supported by the module.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    /// Capabilities requested by the module, only used from protocol version 2 on.
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+impl Register {
+    /// Negotiates the protocol version and the capabilities to use with the registering module.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dtn7::cla::ecla::{Capabilities, Packet, ECLA_PROTOCOL_VERSION};
+    ///
+    /// let legacy = r#"{"type": "Register", "name": "lora", "enable_beacon": false, "port": null}"#;
+    /// let Packet::Register(reg) = serde_json::from_str(legacy).unwrap() else { panic!() };
+    /// assert_eq!(reg.negotiate(), (1, Capabilities::default()));
+    ///
+    /// let current = r#"{"type": "Register", "name": "lora", "enable_beacon": false, "port": null,
+    ///     "version": 3, "capabilities": {"transfer_ack": true, "credits": 4}}"#;
+    /// let Packet::Register(reg) = serde_json::from_str(current).unwrap() else { panic!() };
+    /// let (version, caps) = reg.negotiate();
+    /// assert_eq!(version, ECLA_PROTOCOL_VERSION);
+    /// assert!(caps.transfer_ack);
+    /// assert_eq!(caps.credits, Some(4));
+    /// ```
+    pub fn negotiate(&self) -> (u32, Capabilities) {
+        let version = self.version.clamp(1, ECLA_PROTOCOL_VERSION);
+        if version < 2 {
+            return (version, Capabilities::default());
+        }
+        (version, self.capabilities.clone())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -111,6 +212,24 @@ pub struct ForwardData {
     pub bundle_id: String,
     #[serde(with = "base64")]
     pub data: Vec<u8>,
+    /// Set by dtnd if the module requested transfer acknowledgements, to be returned in the TransferAck.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TransferAck {
+    pub transfer_id: u64,
+    /// Whether the bundle was handed to the transmission layer.
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Credit {
+    /// Number of additional ForwardData packets dtnd may send.
+    pub credits: u32,
 }
 
 /// Connection represents the session of a connection with a Tx channel to send data
@@ -119,6 +238,8 @@ pub struct ForwardData {
 struct Connection<A> {
     tx: Sender<A>,
     close: Option<oneshot::Sender<()>>,
+    /// Encoding of the packets, if not requested on connect it is taken from the first received packet.
+    encoding: Option<Encoding>,
 }
 
 #[enum_dispatch]
@@ -1,4 +1,4 @@
-use super::{Beacon, Connector, ForwardData, Packet};
+use super::{Beacon, Capabilities, Connector, ForwardData, Packet};
 use crate::cla::ecla::tcp::TCPConnector;
 use crate::cla::ecla::ws::WebsocketConnector;
 use crate::cla::ecla::{ConnectorEnum, Error, Registered};
@@ -12,15 +12,23 @@ use crate::{cla_names, CLAS, DTNCORE};
 use crate::{lazy_static, RoutingNotifcation};
 use crate::{peers_add, DtnPeer};
 use bp7::{Bundle, ByteBuffer};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::__private::TryFrom;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::time::interval;
+use std::time::Duration;
+use tokio::sync::{oneshot, Semaphore};
+use tokio::time::{interval, timeout};
 
 /// Specifies the maximum length for a name of ECLA modules.
 const ECLA_NAME_MAX_LEN: usize = 64;
 
+/// Time to wait for a credit or an acknowledgement before a transfer is considered failed.
+const ECLA_TRANSFER_TIMEOUT: Duration = Duration::from_secs(120);
+
+static TRANSFER_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 type ModuleMap = Arc<Mutex<HashMap<String, Module>>>;
 type LayerMap = Arc<Mutex<HashMap<String, ConnectorEnum>>>;
 
@@ -49,6 +57,14 @@ struct Module {
     connector: String,
     /// Specifies if the Module requested the optional service discovery to be enabled.
     enable_beacon: bool,
+    /// Negotiated protocol version.
+    version: u32,
+    /// Negotiated capabilities.
+    capabilities: Capabilities,
+    /// Remaining ForwardData packets the module accepts if flow control is enabled.
+    credits: Option<Arc<Semaphore>>,
+    /// Transfers waiting for an acknowledgement by transfer id.
+    pending: HashMap<u64, oneshot::Sender<TransferResult>>,
 }
 
 /// Generates a beacon packet that contains advertisement information about this dtnd instance.
@@ -88,7 +104,8 @@ async fn announcer() {
         task.tick().await;
 
         MODULE_MAP.lock().unwrap().iter().for_each(|(addr, value)| {
-            if !value.enable_beacon {
+            // unregistered modules might not have chosen their encoding yet
+            if !value.enable_beacon || !matches!(value.state, ModuleState::Active) {
                 return;
             }
 
@@ -146,10 +163,19 @@ pub fn handle_packet(connector_name: String, addr: String, packet: Packet) {
                     );
                     connector.close(addr.as_str());
                 } else if !cla_names().contains(&ident.name) {
+                    let (version, capabilities) = ident.negotiate();
                     me.name = ident.name;
                     me.state = ModuleState::Active;
-
-                    info!("Adding CLA '{}'", me.name);
+                    me.version = version;
+                    me.credits = capabilities
+                        .credits
+                        .map(|credits| Arc::new(Semaphore::new(credits as usize)));
+                    me.capabilities = capabilities;
+
+                    info!(
+                        "Adding CLA '{}' (protocol version {}, {:?})",
+                        me.name, me.version, me.capabilities
+                    );
 
                     let mut settings: HashMap<String, String> = HashMap::new();
                     settings.insert("name".to_string(), me.name.clone());
@@ -165,7 +191,12 @@ pub fn handle_packet(connector_name: String, addr: String, packet: Packet) {
                     let nodeid = CONFIG.lock().nodeid.clone();
                     connector.send_packet(
                         addr.as_str(),
-                        &Packet::Registered(Registered { eid, nodeid }),
+                        &Packet::Registered(Registered {
+                            eid,
+                            nodeid,
+                            version: me.version,
+                            capabilities: me.capabilities.clone(),
+                        }),
                     );
 
                     // Send initial beacon
@@ -189,8 +220,10 @@ pub fn handle_packet(connector_name: String, addr: String, packet: Packet) {
         ModuleState::Active => match packet {
             // We got a new Bundle Packet that needs to be parsed and processed.
             Packet::ForwardData(fwd) => {
+                let len = fwd.data.len();
                 if let Ok(bndl) = Bundle::try_from(fwd.data) {
                     info!("Received bundle: {} from {}", bndl.id(), me.name);
+                    crate::peers_report_receive(&bndl, &me.name, len);
                     {
                         tokio::spawn(async move {
                             if let Err(err) = crate::core::processing::receive(bndl).await {
@@ -226,6 +259,38 @@ pub fn handle_packet(connector_name: String, addr: String, packet: Packet) {
                     error!("Failed to send encountered peer notification: {}", err);
                 }
             }
+            // The module reports the outcome of a transfer.
+            Packet::TransferAck(ack) => {
+                if let Some(reply) = me.pending.remove(&ack.transfer_id) {
+                    let result = if ack.success {
+                        TransferResult::Successful
+                    } else {
+                        warn!(
+                            "ECLA '{}' failed transfer {}: {}",
+                            me.name,
+                            ack.transfer_id,
+                            ack.reason.unwrap_or_default()
+                        );
+                        TransferResult::Failure
+                    };
+                    let _ = reply.send(result);
+                } else {
+                    debug!(
+                        "ECLA '{}' acknowledged unknown transfer {}",
+                        me.name, ack.transfer_id
+                    );
+                }
+            }
+            // The module accepts more transfers.
+            Packet::Credit(credit) => {
+                if let Some(credits) = &me.credits {
+                    let granted = (credit.credits as usize)
+                        .min(Semaphore::MAX_PERMITS - credits.available_permits());
+                    credits.add_permits(granted);
+                } else {
+                    debug!("ECLA '{}' sent credits without flow control", me.name);
+                }
+            }
             _ => {}
         },
     }
@@ -241,6 +306,10 @@ pub fn handle_connect(connector_name: String, from: String) {
             name: "".to_string(),
             connector: connector_name,
             enable_beacon: true,
+            version: 1,
+            capabilities: Capabilities::default(),
+            credits: None,
+            pending: HashMap::new(),
         },
     );
 }
@@ -250,44 +319,129 @@ pub fn handle_connect(connector_name: String, from: String) {
 pub fn handle_disconnect(addr: String) {
     info!("ECLA {} disconnected", &addr);
 
-    if let Some(module) = MODULE_MAP.lock().unwrap().get(&addr) {
+    if let Some(module) = MODULE_MAP.lock().unwrap().remove(&addr) {
         if let ModuleState::Active = module.state {
             cla_remove(module.name.clone());
         }
+        // wake up transfers waiting for credits, pending acknowledgements fail on drop
+        if let Some(credits) = module.credits {
+            credits.close();
+        }
     }
-
-    MODULE_MAP.lock().unwrap().remove(&addr);
 }
 
-/// Will schedule a submission to a module by name
-pub fn scheduled_submission(name: String, dest: String, ready: &ByteBuffer) -> TransferResult {
+/// Will schedule a submission to a module by name.
+///
+/// Waits for a credit if the module uses flow control and for the acknowledgement
+/// if the module requested them, otherwise a successful hand-over counts as success.
+pub async fn scheduled_submission(
+    name: String,
+    dest: String,
+    ready: &ByteBuffer,
+) -> TransferResult {
     debug!(
             "Scheduled submission External Convergence Layer for Destination with Module '{}' and Target '{}'",
             name, dest
         );
 
-    let mut was_sent = TransferResult::Failure;
-    let mut connectors_map = CONNECTORS_MAP.lock().unwrap();
-    let module_map = MODULE_MAP.lock().unwrap();
-    module_map.iter().for_each(|(addr, value)| {
-        if value.name == name {
-            if let Ok(bndl) = Bundle::try_from(ready.as_slice()) {
-                let packet: Packet = Packet::ForwardData(ForwardData {
-                    dst: dest.to_string(),
-                    src: "".to_string(), // Leave blank for now and let the Module set it to a protocol-specific address on its side
-                    bundle_id: bndl.id(),
-                    data: ready.to_vec(),
-                });
-
-                if let Some(connector) = connectors_map.get_mut(value.connector.as_str()) {
-                    connector.send_packet(addr, &packet);
-                    was_sent = TransferResult::Successful;
-                }
+    let bndl = match Bundle::try_from(ready.as_slice()) {
+        Ok(bndl) => bndl,
+        Err(_) => return TransferResult::Failure,
+    };
+
+    let (addr, connector, capabilities, credits) = {
+        let module_map = MODULE_MAP.lock().unwrap();
+        let module = module_map
+            .iter()
+            .find(|(_, m)| m.name == name && matches!(m.state, ModuleState::Active));
+        match module {
+            Some((addr, m)) => (
+                addr.clone(),
+                m.connector.clone(),
+                m.capabilities.clone(),
+                m.credits.clone(),
+            ),
+            None => return TransferResult::Failure,
+        }
+    };
+
+    if let Some(max) = capabilities.max_bundle_size {
+        if ready.len() as u64 > max {
+            warn!(
+                "Bundle {} exceeds maximum bundle size of ECLA '{}' ({} > {})",
+                bndl.id(),
+                name,
+                ready.len(),
+                max
+            );
+            return TransferResult::Failure;
+        }
+    }
+
+    if let Some(credits) = &credits {
+        match timeout(ECLA_TRANSFER_TIMEOUT, credits.acquire()).await {
+            Ok(Ok(permit)) => permit.forget(),
+            _ => {
+                warn!("No credits from ECLA '{}' for bundle {}", name, bndl.id());
+                return TransferResult::Failure;
             }
         }
-    });
+    }
 
-    was_sent
+    let mut fwd = ForwardData {
+        dst: dest.to_string(),
+        src: "".to_string(), // Leave blank for now and let the Module set it to a protocol-specific address on its side
+        bundle_id: bndl.id(),
+        data: ready.to_vec(),
+        transfer_id: None,
+    };
+    let mut ack = None;
+    if capabilities.transfer_ack {
+        let transfer_id = TRANSFER_ID_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        match MODULE_MAP.lock().unwrap().get_mut(&addr) {
+            Some(module) => module.pending.insert(transfer_id, tx),
+            None => return TransferResult::Failure,
+        };
+        fwd.transfer_id = Some(transfer_id);
+        ack = Some((transfer_id, rx));
+    }
+
+    let sent = CONNECTORS_MAP
+        .lock()
+        .unwrap()
+        .get(connector.as_str())
+        .is_some_and(|connector| connector.send_packet(&addr, &Packet::ForwardData(fwd)));
+    if !sent {
+        if let Some(credits) = &credits {
+            credits.add_permits(1);
+        }
+        if let Some((transfer_id, _)) = ack {
+            if let Some(module) = MODULE_MAP.lock().unwrap().get_mut(&addr) {
+                module.pending.remove(&transfer_id);
+            }
+        }
+        return TransferResult::Failure;
+    }
+
+    let Some((transfer_id, rx)) = ack else {
+        return TransferResult::Successful;
+    };
+    match timeout(ECLA_TRANSFER_TIMEOUT, rx).await {
+        Ok(Ok(result)) => result,
+        // module disconnected
+        Ok(Err(_)) => TransferResult::Failure,
+        Err(_) => {
+            warn!(
+                "ECLA '{}' did not acknowledge transfer {}",
+                name, transfer_id
+            );
+            if let Some(module) = MODULE_MAP.lock().unwrap().get_mut(&addr) {
+                module.pending.remove(&transfer_id);
+            }
+            TransferResult::Failure
+        }
+    }
 }
 
 /// Adds a connector to the registered connectors.
@@ -1,18 +1,18 @@
 use super::Connector;
 use crate::cla::ecla::processing::{handle_connect, handle_disconnect, handle_packet};
 use crate::cla::ecla::Packet;
+use crate::core::encoding::Encoding;
 use crate::lazy_static;
 use async_trait::async_trait;
 use futures_util::{future, stream::TryStreamExt};
 use log::info;
-use log::{debug, error};
+use log::{debug, error, warn};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
-use tokio_serde::formats::SymmetricalJson;
 use tokio_util::codec::{FramedRead, LengthDelimitedCodec};
 
 type TCPConnection = super::Connection<Vec<u8>>;
@@ -35,6 +35,7 @@ async fn handle_connection(raw_stream: TcpStream, addr: SocketAddr) {
         TCPConnection {
             tx,
             close: Some(tx_close),
+            encoding: None,
         },
     );
     handle_connect("TCP".to_string(), addr.to_string());
@@ -44,14 +45,21 @@ async fn handle_connection(raw_stream: TcpStream, addr: SocketAddr) {
     // Delimit frames using a length header
     let length_delimited = FramedRead::new(incoming, LengthDelimitedCodec::new());
 
-    // Deserialize frames
-    let deserialized = tokio_serde::SymmetricallyFramed::new(
-        length_delimited,
-        SymmetricalJson::<Packet>::default(),
-    );
-
-    let incoming = deserialized.try_for_each(|packet| {
-        handle_packet("TCP".to_string(), addr.to_string(), packet);
+    // Deserialize frames, the encoding is taken from the first frame
+    let incoming = length_delimited.try_for_each(|frame| {
+        let encoding = match PEER_MAP.lock().unwrap().get_mut(&addr.to_string()) {
+            Some(conn) => *conn
+                .encoding
+                .get_or_insert_with(|| Encoding::detect(&frame)),
+            None => return future::ok(()),
+        };
+        match encoding.decode::<Packet>(&frame) {
+            Ok(packet) => handle_packet("TCP".to_string(), addr.to_string(), packet),
+            Err(err) => warn!(
+                "Failed to decode {} packet from {}: {}",
+                encoding, addr, err
+            ),
+        }
         future::ok(())
     });
 
@@ -112,16 +120,20 @@ impl Connector for TCPConnector {
         debug!("Sending Packet to {} ({})", dest, self.name());
 
         let peer_map = PEER_MAP.lock().unwrap();
-        let target = peer_map.get(dest);
-        if target.is_some() {
+        if let Some(target) = peer_map.get(dest) {
             // Build the packet frame [ len: u32 | frame payload (data) ]
-            let mut data = serde_json::to_vec(&packet).unwrap();
+            let encoding = target.encoding.unwrap_or_default();
+            let mut data = match encoding.encode(packet) {
+                Ok(data) => data,
+                Err(err) => {
+                    error!("Failed to encode packet for {}: {}", dest, err);
+                    return false;
+                }
+            };
             let len = (data.len() as u32).to_be_bytes();
             data.splice(0..0, len.iter().cloned());
 
-            if let Some(target) = target {
-                return target.tx.try_send(data).is_ok();
-            }
+            return target.tx.try_send(data).is_ok();
         }
 
         false
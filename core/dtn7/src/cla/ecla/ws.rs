@@ -1,12 +1,12 @@
 use super::Connector;
 use crate::cla::ecla::processing::{handle_connect, handle_disconnect, handle_packet};
 use crate::cla::ecla::Packet;
+use crate::core::encoding::Encoding;
 use crate::lazy_static;
 use async_trait::async_trait;
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::{future, stream::TryStreamExt, SinkExt, StreamExt};
 use log::{error, warn, info, debug, trace};
-use serde_json::Result;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
@@ -24,8 +24,8 @@ lazy_static! {
 static ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 static LAYER_NAME: &str = "Websocket";
 
-/// Handles the websocket connection coming from httpd
-pub async fn handle_connection(ws: WebSocket) {
+/// Handles the websocket connection coming from httpd, `encoding` is the encoding requested on connect.
+pub async fn handle_connection(ws: WebSocket, encoding: Option<Encoding>) {
     // We can't get a remote address from ws, so we create our own monotonic increasing IDs
     let id = ID_COUNTER.fetch_add(1, Ordering::SeqCst);
 
@@ -37,6 +37,7 @@ pub async fn handle_connection(ws: WebSocket) {
         WebSocketConnection {
             tx,
             close: Some(tx_close),
+            encoding,
         },
     );
     handle_connect(LAYER_NAME.to_string(), id.to_string());
@@ -46,7 +47,7 @@ pub async fn handle_connection(ws: WebSocket) {
     // Process incoming messages from the websocket client
     let broadcast_incoming = incoming.try_for_each(|msg| {
 
-        let packet: Result<Packet>;
+        let packet: anyhow::Result<Packet>;
         {
             // Get own peer
             let mut peer_map = PEER_MAP.lock().unwrap();
@@ -56,25 +57,28 @@ pub async fn handle_connection(ws: WebSocket) {
                 return future::ok(());
             }
 
-            // Try to convert the message to text
-            let msg_text = match msg.to_text() {
-                Ok(text) => {
+            // Text messages carry JSON, binary messages CBOR
+            let (data, msg_encoding) = match &msg {
+                Message::Text(text) => {
+                    trace!("Received a message from ECLA id {}: {}", id, text.trim());
+                    (text.as_bytes(), Encoding::Json)
+                }
+                Message::Binary(data) => {
                     trace!(
-                        "Received a message from ECLA id {}: {}",
+                        "Received a binary message from ECLA id {}: {} bytes",
                         id,
-                        text.trim()
+                        data.len()
                     );
-                    text.trim()
-                },
-                Err(e) => {
-                    warn!("Failed to convert message to text from ECLA id {}: {}", id, e);
-                    return future::ok(());
+                    (data.as_slice(), Encoding::Cbor)
                 }
+                _ => return future::ok(()),
             };
+            me_opt.unwrap().encoding.get_or_insert(msg_encoding);
 
             // Deserialize Packet
-            packet = serde_json::from_str(msg_text);
-            if packet.is_err() {
+            packet = msg_encoding.decode(data);
+            if let Err(err) = &packet {
+                warn!("Failed to decode packet from ECLA id {}: {}", id, err);
                 return future::ok(());
             }
         }
@@ -130,8 +134,11 @@ impl Connector for WebsocketConnector {
 
         let peer_map = PEER_MAP.lock().unwrap();
         if let Some(target) = peer_map.get(dest) {
-            let data = serde_json::to_string(&packet);
-            return target.tx.try_send(Message::Text(data.unwrap())).is_ok();
+            let msg = match target.encoding.unwrap_or_default() {
+                Encoding::Json => serde_json::to_string(&packet).map(Message::Text).ok(),
+                Encoding::Cbor => serde_cbor::to_vec(&packet).map(Message::Binary).ok(),
+            };
+            return msg.is_some_and(|msg| target.tx.try_send(msg).is_ok());
         }
 
         false
@@ -1,11 +1,11 @@
-use super::{Packet, Register};
+use super::{Capabilities, Packet, Register, ECLA_PROTOCOL_VERSION};
 use crate::cla::ecla;
 use crate::cla::ecla::ws_client::Command::SendPacket;
+use crate::core::encoding::Encoding;
 use anyhow::bail;
 use futures::channel::mpsc::unbounded;
 use futures_util::{future, pin_mut, SinkExt, StreamExt};
 use log::{error, info, warn};
-use serde_json::Result;
 use std::str::FromStr;
 use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
@@ -25,6 +25,8 @@ pub struct Client {
     id: String,
     port: u16,
     ecla_port: Option<u16>,
+    capabilities: Capabilities,
+    encoding: Encoding,
     cmd_receiver: mpsc::Receiver<Command>,
     cmd_sender: mpsc::Sender<Command>,
     packet_out: mpsc::Sender<Packet>,
@@ -72,6 +74,8 @@ pub fn new(
         id: current_id.to_string(),
         port: port.unwrap(),
         ecla_port: None,
+        capabilities: Capabilities::default(),
+        encoding: Encoding::default(),
         enable_beacon,
         cmd_receiver,
         cmd_sender,
@@ -82,8 +86,12 @@ pub fn new(
 impl Client {
     /// Connects and starts to handle packets. Will block until a severe error is encountered or the client is closed.
     pub async fn serve(&mut self) -> anyhow::Result<()> {
-        let (ws_stream, _) =
-            connect_async(format!("ws://{}:{}/ws/ecla", self.ip, self.port)).await?;
+        let encoding = self.encoding;
+        let (ws_stream, _) = connect_async(format!(
+            "ws://{}:{}/ws/ecla?encoding={}",
+            self.ip, self.port, encoding
+        ))
+        .await?;
 
         info!("WebSocket handshake has been successfully completed");
 
@@ -96,6 +104,8 @@ impl Client {
                 name: self.module_name.to_string(),
                 enable_beacon: self.enable_beacon,
                 port: self.ecla_port,
+                version: ECLA_PROTOCOL_VERSION,
+                capabilities: self.capabilities.clone(),
             })))
             .await
         {
@@ -108,8 +118,17 @@ impl Client {
             while let Some(command) = cmd_receiver.recv().await {
                 match command {
                     Command::SendPacket(packet) => {
-                        let data = serde_json::to_string(&packet);
-                        if write.send(Message::Text(data.unwrap())).await.is_err() {
+                        let msg = match encoding {
+                            Encoding::Json => {
+                                serde_json::to_string(&packet).map(Message::Text).ok()
+                            }
+                            Encoding::Cbor => serde_cbor::to_vec(&packet).map(Message::Binary).ok(),
+                        };
+                        let sent = match msg {
+                            Some(msg) => write.send(msg).await.is_ok(),
+                            None => false,
+                        };
+                        if !sent {
                             error!("Error while sending packet");
                         }
                     }
@@ -129,9 +148,11 @@ impl Client {
                     return;
                 }
 
-                let data = message.unwrap().into_text();
-
-                let packet: Result<Packet> = serde_json::from_str(data.unwrap().as_str());
+                let packet: anyhow::Result<Packet> = match message.unwrap() {
+                    Message::Text(text) => Encoding::Json.decode(text.as_bytes()),
+                    Message::Binary(data) => Encoding::Cbor.decode(&data),
+                    _ => return,
+                };
                 if let Ok(packet) = packet {
                     // Pass received packets to read channel
                     match packet {
@@ -185,6 +206,16 @@ impl Client {
         self.ecla_port = Some(port);
     }
 
+    /// Sets the capabilities requested on registration, e.g., transfer acknowledgements or flow control.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Sets the encoding of the packets, CBOR avoids the base64 overhead for bundle data.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
     pub fn set_current_id(&mut self, id: &str) {
         self.id = id.to_string();
     }
@@ -43,7 +43,7 @@ impl ExternalConvergenceLayer {
                             tokio::spawn(async move {
                                 debug!("ExternalConvergenceLayer will schedule submission");
                                 reply
-                                    .send(scheduled_submission(name, dest, &ready))
+                                    .send(scheduled_submission(name, dest, &ready).await)
                                     .unwrap();
                             });
                         } else {
@@ -0,0 +1,445 @@
+use crate::cla::{ClaSenderTask, ConvergenceLayerAgent, TransferResult};
+use crate::core::peer::{DtnPeer, PeerAddress, PeerType};
+use crate::CONFIG;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bp7::{Bundle, EndpointID};
+use core::convert::TryFrom;
+use dtn7_codegen::cla;
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use super::HelpStr;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const BUNDLE_DIR: &str = "bundles";
+
+lazy_static! {
+    /// Serializes manifest updates of exports and imports
+    static ref MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+    /// Destination nodes accepted by pseudo-peers restricted with `destinations`
+    static ref FILE_DESTINATIONS: Mutex<HashMap<String, HashSet<String>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Description of a bundle file on the carried medium.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// file name relative to the bundle directory
+    pub file: String,
+    pub id: String,
+    pub source: String,
+    pub destination: String,
+    pub size: u64,
+    /// node name of the exporting node
+    pub exported_by: String,
+    /// unix time in seconds
+    pub exported_at: u64,
+    /// nodes that already imported this bundle, only used if files are kept after import
+    #[serde(default)]
+    pub imported_by: Vec<String>,
+}
+
+/// Index of all bundles in an exchange directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Manifest {
+    pub bundles: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Manifest> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes the manifest atomically to not leave a broken file if the medium is pulled.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FileSettings {
+    path: PathBuf,
+    peer: String,
+    destinations: HashSet<String>,
+    interval: Duration,
+    remove: bool,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+fn local_node() -> String {
+    CONFIG.lock().host_eid.node().unwrap_or_default()
+}
+
+/// Turns a bundle id into a portable file name.
+pub fn bundle_file_name(bid: &str) -> String {
+    let name: String = bid
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}.cbor", name)
+}
+
+/// Writes a bundle into the exchange directory `dir` and adds it to the manifest.
+pub fn export_bundle(dir: &Path, data: &[u8]) -> Result<ManifestEntry> {
+    if !dir.is_dir() {
+        // do not silently write to the mount point of a missing medium
+        bail!("exchange directory {} not available", dir.display());
+    }
+    let bndl = Bundle::try_from(data.to_vec())?;
+    let bundle_dir = dir.join(BUNDLE_DIR);
+    fs::create_dir_all(&bundle_dir)?;
+
+    let entry = ManifestEntry {
+        file: bundle_file_name(&bndl.id()),
+        id: bndl.id(),
+        source: bndl.primary.source.to_string(),
+        destination: bndl.primary.destination.to_string(),
+        size: data.len() as u64,
+        exported_by: local_node(),
+        exported_at: now_secs(),
+        imported_by: Vec::new(),
+    };
+    let tmp = bundle_dir.join(format!("{}.tmp", entry.file));
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, bundle_dir.join(&entry.file))?;
+
+    let _guard = MANIFEST_LOCK.lock();
+    let mut manifest = Manifest::load(dir)?;
+    manifest.bundles.retain(|e| e.id != entry.id);
+    manifest.bundles.push(entry.clone());
+    manifest.save(dir)?;
+    Ok(entry)
+}
+
+/// Lists the bundles in the exchange directory `dir` that were not exported by this node.
+///
+/// Files stay untouched until the bundle is passed to [`complete_import`] after it was
+/// received successfully. Bundles already present in the local store are not returned but
+/// completed right away. The bundles are read one at a time with [`read_import`].
+pub fn pending_imports(dir: &Path, remove: bool) -> Result<Vec<ManifestEntry>> {
+    let node = local_node();
+    let bundle_dir = dir.join(BUNDLE_DIR);
+    let _guard = MANIFEST_LOCK.lock();
+    let mut manifest = Manifest::load(dir)?;
+    let mut pending = Vec::new();
+    let mut done = Vec::new();
+    let mut changed = false;
+
+    manifest.bundles.retain(|entry| {
+        if entry.exported_by == node || entry.imported_by.contains(&node) {
+            return true;
+        }
+        if !bundle_dir.join(&entry.file).is_file() {
+            warn!("Dropping manifest entry for missing file {}", entry.file);
+            changed = true;
+            return false;
+        }
+        if crate::store_has_item(&entry.id) {
+            debug!("Skipping already known bundle {}", entry.id);
+            done.push(entry.id.clone());
+        } else {
+            pending.push(entry.clone());
+        }
+        true
+    });
+    if !done.is_empty() {
+        complete_entries(dir, &mut manifest, &node, &done, remove);
+        changed = true;
+    }
+    if changed {
+        manifest.save(dir)?;
+    }
+    Ok(pending)
+}
+
+/// Reads and decodes the bundle of a manifest entry returned by [`pending_imports`].
+///
+/// Broken files are completed right away and yield `None`.
+pub fn read_import(dir: &Path, entry: &ManifestEntry, remove: bool) -> Result<Option<Bundle>> {
+    let data = fs::read(dir.join(BUNDLE_DIR).join(&entry.file))?;
+    match Bundle::try_from(data) {
+        Ok(bndl) => Ok(Some(bndl)),
+        Err(err) => {
+            crate::STATS.lock().broken += 1;
+            warn!("Error decoding bundle file {}: {}", entry.file, err);
+            complete_import(dir, &entry.id, remove)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Marks an imported bundle as done after it was received, its file is removed if `remove`
+/// is set, otherwise this node is recorded in the manifest.
+pub fn complete_import(dir: &Path, id: &str, remove: bool) -> Result<()> {
+    let node = local_node();
+    let _guard = MANIFEST_LOCK.lock();
+    let mut manifest = Manifest::load(dir)?;
+    complete_entries(dir, &mut manifest, &node, &[id.to_string()], remove);
+    manifest.save(dir)
+}
+
+fn complete_entries(dir: &Path, manifest: &mut Manifest, node: &str, ids: &[String], remove: bool) {
+    let bundle_dir = dir.join(BUNDLE_DIR);
+    manifest.bundles.retain_mut(|entry| {
+        if !ids.contains(&entry.id) {
+            return true;
+        }
+        if remove {
+            if let Err(err) = fs::remove_file(bundle_dir.join(&entry.file)) {
+                warn!("Error removing bundle file {}: {}", entry.file, err);
+            }
+            false
+        } else {
+            if !entry.imported_by.iter().any(|n| n == node) {
+                entry.imported_by.push(node.to_string());
+            }
+            true
+        }
+    });
+}
+
+/// Removes senders to file pseudo-peers that are not configured for the bundle destination.
+///
+/// Restricted destinations are not a transmission failure, so such bundles are never handed
+/// to the CLA in the first place.
+pub fn filter_senders(destination: &EndpointID, senders: &mut Vec<ClaSenderTask>) {
+    let restricted = FILE_DESTINATIONS.lock();
+    if restricted.is_empty() {
+        return;
+    }
+    let dest = destination.node();
+    senders.retain(|sender| {
+        if sender.cla_name != "file" {
+            return true;
+        }
+        match sender
+            .next_hop
+            .node()
+            .and_then(|peer| restricted.get(&peer))
+        {
+            Some(allowed) => dest.as_ref().is_some_and(|d| allowed.contains(d)),
+            None => true,
+        }
+    });
+}
+
+async fn file_importer(settings: FileSettings) {
+    let mut task = tokio::time::interval(settings.interval);
+    loop {
+        task.tick().await;
+        if !settings.path.join(MANIFEST_FILE).exists() {
+            continue;
+        }
+        let (path, remove) = (settings.path.clone(), settings.remove);
+        let entries = match tokio::task::spawn_blocking(move || pending_imports(&path, remove))
+            .await
+            .unwrap_or_else(|err| Err(err.into()))
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!(
+                    "Error importing bundles from {}: {}",
+                    settings.path.display(),
+                    err
+                );
+                continue;
+            }
+        };
+        for entry in entries {
+            let (path, remove) = (settings.path.clone(), settings.remove);
+            let (file, size) = (entry.file.clone(), entry.size);
+            let bndl = match tokio::task::spawn_blocking(move || read_import(&path, &entry, remove))
+                .await
+                .unwrap_or_else(|err| Err(err.into()))
+            {
+                Ok(Some(bndl)) => bndl,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!("Error reading bundle file {}: {}", file, err);
+                    continue;
+                }
+            };
+            let bid = bndl.id();
+            info!("Received bundle: {} from {}", bid, settings.path.display());
+            crate::peers_report_receive(&bndl, "file", size as usize);
+            if let Err(err) = crate::core::processing::receive(bndl).await {
+                // the file is kept and imported again on the next scan
+                error!("Failed to process bundle: {}", err);
+                continue;
+            }
+            let (path, remove) = (settings.path.clone(), settings.remove);
+            match tokio::task::spawn_blocking(move || complete_import(&path, &bid, remove))
+                .await
+                .unwrap_or_else(|err| Err(err.into()))
+            {
+                Ok(()) => {}
+                Err(err) => warn!("Error completing import: {}", err),
+            }
+        }
+    }
+}
+
+fn file_export(settings: &FileSettings, data: &[u8]) -> TransferResult {
+    match export_bundle(&settings.path, data) {
+        Ok(entry) => {
+            debug!(
+                "Exported bundle {} to {}",
+                entry.id,
+                settings.path.display()
+            );
+            TransferResult::Successful
+        }
+        Err(err) => {
+            warn!("Error exporting bundle: {}", err);
+            TransferResult::Failure
+        }
+    }
+}
+
+#[cla(file)]
+#[derive(Debug, Clone)]
+pub struct FileConvergenceLayer {
+    settings: FileSettings,
+    tx: mpsc::Sender<super::ClaCmd>,
+}
+
+impl FileConvergenceLayer {
+    pub fn new(local_settings: Option<&HashMap<String, String>>) -> FileConvergenceLayer {
+        let get = |key: &str| {
+            local_settings
+                .and_then(|settings| settings.get(key))
+                .map(|s| s.to_string())
+        };
+        let settings = FileSettings {
+            path: get("path")
+                .unwrap_or_else(|| "/media/dtn7".to_string())
+                .into(),
+            peer: get("peer").unwrap_or_else(|| "sneakernet".to_string()),
+            destinations: get("destinations")
+                .map(|d| {
+                    d.split(',')
+                        .filter(|n| !n.is_empty())
+                        .map(|n| n.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            interval: get("interval")
+                .and_then(|i| humantime::parse_duration(&i).ok())
+                .unwrap_or(Duration::from_secs(10)),
+            remove: get("remove")
+                .map(|r| r == "true" || r == "1")
+                .unwrap_or(false),
+        };
+        let (tx, mut rx) = mpsc::channel(100);
+        let task_settings = settings.clone();
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    super::ClaCmd::Transfer(remote, data, reply) => {
+                        debug!(
+                            "FileConvergenceLayer: received transfer command for {}",
+                            remote
+                        );
+                        if !data.is_empty() {
+                            let settings = task_settings.clone();
+                            let res =
+                                tokio::task::spawn_blocking(move || file_export(&settings, &data))
+                                    .await
+                                    .unwrap_or(TransferResult::Failure);
+                            reply.send(res).unwrap();
+                        } else {
+                            debug!("Nothing to forward.");
+                            reply.send(TransferResult::Successful).unwrap();
+                        }
+                    }
+                    super::ClaCmd::Shutdown => {
+                        debug!("FileConvergenceLayer: received shutdown command");
+                        break;
+                    }
+                }
+            }
+        });
+        FileConvergenceLayer { settings, tx }
+    }
+
+    pub async fn spawn_listener(&self) -> Result<()> {
+        debug!(
+            "spawning file importer for {} every {:?}",
+            self.settings.path.display(),
+            self.settings.interval
+        );
+        tokio::spawn(file_importer(self.settings.clone()));
+        if !self.settings.destinations.is_empty() {
+            FILE_DESTINATIONS.lock().insert(
+                self.settings.peer.clone(),
+                self.settings.destinations.clone(),
+            );
+        }
+
+        let eid = EndpointID::try_from(format!("dtn://{}/", self.settings.peer))?;
+        info!(
+            "Adding pseudo peer {} for {}",
+            eid,
+            self.settings.path.display()
+        );
+        crate::peers_add(DtnPeer::new(
+            eid,
+            PeerAddress::Generic(self.settings.path.display().to_string()),
+            PeerType::Static,
+            None,
+            vec![("file".into(), None)],
+            HashMap::new(),
+        ));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConvergenceLayerAgent for FileConvergenceLayer {
+    async fn setup(&mut self) {
+        self.spawn_listener()
+            .await
+            .expect("error setting up file importer");
+    }
+    fn port(&self) -> u16 {
+        0
+    }
+    fn name(&self) -> &str {
+        "file"
+    }
+    fn channel(&self) -> tokio::sync::mpsc::Sender<super::ClaCmd> {
+        self.tx.clone()
+    }
+}
+
+impl HelpStr for FileConvergenceLayer {
+    fn local_help_str() -> &'static str {
+        "path=/media/dtn7:peer=sneakernet:destinations=<node1,node2>:interval=10s:remove=false"
+    }
+}
+impl std::fmt::Display for FileConvergenceLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "file:{}", self.settings.path.display())
+    }
+}
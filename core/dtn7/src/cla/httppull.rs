@@ -1,91 +1,161 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::time::Duration;
 
-use crate::core::helpers::get_complete_digest;
-use crate::{store_has_item, CONFIG};
+use crate::core::reconciliation::{
+    full_range_digest, local_bids, range_digest, RangeDigest, SyncFilter, SyncRequest,
+};
+use crate::{store_get_metadata, CONFIG};
 
 use super::TransferResult;
 use super::{ConvergenceLayerAgent, HelpStr};
+use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use bp7::EndpointID;
 use dtn7_codegen::cla;
+use hyper::body::Bytes;
+use hyper::{Body, Client, Method, Request, StatusCode};
 use log::{debug, error, info, warn};
+use serde::Serialize;
 use tokio::select;
 use tokio::sync::mpsc;
 
+/// Upper bound of reconciliation rounds per pull, each round descends one level into the ranges.
+const MAX_ROUNDS: usize = 32;
+/// Time a single request to a remote may take including its response body.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+struct HttpPullSettings {
+    filter: SyncFilter,
+    /// number of bundles requested at once
+    batch: usize,
+}
+
 #[cla(httppull)]
 #[derive(Debug, Clone)]
 pub struct HttpPullConvergenceLayer {
     tx: mpsc::Sender<super::ClaCmd>,
 }
 
-/// pulls missing bundles from node
-/// addr can be either an IP or a DNS name
-async fn http_pull_from_node(
-    eid: EndpointID,
-    addr: String,
-    port: u16,
-    local_digest: String,
-) -> TransferResult {
-    let now = std::time::Instant::now();
-    let mut transfers = 0;
+/// Sends a request and reads the response body, both within `REQUEST_TIMEOUT`.
+async fn fetch(req: Request<Body>) -> Result<(StatusCode, Bytes)> {
+    let uri = req.uri().clone();
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        let response = Client::new().request(req).await?;
+        let status = response.status();
+        Ok((status, hyper::body::to_bytes(response.into_body()).await?))
+    })
+    .await
+    .with_context(|| format!("request to {} timed out", uri))?
+}
 
-    debug!("pulling bundles from {} / {}", eid, addr);
-    tokio::task::spawn_blocking(move || {
-        // get digest of remote node
-        let response =
-            attohttpc::get(format!("http://{}:{}/status/bundles/digest", addr, port)).send();
-        let digest = match response {
-            Ok(digest) => digest.text().unwrap(),
-            Err(e) => {
-                error!("could not get digest from remote: {}", e);
-                //bail!("could not get digest from remote: {}", e);
-                return TransferResult::Failure;
-            }
+async fn get(url: &str) -> Result<(StatusCode, Bytes)> {
+    fetch(Request::get(url).body(Body::empty())?).await
+}
+
+async fn post_json<T: Serialize>(url: &str, body: &T) -> Result<(StatusCode, Bytes)> {
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?;
+    fetch(req).await
+}
+
+/// Bundles this node never had, bundles deleted here leave a tombstone and are not pulled again.
+fn is_unknown(bid: &str) -> bool {
+    store_get_metadata(bid).is_none()
+}
+
+/// Falls back to the complete bundle list for remotes without reconciliation support.
+async fn legacy_missing_bids(base: &str) -> Result<Vec<String>> {
+    let (_, body) = get(&format!("{}/status/bundles", base)).await?;
+    let bids: Vec<String> = serde_json::from_slice(&body)?;
+    Ok(bids.into_iter().filter(|bid| is_unknown(bid)).collect())
+}
+
+/// Determines the bundles known to the remote but not to this node.
+async fn missing_bids(base: &str, filter: &SyncFilter) -> Result<Vec<String>> {
+    let local = local_bids(filter);
+    let mut ranges = vec![full_range_digest(&local)];
+    let mut missing = Vec::new();
+
+    for round in 0..MAX_ROUNDS {
+        if ranges.is_empty() {
+            debug!("reconciliation with {} done after {} rounds", base, round);
+            return Ok(missing);
+        }
+        let request = SyncRequest {
+            filter: filter.clone(),
+            ranges,
         };
-        if digest == local_digest || digest.len() != 40 {
-            debug!("no new bundles on remote");
-            return TransferResult::Successful;
-        } else {
+        let (status, body) = post_json(&format!("{}/sync/ranges", base), &request).await?;
+        if status == StatusCode::NOT_FOUND {
             debug!(
-                "remote ({}) has new bundles (remote: {} vs local: {})",
-                eid, digest, local_digest
+                "{} does not support reconciliation, fetching full list",
+                base
             );
+            return legacy_missing_bids(base).await;
         }
-        // get list of bundles from remote node
-        let response = attohttpc::get(format!("http://{}:{}/status/bundles", addr, port)).send();
-        let bid_list = match response {
-            Ok(bid_list) => bid_list.text().unwrap(),
-            Err(e) => {
-                error!("could not get bundle ID list from remote: {}", e);
-                return TransferResult::Failure;
-            }
-        };
-        let bids: Vec<String> = serde_json::from_str(&bid_list).unwrap();
-
-        // calculate missing bundles
-        let mut missing = Vec::new();
-
-        for bid in bids {
-            if !store_has_item(&bid) {
-                missing.push(bid);
+        if !status.is_success() {
+            bail!("unexpected response from remote: {}", status);
+        }
+        let replies: Vec<RangeDigest> = serde_json::from_slice(&body)?;
+        ranges = Vec::new();
+        for reply in replies {
+            if let Some(bids) = reply.bids {
+                missing.extend(bids.into_iter().filter(|bid| is_unknown(bid)));
+            } else {
+                let own = range_digest(&local, &reply.lo, reply.hi.as_deref());
+                if own.count != reply.count || own.digest != reply.digest {
+                    ranges.push(own);
+                }
             }
         }
+    }
+    warn!("reconciliation with {} did not converge", base);
+    Ok(missing)
+}
 
-        // fetch missing bundles from remote node
-        for bid in missing {
-            transfers += 1;
-            let response =
-                attohttpc::get(format!("http://{}:{}/download?{}", addr, port, bid)).send();
+/// pulls missing bundles from node
+/// addr can be either an IP or a DNS name
+async fn http_pull_from_node(
+    eid: EndpointID,
+    addr: String,
+    port: u16,
+    settings: HttpPullSettings,
+) -> Result<usize> {
+    let base = format!("http://{}:{}", addr, port);
+    debug!("pulling bundles from {} / {}", eid, addr);
 
-            let bundle_buf = match response {
-                Ok(bundle) => bundle.bytes().unwrap(),
-                Err(e) => {
-                    error!("could not get bundle from remote: {}", e);
-                    return TransferResult::Failure;
+    let missing = missing_bids(&base, &settings.filter).await?;
+    if missing.is_empty() {
+        debug!("no new bundles on remote");
+        return Ok(0);
+    }
+    debug!("remote ({}) has {} new bundles", eid, missing.len());
+
+    let mut transfers = 0;
+    for batch in missing.chunks(settings.batch.max(1)) {
+        let (status, body) = post_json(&format!("{}/sync/bundles", base), &batch).await?;
+        let buffers: Vec<serde_bytes::ByteBuf> = if status == StatusCode::NOT_FOUND {
+            // remote without batch download
+            let mut buffers = Vec::new();
+            for bid in batch {
+                let (status, buf) = get(&format!("{}/download?{}", base, bid)).await?;
+                if status.is_success() {
+                    buffers.push(serde_bytes::ByteBuf::from(buf.to_vec()));
                 }
-            };
-            let bundle = match bp7::Bundle::try_from(bundle_buf.as_ref()) {
+            }
+            buffers
+        } else if status.is_success() {
+            serde_cbor::from_slice(&body)?
+        } else {
+            bail!("unexpected response from remote: {}", status);
+        };
+        for buf in buffers {
+            let bundle = match bp7::Bundle::try_from(buf.as_ref()) {
                 Ok(bundle) => bundle,
                 Err(e) => {
                     crate::STATS.lock().broken += 1;
@@ -93,36 +163,51 @@ async fn http_pull_from_node(
                     continue;
                 }
             };
+            transfers += 1;
             info!("Downloaded bundle: {} from {}", bundle.id(), addr);
-            {
-                tokio::spawn(async move {
-                    if let Err(err) = crate::core::processing::receive(bundle).await {
-                        error!("Failed to process bundle: {}", err);
-                    }
-                });
-            }
+            crate::peers_report_receive(&bundle, "httppull", buf.len());
+            tokio::spawn(async move {
+                if let Err(err) = crate::core::processing::receive(bundle).await {
+                    error!("Failed to process bundle: {}", err);
+                }
+            });
         }
-        debug!(
-            "finished pulling {} bundles from {} / {} in {:?}",
-            transfers,
-            eid,
-            addr,
-            now.elapsed()
-        );
-        TransferResult::Successful
-    })
-    .await
-    .unwrap()
+    }
+    Ok(transfers)
 }
-async fn http_pull_bundles() {
-    debug!("pulling bundles from peers");
 
-    let local_digest = get_complete_digest();
+async fn http_pull_logged(
+    eid: EndpointID,
+    addr: String,
+    port: u16,
+    settings: HttpPullSettings,
+) -> TransferResult {
+    let now = std::time::Instant::now();
+    match http_pull_from_node(eid.clone(), addr.clone(), port, settings).await {
+        Ok(transfers) => {
+            debug!(
+                "finished pulling {} bundles from {} / {} in {:?}",
+                transfers,
+                eid,
+                addr,
+                now.elapsed()
+            );
+            TransferResult::Successful
+        }
+        Err(err) => {
+            error!("could not pull bundles from {} / {}: {}", eid, addr, err);
+            TransferResult::Failure
+        }
+    }
+}
+
+async fn http_pull_bundles(settings: &HttpPullSettings) {
+    debug!("pulling bundles from peers");
 
     let peers = crate::PEERS.lock().clone();
     for (_, p) in peers.iter() {
         let peer = p.clone();
-        let local_digest = local_digest.clone();
+        let settings = settings.clone();
         let mut port = 3000;
         for cla in p.cla_list.iter() {
             if cla.0 == "httppull" {
@@ -134,15 +219,15 @@ async fn http_pull_bundles() {
         }
         if CONFIG.lock().parallel_bundle_processing {
             tokio::spawn(async move {
-                http_pull_from_node(peer.eid, peer.addr.to_string(), port, local_digest).await;
+                http_pull_logged(peer.eid, peer.addr.to_string(), port, settings).await;
             });
         } else {
-            http_pull_from_node(peer.eid, peer.addr.to_string(), port, local_digest).await;
+            http_pull_logged(peer.eid, peer.addr.to_string(), port, settings).await;
         }
     }
     debug!("finished pulling bundles from peers");
 }
-async fn http_puller_loop(rx: mpsc::Receiver<bool>) {
+async fn http_puller_loop(rx: mpsc::Receiver<bool>, settings: HttpPullSettings) {
     let mut rx = rx;
     let interval = CONFIG.lock().janitor_interval;
     loop {
@@ -153,7 +238,7 @@ async fn http_puller_loop(rx: mpsc::Receiver<bool>) {
           }
           _ = tokio::time::sleep(interval) => {
             let now = std::time::Instant::now();
-            http_pull_bundles().await;
+            http_pull_bundles(&settings).await;
             debug!("http puller took {:?}", now.elapsed());
           }
         }
@@ -161,10 +246,20 @@ async fn http_puller_loop(rx: mpsc::Receiver<bool>) {
 }
 
 impl HttpPullConvergenceLayer {
-    pub fn new(_local_settings: Option<&HashMap<String, String>>) -> HttpPullConvergenceLayer {
+    pub fn new(local_settings: Option<&HashMap<String, String>>) -> HttpPullConvergenceLayer {
+        let get = |key: &str| local_settings.and_then(|settings| settings.get(key));
+        let settings = HttpPullSettings {
+            filter: SyncFilter {
+                dst: get("dst").cloned(),
+                min_lifetime: get("min_lifetime")
+                    .and_then(|d| humantime::parse_duration(d).ok())
+                    .map(|d: Duration| d.as_secs()),
+            },
+            batch: get("batch").and_then(|b| b.parse().ok()).unwrap_or(64),
+        };
         let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
         tokio::spawn(async move {
-            http_puller_loop(shutdown_rx).await;
+            http_puller_loop(shutdown_rx, settings).await;
         });
         let (tx, mut rx) = mpsc::channel(1);
         tokio::spawn(async move {
@@ -210,7 +305,11 @@ impl ConvergenceLayerAgent for HttpPullConvergenceLayer {
     }
 }
 
-impl HelpStr for HttpPullConvergenceLayer {}
+impl HelpStr for HttpPullConvergenceLayer {
+    fn local_help_str() -> &'static str {
+        "dst=<substring>:min_lifetime=1h:batch=64"
+    }
+}
 
 impl std::fmt::Display for HttpPullConvergenceLayer {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -1,9 +1,14 @@
 pub mod dummy;
 pub mod ecla;
 pub mod external;
+pub mod file;
 pub mod http;
 pub mod httppull;
 pub mod mtcp;
+#[cfg(feature = "cla_quic")]
+pub mod quic;
+#[cfg(feature = "cla_serial")]
+pub mod serial;
 pub mod tcp;
 pub mod udp;
 
@@ -15,9 +20,14 @@ use dtn7_codegen::init_cla_subsystem;
 use dummy::DummyConvergenceLayer;
 use enum_dispatch::enum_dispatch;
 use external::ExternalConvergenceLayer;
+use file::FileConvergenceLayer;
 use httppull::HttpPullConvergenceLayer;
 use mtcp::MtcpConvergenceLayer;
+#[cfg(feature = "cla_quic")]
+use quic::QuicConvergenceLayer;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "cla_serial")]
+use serial::SerialConvergenceLayer;
 use std::str::FromStr;
 use std::{
     collections::HashMap,
@@ -1,30 +1,39 @@
 use crate::cla::{ConvergenceLayerAgent, TransferResult};
+use crate::core::events::{self, Event};
+use crate::core::peer::{DtnPeer, PeerAddress, PeerType};
 use async_trait::async_trait;
-use bp7::{Bundle, ByteBuffer};
+use bp7::{Bundle, ByteBuffer, EndpointID};
 use bytes::buf::Buf;
 use bytes::{BufMut, BytesMut};
 use core::convert::TryFrom;
 use dtn7_codegen::cla;
+use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
 use std::collections::HashMap;
-use std::io::Write;
 use std::net::SocketAddr;
 use std::net::SocketAddrV4;
-use std::net::TcpStream;
-use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io;
-use tokio::net::TcpListener;
-use tokio::sync::mpsc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::codec::{Decoder, Encoder, Framed};
 
 use super::HelpStr;
 
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
 lazy_static! {
-    pub static ref MTCP_CONNECTIONS: Mutex<HashMap<SocketAddr, TcpStream>> =
+    /// Established sessions by remote address, used for sending in both directions
+    pub static ref MTCP_CONNECTIONS: Mutex<HashMap<SocketAddr, MtcpSession>> =
         Mutex::new(HashMap::new());
 }
 
@@ -197,64 +206,279 @@ impl Decoder for MPDUCodec {
         Ok(None)
     }
 }
-async fn mtcp_listener(addr: String, port: u16) -> Result<(), io::Error> {
+async fn mtcp_listener(addr: String, port: u16, settings: MtcpSettings) -> Result<(), io::Error> {
     let addr: SocketAddrV4 = format!("{}:{}", addr, port).parse().unwrap();
     let listener = TcpListener::bind(&addr)
         .await
         .expect("failed to bind tcp port");
     debug!("spawning MTCP listener on port {}", port);
     loop {
-        let (socket, _) = listener.accept().await.unwrap();
+        let (socket, peer_addr) = listener.accept().await.unwrap();
+        info!("Incoming connection from {}", peer_addr);
+        spawn_session(socket, peer_addr, settings, false);
+    }
+}
+
+/// Timing parameters of MTCP sessions.
+#[derive(Debug, Clone, Copy)]
+pub struct MtcpSettings {
+    /// close sessions without any traffic after this duration, zero disables the timeout
+    pub idle: Duration,
+    /// interval of TCP keepalive probes, zero disables keepalive
+    pub keepalive: Duration,
+}
+
+impl Default for MtcpSettings {
+    fn default() -> Self {
+        MtcpSettings {
+            idle: Duration::from_secs(600),
+            keepalive: Duration::from_secs(30),
+        }
+    }
+}
+
+type SessionCmd = (MPDU, oneshot::Sender<bool>);
+
+/// Handle of an established MTCP connection, regardless of which side opened it.
+#[derive(Debug, Clone)]
+pub struct MtcpSession {
+    id: u64,
+    tx: mpsc::Sender<SessionCmd>,
+}
+
+impl MtcpSession {
+    /// Queues a MPDU for transmission and waits until it has been written to the socket.
+    pub async fn send(&self, mpdu: MPDU) -> bool {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.tx.send((mpdu, reply_tx)).await.is_err() {
+            return false;
+        }
+        reply_rx.await.unwrap_or(false)
+    }
+}
+
+fn spawn_session(
+    socket: TcpStream,
+    peer_addr: SocketAddr,
+    settings: MtcpSettings,
+    outbound: bool,
+) -> MtcpSession {
+    if !settings.keepalive.is_zero() {
+        let keepalive = TcpKeepalive::new()
+            .with_time(settings.keepalive)
+            .with_interval(settings.keepalive);
+        if let Err(err) = SockRef::from(&socket).set_tcp_keepalive(&keepalive) {
+            warn!("Error enabling keepalive for {}: {}", peer_addr, err);
+        }
+    }
+    let (tx, rx) = mpsc::channel(100);
+    let session = MtcpSession {
+        id: SESSION_ID.fetch_add(1, Ordering::Relaxed),
+        tx,
+    };
+    MTCP_CONNECTIONS.lock().insert(peer_addr, session.clone());
+    events::emit(Event::ClaSessionUp {
+        cla: "mtcp".into(),
+        addr: peer_addr.to_string(),
+        peer: None,
+    });
+    let id = session.id;
+    tokio::spawn(async move {
+        handle_session(socket, peer_addr, rx, settings, outbound).await;
+        events::emit(Event::ClaSessionDown {
+            cla: "mtcp".into(),
+            addr: peer_addr.to_string(),
+            peer: None,
+        });
+        let mut connections = MTCP_CONNECTIONS.lock();
+        // a newer session to the same address might have replaced this one already
+        if connections.get(&peer_addr).map(|s| s.id) == Some(id) {
+            connections.remove(&peer_addr);
+        }
+    });
+    session
+}
+
+/// Runs a session until the connection is closed or idle.
+///
+/// Bundles are received in both directions. For inbound connections the sending node is
+/// registered as a peer reachable via this connection for the lifetime of the session, so
+/// bundles for nodes that cannot be connected to, e.g., behind NAT, are sent back over the
+/// session they initiated.
+async fn handle_session(
+    socket: TcpStream,
+    peer_addr: SocketAddr,
+    mut rx: mpsc::Receiver<SessionCmd>,
+    settings: MtcpSettings,
+    outbound: bool,
+) {
+    let (mut sink, mut stream) = Framed::new(socket, MPDUCodec::new()).split();
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let mut learned_peer: Option<String> = None;
+
+    let reader = async {
+        while let Some(frame) = stream.next().await {
+            match frame {
+                Ok(frame) => {
+                    *last_activity.lock() = Instant::now();
+                    let len = frame.0.len();
+                    if let Ok(bndl) = Bundle::try_from(frame) {
+                        info!("Received bundle: {} from {}", bndl.id(), peer_addr);
+                        if !outbound && learned_peer.is_none() {
+                            learned_peer = learn_peer(&bndl, peer_addr);
+                        }
+                        crate::peers_report_receive(&bndl, "mtcp", len);
+                        tokio::spawn(async move {
+                            if let Err(err) = crate::core::processing::receive(bndl).await {
+                                error!("Failed to process bundle: {}", err);
+                            }
+                        });
+                    } else {
+                        crate::STATS.lock().broken += 1;
+                        info!("Error decoding bundle from {}", peer_addr);
+                        break;
+                    }
+                }
+                Err(err) => {
+                    info!("Lost connection from {} ({})", peer_addr, err);
+                    break;
+                }
+            }
+        }
+    };
+    let writer = async {
+        while let Some((mpdu, reply)) = rx.recv().await {
+            let res = sink.send(mpdu).await;
+            *last_activity.lock() = Instant::now();
+            let success = res.is_ok();
+            let _ = reply.send(success);
+            if let Err(err) = res {
+                error!("Error writing data to {} ({})", peer_addr, err);
+                break;
+            }
+        }
+    };
+    let idle = async {
+        if settings.idle.is_zero() {
+            return futures::future::pending().await;
+        }
+        loop {
+            let deadline = *last_activity.lock() + settings.idle;
+            if Instant::now() >= deadline {
+                info!("Closing idle connection to {}", peer_addr);
+                break;
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    };
+    tokio::select! {
+        _ = reader => {},
+        _ = writer => {},
+        _ = idle => {},
+    }
+    if let Some(node) = learned_peer {
+        forget_peer(&node, peer_addr);
+    }
+    info!("Disconnected {}", peer_addr);
+}
+
+fn session_cla(peer_addr: SocketAddr) -> Vec<(String, Option<u16>)> {
+    vec![("mtcp".into(), Some(peer_addr.port()))]
+}
 
-        tokio::spawn(MtcpConvergenceLayer::handle_connection(socket));
+/// Registers the previous hop of a bundle as peer reachable over an inbound connection.
+///
+/// The peer is static as it is not announced by discovery but reachable as long as the
+/// session lasts, it is removed again by [`forget_peer`] when the session ends. Bundles
+/// without a previous node block are ignored, their source may be many hops away.
+fn learn_peer(bndl: &Bundle, peer_addr: SocketAddr) -> Option<String> {
+    let eid = bndl.previous_node()?;
+    let node = eid.node()?;
+    if crate::peers_known(&node) {
+        return None;
     }
+    let eid = EndpointID::try_from(eid.node_id()?).ok()?;
+    debug!("Reaching {} via inbound connection {}", node, peer_addr);
+    crate::peers_add(DtnPeer::new(
+        eid,
+        PeerAddress::Ip(peer_addr.ip()),
+        PeerType::Static,
+        None,
+        session_cla(peer_addr),
+        HashMap::new(),
+    ));
+    Some(node)
 }
 
-pub fn mtcp_send_bundles(addr: SocketAddr, bundles: Vec<ByteBuffer>) -> TransferResult {
-    // TODO: implement correct error handling
-    // TODO: classic sending thread, tokio code would block and not complete large transmissions
+/// Removes a peer learned by [`learn_peer`], unless it was replaced in the meantime, e.g., by
+/// discovery or a newer session.
+fn forget_peer(node: &str, peer_addr: SocketAddr) {
+    let mut peers = crate::PEERS.lock();
+    let learned = peers.get(node).is_some_and(|peer| {
+        peer.con_type == PeerType::Static
+            && peer.addr == PeerAddress::Ip(peer_addr.ip())
+            && peer.cla_list == session_cla(peer_addr)
+    });
+    if !learned {
+        return;
+    }
+    if let Some(peer) = peers.remove(node) {
+        drop(peers);
+        crate::peers_retire(peer);
+    }
+}
+
+async fn mtcp_session(addr: SocketAddr, settings: MtcpSettings) -> Option<MtcpSession> {
+    if let Some(session) = MTCP_CONNECTIONS.lock().get(&addr) {
+        debug!("Already connected to {}", addr);
+        return Some(session.clone());
+    }
+    debug!("Connecting to {}", addr);
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+        Ok(Ok(socket)) => Some(spawn_session(socket, addr, settings, true)),
+        _ => {
+            error!("Error connecting to remote {}", addr);
+            None
+        }
+    }
+}
+
+pub async fn mtcp_send_bundles(
+    addr: SocketAddr,
+    bundles: Vec<ByteBuffer>,
+    settings: MtcpSettings,
+) -> TransferResult {
     let now = Instant::now();
     let num_bundles = bundles.len();
-    let mut buf = Vec::new();
+    let total_bytes: usize = bundles.iter().map(|b| b.len()).sum();
+
     for b in bundles {
         let mpdu = MPDU(b);
-        if let Ok(buf2) = serde_cbor::to_vec(&mpdu) {
-            buf.extend_from_slice(&buf2);
-        } else {
-            error!("MPDU encoding error!");
-            return TransferResult::Failure;
+        let mut sent = false;
+        // a cached session might have been closed by the remote in the meantime
+        for _ in 0..2 {
+            let Some(session) = mtcp_session(addr, settings).await else {
+                return TransferResult::Failure;
+            };
+            if session.send(mpdu.clone()).await {
+                sent = true;
+                break;
+            }
+            let mut connections = MTCP_CONNECTIONS.lock();
+            if connections.get(&addr).map(|s| s.id) == Some(session.id) {
+                connections.remove(&addr);
+            }
         }
-    }
-
-    #[allow(clippy::map_entry)]
-    if !MTCP_CONNECTIONS.lock().contains_key(&addr) {
-        debug!("Connecting to {}", addr);
-        if let Ok(stream) = TcpStream::connect(addr) {
-            MTCP_CONNECTIONS.lock().insert(addr, stream);
-        } else {
-            error!("Error connecting to remote {}", addr);
+        if !sent {
+            error!("Error writing data to {}", addr);
             return TransferResult::Failure;
         }
-    } else {
-        debug!("Already connected to {}", addr);
-    };
-    let mut s1 = MTCP_CONNECTIONS
-        .lock()
-        .get(&addr)
-        .unwrap()
-        .try_clone()
-        .unwrap();
-
-    if s1.write_all(&buf).is_err() {
-        error!("Error writing data to {}", addr);
-        MTCP_CONNECTIONS.lock().remove(&addr);
-        return TransferResult::Failure;
     }
     debug!(
         "Transmission time: {:?} for {} bundles in {} bytes to {}",
         now.elapsed(),
         num_bundles,
-        buf.len(),
+        total_bytes,
         addr
     );
 
@@ -266,6 +490,7 @@ pub fn mtcp_send_bundles(addr: SocketAddr, bundles: Vec<ByteBuffer>) -> Transfer
 pub struct MtcpConvergenceLayer {
     local_addr: String,
     local_port: u16,
+    settings: MtcpSettings,
     tx: mpsc::Sender<super::ClaCmd>,
 }
 
@@ -279,6 +504,16 @@ impl MtcpConvergenceLayer {
             .and_then(|settings| settings.get("port"))
             .and_then(|port_str| port_str.parse::<u16>().ok())
             .unwrap_or(16162);
+        let duration = |key: &str, default: Duration| {
+            local_settings
+                .and_then(|settings| settings.get(key))
+                .and_then(|d| humantime::parse_duration(d).ok())
+                .unwrap_or(default)
+        };
+        let settings = MtcpSettings {
+            idle: duration("idle", MtcpSettings::default().idle),
+            keepalive: duration("keepalive", MtcpSettings::default().keepalive),
+        };
         let (tx, mut rx) = mpsc::channel(100);
         tokio::spawn(async move {
             while let Some(cmd) = rx.recv().await {
@@ -292,7 +527,9 @@ impl MtcpConvergenceLayer {
                             let peeraddr: SocketAddr = remote.parse().unwrap();
                             debug!("forwarding to {:?}", peeraddr);
                             tokio::spawn(async move {
-                                reply.send(mtcp_send_bundles(peeraddr, vec![data])).unwrap();
+                                reply
+                                    .send(mtcp_send_bundles(peeraddr, vec![data], settings).await)
+                                    .unwrap();
                             });
                         } else {
                             debug!("Nothing to forward.");
@@ -309,96 +546,25 @@ impl MtcpConvergenceLayer {
         MtcpConvergenceLayer {
             local_addr: addr,
             local_port: port,
+            settings,
             tx,
         }
     }
-    async fn handle_connection(socket: tokio::net::TcpStream) -> anyhow::Result<()> {
-        let peer_addr = socket.peer_addr().unwrap();
-        info!("Incoming connection from {}", peer_addr);
-        let mut framed_sock = Framed::new(socket, MPDUCodec::new());
-        while let Some(frame) = framed_sock.next().await {
-            match frame {
-                Ok(frame) => {
-                    if let Ok(bndl) = Bundle::try_from(frame) {
-                        info!("Received bundle: {} from {}", bndl.id(), peer_addr);
-                        {
-                            tokio::spawn(async move {
-                                if let Err(err) = crate::core::processing::receive(bndl).await {
-                                    error!("Failed to process bundle: {}", err);
-                                }
-                            });
-                        }
-                    } else {
-                        crate::STATS.lock().broken += 1;
-                        info!("Error decoding bundle from {}", peer_addr);
-                        break;
-                    }
-                }
-                Err(err) => {
-                    info!("Lost connection from {} ({})", peer_addr, err);
-                    break;
-                }
-            }
-        }
-        info!("Disconnected {}", peer_addr);
-        Ok(())
+
+    /// Sends bundles to `addr` over a session with the settings of this CLA.
+    pub async fn send_bundles(&self, addr: SocketAddr, bundles: Vec<ByteBuffer>) -> bool {
+        mtcp_send_bundles(addr, bundles, self.settings).await == TransferResult::Successful
     }
 
     pub async fn spawn_listener(&self) -> std::io::Result<()> {
         // TODO: bubble up errors from run
-        tokio::spawn(mtcp_listener(self.local_addr.clone(), self.local_port)); /*.await.unwrap()*/
+        tokio::spawn(mtcp_listener(
+            self.local_addr.clone(),
+            self.local_port,
+            self.settings,
+        ));
         Ok(())
     }
-    pub fn send_bundles(&self, addr: SocketAddr, bundles: Vec<ByteBuffer>) -> bool {
-        // TODO: implement correct error handling
-        // TODO: classic sending thread, tokio code would block and not complete large transmissions
-        let now = Instant::now();
-        let num_bundles = bundles.len();
-        let mut buf = Vec::new();
-        for b in bundles {
-            let mpdu = MPDU(b);
-            if let Ok(buf2) = serde_cbor::to_vec(&mpdu) {
-                buf.extend_from_slice(&buf2);
-            } else {
-                error!("MPDU encoding error!");
-                return false;
-            }
-        }
-
-        #[allow(clippy::map_entry)]
-        if !MTCP_CONNECTIONS.lock().contains_key(&addr) {
-            debug!("Connecting to {}", addr);
-            if let Ok(stream) = TcpStream::connect(addr) {
-                MTCP_CONNECTIONS.lock().insert(addr, stream);
-            } else {
-                error!("Error connecting to remote {}", addr);
-                return false;
-            }
-        } else {
-            debug!("Already connected to {}", addr);
-        };
-        let mut s1 = MTCP_CONNECTIONS
-            .lock()
-            .get(&addr)
-            .unwrap()
-            .try_clone()
-            .unwrap();
-
-        if s1.write_all(&buf).is_err() {
-            error!("Error writing data to {}", addr);
-            MTCP_CONNECTIONS.lock().remove(&addr);
-            return false;
-        }
-        info!(
-            "Transmission time: {:?} for {} bundles in {} bytes to {}",
-            now.elapsed(),
-            num_bundles,
-            buf.len(),
-            addr
-        );
-
-        true
-    }
 }
 
 #[async_trait]
@@ -421,7 +587,7 @@ impl ConvergenceLayerAgent for MtcpConvergenceLayer {
 
 impl HelpStr for MtcpConvergenceLayer {
     fn local_help_str() -> &'static str {
-        "port=16162:bind=0.0.0.0"
+        "port=16162:bind=0.0.0.0:idle=10m:keepalive=30s"
     }
 }
 impl std::fmt::Display for MtcpConvergenceLayer {
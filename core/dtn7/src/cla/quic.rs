@@ -0,0 +1,457 @@
+use super::{ConvergenceLayerAgent, HelpStr, TransferResult};
+use crate::core::events::{self, Event};
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use bp7::{Bundle, ByteBuffer};
+use core::convert::TryFrom;
+use dtn7_codegen::cla;
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/*
+    Every bundle is transferred on its own bidirectional QUIC stream, all streams to a peer
+    are multiplexed over a single connection. The sender finishes its half of the stream after
+    writing the serialized bundle, the receiver acknowledges the successful reception by
+    answering with a single byte before finishing its half of the stream.
+    If enabled, session tickets of previous connections are used for 0-RTT resumption.
+*/
+
+const ALPN_DTN7_QUIC: &[u8] = b"dtn7-quic";
+const QUIC_ACK: u8 = 0x01;
+/// Maximum size of a single bundle accepted via QUIC
+const MAX_BUNDLE_SIZE: usize = 128 * 1024 * 1024;
+
+lazy_static! {
+    static ref QUIC_ENDPOINT: Mutex<Option<quinn::Endpoint>> = Mutex::new(None);
+    static ref QUIC_EARLY_DATA: Mutex<bool> = Mutex::new(false);
+    pub static ref QUIC_CONNECTIONS: Mutex<HashMap<SocketAddr, quinn::Connection>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Accepts any server certificate, only used if explicitly configured with `insecure=true`.
+/// The transport is still encrypted with TLS 1.3 but open to man-in-the-middle attacks.
+#[derive(Debug)]
+struct SkipServerVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn load_certs(path: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = std::io::BufReader::new(
+        std::fs::File::open(path).with_context(|| format!("opening cert file {}", path))?,
+    );
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        bail!("no certificates found in {}", path);
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let mut reader = std::io::BufReader::new(
+        std::fs::File::open(path).with_context(|| format!("opening key file {}", path))?,
+    );
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| anyhow!("no key found in {}", path))
+}
+
+fn transport_config(keepalive: Duration) -> Arc<quinn::TransportConfig> {
+    let mut transport = quinn::TransportConfig::default();
+    if !keepalive.is_zero() {
+        transport.keep_alive_interval(Some(keepalive));
+        transport.max_idle_timeout(quinn::IdleTimeout::try_from(keepalive * 3).ok());
+    }
+    Arc::new(transport)
+}
+
+#[derive(Debug, Clone)]
+struct QuicSettings {
+    bind: String,
+    port: u16,
+    cert: Option<String>,
+    key: Option<String>,
+    ca: Option<String>,
+    insecure: bool,
+    early_data: bool,
+    keepalive: Duration,
+}
+
+fn server_config(settings: &QuicSettings) -> anyhow::Result<quinn::ServerConfig> {
+    let (certs, key) = match (&settings.cert, &settings.key) {
+        (Some(cert), Some(key)) => (load_certs(cert)?, load_key(key)?),
+        (None, None) => {
+            let node_name = crate::CONFIG
+                .lock()
+                .host_eid
+                .node()
+                .unwrap_or_else(|| "localhost".into());
+            info!("No QUIC certificate configured, generating a self-signed one");
+            let certified =
+                rcgen::generate_simple_self_signed(vec![node_name, "localhost".into()])?;
+            (
+                vec![certified.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()),
+            )
+        }
+        _ => bail!("QUIC cert and key must be configured together"),
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    crypto.alpn_protocols = vec![ALPN_DTN7_QUIC.to_vec()];
+    if settings.early_data {
+        // allow 0-RTT data from resumed sessions
+        crypto.max_early_data_size = u32::MAX;
+    }
+
+    let mut config =
+        quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    config.transport_config(transport_config(settings.keepalive));
+    Ok(config)
+}
+
+fn client_config(settings: &QuicSettings) -> anyhow::Result<quinn::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?;
+    let mut crypto = if let Some(ca) = &settings.ca {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in load_certs(ca)? {
+            roots.add(cert)?;
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    } else if settings.insecure {
+        warn!("QUIC certificates of peers are NOT verified, connections can be intercepted");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+            .with_no_client_auth()
+    } else {
+        bail!("QUIC needs a ca file to verify peers, use insecure=true to skip verification");
+    };
+    crypto.alpn_protocols = vec![ALPN_DTN7_QUIC.to_vec()];
+    crypto.enable_early_data = settings.early_data;
+
+    let mut config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto)?));
+    config.transport_config(transport_config(settings.keepalive));
+    Ok(config)
+}
+
+/// Returns an open connection to the given peer, establishing a new one if needed.
+async fn quic_connection(addr: SocketAddr) -> anyhow::Result<quinn::Connection> {
+    let early_data = *QUIC_EARLY_DATA.lock();
+    if let Some(conn) = QUIC_CONNECTIONS.lock().get(&addr) {
+        if conn.close_reason().is_none() {
+            debug!("Already connected to {}", addr);
+            return Ok(conn.clone());
+        }
+    }
+    let endpoint = QUIC_ENDPOINT
+        .lock()
+        .clone()
+        .context("QUIC endpoint not set up")?;
+    debug!("Connecting to {}", addr);
+    let connecting = endpoint.connect(addr, &addr.ip().to_string())?;
+    let conn = if early_data {
+        match connecting.into_0rtt() {
+            Ok((conn, _accepted)) => {
+                debug!("Resumed QUIC session with {} using 0-RTT", addr);
+                conn
+            }
+            Err(connecting) => connecting.await?,
+        }
+    } else {
+        connecting.await?
+    };
+    QUIC_CONNECTIONS.lock().insert(addr, conn.clone());
+    session_events(conn.clone());
+    Ok(conn)
+}
+
+/// Reports a QUIC connection as CLA session until it is closed.
+fn session_events(conn: quinn::Connection) {
+    let addr = conn.remote_address().to_string();
+    events::emit(Event::ClaSessionUp {
+        cla: "quic".into(),
+        addr: addr.clone(),
+        peer: None,
+    });
+    tokio::spawn(async move {
+        conn.closed().await;
+        events::emit(Event::ClaSessionDown {
+            cla: "quic".into(),
+            addr,
+            peer: None,
+        });
+    });
+}
+
+async fn quic_send_bundle(addr: SocketAddr, data: &[u8]) -> anyhow::Result<()> {
+    let conn = quic_connection(addr).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(data).await?;
+    send.finish()?;
+    let ack = recv.read_to_end(1).await?;
+    if ack != [QUIC_ACK] {
+        bail!("bundle was not acknowledged by {}", addr);
+    }
+    Ok(())
+}
+
+pub async fn quic_send_bundles(addr: SocketAddr, bundles: Vec<ByteBuffer>) -> TransferResult {
+    let now = Instant::now();
+    let num_bundles = bundles.len();
+    let total_bytes: usize = bundles.iter().map(|b| b.len()).sum();
+
+    for b in bundles {
+        if let Err(err) = quic_send_bundle(addr, &b).await {
+            // the cached connection might be stale or 0-RTT data was rejected, retry once
+            debug!("Retrying QUIC transfer to {} after error: {}", addr, err);
+            QUIC_CONNECTIONS.lock().remove(&addr);
+            if let Err(err) = quic_send_bundle(addr, &b).await {
+                error!("Error sending bundle via QUIC to {}: {}", addr, err);
+                QUIC_CONNECTIONS.lock().remove(&addr);
+                return TransferResult::Failure;
+            }
+        }
+    }
+    debug!(
+        "Transmission time: {:?} for {} bundles in {} bytes to {}",
+        now.elapsed(),
+        num_bundles,
+        total_bytes,
+        addr
+    );
+
+    TransferResult::Successful
+}
+
+async fn handle_stream(
+    peer_addr: SocketAddr,
+    mut send: quinn::SendStream,
+    mut recv: quinn::RecvStream,
+) -> anyhow::Result<()> {
+    let buf = recv.read_to_end(MAX_BUNDLE_SIZE).await?;
+    let len = buf.len();
+    if let Ok(bndl) = Bundle::try_from(buf) {
+        info!("Received bundle: {} from {}", bndl.id(), peer_addr);
+        crate::peers_report_receive(&bndl, "quic", len);
+        send.write_all(&[QUIC_ACK]).await?;
+        send.finish()?;
+        tokio::spawn(async move {
+            if let Err(err) = crate::core::processing::receive(bndl).await {
+                error!("Failed to process bundle: {}", err);
+            }
+        });
+    } else {
+        crate::STATS.lock().broken += 1;
+        info!("Error decoding bundle from {}", peer_addr);
+        send.reset(0u32.into())?;
+    }
+    Ok(())
+}
+
+async fn handle_connection(incoming: quinn::Incoming) -> anyhow::Result<()> {
+    let connecting = incoming.accept()?;
+    let conn = if *QUIC_EARLY_DATA.lock() {
+        match connecting.into_0rtt() {
+            Ok((conn, _)) => conn,
+            Err(connecting) => connecting.await?,
+        }
+    } else {
+        connecting.await?
+    };
+    let peer_addr = conn.remote_address();
+    info!("Incoming QUIC connection from {}", peer_addr);
+    session_events(conn.clone());
+    loop {
+        match conn.accept_bi().await {
+            Ok((send, recv)) => {
+                tokio::spawn(async move {
+                    if let Err(err) = handle_stream(peer_addr, send, recv).await {
+                        warn!("Error receiving bundle from {}: {}", peer_addr, err);
+                    }
+                });
+            }
+            Err(err) => {
+                info!("Lost connection from {} ({})", peer_addr, err);
+                break;
+            }
+        }
+    }
+    info!("Disconnected {}", peer_addr);
+    Ok(())
+}
+
+async fn quic_listener(endpoint: quinn::Endpoint) {
+    while let Some(incoming) = endpoint.accept().await {
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(incoming).await {
+                warn!("Error handling incoming QUIC connection: {}", err);
+            }
+        });
+    }
+}
+
+#[cla(quic)]
+#[derive(Debug, Clone)]
+pub struct QuicConvergenceLayer {
+    settings: QuicSettings,
+    tx: mpsc::Sender<super::ClaCmd>,
+}
+
+impl QuicConvergenceLayer {
+    pub fn new(local_settings: Option<&HashMap<String, String>>) -> QuicConvergenceLayer {
+        let get = |key: &str| {
+            local_settings
+                .and_then(|settings| settings.get(key))
+                .map(|s| s.to_string())
+        };
+        let settings = QuicSettings {
+            bind: get("bind").unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: get("port")
+                .and_then(|port_str| port_str.parse::<u16>().ok())
+                .unwrap_or(4560),
+            cert: get("cert"),
+            key: get("key"),
+            ca: get("ca"),
+            insecure: get("insecure")
+                .and_then(|v| v.parse::<bool>().ok())
+                .unwrap_or(false),
+            early_data: get("0rtt")
+                .and_then(|v| v.parse::<bool>().ok())
+                .unwrap_or(false),
+            keepalive: get("keepalive")
+                .and_then(|k| humantime::parse_duration(&k).ok())
+                .unwrap_or(Duration::from_secs(10)),
+        };
+        let (tx, mut rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    super::ClaCmd::Transfer(remote, data, reply) => {
+                        debug!(
+                            "QuicConvergenceLayer: received transfer command for {}",
+                            remote
+                        );
+                        if !data.is_empty() {
+                            let peeraddr: SocketAddr = remote.parse().unwrap();
+                            debug!("forwarding to {:?}", peeraddr);
+                            tokio::spawn(async move {
+                                reply
+                                    .send(quic_send_bundles(peeraddr, vec![data]).await)
+                                    .unwrap();
+                            });
+                        } else {
+                            debug!("Nothing to forward.");
+                            reply.send(TransferResult::Successful).unwrap();
+                        }
+                    }
+                    super::ClaCmd::Shutdown => {
+                        debug!("QuicConvergenceLayer: received shutdown command");
+                        if let Some(endpoint) = QUIC_ENDPOINT.lock().take() {
+                            endpoint.close(0u32.into(), b"shutdown");
+                        }
+                        break;
+                    }
+                }
+            }
+        });
+        QuicConvergenceLayer { settings, tx }
+    }
+
+    pub async fn spawn_listener(&self) -> anyhow::Result<()> {
+        let addr: SocketAddr = format!("{}:{}", self.settings.bind, self.settings.port).parse()?;
+        let client_config = client_config(&self.settings)?;
+        let mut endpoint = quinn::Endpoint::server(server_config(&self.settings)?, addr)?;
+        endpoint.set_default_client_config(client_config);
+        debug!("spawning QUIC listener on port {}", self.settings.port);
+        *QUIC_EARLY_DATA.lock() = self.settings.early_data;
+        *QUIC_ENDPOINT.lock() = Some(endpoint.clone());
+        tokio::spawn(quic_listener(endpoint));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConvergenceLayerAgent for QuicConvergenceLayer {
+    async fn setup(&mut self) {
+        self.spawn_listener()
+            .await
+            .expect("error setting up quic listener");
+    }
+    fn port(&self) -> u16 {
+        self.settings.port
+    }
+    fn name(&self) -> &str {
+        "quic"
+    }
+    fn channel(&self) -> tokio::sync::mpsc::Sender<super::ClaCmd> {
+        self.tx.clone()
+    }
+}
+
+impl HelpStr for QuicConvergenceLayer {
+    fn local_help_str() -> &'static str {
+        "port=4560:bind=0.0.0.0:cert=<pem file>:key=<pem file>:ca=<pem file>:insecure=false:0rtt=false:keepalive=10s"
+    }
+}
+impl std::fmt::Display for QuicConvergenceLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "quic:{}:{}", self.settings.bind, self.settings.port)
+    }
+}
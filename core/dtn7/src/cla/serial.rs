@@ -0,0 +1,669 @@
+use crate::cla::{ConvergenceLayerAgent, TransferResult};
+use crate::core::peer::{DtnPeer, PeerAddress, PeerType};
+use anyhow::bail;
+use async_trait::async_trait;
+use bp7::{Bundle, ByteBuffer, EndpointID};
+use bytes::{Buf, BufMut, BytesMut};
+use core::convert::TryFrom;
+use crc::{Crc, CRC_16_IBM_SDLC};
+use dtn7_codegen::cla;
+use futures::stream::SplitSink;
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use super::HelpStr;
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// Size of type, sequence number, fragment index and fragment count
+const HEADER_LEN: usize = 7;
+const CRC_LEN: usize = 2;
+/// Upper bound for a single encoded frame, anything longer is considered line noise
+const MAX_FRAME_LEN: usize = 65535;
+/// Largest payload per frame, leaves room for header, CRC and COBS overhead within `MAX_FRAME_LEN`
+pub const MAX_MTU: usize = 65000;
+/// Incomplete bundles are dropped after not receiving a fragment for this long
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_PENDING_REASSEMBLIES: usize = 8;
+/// Largest bundle that is reassembled, fragments of larger bundles are dropped
+pub const MAX_BUNDLE_SIZE: usize = 16 * 1024 * 1024;
+/// Time to wait before reopening the serial device after a read error
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+/// Number of completed sequence numbers remembered to drop retransmitted fragments
+const MAX_COMPLETED: usize = 16;
+
+/// Stuffs `data` so that it contains no zero bytes (consistent overhead byte stuffing).
+pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_idx = 0;
+    let mut code: u8 = 1;
+    out.push(0);
+    for &b in data {
+        if b == 0 {
+            out[code_idx] = code;
+            code_idx = out.len();
+            out.push(0);
+            code = 1;
+        } else {
+            out.push(b);
+            code += 1;
+            if code == 0xff {
+                out[code_idx] = code;
+                code_idx = out.len();
+                out.push(0);
+                code = 1;
+            }
+        }
+    }
+    out[code_idx] = code;
+    out
+}
+
+/// Reverses [`cobs_encode`], returns `None` for malformed input.
+pub fn cobs_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let code = data[i];
+        if code == 0 {
+            return None;
+        }
+        i += 1;
+        for _ in 1..code {
+            out.push(*data.get(i)?);
+            i += 1;
+        }
+        if code < 0xff && i < data.len() {
+            out.push(0);
+        }
+    }
+    Some(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// Fragment of a bundle, no acknowledgement requested
+    Data = 1,
+    /// Fragment of a bundle, receiver has to answer with an ack
+    DataAckReq = 2,
+    /// Acknowledgement of a single fragment
+    Ack = 3,
+}
+
+impl TryFrom<u8> for FrameType {
+    type Error = io::Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(FrameType::Data),
+            2 => Ok(FrameType::DataAckReq),
+            3 => Ok(FrameType::Ack),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown serial frame type",
+            )),
+        }
+    }
+}
+
+/// A single link-layer frame.
+///
+/// On the wire a frame consists of the header, the payload and a CRC-16 (X.25) over both,
+/// COBS encoded and terminated by a zero byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialFrame {
+    pub frame_type: FrameType,
+    /// sequence number of the bundle this fragment belongs to
+    pub seq: u16,
+    pub index: u16,
+    pub count: u16,
+    pub payload: Vec<u8>,
+}
+
+impl SerialFrame {
+    pub fn ack(seq: u16, index: u16) -> SerialFrame {
+        SerialFrame {
+            frame_type: FrameType::Ack,
+            seq,
+            index,
+            count: 0,
+            payload: Vec::new(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.payload.len() + CRC_LEN);
+        buf.push(self.frame_type as u8);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.index.to_be_bytes());
+        buf.extend_from_slice(&self.count.to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf.extend_from_slice(&CRC16.checksum(&buf).to_be_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> io::Result<SerialFrame> {
+        if buf.len() < HEADER_LEN + CRC_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "serial frame too short",
+            ));
+        }
+        let (data, crc) = buf.split_at(buf.len() - CRC_LEN);
+        if CRC16.checksum(data) != u16::from_be_bytes([crc[0], crc[1]]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "serial frame checksum mismatch",
+            ));
+        }
+        Ok(SerialFrame {
+            frame_type: FrameType::try_from(data[0])?,
+            seq: u16::from_be_bytes([data[1], data[2]]),
+            index: u16::from_be_bytes([data[3], data[4]]),
+            count: u16::from_be_bytes([data[5], data[6]]),
+            payload: data[HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Splits a bundle into frames carrying at most `mtu` bytes of payload each.
+///
+/// The `mtu` is limited to [`MAX_MTU`]. Fails if the bundle needs more than `u16::MAX` frames.
+pub fn fragment(seq: u16, data: &[u8], mtu: usize, ack: bool) -> anyhow::Result<Vec<SerialFrame>> {
+    let frame_type = if ack {
+        FrameType::DataAckReq
+    } else {
+        FrameType::Data
+    };
+    let chunks: Vec<&[u8]> = data.chunks(mtu.clamp(1, MAX_MTU)).collect();
+    let count = match u16::try_from(chunks.len()) {
+        Ok(count) => count,
+        Err(_) => bail!(
+            "bundle of {} bytes needs {} frames, at most {} are possible",
+            data.len(),
+            chunks.len(),
+            u16::MAX
+        ),
+    };
+    Ok(chunks
+        .into_iter()
+        .zip(0..count)
+        .map(|(chunk, index)| SerialFrame {
+            frame_type,
+            seq,
+            index,
+            count,
+            payload: chunk.to_vec(),
+        })
+        .collect())
+}
+
+/// Codec for COBS framed, CRC checked serial frames.
+///
+/// Corrupted frames are logged and skipped instead of terminating the stream,
+/// as bit errors are expected on radio links.
+#[derive(Debug, Default)]
+pub struct SerialFrameCodec;
+
+impl Encoder<SerialFrame> for SerialFrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: SerialFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let buf = cobs_encode(&item.to_bytes());
+        dst.reserve(buf.len() + 1);
+        dst.put_slice(&buf);
+        dst.put_u8(0);
+        Ok(())
+    }
+}
+
+impl Decoder for SerialFrameCodec {
+    type Item = SerialFrame;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<SerialFrame>> {
+        while let Some(end) = buf.iter().position(|b| *b == 0) {
+            let encoded = buf.split_to(end);
+            buf.advance(1);
+            if encoded.is_empty() {
+                continue;
+            }
+            match cobs_decode(&encoded)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid cobs data"))
+                .and_then(|data| SerialFrame::from_bytes(&data))
+            {
+                Ok(frame) => return Ok(Some(frame)),
+                Err(err) => {
+                    debug!("Dropping serial frame: {}", err);
+                    crate::STATS.lock().broken += 1;
+                }
+            }
+        }
+        if buf.len() > MAX_FRAME_LEN {
+            debug!("Discarding {} bytes without frame delimiter", buf.len());
+            buf.clear();
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug)]
+struct PartialBundle {
+    fragments: Vec<Option<Vec<u8>>>,
+    missing: usize,
+    size: usize,
+    last_update: Instant,
+}
+
+impl PartialBundle {
+    fn new(count: u16) -> PartialBundle {
+        PartialBundle {
+            fragments: vec![None; count as usize],
+            missing: count as usize,
+            size: 0,
+            last_update: Instant::now(),
+        }
+    }
+}
+
+/// Collects fragments until a bundle is complete.
+///
+/// Bundles larger than `max_size` are dropped, so the buffered data is bounded by
+/// `MAX_PENDING_REASSEMBLIES` times `max_size`.
+#[derive(Debug)]
+pub struct Reassembler {
+    pending: HashMap<u16, PartialBundle>,
+    completed: VecDeque<u16>,
+    max_size: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Reassembler::with_max_size(MAX_BUNDLE_SIZE)
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Default::default()
+    }
+
+    /// Creates a reassembler dropping bundles of more than `max_size` bytes.
+    pub fn with_max_size(max_size: usize) -> Reassembler {
+        Reassembler {
+            pending: HashMap::new(),
+            completed: VecDeque::new(),
+            max_size,
+        }
+    }
+
+    /// Number of bundles that are not yet complete.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Adds a data frame, returns the bundle data once all fragments have been received.
+    pub fn push(&mut self, frame: SerialFrame) -> Option<Vec<u8>> {
+        if frame.count == 0 || frame.index >= frame.count || self.completed.contains(&frame.seq) {
+            return None;
+        }
+        // all but the last fragment carry a full mtu
+        let last = frame.index + 1 == frame.count;
+        let min_size = if last {
+            frame.payload.len() + frame.count as usize - 1
+        } else {
+            frame.count as usize * frame.payload.len()
+        };
+        if min_size > self.max_size {
+            debug!(
+                "Dropping fragment {} of bundle {}, the bundle exceeds {} bytes",
+                frame.index, frame.seq, self.max_size
+            );
+            self.pending.remove(&frame.seq);
+            return None;
+        }
+        self.pending
+            .retain(|_, p| p.last_update.elapsed() < REASSEMBLY_TIMEOUT);
+        if !self.pending.contains_key(&frame.seq) && self.pending.len() >= MAX_PENDING_REASSEMBLIES
+        {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.last_update)
+                .map(|(seq, _)| *seq);
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+        let partial = self
+            .pending
+            .entry(frame.seq)
+            .or_insert_with(|| PartialBundle::new(frame.count));
+        if partial.fragments.len() != frame.count as usize {
+            // sender restarted and reused the sequence number
+            *partial = PartialBundle::new(frame.count);
+        }
+        partial.last_update = Instant::now();
+        let slot = &mut partial.fragments[frame.index as usize];
+        if slot.is_none() {
+            if partial.size + frame.payload.len() > self.max_size {
+                debug!(
+                    "Dropping bundle {}, it exceeds {} bytes",
+                    frame.seq, self.max_size
+                );
+                self.pending.remove(&frame.seq);
+                return None;
+            }
+            partial.size += frame.payload.len();
+            *slot = Some(frame.payload);
+            partial.missing -= 1;
+        }
+        if partial.missing > 0 {
+            return None;
+        }
+        let partial = self.pending.remove(&frame.seq)?;
+        if self.completed.len() >= MAX_COMPLETED {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(frame.seq);
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+}
+
+type FrameStream = futures::stream::SplitStream<Framed<SerialStream, SerialFrameCodec>>;
+type FrameSplitSink = SplitSink<Framed<SerialStream, SerialFrameCodec>, SerialFrame>;
+type FrameSink = Arc<Mutex<FrameSplitSink>>;
+
+#[derive(Debug, Clone)]
+struct SerialSettings {
+    path: String,
+    baud: u32,
+    mtu: usize,
+    ack: bool,
+    retries: u32,
+    timeout: Duration,
+    peer: Option<String>,
+}
+
+fn open_port(settings: &SerialSettings) -> anyhow::Result<(FrameSplitSink, FrameStream)> {
+    let port = tokio_serial::new(&settings.path, settings.baud).open_native_async()?;
+    Ok(Framed::new(port, SerialFrameCodec).split())
+}
+
+/// Reads frames from the device, reopens it after read errors and replaces the shared sink.
+async fn serial_reader(
+    settings: SerialSettings,
+    mut stream: FrameStream,
+    sink: FrameSink,
+    acks: mpsc::UnboundedSender<(u16, u16)>,
+) {
+    let mut reassembler = Reassembler::new();
+    loop {
+        match stream.next().await {
+            Some(Ok(frame)) => {
+                handle_frame(frame, &sink, &acks, &mut reassembler).await;
+                continue;
+            }
+            Some(Err(err)) => error!("Error reading from serial device: {}", err),
+            None => warn!("Serial device {} closed", settings.path),
+        }
+        stream = loop {
+            tokio::time::sleep(REOPEN_DELAY).await;
+            match open_port(&settings) {
+                Ok((new_sink, new_stream)) => {
+                    info!("Reopened serial device {}", settings.path);
+                    *sink.lock().await = new_sink;
+                    break new_stream;
+                }
+                Err(err) => warn!("Error reopening serial device {}: {}", settings.path, err),
+            }
+        };
+    }
+}
+
+async fn handle_frame(
+    frame: SerialFrame,
+    sink: &FrameSink,
+    acks: &mpsc::UnboundedSender<(u16, u16)>,
+    reassembler: &mut Reassembler,
+) {
+    match frame.frame_type {
+        FrameType::Ack => {
+            let _ = acks.send((frame.seq, frame.index));
+        }
+        FrameType::Data | FrameType::DataAckReq => {
+            if frame.frame_type == FrameType::DataAckReq {
+                // retransmissions of already received fragments are acked again
+                let ack = SerialFrame::ack(frame.seq, frame.index);
+                if let Err(err) = sink.lock().await.send(ack).await {
+                    warn!("Error sending serial ack: {}", err);
+                }
+            }
+            if let Some(data) = reassembler.push(frame) {
+                let len = data.len();
+                if let Ok(bndl) = Bundle::try_from(data) {
+                    info!("Received bundle: {} via serial", bndl.id());
+                    crate::peers_report_receive(&bndl, "serial", len);
+                    tokio::spawn(async move {
+                        if let Err(err) = crate::core::processing::receive(bndl).await {
+                            error!("Failed to process bundle: {}", err);
+                        }
+                    });
+                } else {
+                    crate::STATS.lock().broken += 1;
+                    info!("Error decoding bundle received via serial");
+                }
+            }
+        }
+    }
+}
+
+async fn serial_send_bundle(
+    sink: &FrameSink,
+    acks: &mut mpsc::UnboundedReceiver<(u16, u16)>,
+    settings: &SerialSettings,
+    seq: u16,
+    data: ByteBuffer,
+) -> TransferResult {
+    let now = Instant::now();
+    let frames = match fragment(seq, &data, settings.mtu, settings.ack) {
+        Ok(frames) => frames,
+        Err(err) => {
+            error!("Cannot send bundle via {}: {}", settings.path, err);
+            return TransferResult::Failure;
+        }
+    };
+    for frame in frames {
+        let index = frame.index;
+        let mut attempts = 0;
+        loop {
+            if let Err(err) = sink.lock().await.send(frame.clone()).await {
+                error!("Error writing to serial device: {}", err);
+                return TransferResult::Failure;
+            }
+            if !settings.ack {
+                break;
+            }
+            let deadline = tokio::time::Instant::now() + settings.timeout;
+            let mut acked = false;
+            while let Ok(Some(ack)) = tokio::time::timeout_at(deadline, acks.recv()).await {
+                if ack == (seq, index) {
+                    acked = true;
+                    break;
+                }
+            }
+            if acked {
+                break;
+            }
+            attempts += 1;
+            if attempts > settings.retries {
+                warn!(
+                    "No ack for fragment {} of bundle {} after {} retries",
+                    index, seq, settings.retries
+                );
+                return TransferResult::Failure;
+            }
+            debug!("Retransmitting fragment {} of bundle {}", index, seq);
+        }
+    }
+    debug!(
+        "Transmission time: {:?} for {} bytes via {}",
+        now.elapsed(),
+        data.len(),
+        settings.path
+    );
+    TransferResult::Successful
+}
+
+#[cla(serial)]
+#[derive(Debug)]
+pub struct SerialConvergenceLayer {
+    settings: SerialSettings,
+    tx: mpsc::Sender<super::ClaCmd>,
+    rx: Option<mpsc::Receiver<super::ClaCmd>>,
+}
+
+impl SerialConvergenceLayer {
+    pub fn new(local_settings: Option<&HashMap<String, String>>) -> SerialConvergenceLayer {
+        let get = |key: &str| {
+            local_settings
+                .and_then(|settings| settings.get(key))
+                .map(|s| s.to_string())
+        };
+        let settings = SerialSettings {
+            path: get("path").unwrap_or_else(|| "/dev/ttyUSB0".to_string()),
+            baud: get("baud")
+                .and_then(|b| b.parse::<u32>().ok())
+                .unwrap_or(115200),
+            mtu: get("mtu")
+                .and_then(|m| m.parse::<usize>().ok())
+                .filter(|m| *m > 0)
+                .map(|m| {
+                    if m > MAX_MTU {
+                        warn!("Serial mtu {} exceeds the maximum, using {}", m, MAX_MTU);
+                    }
+                    m.min(MAX_MTU)
+                })
+                .unwrap_or(240),
+            ack: get("ack").map(|a| a == "true" || a == "1").unwrap_or(false),
+            retries: get("retries")
+                .and_then(|r| r.parse::<u32>().ok())
+                .unwrap_or(3),
+            timeout: get("timeout")
+                .and_then(|t| humantime::parse_duration(&t).ok())
+                .unwrap_or(Duration::from_secs(2)),
+            peer: get("peer"),
+        };
+        let (tx, rx) = mpsc::channel(100);
+        SerialConvergenceLayer {
+            settings,
+            tx,
+            rx: Some(rx),
+        }
+    }
+
+    pub async fn spawn_listener(&mut self) -> anyhow::Result<()> {
+        let (sink, stream) = open_port(&self.settings)?;
+        let sink: FrameSink = Arc::new(Mutex::new(sink));
+        let (ack_tx, mut ack_rx) = mpsc::unbounded_channel();
+        debug!(
+            "spawning serial listener on {} with {} baud",
+            self.settings.path, self.settings.baud
+        );
+        tokio::spawn(serial_reader(
+            self.settings.clone(),
+            stream,
+            sink.clone(),
+            ack_tx,
+        ));
+
+        let mut rx = self
+            .rx
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("serial convergence layer already set up"))?;
+        let settings = self.settings.clone();
+        tokio::spawn(async move {
+            let mut seq: u16 = rand::random();
+            // transfers are handled one at a time as the link is usually half-duplex
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    super::ClaCmd::Transfer(remote, data, reply) => {
+                        debug!(
+                            "SerialConvergenceLayer: received transfer command for {}",
+                            remote
+                        );
+                        if !data.is_empty() {
+                            seq = seq.wrapping_add(1);
+                            let res =
+                                serial_send_bundle(&sink, &mut ack_rx, &settings, seq, data).await;
+                            reply.send(res).unwrap();
+                        } else {
+                            debug!("Nothing to forward.");
+                            reply.send(TransferResult::Successful).unwrap();
+                        }
+                    }
+                    super::ClaCmd::Shutdown => {
+                        debug!("SerialConvergenceLayer: received shutdown command");
+                        break;
+                    }
+                }
+            }
+        });
+
+        if let Some(peer) = &self.settings.peer {
+            let eid = if peer.parse::<u64>().is_ok() {
+                EndpointID::try_from(format!("ipn:{}.0", peer))?
+            } else {
+                EndpointID::try_from(format!("dtn://{}/", peer))?
+            };
+            info!(
+                "Adding static serial peer {} on {}",
+                eid, self.settings.path
+            );
+            crate::peers_add(DtnPeer::new(
+                eid,
+                PeerAddress::Generic(self.settings.path.clone()),
+                PeerType::Static,
+                None,
+                vec![("serial".into(), None)],
+                HashMap::new(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConvergenceLayerAgent for SerialConvergenceLayer {
+    async fn setup(&mut self) {
+        self.spawn_listener()
+            .await
+            .expect("error setting up serial device");
+    }
+    fn port(&self) -> u16 {
+        0
+    }
+    fn name(&self) -> &str {
+        "serial"
+    }
+    fn channel(&self) -> tokio::sync::mpsc::Sender<super::ClaCmd> {
+        self.tx.clone()
+    }
+}
+
+impl HelpStr for SerialConvergenceLayer {
+    fn local_help_str() -> &'static str {
+        "path=/dev/ttyUSB0:baud=115200:mtu=240:ack=false:retries=3:timeout=2s:peer=<node name>"
+    }
+}
+impl std::fmt::Display for SerialConvergenceLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "serial:{}:{}", self.settings.path, self.settings.baud)
+    }
+}
@@ -22,6 +22,7 @@ use tokio::sync::Mutex;
 use tokio::time::{self};
 //use std::net::TcpStream;
 use super::tcp::proto::*;
+use crate::core::events::{self, Event};
 use crate::core::store::BundleStore;
 use crate::core::PeerType;
 use crate::{peers_add, peers_known, STORE};
@@ -223,8 +224,10 @@ impl TcpSession {
         (ReceiveState::Terminated, SendState::Terminated)
     }
     async fn process_bundle(&mut self, vec: Vec<u8>, tid: u64) -> anyhow::Result<ReceiveState> {
+        let len = vec.len();
         match Bundle::try_from(vec) {
             Ok(bundle) => {
+                crate::peers_report_receive(&bundle, "tcp", len);
                 tokio::spawn(async move {
                     if let Err(err) = crate::core::processing::receive(bundle).await {
                         error!("Failed to process bundle: {}", err);
@@ -587,7 +590,18 @@ impl TcpConnection {
                     last_tid: 0u64,
                     rx_session_queue,
                 };
+                let peer = remote_eid.node();
+                events::emit(Event::ClaSessionUp {
+                    cla: "tcp".into(),
+                    addr: self.addr.to_string(),
+                    peer: peer.clone(),
+                });
                 session.run().await;
+                events::emit(Event::ClaSessionDown {
+                    cla: "tcp".into(),
+                    addr: self.addr.to_string(),
+                    peer,
+                });
             }
             Err(err) => bail!("Failed to negotiate session for {}: {}", self.addr, err),
         }
@@ -845,7 +859,7 @@ mod tests {
         if buf.len() > config.transfer_mru as usize {
             bail!("bundle too big");
         }
-        let fitting = u64::from(buf.len() as u64 % config.segment_mru != 0);
+        let fitting = u64::from(!(buf.len() as u64).is_multiple_of(config.segment_mru));
         let num_segs = (buf.len() as u64 / config.segment_mru) + fitting;
 
         for i in 0..num_segs {
@@ -894,7 +908,7 @@ mod tests {
         //        let data_raw: [u8; data_len] = [0; data_len];
         let data_raw: Vec<u8> = vec![0x90; data_len as usize];
 
-        let fitting = u64::from(data_len % segment_mru != 0);
+        let fitting = u64::from(!data_len.is_multiple_of(segment_mru));
         let num_expected_segs = ((data_len / segment_mru) + fitting) as usize;
 
         //let data = Bytes::copy_from_slice(&data_raw);
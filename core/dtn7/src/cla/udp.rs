@@ -26,6 +26,7 @@ async fn udp_listener(addr: String, port: u16) -> Result<(), io::Error> {
         let buf = &buf[..amt];
         if let Ok(bndl) = Bundle::try_from(buf.to_vec()) {
             info!("Received bundle: {} from {}", bndl.id(), src);
+            crate::peers_report_receive(&bndl, "udp", amt);
             {
                 tokio::spawn(async move {
                     if let Err(err) = crate::core::processing::receive(bndl).await {
@@ -1,18 +1,35 @@
 use bp7::{Bundle, EndpointID};
 use enum_dispatch::enum_dispatch;
-use log::{debug, trace};
-use std::collections::VecDeque;
+use log::{debug, info, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::Sender;
 
+use crate::core::bundlepack::{BundlePack, Constraint};
+use crate::core::handler_agent::HandlerApplicationAgent;
+use crate::core::store::BundleStore;
 use crate::dtnd::ws::BundleDelivery;
-use crate::store_remove_if_singleton_bundle;
+use crate::{store_get_bundle, store_has_item, store_mark_delivered, STORE};
 //use crate::dtnd::ws::WsAASession;
 
 #[enum_dispatch]
 #[derive(Debug)]
 pub enum ApplicationAgentEnum {
     SimpleApplicationAgent,
+    GroupApplicationAgent,
+    HandlerApplicationAgent,
+}
+
+/// Creates the application agent matching the kind of endpoint, group (non-singleton) endpoints
+/// get a `GroupApplicationAgent`, all others a `SimpleApplicationAgent`.
+pub fn new(eid: EndpointID) -> ApplicationAgentEnum {
+    if eid.is_non_singleton() {
+        GroupApplicationAgent::with(eid).into()
+    } else {
+        SimpleApplicationAgent::with(eid).into()
+    }
 }
 
 #[enum_dispatch(ApplicationAgentEnum)]
@@ -20,15 +37,19 @@ pub trait ApplicationAgent: Debug {
     fn eid(&self) -> &EndpointID;
     fn push(&mut self, bundle: &Bundle);
     fn pop(&mut self) -> Option<Bundle>;
+    /// Rebuilds the delivery queue from the bundles kept in the store with the `LocalEndpoint` constraint.
+    fn restore(&mut self);
     fn set_delivery_addr(&mut self, addr: Sender<BundleDelivery>);
     fn clear_delivery_addr(&mut self);
     fn delivery_addr(&self) -> Option<Sender<BundleDelivery>>;
 }
 
+/// Application agent that queues the IDs of undelivered bundles, the bundles themselves stay in the store
+/// with the `LocalEndpoint` constraint until they are delivered.
 #[derive(Debug, Clone)]
 pub struct SimpleApplicationAgent {
     eid: EndpointID,
-    bundles: VecDeque<Bundle>,
+    bundles: VecDeque<String>,
     delivery: Option<Sender<BundleDelivery>>,
 }
 
@@ -44,22 +65,38 @@ impl ApplicationAgent for SimpleApplicationAgent {
         if let Some(addr) = self.delivery_addr() {
             // TODO: remove clone and work with reference
 
-            if addr.try_send(BundleDelivery(bundle.clone())).is_err() {
-                self.bundles.push_back(bundle.clone());
-            } else {
-                store_remove_if_singleton_bundle(bundle);
+            if addr.try_send(BundleDelivery(bundle.clone())).is_ok() {
+                store_mark_delivered(&bundle.id());
+                return;
             }
-        } else {
-            // save in temp buffer for delivery
-            self.bundles.push_back(bundle.clone());
+        }
+        // remember for later delivery, the bundle itself stays in the store
+        let bid = bundle.id();
+        if !self.bundles.contains(&bid) {
+            self.bundles.push_back(bid);
         }
     }
     fn pop(&mut self) -> Option<Bundle> {
-        let bundle = self.bundles.pop_front();
-        if let Some(bndl) = bundle.as_ref() {
-            store_remove_if_singleton_bundle(bndl);
-        };
-        bundle
+        while let Some(bid) = self.bundles.pop_front() {
+            // bundles might have expired or been deleted in the meantime
+            if let Some(bundle) = store_get_bundle(&bid) {
+                store_mark_delivered(&bid);
+                return Some(bundle);
+            }
+            debug!("Queued bundle {} no longer in store", bid);
+        }
+        None
+    }
+    fn restore(&mut self) {
+        let pending = local_bundles(&self.eid);
+        if !pending.is_empty() {
+            debug!(
+                "Restored {} undelivered bundles for {}",
+                pending.len(),
+                self.eid
+            );
+        }
+        self.bundles = pending.into();
     }
 
     fn set_delivery_addr(&mut self, addr: Sender<BundleDelivery>) {
@@ -84,3 +121,317 @@ impl SimpleApplicationAgent {
         }
     }
 }
+
+/// IDs of the bundles for `eid` kept in the store with the `LocalEndpoint` constraint, oldest first.
+pub(crate) fn local_bundles(eid: &EndpointID) -> Vec<String> {
+    let mut pending: Vec<BundlePack> = {
+        let store = STORE.lock();
+        store
+            .query_constraint(Constraint::LocalEndpoint)
+            .iter()
+            .filter_map(|bid| store.get_metadata(bid))
+            .filter(|bp| &bp.destination == eid && !bp.has_constraint(Constraint::Deleted))
+            .collect()
+    };
+    pending.sort_unstable_by(|a, b| (a.creation_time, &a.id).cmp(&(b.creation_time, &b.id)));
+    pending.into_iter().map(|bp| bp.id).collect()
+}
+
+/// Lease of group members that did not ask for a specific one.
+pub const GROUP_LEASE_DEFAULT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Member receiving group bundles on behalf of anonymous clients, e.g. the HTTP `/endpoint` API
+/// or websocket subscriptions without member name. It shares one cursor and never expires.
+pub const GROUP_DEFAULT_MEMBER: &str = "";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GroupMember {
+    /// sequence number of the next bundle to deliver
+    cursor: u64,
+    /// sequence number of the first bundle not yet acknowledged
+    acked: u64,
+    lease: Duration,
+    expires: SystemTime,
+    #[serde(skip)]
+    delivery: Option<Sender<BundleDelivery>>,
+}
+
+/// Delivery state of a group member as reported by the status API.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupMemberInfo {
+    pub member: String,
+    pub connected: bool,
+    /// bundles of the group log not yet acknowledged by the member
+    pub unacked: usize,
+    /// lease expiry as unix timestamp in seconds, none while connected
+    pub expires: Option<u64>,
+}
+
+/// Application agent for group (non-singleton) endpoints such as `dtn://sensors/~telemetry`.
+///
+/// Received bundles are appended to a log shared by all members, every member has its own
+/// delivery cursor and acknowledges bundles cumulatively. When a member reconnects, delivery
+/// resumes after its last acknowledged bundle, giving at-least-once delivery per member.
+/// Bundles stay in the store with the `LocalEndpoint` constraint until their lifetime expires,
+/// so late joiners still receive them and forwarding to other group nodes continues.
+/// Members that are not connected are dropped once their lease runs out.
+/// dtnd saves the log and the members with every janitor run, so they survive a restart,
+/// bundles acknowledged after the last save are delivered again.
+#[derive(Debug, Clone)]
+pub struct GroupApplicationAgent {
+    eid: EndpointID,
+    log: BTreeMap<u64, String>,
+    /// sequence numbers of the bundles in the log
+    seqs: HashMap<String, u64>,
+    next_seq: u64,
+    members: BTreeMap<String, GroupMember>,
+}
+
+/// Persistent part of a `GroupApplicationAgent`: the group log and the delivery positions,
+/// acknowledgements and leases of its members.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupState {
+    log: BTreeMap<u64, String>,
+    next_seq: u64,
+    members: BTreeMap<String, GroupMember>,
+}
+
+impl ApplicationAgent for GroupApplicationAgent {
+    fn eid(&self) -> &EndpointID {
+        &self.eid
+    }
+    fn push(&mut self, bundle: &Bundle) {
+        debug!("Received {:?} for group {}", bundle.id(), self.eid);
+        trace!("Received raw: {:?}", bundle);
+
+        self.append(bundle.id());
+        let members: Vec<String> = self.members.keys().cloned().collect();
+        for member in members {
+            self.flush(&member);
+        }
+    }
+    fn pop(&mut self) -> Option<Bundle> {
+        let first = self.first_seq();
+        let member = self
+            .members
+            .entry(GROUP_DEFAULT_MEMBER.to_string())
+            .or_insert_with(|| GroupMember::new(first));
+        for (seq, bid) in self.log.range(member.cursor..) {
+            member.cursor = seq + 1;
+            member.acked = member.cursor;
+            if let Some(bundle) = store_get_bundle(bid) {
+                return Some(bundle);
+            }
+        }
+        None
+    }
+    fn restore(&mut self) {
+        self.forget_removed();
+        for bid in local_bundles(&self.eid) {
+            self.append(bid);
+        }
+        if !self.log.is_empty() {
+            debug!("Restored {} bundles for group {}", self.log.len(), self.eid);
+        }
+    }
+
+    fn set_delivery_addr(&mut self, addr: Sender<BundleDelivery>) {
+        self.attach(GROUP_DEFAULT_MEMBER, addr);
+    }
+
+    fn clear_delivery_addr(&mut self) {
+        self.detach(GROUP_DEFAULT_MEMBER);
+    }
+
+    fn delivery_addr(&self) -> Option<Sender<BundleDelivery>> {
+        self.members
+            .get(GROUP_DEFAULT_MEMBER)
+            .and_then(|m| m.delivery.clone())
+    }
+}
+
+impl GroupMember {
+    fn new(start: u64) -> GroupMember {
+        GroupMember {
+            cursor: start,
+            acked: start,
+            lease: GROUP_LEASE_DEFAULT,
+            expires: SystemTime::now() + GROUP_LEASE_DEFAULT,
+            delivery: None,
+        }
+    }
+}
+
+impl GroupApplicationAgent {
+    pub fn with(eid: EndpointID) -> GroupApplicationAgent {
+        GroupApplicationAgent {
+            eid,
+            log: BTreeMap::new(),
+            seqs: HashMap::new(),
+            next_seq: 0,
+            members: BTreeMap::new(),
+        }
+    }
+
+    fn append(&mut self, bid: String) {
+        if !self.seqs.contains_key(&bid) {
+            self.seqs.insert(bid.clone(), self.next_seq);
+            self.log.insert(self.next_seq, bid);
+            self.next_seq += 1;
+        }
+    }
+
+    fn forget_removed(&mut self) {
+        self.log.retain(|_, bid| store_has_item(bid));
+        self.seqs.retain(|_, seq| self.log.contains_key(seq));
+    }
+
+    /// Snapshot of the group log and the member positions for persisting them.
+    pub fn state(&self) -> GroupState {
+        GroupState {
+            log: self.log.clone(),
+            next_seq: self.next_seq,
+            members: self.members.clone(),
+        }
+    }
+
+    /// Replaces the group log and members with a persisted state, e.g. after a restart.
+    /// Members resume after their last acknowledged bundle once they reconnect,
+    /// bundles that left the store in the meantime are dropped and newly stored ones appended.
+    pub fn load_state(&mut self, state: GroupState) {
+        self.seqs = state
+            .log
+            .iter()
+            .map(|(seq, bid)| (bid.clone(), *seq))
+            .collect();
+        self.log = state.log;
+        self.next_seq = self.next_seq.max(state.next_seq);
+        self.members = state.members;
+        for m in self.members.values_mut() {
+            m.cursor = m.acked;
+        }
+        self.restore();
+    }
+
+    fn first_seq(&self) -> u64 {
+        self.log.keys().next().copied().unwrap_or(self.next_seq)
+    }
+
+    /// Joins the group or renews the lease of an existing membership.
+    /// New members start with the oldest bundle still kept for the group.
+    pub fn join(&mut self, member: &str, lease: Option<Duration>) {
+        let first = self.first_seq();
+        let m = self.members.entry(member.to_string()).or_insert_with(|| {
+            info!("{} joined group {}", member, self.eid);
+            GroupMember::new(first)
+        });
+        if let Some(lease) = lease {
+            m.lease = lease;
+        }
+        m.expires = SystemTime::now() + m.lease;
+    }
+
+    /// Ends a membership, returns false if `member` was not part of the group.
+    pub fn leave(&mut self, member: &str) -> bool {
+        let removed = self.members.remove(member).is_some();
+        if removed {
+            info!("{} left group {}", member, self.eid);
+        }
+        removed
+    }
+
+    /// Connects a member to a delivery channel, joining the group if necessary.
+    /// Delivery restarts after the last acknowledged bundle.
+    pub fn attach(&mut self, member: &str, addr: Sender<BundleDelivery>) {
+        if !self.members.contains_key(member) {
+            self.join(member, None);
+        }
+        if let Some(m) = self.members.get_mut(member) {
+            m.cursor = m.acked;
+            m.delivery = Some(addr);
+        }
+        self.flush(member);
+    }
+
+    /// Disconnects a member, its lease starts running from now on.
+    pub fn detach(&mut self, member: &str) {
+        if let Some(m) = self.members.get_mut(member) {
+            m.delivery = None;
+            m.expires = SystemTime::now() + m.lease;
+        }
+    }
+
+    /// Acknowledges `bid` and all bundles delivered to `member` before it.
+    /// Returns false if the bundle is not part of the group log.
+    pub fn ack(&mut self, member: &str, bid: &str) -> bool {
+        let Some(&seq) = self.seqs.get(bid) else {
+            return false;
+        };
+        let Some(m) = self.members.get_mut(member) else {
+            return false;
+        };
+        m.acked = m.acked.max(seq + 1);
+        m.cursor = m.cursor.max(m.acked);
+        true
+    }
+
+    /// Hands pending bundles to a connected member until its delivery channel is full.
+    pub fn flush(&mut self, member: &str) {
+        let Some(m) = self.members.get_mut(member) else {
+            return;
+        };
+        let Some(addr) = m.delivery.clone() else {
+            return;
+        };
+        for (seq, bid) in self.log.range(m.cursor..) {
+            if let Some(bundle) = store_get_bundle(bid) {
+                if addr.try_send(BundleDelivery(bundle)).is_err() {
+                    break;
+                }
+            }
+            m.cursor = seq + 1;
+            if member == GROUP_DEFAULT_MEMBER {
+                m.acked = m.cursor;
+            }
+        }
+    }
+
+    /// Drops memberships with expired leases, forgets bundles that left the store and
+    /// retries delivery to connected members whose channel was full before.
+    pub fn maintain(&mut self) {
+        let now = SystemTime::now();
+        let eid = &self.eid;
+        self.members.retain(|name, m| {
+            let keep = name == GROUP_DEFAULT_MEMBER || m.delivery.is_some() || m.expires > now;
+            if !keep {
+                info!("lease of {} in group {} expired", name, eid);
+            }
+            keep
+        });
+        self.forget_removed();
+        let members: Vec<String> = self.members.keys().cloned().collect();
+        for member in members {
+            self.flush(&member);
+        }
+    }
+
+    pub fn members(&self) -> Vec<GroupMemberInfo> {
+        self.members
+            .iter()
+            .filter(|(name, _)| name.as_str() != GROUP_DEFAULT_MEMBER)
+            .map(|(name, m)| GroupMemberInfo {
+                member: name.clone(),
+                connected: m.delivery.is_some(),
+                unacked: self.log.range(m.acked..).count(),
+                expires: if m.delivery.is_some() {
+                    None
+                } else {
+                    m.expires
+                        .duration_since(UNIX_EPOCH)
+                        .ok()
+                        .map(|d| d.as_secs())
+                },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,75 @@
+//! Encodings of the packets exchanged with external modules over the ECLA and erouting interfaces.
+//!
+//! JSON is the default. CBOR carries the same packets with binary data as raw byte strings
+//! instead of base64 or number arrays, saving bandwidth and parsing time for constrained modules.
+//! On WebSockets, JSON packets are sent as text and CBOR packets as binary messages.
+
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Encoding::Json),
+            "cbor" => Ok(Encoding::Cbor),
+            _ => bail!("unknown encoding {}, use json or cbor", s),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Json => write!(f, "json"),
+            Encoding::Cbor => write!(f, "cbor"),
+        }
+    }
+}
+
+impl Encoding {
+    /// Guesses the encoding of a received frame, JSON packets are objects starting with `{`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dtn7::core::encoding::Encoding;
+    ///
+    /// let packet = serde_json::json!({"type": "Credit", "credits": 1});
+    /// let json = Encoding::Json.encode(&packet).unwrap();
+    /// let cbor = Encoding::Cbor.encode(&packet).unwrap();
+    /// assert_eq!(Encoding::detect(&json), Encoding::Json);
+    /// assert_eq!(Encoding::detect(&cbor), Encoding::Cbor);
+    /// assert!(cbor.len() < json.len());
+    /// ```
+    pub fn detect(buf: &[u8]) -> Encoding {
+        match buf.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Encoding::Json,
+            _ => Encoding::Cbor,
+        }
+    }
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            Encoding::Json => serde_json::to_vec(value)?,
+            Encoding::Cbor => serde_cbor::to_vec(value)?,
+        })
+    }
+    pub fn decode<T: DeserializeOwned>(&self, buf: &[u8]) -> Result<T> {
+        Ok(match self {
+            Encoding::Json => serde_json::from_slice(buf)?,
+            Encoding::Cbor => serde_cbor::from_slice(buf)?,
+        })
+    }
+}
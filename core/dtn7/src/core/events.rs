@@ -0,0 +1,333 @@
+//! Structured events of daemon activity.
+//!
+//! Components publish typed [`Event`]s on the global [`EVENTS`](crate::EVENTS) bus. Each event
+//! is wrapped in an [`EventRecord`] with a sequence number and timestamp and handed to all
+//! subscribers, e.g., the `/ws/events` and `/events` endpoints of the web interface, and
+//! appended to the newline-delimited JSON event log if one is configured. The log is written by
+//! a background thread, so publishing never waits for the disk.
+//!
+//! Subscribers falling behind miss events, which shows as a gap in the sequence numbers.
+
+use crate::BundleID;
+use anyhow::{Context, Result};
+use log::{error, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Number of events buffered for each subscriber before it starts missing events
+pub const EVENT_BUFFER: usize = 1024;
+
+/// Why a bundle was evicted from the store without being processed further.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    /// lifetime of the bundle expired
+    Expired,
+    /// tombstone of a deleted bundle is older than the tombstone horizon
+    TombstoneExpired,
+    /// metadata without a stored bundle
+    MissingBundle,
+    /// stored bundle without metadata
+    OrphanedBundle,
+    /// metadata or bundle that can not be decoded
+    Corrupt,
+}
+
+/// Activity of the node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// bundle was created by a local application
+    BundleCreated {
+        bid: BundleID,
+        source: String,
+        destination: String,
+    },
+    /// bundle was received for the first time, duplicates are not reported
+    BundleReceived {
+        bid: BundleID,
+        source: String,
+        destination: String,
+        /// node the bundle was received from, if it carries a previous node block
+        previous_node: Option<String>,
+    },
+    /// bundle was handed to a peer
+    BundleForwarded {
+        bid: BundleID,
+        peer: String,
+        cla: String,
+        bytes: u64,
+        /// duration of the transmission in milliseconds
+        duration: u64,
+    },
+    /// bundle was delivered to a local endpoint
+    BundleDelivered { bid: BundleID, endpoint: String },
+    /// bundle was deleted before reaching its destination or is no longer needed
+    BundleDeleted { bid: BundleID, reason: String },
+    /// transmission of a bundle to a peer failed
+    TransferFailed {
+        bid: BundleID,
+        peer: String,
+        cla: String,
+        error: String,
+    },
+    /// bundle or store entry was removed by store maintenance
+    StoreEvicted {
+        bid: BundleID,
+        reason: EvictionReason,
+    },
+    /// peer became known, either discovered, configured or connecting on its own
+    PeerEncountered { peer: String, addr: String },
+    /// peer timed out, failed too often or was removed
+    PeerDropped { peer: String },
+    /// convergence layer connection was established, only reported by tcp, mtcp and quic
+    ClaSessionUp {
+        cla: String,
+        addr: String,
+        /// remote node, if known when the session is established
+        peer: Option<String>,
+    },
+    /// convergence layer connection was closed
+    ClaSessionDown {
+        cla: String,
+        addr: String,
+        peer: Option<String>,
+    },
+}
+
+impl Event {
+    /// Name of the event type as used in JSON and for filtering, e.g., `bundle_received`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::BundleCreated { .. } => "bundle_created",
+            Event::BundleReceived { .. } => "bundle_received",
+            Event::BundleForwarded { .. } => "bundle_forwarded",
+            Event::BundleDelivered { .. } => "bundle_delivered",
+            Event::BundleDeleted { .. } => "bundle_deleted",
+            Event::TransferFailed { .. } => "transfer_failed",
+            Event::StoreEvicted { .. } => "store_evicted",
+            Event::PeerEncountered { .. } => "peer_encountered",
+            Event::PeerDropped { .. } => "peer_dropped",
+            Event::ClaSessionUp { .. } => "cla_session_up",
+            Event::ClaSessionDown { .. } => "cla_session_down",
+        }
+    }
+
+    /// ID of the bundle the event is about, if any.
+    pub fn bid(&self) -> Option<&str> {
+        match self {
+            Event::BundleCreated { bid, .. }
+            | Event::BundleReceived { bid, .. }
+            | Event::BundleForwarded { bid, .. }
+            | Event::BundleDelivered { bid, .. }
+            | Event::BundleDeleted { bid, .. }
+            | Event::TransferFailed { bid, .. }
+            | Event::StoreEvicted { bid, .. } => Some(bid),
+            _ => None,
+        }
+    }
+}
+
+/// An event as published to subscribers and written to the event log.
+///
+/// ```
+/// use dtn7::core::events::{Event, EventRecord};
+///
+/// let record = EventRecord {
+///     seq: 7,
+///     time: 1680634888476,
+///     node: "dtn://node1/".into(),
+///     event: Event::PeerDropped { peer: "node2".into() },
+/// };
+/// assert_eq!(
+///     serde_json::to_string(&record).unwrap(),
+///     r#"{"seq":7,"time":1680634888476,"node":"dtn://node1/","type":"peer_dropped","peer":"node2"}"#
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// consecutive number of the event since the start of the node
+    pub seq: u64,
+    /// unix time as milliseconds
+    pub time: u64,
+    /// node ID of the node reporting the event
+    pub node: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Selects events by type and bundle, empty criteria match all events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub types: Vec<String>,
+    pub bid: Option<String>,
+}
+
+impl EventFilter {
+    /// Builds a filter from the query parameters `types`, a comma separated list of event
+    /// types, and `bid`.
+    pub fn from_params(params: &HashMap<String, String>) -> EventFilter {
+        EventFilter {
+            types: params
+                .get("types")
+                .map(|t| {
+                    t.split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            bid: params.get("bid").cloned(),
+        }
+    }
+
+    pub fn matches(&self, record: &EventRecord) -> bool {
+        if !self.types.is_empty() && !self.types.iter().any(|t| t == record.event.name()) {
+            return false;
+        }
+        match &self.bid {
+            Some(bid) => record.event.bid() == Some(bid.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// Work of the event log writer.
+enum LogEntry {
+    Record(EventRecord),
+    /// confirms that all previous records are written
+    Flush(mpsc::Sender<()>),
+}
+
+/// Distributes events to subscribers and the event log.
+pub struct EventBus {
+    tx: broadcast::Sender<EventRecord>,
+    seq: AtomicU64,
+    node: Mutex<String>,
+    log: Mutex<Option<mpsc::Sender<LogEntry>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus {
+            tx,
+            seq: AtomicU64::new(0),
+            node: Mutex::new(String::new()),
+            log: Mutex::new(None),
+        }
+    }
+
+    /// Sets the node ID recorded with all following events.
+    pub fn set_node(&self, node: &str) {
+        *self.node.lock() = node.to_string();
+    }
+
+    /// Appends all following events to the given file, one JSON object per line.
+    pub fn open_log(&self, path: &Path) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open event log {}", path.display()))?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("event-log".into())
+            .spawn(move || write_log(LineWriter::new(file), rx))
+            .context("cannot start event log writer")?;
+        // replacing the sender ends a previous writer once it wrote its backlog
+        *self.log.lock() = Some(tx);
+        Ok(())
+    }
+
+    /// Waits until all events published so far are written to the event log.
+    pub fn flush_log(&self) {
+        let (tx, rx) = mpsc::channel();
+        let sent = match self.log.lock().as_ref() {
+            Some(log) => log.send(LogEntry::Flush(tx)).is_ok(),
+            None => false,
+        };
+        if sent {
+            let _ = rx.recv();
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.tx.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) -> EventRecord {
+        let record = EventRecord {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            node: self.node.lock().clone(),
+            event,
+        };
+        if let Some(log) = self.log.lock().as_ref() {
+            if log.send(LogEntry::Record(record.clone())).is_err() {
+                error!("Event log writer is gone, dropping event {}", record.seq);
+            }
+        }
+        // no subscribers is not an error
+        let _ = self.tx.send(record.clone());
+        record
+    }
+}
+
+fn write_log(mut log: LineWriter<File>, rx: mpsc::Receiver<LogEntry>) {
+    for entry in rx {
+        match entry {
+            LogEntry::Record(record) => {
+                let line = serde_json::to_string(&record).expect("event serialization failed");
+                if let Err(err) = writeln!(log, "{}", line) {
+                    error!("Error writing event log: {}", err);
+                }
+            }
+            LogEntry::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Publishes an event on the global event bus.
+pub fn emit(event: Event) {
+    crate::EVENTS.publish(event);
+}
+
+/// Waits for the next event matching the filter.
+///
+/// Returns `None` once the bus is gone. Missed events are only logged, subscribers can
+/// detect them by gaps in the sequence numbers.
+pub async fn next_matching(
+    rx: &mut broadcast::Receiver<EventRecord>,
+    filter: &EventFilter,
+) -> Option<EventRecord> {
+    loop {
+        match rx.recv().await {
+            Ok(record) if filter.matches(&record) => return Some(record),
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Event subscriber missed {} events", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::core::processing::update_bundle_age;
+use crate::core::rpc::CorrelationBlock;
+use crate::core::transfer::TransferBlock;
+use crate::CONFIG;
+use bp7::administrative_record::{StatusReportReason, HOP_LIMIT_EXCEEDED, LIFETIME_EXPIRED};
+use bp7::canonical::{
+    CanonicalBlock, CanonicalBlockType, BUNDLE_AGE_BLOCK, HOP_COUNT_BLOCK, PAYLOAD_BLOCK,
+    PREVIOUS_NODE_BLOCK,
+};
+use bp7::flags::BlockControlFlags;
+use bp7::{Bundle, CanonicalData};
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// What to do with a bundle after an extension block was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockAction {
+    /// keep the bundle and the block
+    Keep,
+    /// drop all blocks of the handled type from the bundle
+    RemoveBlock,
+    /// delete the whole bundle for the given reason
+    DeleteBundle(StatusReportReason),
+}
+
+/// Handler for one canonical block type.
+///
+/// Blocks without a registered handler are unintelligible to dtnd and processed according
+/// to their block processing control flags.
+pub trait ExtensionBlockHandler: Send + Sync {
+    fn block_type(&self) -> CanonicalBlockType;
+    fn name(&self) -> &str;
+    /// Validates a block of a newly received bundle.
+    fn on_receive(&self, _bundle: &Bundle, _block: &CanonicalBlock) -> BlockAction {
+        BlockAction::Keep
+    }
+    /// Updates the bundle before it is forwarded, called whether the bundle contains the block or not.
+    fn on_forward(&self, _bundle: &mut Bundle) -> BlockAction {
+        BlockAction::Keep
+    }
+    /// Decodes a block for application agents.
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        match block.data() {
+            CanonicalData::Unknown(data) | CanonicalData::Data(data) => {
+                serde_json::json!(bp7::helpers::hexify(data))
+            }
+            _ => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Canonical block as shown to application agents.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockInfo {
+    pub block_type: CanonicalBlockType,
+    pub block_number: u64,
+    /// name of the handler, none for unknown blocks
+    pub name: Option<String>,
+    pub data: serde_json::Value,
+}
+
+/// Handlers of all known extension block types.
+#[derive(Clone)]
+pub struct ExtensionBlockRegistry {
+    handlers: BTreeMap<CanonicalBlockType, Arc<dyn ExtensionBlockHandler>>,
+}
+
+impl Default for ExtensionBlockRegistry {
+    fn default() -> Self {
+        let mut registry = ExtensionBlockRegistry {
+            handlers: BTreeMap::new(),
+        };
+        registry.register(Arc::new(HopCountBlock));
+        registry.register(Arc::new(PreviousNodeBlock));
+        registry.register(Arc::new(BundleAgeBlock));
+        registry.register(Arc::new(TransferBlock));
+        registry.register(Arc::new(CorrelationBlock));
+        registry
+    }
+}
+
+impl std::fmt::Debug for ExtensionBlockRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.handlers.iter().map(|(t, h)| (t, h.name())))
+            .finish()
+    }
+}
+
+impl ExtensionBlockRegistry {
+    /// Registers a handler, replacing any previous handler of the same block type.
+    pub fn register(&mut self, handler: Arc<dyn ExtensionBlockHandler>) {
+        let block_type = handler.block_type();
+        if let Some(previous) = self.handlers.insert(block_type, handler) {
+            warn!(
+                "Replaced handler {} for block type {}",
+                previous.name(),
+                block_type
+            );
+        }
+    }
+    pub fn unregister(&mut self, block_type: CanonicalBlockType) -> bool {
+        self.handlers.remove(&block_type).is_some()
+    }
+    pub fn get(&self, block_type: CanonicalBlockType) -> Option<Arc<dyn ExtensionBlockHandler>> {
+        self.handlers.get(&block_type).cloned()
+    }
+    /// All handlers ordered by block type.
+    pub fn handlers(&self) -> Vec<Arc<dyn ExtensionBlockHandler>> {
+        self.handlers.values().cloned().collect()
+    }
+    pub fn is_known(&self, block_type: CanonicalBlockType) -> bool {
+        block_type == PAYLOAD_BLOCK || self.handlers.contains_key(&block_type)
+    }
+    /// Decodes the extension blocks of a bundle, the payload block is left out.
+    pub fn describe(&self, bundle: &Bundle) -> Vec<BlockInfo> {
+        bundle
+            .canonicals
+            .iter()
+            .filter(|cb| cb.block_type != PAYLOAD_BLOCK)
+            .map(|cb| {
+                let handler = self.handlers.get(&cb.block_type);
+                BlockInfo {
+                    block_type: cb.block_type,
+                    block_number: cb.block_number,
+                    name: handler.map(|h| h.name().to_string()),
+                    data: match handler {
+                        Some(h) => h.describe(cb),
+                        None => UnknownBlock.describe(cb),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Used to show the raw data of blocks without handler.
+struct UnknownBlock;
+
+impl ExtensionBlockHandler for UnknownBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        0
+    }
+    fn name(&self) -> &str {
+        "unknown"
+    }
+}
+
+/// Hop Count Block (RFC 9171 4.4.3), deletes bundles exceeding their hop limit.
+pub struct HopCountBlock;
+
+impl ExtensionBlockHandler for HopCountBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        HOP_COUNT_BLOCK
+    }
+    fn name(&self) -> &str {
+        "hop count"
+    }
+    fn on_forward(&self, bundle: &mut Bundle) -> BlockAction {
+        let bid = bundle.id();
+        if let Some(hc) = bundle.extension_block_by_type_mut(HOP_COUNT_BLOCK) {
+            if hc.hop_count_increase() {
+                let (hc_limit, hc_count) = hc
+                    .hop_count_get()
+                    .expect("hop count data missing from hop count block");
+                debug!(
+                    "Bundle contains an hop count block: {} {} {}",
+                    &bid, hc_limit, hc_count
+                );
+                if hc.hop_count_exceeded() {
+                    warn!(
+                        "Bundle contains an exceeded hop count block: {} {} {}",
+                        &bid, hc_limit, hc_count
+                    );
+                    return BlockAction::DeleteBundle(HOP_LIMIT_EXCEEDED);
+                }
+            }
+        }
+        BlockAction::Keep
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        match block.hop_count_get() {
+            Some((limit, count)) => serde_json::json!({"limit": limit, "count": count}),
+            None => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Previous Node Block (RFC 9171 4.4.1), always added or updated with the local node ID.
+pub struct PreviousNodeBlock;
+
+impl ExtensionBlockHandler for PreviousNodeBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        PREVIOUS_NODE_BLOCK
+    }
+    fn name(&self) -> &str {
+        "previous node"
+    }
+    fn on_forward(&self, bundle: &mut Bundle) -> BlockAction {
+        let local_eid = CONFIG.lock().host_eid.clone();
+        let bid = bundle.id();
+        if let Some(pnb) = bundle.extension_block_by_type_mut(PREVIOUS_NODE_BLOCK) {
+            let prev_eid = pnb
+                .previous_node_get()
+                .expect("no previoud node EID found!")
+                .clone();
+            pnb.previous_node_update(local_eid.clone());
+            debug!(
+                "Previous Node Block was updated: {} {} {}",
+                bid, prev_eid, local_eid
+            );
+        } else {
+            // according to rfc always add a previous node block
+            let pnb =
+                bp7::canonical::new_previous_node_block(0, BlockControlFlags::empty(), local_eid);
+            bundle.add_canonical_block(pnb);
+        }
+        BlockAction::Keep
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        match block.previous_node_get() {
+            Some(eid) => serde_json::json!(eid.to_string()),
+            None => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Bundle Age Block (RFC 9171 4.4.2), deletes bundles older than their lifetime.
+pub struct BundleAgeBlock;
+
+impl ExtensionBlockHandler for BundleAgeBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        BUNDLE_AGE_BLOCK
+    }
+    fn name(&self) -> &str {
+        "bundle age"
+    }
+    fn on_forward(&self, bundle: &mut Bundle) -> BlockAction {
+        if let Some(age) = update_bundle_age(bundle) {
+            if std::time::Duration::from_micros(age) >= bundle.primary.lifetime {
+                warn!("Dropping bundle, age exceeds lifetime: {}", bundle.id());
+                return BlockAction::DeleteBundle(LIFETIME_EXPIRED);
+            }
+        }
+        BlockAction::Keep
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        match block.bundle_age_get() {
+            Some(age) => serde_json::json!(age as u64),
+            None => serde_json::Value::Null,
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use crate::core::application_agent::{local_bundles, ApplicationAgent};
+use crate::core::rpc;
+use crate::core::stats::FailureAction;
+use crate::dtnd::ws::BundleDelivery;
+use crate::{store_get_bundle, store_mark_delivered, CONFIG, STATS};
+use anyhow::{anyhow, bail, Context, Result};
+use bp7::flags::{BlockControlFlags, BundleControlFlags};
+use bp7::{Bundle, CreationTimestamp, EndpointID};
+use hyper::{Body, Client, Method, Request};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Sender, UnboundedSender, WeakUnboundedSender};
+
+/// Time a webhook has to answer a delivery.
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an exec command may run unless configured otherwise.
+pub const EXEC_TIMEOUT_DEFAULT: Duration = Duration::from_secs(60);
+
+/// What a `HandlerApplicationAgent` does with every bundle delivered to its endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentHandler {
+    /// Runs `command <source> <payload file>` like `dtntrigger`, the command is killed after `timeout`.
+    Exec {
+        command: Vec<String>,
+        timeout: Duration,
+    },
+    /// Writes the payload into a spool directory.
+    FileDrop { dir: PathBuf },
+    /// Sends the payload back to the source of the bundle like `dtnecho2`.
+    Echo,
+    /// Posts the payload to an HTTP webhook.
+    Webhook { url: String },
+}
+
+/// Retry settings of a `HandlerApplicationAgent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// additional attempts after the first failure
+    pub retries: u32,
+    /// time between attempts
+    pub delay: Duration,
+    /// what happens once all attempts failed
+    pub failure_action: FailureAction,
+    /// time until deferred bundles are attempted again
+    pub defer_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            delay: Duration::from_secs(5),
+            failure_action: FailureAction::Defer,
+            defer_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Bundle ID and number of the attempt to handle it.
+type Job = (String, u32);
+
+/// Application agent that handles delivered bundles inside dtnd instead of handing them
+/// to a client. Bundles are processed one after another by a background task and stay in
+/// the store with the `LocalEndpoint` constraint until they were handled successfully.
+/// Failed bundles are queued again after the retry delay, so they do not hold up the others.
+#[derive(Debug, Clone)]
+pub struct HandlerApplicationAgent {
+    eid: EndpointID,
+    handler: AgentHandler,
+    retry: RetryPolicy,
+    queue: UnboundedSender<Job>,
+}
+
+impl ApplicationAgent for HandlerApplicationAgent {
+    fn eid(&self) -> &EndpointID {
+        &self.eid
+    }
+    fn push(&mut self, bundle: &Bundle) {
+        debug!("Received {:?} for {:?} handler", bundle.id(), self.handler);
+        self.queue.send((bundle.id(), 0)).ok();
+    }
+    fn pop(&mut self) -> Option<Bundle> {
+        // bundles are consumed by the handler
+        None
+    }
+    fn restore(&mut self) {
+        for bid in local_bundles(&self.eid) {
+            self.queue.send((bid, 0)).ok();
+        }
+    }
+    fn set_delivery_addr(&mut self, _addr: Sender<BundleDelivery>) {
+        warn!(
+            "{} is served by a {:?} handler, clients can not subscribe to it",
+            self.eid, self.handler
+        );
+    }
+    fn clear_delivery_addr(&mut self) {}
+    fn delivery_addr(&self) -> Option<Sender<BundleDelivery>> {
+        None
+    }
+}
+
+impl HandlerApplicationAgent {
+    /// Creates the agent and spawns the task handling its bundles, must be called within a tokio runtime.
+    pub fn new(eid: EndpointID, handler: AgentHandler, retry: RetryPolicy) -> Self {
+        let (queue, mut rx) = mpsc::unbounded_channel::<Job>();
+        // the worker only holds a weak sender so it ends once the agent is unregistered
+        let worker = (eid.clone(), handler.clone(), queue.downgrade());
+        tokio::spawn(async move {
+            let (eid, handler, queue) = worker;
+            while let Some((bid, attempt)) = rx.recv().await {
+                handle_attempt(&eid, &handler, &retry, &queue, bid, attempt).await;
+            }
+        });
+        HandlerApplicationAgent {
+            eid,
+            handler,
+            retry,
+            queue,
+        }
+    }
+
+    /// Creates an agent from the settings of an `[endpoints.agent.<name>]` config section.
+    ///
+    /// ```
+    /// use dtn7::core::handler_agent::{AgentHandler, HandlerApplicationAgent};
+    /// use dtn7::core::stats::FailureAction;
+    /// use std::collections::HashMap;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let settings: HashMap<String, String> = [
+    ///     ("type", "webhook"),
+    ///     ("endpoint", "dtn://node1/sensors"),
+    ///     ("url", "http://127.0.0.1:8080/sensors"),
+    ///     ("failure_action", "abandon"),
+    /// ]
+    /// .iter()
+    /// .map(|(k, v)| (k.to_string(), v.to_string()))
+    /// .collect();
+    /// let aa = HandlerApplicationAgent::from_settings(&settings).unwrap();
+    /// assert_eq!(aa.failure_action(), FailureAction::Abandon);
+    /// assert!(matches!(aa.handler(), AgentHandler::Webhook { .. }));
+    /// # }
+    /// ```
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self> {
+        let endpoint = settings.get("endpoint").context("missing endpoint")?;
+        let eid = match EndpointID::try_from(endpoint.as_str()) {
+            Ok(eid) => eid,
+            Err(_) => CONFIG
+                .lock()
+                .host_eid
+                .new_endpoint(endpoint)
+                .map_err(|err| anyhow!("invalid endpoint {}: {}", endpoint, err))?,
+        };
+        let setting = |key: &str| {
+            settings
+                .get(key)
+                .cloned()
+                .with_context(|| format!("missing {}", key))
+        };
+        let handler = match settings.get("type").map(|t| t.as_str()) {
+            Some("exec") => AgentHandler::Exec {
+                command: shlex::split(&setting("command")?)
+                    .filter(|args| !args.is_empty())
+                    .context("invalid command")?,
+                timeout: match settings.get("timeout") {
+                    Some(timeout) => {
+                        humantime::parse_duration(timeout).context("invalid timeout")?
+                    }
+                    None => EXEC_TIMEOUT_DEFAULT,
+                },
+            },
+            Some("file-drop") => AgentHandler::FileDrop {
+                dir: setting("dir")?.into(),
+            },
+            Some("echo") => AgentHandler::Echo,
+            Some("webhook") => AgentHandler::Webhook {
+                url: setting("url")?,
+            },
+            Some(other) => bail!("unknown agent type {}", other),
+            None => bail!("missing type"),
+        };
+
+        let mut retry = RetryPolicy::default();
+        if let Some(retries) = settings.get("retries") {
+            retry.retries = retries.parse().context("invalid retries")?;
+        }
+        if let Some(delay) = settings.get("retry_delay") {
+            retry.delay = humantime::parse_duration(delay).context("invalid retry_delay")?;
+        }
+        if let Some(delay) = settings.get("defer_delay") {
+            retry.defer_delay = humantime::parse_duration(delay).context("invalid defer_delay")?;
+        }
+        if let Some(action) = settings.get("failure_action") {
+            retry.failure_action = action.parse()?;
+        }
+        Ok(HandlerApplicationAgent::new(eid, handler, retry))
+    }
+
+    pub fn handler(&self) -> &AgentHandler {
+        &self.handler
+    }
+
+    pub fn failure_action(&self) -> FailureAction {
+        self.retry.failure_action
+    }
+}
+
+/// Makes one attempt to handle a bundle, failed bundles are queued again after the retry delay
+/// until the failure action applies.
+async fn handle_attempt(
+    eid: &EndpointID,
+    handler: &AgentHandler,
+    retry: &RetryPolicy,
+    queue: &WeakUnboundedSender<Job>,
+    bid: String,
+    attempt: u32,
+) {
+    // bundles might have expired or been deleted in the meantime
+    let Some(bundle) = store_get_bundle(&bid) else {
+        debug!("Bundle {} for {} no longer in store", bid, eid);
+        return;
+    };
+    match handle(eid, handler, &bundle).await {
+        Ok(()) => {
+            debug!("Handled {} for {}", bid, eid);
+            store_mark_delivered(&bid);
+            return;
+        }
+        Err(err) => warn!(
+            "Handling {} for {} failed (attempt {}/{}): {}",
+            bid,
+            eid,
+            attempt + 1,
+            retry.retries + 1,
+            err
+        ),
+    }
+    if attempt < retry.retries {
+        requeue(queue, (bid, attempt + 1), retry.delay);
+        return;
+    }
+    match retry.failure_action {
+        FailureAction::Abandon => {
+            info!("Abandoning delivery of {} to {}", bid, eid);
+            store_mark_delivered(&bid);
+            STATS.lock().node.error_info.abandoned_delivery_bundle_count += 1;
+        }
+        FailureAction::Defer => {
+            info!(
+                "Deferring delivery of {} to {} for {:?}",
+                bid, eid, retry.defer_delay
+            );
+            requeue(queue, (bid, 0), retry.defer_delay);
+        }
+    }
+}
+
+fn requeue(queue: &WeakUnboundedSender<Job>, job: Job, delay: Duration) {
+    let queue = queue.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if let Some(queue) = queue.upgrade() {
+            queue.send(job).ok();
+        }
+    });
+}
+
+async fn handle(eid: &EndpointID, handler: &AgentHandler, bundle: &Bundle) -> Result<()> {
+    let payload = bundle.payload().map(|p| p.to_vec()).unwrap_or_default();
+    match handler {
+        AgentHandler::Exec { command, timeout } => exec(command, *timeout, bundle, payload).await,
+        AgentHandler::FileDrop { dir } => file_drop(dir, &bundle.id(), &payload),
+        AgentHandler::Echo => {
+            echo(eid, bundle, payload).await;
+            Ok(())
+        }
+        AgentHandler::Webhook { url } => webhook(url, bundle, payload).await,
+    }
+}
+
+async fn exec(
+    command: &[String],
+    timeout: Duration,
+    bundle: &Bundle,
+    payload: Vec<u8>,
+) -> Result<()> {
+    let (program, args) = command.split_first().context("empty command")?;
+    let data_file = tokio::task::spawn_blocking(move || -> Result<_> {
+        let mut data_file = tempfile::NamedTempFile::new()?;
+        data_file.write_all(&payload)?;
+        data_file.flush()?;
+        Ok(data_file)
+    })
+    .await??;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("DTN_BUNDLE_ID", bundle.id())
+        .env("DTN_DESTINATION", bundle.primary.destination.to_string())
+        .arg(bundle.primary.source.to_string())
+        .arg(data_file.path())
+        .kill_on_drop(true)
+        .spawn()?;
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            child.kill().await.ok();
+            bail!(
+                "command killed after {}",
+                humantime::format_duration(timeout)
+            );
+        }
+    };
+    if !status.success() {
+        bail!("command exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Writes the payload to `<dir>/<bundle id>`, the file only appears once it is complete.
+fn file_drop(dir: &Path, bid: &str, payload: &[u8]) -> Result<()> {
+    let name: String = bid
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    std::fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(payload)?;
+    file.persist(dir.join(name))?;
+    Ok(())
+}
+
+async fn echo(eid: &EndpointID, bundle: &Bundle, payload: Vec<u8>) {
+    let dst = bundle.primary.source.clone();
+    if dst == EndpointID::none() || bundle.is_administrative_record() {
+        debug!("Not echoing {} from {}", bundle.id(), dst);
+        return;
+    }
+    if rpc::Correlation::from_bundle(bundle).is_some_and(|c| c.kind == rpc::MessageKind::Request) {
+        // requests are answered with a correlated response to their reply-to endpoint
+        match rpc::reply(&bundle.id(), payload).await {
+            Ok(bid) => debug!("Echoing request {} as {}", bundle.id(), bid),
+            Err(err) => warn!("Echoing request {} failed: {}", bundle.id(), err),
+        }
+        return;
+    }
+    let pblock = bp7::primary::PrimaryBlockBuilder::default()
+        .bundle_control_flags(BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED.bits())
+        .destination(dst)
+        .source(eid.clone())
+        .report_to(eid.clone())
+        .creation_timestamp(CreationTimestamp::now())
+        .lifetime(Duration::from_secs(3600 * 24))
+        .build()
+        .unwrap();
+    let mut reply = Bundle::new(
+        pblock,
+        vec![bp7::canonical::new_payload_block(
+            BlockControlFlags::empty(),
+            payload,
+        )],
+    );
+    reply.set_crc(bp7::crc::CRC_NO);
+    debug!("Echoing {} as {}", bundle.id(), reply.id());
+    crate::core::processing::send_bundle(reply).await;
+    STATS.lock().node.bundles.bundles_created += 1;
+}
+
+async fn webhook(url: &str, bundle: &Bundle, payload: Vec<u8>) -> Result<()> {
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header("Content-Type", "application/octet-stream")
+        .header("X-Dtn-Bundle-Id", bundle.id())
+        .header("X-Dtn-Source", bundle.primary.source.to_string())
+        .header("X-Dtn-Destination", bundle.primary.destination.to_string())
+        .body(Body::from(payload))?;
+    let response = tokio::time::timeout(WEBHOOK_TIMEOUT, Client::new().request(req))
+        .await
+        .context("webhook timed out")??;
+    if !response.status().is_success() {
+        bail!("webhook answered {}", response.status());
+    }
+    Ok(())
+}
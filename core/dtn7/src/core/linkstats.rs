@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of finished contacts kept per peer.
+pub const MAX_CONTACT_HISTORY: usize = 32;
+/// Maximum number of transfer records kept per peer.
+pub const MAX_TRANSFER_HISTORY: usize = 64;
+/// A link is unreliable after more consecutive failed transfers than this.
+pub const MAX_CONSECUTIVE_FAILURES: usize = 3;
+/// Number of most recent transfers the success ratio of an unreliable link is computed over.
+pub const RECENT_TRANSFERS: usize = 16;
+/// Minimum number of recent transfers before a link is judged by its success ratio.
+pub const MIN_RECENT_TRANSFERS: usize = 8;
+/// A link with a lower success ratio over its recent transfers is unreliable.
+pub const MIN_SUCCESS_RATIO: f64 = 0.5;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_millis() as u64
+}
+
+/// A single contact with a peer, timestamps are unix time in seconds.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Contact {
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Outcome of a single bundle transmission towards a peer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TransferRecord {
+    /// time of the transfer in unix time as milliseconds
+    pub timestamp: u64,
+    pub cla: String,
+    pub bytes: u64,
+    /// time it took the CLA to finish the transfer in milliseconds
+    pub duration: u64,
+    pub success: bool,
+}
+
+/// Traffic counters of a peer for a single convergence layer.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ClaLinkStats {
+    pub bundles_sent: u64,
+    pub bytes_sent: u64,
+    pub bundles_received: u64,
+    pub bytes_received: u64,
+    pub transfers_failed: u64,
+    /// accumulated time of successful transfers in milliseconds
+    pub transfer_time: u64,
+}
+
+impl ClaLinkStats {
+    /// Average throughput of successful transfers in bytes per second.
+    pub fn throughput(&self) -> Option<u64> {
+        if self.transfer_time == 0 {
+            return None;
+        }
+        Some(self.bytes_sent * 1000 / self.transfer_time)
+    }
+}
+
+/// Link quality and contact history of a peer.
+///
+/// # Example
+///
+/// ```
+/// use dtn7::core::linkstats::LinkStats;
+/// use std::time::Duration;
+///
+/// let mut stats = LinkStats::default();
+/// stats.contact_started();
+/// stats.record_transfer("mtcp", 1000, Duration::from_millis(100), true);
+/// stats.record_transfer("mtcp", 1000, Duration::from_millis(100), false);
+/// stats.record_receive("mtcp", 500);
+///
+/// assert!(stats.in_contact());
+/// assert_eq!(stats.success_ratio(), Some(0.5));
+/// assert_eq!(stats.clas["mtcp"].bytes_received, 500);
+/// assert_eq!(stats.clas["mtcp"].throughput(), Some(10000));
+///
+/// stats.contact_ended();
+/// assert!(!stats.in_contact());
+/// assert_eq!(stats.contacts.len(), 1);
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// start of the ongoing contact in unix time as seconds
+    pub contact_start: Option<u64>,
+    /// finished contacts, oldest first
+    pub contacts: VecDeque<Contact>,
+    /// most recent transfers, oldest first
+    pub transfers: VecDeque<TransferRecord>,
+    /// counters per convergence layer
+    pub clas: BTreeMap<String, ClaLinkStats>,
+}
+
+impl LinkStats {
+    pub fn in_contact(&self) -> bool {
+        self.contact_start.is_some()
+    }
+
+    /// Marks the beginning of a contact, does nothing if a contact is already ongoing.
+    pub fn contact_started(&mut self) {
+        if self.contact_start.is_none() {
+            self.contact_start = Some(now_secs());
+        }
+    }
+
+    /// Closes the ongoing contact and adds it to the contact history.
+    pub fn contact_ended(&mut self) {
+        if let Some(start) = self.contact_start.take() {
+            if self.contacts.len() >= MAX_CONTACT_HISTORY {
+                self.contacts.pop_front();
+            }
+            self.contacts.push_back(Contact {
+                start,
+                end: now_secs(),
+            });
+        }
+    }
+
+    /// Duration of the ongoing contact in seconds.
+    pub fn current_contact_duration(&self) -> Option<u64> {
+        self.contact_start
+            .map(|start| now_secs().saturating_sub(start))
+    }
+
+    /// Average duration of the finished contacts in seconds.
+    pub fn avg_contact_duration(&self) -> Option<u64> {
+        if self.contacts.is_empty() {
+            return None;
+        }
+        let total: u64 = self.contacts.iter().map(|c| c.duration()).sum();
+        Some(total / self.contacts.len() as u64)
+    }
+
+    pub fn record_transfer(&mut self, cla: &str, bytes: u64, duration: Duration, success: bool) {
+        let cla_stats = self.clas.entry(cla.to_string()).or_default();
+        if success {
+            cla_stats.bundles_sent += 1;
+            cla_stats.bytes_sent += bytes;
+            cla_stats.transfer_time += duration.as_millis() as u64;
+        } else {
+            cla_stats.transfers_failed += 1;
+        }
+        if self.transfers.len() >= MAX_TRANSFER_HISTORY {
+            self.transfers.pop_front();
+        }
+        self.transfers.push_back(TransferRecord {
+            timestamp: now_millis(),
+            cla: cla.to_string(),
+            bytes,
+            duration: duration.as_millis() as u64,
+            success,
+        });
+    }
+
+    pub fn record_receive(&mut self, cla: &str, bytes: u64) {
+        let cla_stats = self.clas.entry(cla.to_string()).or_default();
+        cla_stats.bundles_received += 1;
+        cla_stats.bytes_received += bytes;
+    }
+
+    /// Ratio of successful transfers within the recorded transfer history.
+    pub fn success_ratio(&self) -> Option<f64> {
+        if self.transfers.is_empty() {
+            return None;
+        }
+        let successful = self.transfers.iter().filter(|t| t.success).count();
+        Some(successful as f64 / self.transfers.len() as f64)
+    }
+
+    /// Number of failed transfers since the last successful one.
+    pub fn consecutive_failures(&self) -> usize {
+        self.transfers
+            .iter()
+            .rev()
+            .take_while(|t| !t.success)
+            .count()
+    }
+
+    /// Ratio of successful transfers among the `RECENT_TRANSFERS` most recent ones.
+    pub fn recent_success_ratio(&self) -> Option<f64> {
+        let recent = self.transfers.len().min(RECENT_TRANSFERS);
+        if recent == 0 {
+            return None;
+        }
+        let successful = self
+            .transfers
+            .iter()
+            .rev()
+            .take(recent)
+            .filter(|t| t.success)
+            .count();
+        Some(successful as f64 / recent as f64)
+    }
+
+    /// Returns true if transfers over this link keep failing.
+    ///
+    /// This is the case after more than `MAX_CONSECUTIVE_FAILURES` failed transfers in a row,
+    /// or if the last transfer failed and less than `MIN_SUCCESS_RATIO` of at least
+    /// `MIN_RECENT_TRANSFERS` recent transfers succeeded.
+    pub fn unreliable(&self) -> bool {
+        let failures = self.consecutive_failures();
+        if failures > MAX_CONSECUTIVE_FAILURES {
+            return true;
+        }
+        failures > 0
+            && self.transfers.len() >= MIN_RECENT_TRANSFERS
+            && self
+                .recent_success_ratio()
+                .is_some_and(|ratio| ratio < MIN_SUCCESS_RATIO)
+    }
+
+    /// Average throughput over all convergence layers in bytes per second.
+    pub fn throughput(&self) -> Option<u64> {
+        let bytes: u64 = self.clas.values().map(|c| c.bytes_sent).sum();
+        let time: u64 = self.clas.values().map(|c| c.transfer_time).sum();
+        if time == 0 {
+            return None;
+        }
+        Some(bytes * 1000 / time)
+    }
+}
@@ -1,10 +1,20 @@
 pub mod application_agent;
 pub mod bundlepack;
+pub mod encoding;
+pub mod events;
+pub mod extension_blocks;
+pub mod handler_agent;
 pub mod helpers;
+pub mod linkstats;
 pub mod peer;
 pub mod processing;
+pub mod reconciliation;
+pub mod rpc;
 pub mod stats;
+pub mod status_reports;
 pub mod store;
+pub mod topics;
+pub mod transfer;
 
 use crate::cla::ConvergenceLayerAgent;
 use crate::core::bundlepack::Constraint;
@@ -13,19 +23,22 @@ use crate::core::stats::{NodeStats, RegistrationInformation};
 use crate::core::store::BundleStore;
 use crate::routing::RoutingAgentsEnum;
 use crate::{
-    routing_notify, store_delete_expired, store_get_bundle, store_get_metadata, CLAS, DTNCORE,
+    peers_retire, routing_notify, store_delete_expired, store_get_bundle, store_get_metadata, CLAS,
+    DTNCORE,
 };
 pub use crate::{store_has_item, store_push_bundle};
 use crate::{RoutingNotifcation, CONFIG};
 use crate::{PEERS, STORE};
 use application_agent::ApplicationAgent;
 use bp7::EndpointID;
-use log::{error, warn, info, trace};
+use log::{error, info, trace, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use crate::core::application_agent::ApplicationAgentEnum;
+use crate::core::application_agent::{ApplicationAgentEnum, GroupApplicationAgent, GroupState};
+use anyhow::{Context, Result};
 
 use self::bundlepack::BundlePack;
 use self::processing::forward;
@@ -64,11 +77,18 @@ impl DtnStatistics {
                 (*DTNCORE.lock()).get_endpoint(&EndpointID::try_from(eid.clone()).unwrap())
             {
                 let singleton = !aa.eid().is_non_singleton();
+                // handler agents serve their endpoint without a connected client
+                let (active, default_failure_action) = match aa {
+                    ApplicationAgentEnum::HandlerApplicationAgent(handler) => {
+                        (true, handler.failure_action())
+                    }
+                    _ => (aa.delivery_addr().is_some(), stats::FailureAction::Defer),
+                };
                 let registration = RegistrationInformation {
                     eid: eid.clone(),
-                    active: aa.delivery_addr().is_some(),
-                    singleton: singleton,
-                    default_failure_action: stats::FailureAction::Defer,
+                    active,
+                    singleton,
+                    default_failure_action,
                 };
                 self.node.registrations.push(registration);
             }
@@ -87,6 +107,10 @@ pub struct DtnCore {
     pub endpoints: Vec<ApplicationAgentEnum>,
     pub service_list: HashMap<u8, String>,
     pub routing_agent: RoutingAgentsEnum,
+    /// File the endpoint registrations are persisted to, if set.
+    registrations_file: Option<PathBuf>,
+    /// File the delivery state of group endpoints is persisted to, if set.
+    groups_file: Option<PathBuf>,
 }
 
 impl Default for DtnCore {
@@ -102,15 +126,20 @@ impl DtnCore {
             service_list: HashMap::new(),
             //routing_agent: crate::routing::flooding::FloodingRoutingAgent::new().into(),
             routing_agent: crate::routing::epidemic::EpidemicRoutingAgent::new().into(),
+            registrations_file: None,
+            groups_file: None,
         }
     }
 
-    pub fn register_application_agent(&mut self, aa: ApplicationAgentEnum) {
+    pub fn register_application_agent(&mut self, mut aa: ApplicationAgentEnum) {
         if self.is_in_endpoints(aa.eid()) {
             info!("Application agent already registered for EID: {}", aa.eid());
         } else {
             info!("Registered new application agent for EID: {}", aa.eid());
+            aa.restore();
             self.endpoints.push(aa);
+            self.save_registrations();
+            self.save_groups();
         }
     }
     pub fn unregister_application_agent(&mut self, aa: ApplicationAgentEnum) {
@@ -119,6 +148,79 @@ impl DtnCore {
             .iter()
             .position(|n| n.eid() == aa.eid())
             .map(|e| self.endpoints.remove(e));
+        self.save_registrations();
+        self.save_groups();
+    }
+    /// Registers the endpoints persisted in `path` and keeps the file up to date on
+    /// later (un)registrations, so registrations survive a restart of dtnd.
+    /// The delivery state of group endpoints is kept in `groups.json` next to it.
+    pub fn load_registrations(&mut self, path: PathBuf) -> Result<()> {
+        if path.exists() {
+            let buf = std::fs::read(&path)
+                .with_context(|| format!("reading registrations {}", path.display()))?;
+            let eids: Vec<String> = serde_json::from_slice(&buf)
+                .with_context(|| format!("parsing registrations {}", path.display()))?;
+            for eid in eids {
+                match EndpointID::try_from(eid.clone()) {
+                    Ok(eid) => self.register_application_agent(application_agent::new(eid)),
+                    Err(err) => warn!("Skipping invalid registration {}: {}", eid, err),
+                }
+            }
+        }
+        let groups = path.with_file_name("groups.json");
+        if groups.exists() {
+            let buf = std::fs::read(&groups)
+                .with_context(|| format!("reading group state {}", groups.display()))?;
+            let mut states: BTreeMap<String, GroupState> = serde_json::from_slice(&buf)
+                .with_context(|| format!("parsing group state {}", groups.display()))?;
+            for aa in self.endpoints.iter_mut() {
+                if let ApplicationAgentEnum::GroupApplicationAgent(group) = aa {
+                    if let Some(state) = states.remove(&group.eid().to_string()) {
+                        group.load_state(state);
+                    }
+                }
+            }
+        }
+        self.registrations_file = Some(path);
+        self.groups_file = Some(groups);
+        self.save_registrations();
+        self.save_groups();
+        Ok(())
+    }
+    fn save_registrations(&self) {
+        let Some(path) = &self.registrations_file else {
+            return;
+        };
+        // handler agents are set up from the configuration on every start
+        let eids: Vec<String> = self
+            .endpoints
+            .iter()
+            .filter(|e| !matches!(e, ApplicationAgentEnum::HandlerApplicationAgent(_)))
+            .map(|e| e.eid().to_string())
+            .collect();
+        if let Err(err) = save_json(path, &eids) {
+            error!("Error saving registrations to {}: {}", path.display(), err);
+        }
+    }
+    /// Saves the delivery state of all group endpoints, done on every (un)registration
+    /// and by the janitor.
+    pub fn save_groups(&self) {
+        if let Some(path) = &self.groups_file {
+            if let Err(err) = save_json(path, &self.group_states()) {
+                error!("Error saving group state to {}: {}", path.display(), err);
+            }
+        }
+    }
+    fn group_states(&self) -> BTreeMap<String, GroupState> {
+        self.endpoints
+            .iter()
+            .filter_map(|aa| match aa {
+                ApplicationAgentEnum::GroupApplicationAgent(group) => {
+                    Some((group.eid().to_string(), group.state()))
+                }
+                _ => None,
+            })
+            .collect()
     }
     pub fn eids(&self) -> Vec<String> {
         self.endpoints.iter().map(|e| e.eid().to_string()).collect()
@@ -146,11 +248,44 @@ impl DtnCore {
     pub fn get_endpoint(&self, eid: &EndpointID) -> Option<&ApplicationAgentEnum> {
         self.endpoints.iter().find(|&aa| eid == aa.eid())
     }
+    pub fn get_group_mut(&mut self, eid: &EndpointID) -> Option<&mut GroupApplicationAgent> {
+        match self.get_endpoint_mut(eid) {
+            Some(ApplicationAgentEnum::GroupApplicationAgent(group)) => Some(group),
+            _ => None,
+        }
+    }
+}
+
+/// Writes `value` as JSON to a temporary file first and moves it to `path` afterwards.
+fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Expires group memberships, forgets group bundles no longer in the store, retries pending
+/// deliveries and saves the state of all groups.
+pub fn process_groups() {
+    let (path, states) = {
+        let mut core = DTNCORE.lock();
+        for aa in core.endpoints.iter_mut() {
+            if let ApplicationAgentEnum::GroupApplicationAgent(group) = aa {
+                group.maintain();
+            }
+        }
+        (core.groups_file.clone(), core.group_states())
+    };
+    if let Some(path) = path {
+        if let Err(err) = save_json(&path, &states) {
+            error!("Error saving group state to {}: {}", path.display(), err);
+        }
+    }
 }
 
 /// Removes peers from global peer list that haven't been seen in a while.
 pub async fn process_peers() {
-    let mut dropped: Vec<EndpointID> = Vec::new();
+    let mut dropped: Vec<DtnPeer> = Vec::new();
 
     (*PEERS.lock()).retain(|_k, v| {
         let val = v.still_valid();
@@ -160,12 +295,14 @@ pub async fn process_peers() {
                 v.eid, v.addr
             );
 
-            dropped.push(v.eid.clone());
+            dropped.push(v.clone());
         }
         v.con_type == PeerType::Static || val
     });
 
-    for eid in dropped {
+    for peer in dropped {
+        let eid = peer.eid.clone();
+        peers_retire(peer);
         if let Err(err) = routing_notify(RoutingNotifcation::DroppedPeer(eid)).await {
             error!("Error while dropping peer: {}", err);
         }
@@ -194,7 +331,7 @@ pub async fn process_bundles() {
         .collect();
 
     // process them in chronological order
-    forwarding_bundles.sort_unstable_by(|a, b| a.creation_time.cmp(&b.creation_time));
+    forwarding_bundles.sort_unstable_by_key(|bp| bp.creation_time);
 
     let num_bundles = forwarding_bundles.len();
 
@@ -1,4 +1,5 @@
 use crate::cla::{ClaSenderTask, ConvergenceLayerAgent};
+use crate::core::linkstats::LinkStats;
 use crate::{CLAS, CONFIG};
 use bp7::EndpointID;
 use serde::{Deserialize, Serialize};
@@ -65,6 +66,8 @@ pub struct DtnPeer {
     pub services: HashMap<u8, String>,
     pub last_contact: u64,
     pub fails: u16,
+    #[serde(default)]
+    pub link_stats: LinkStats,
 }
 
 impl DtnPeer {
@@ -88,6 +91,7 @@ impl DtnPeer {
                 .unwrap()
                 .as_secs(),
             fails: 0,
+            link_stats: LinkStats::default(),
         }
     }
     /// Example
@@ -108,6 +112,7 @@ impl DtnPeer {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
+        self.link_stats.contact_started();
     }
     /// Example
     ///
@@ -133,19 +138,16 @@ impl DtnPeer {
         // that didn't advertise a BeaconPeriod
         let timeout = CONFIG.lock().peer_timeout.as_secs();
         let custom = CONFIG.lock().custom_timeout;
-        if (custom && timeout > 0) || self.period.is_none() {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("time went backwards")
-                .as_secs();
-            now - self.last_contact < timeout
-        // Else if a received beacon contains a BeaconPeriod remove this peer after 2 * received BeaconPeriod
-        } else {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs();
-            now - self.last_contact < self.period.unwrap().as_secs() * 2
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_secs();
+        match self.period {
+            // If a received beacon contains a BeaconPeriod remove this peer after 2 * received BeaconPeriod
+            Some(period) if !(custom && timeout > 0) => {
+                now - self.last_contact < period.as_secs() * 2
+            }
+            _ => now - self.last_contact < timeout,
         }
     }
 
@@ -183,11 +185,34 @@ impl DtnPeer {
         self.fails += 1;
     }
 
+    /// Records the outcome of a bundle transmission to this peer.
+    ///
+    /// A successful transfer resets the failure counter and marks the peer as in contact,
+    /// a failed one ends the ongoing contact.
+    pub fn report_transfer(&mut self, cla: &str, bytes: u64, duration: Duration, success: bool) {
+        self.link_stats
+            .record_transfer(cla, bytes, duration, success);
+        if success {
+            self.reset_fails();
+            self.link_stats.contact_started();
+        } else {
+            self.report_fail();
+            self.link_stats.contact_ended();
+        }
+    }
+
+    /// Records a bundle received from this peer.
+    pub fn report_receive(&mut self, cla: &str, bytes: u64) {
+        self.link_stats.record_receive(cla, bytes);
+        self.link_stats.contact_started();
+    }
+
     pub fn reset_fails(&mut self) {
         self.fails = 0;
     }
 
+    /// Returns true if the link statistics show that transfers to this peer keep failing.
     pub fn failed_too_much(&self) -> bool {
-        self.fails > 3
+        self.link_stats.unreliable()
     }
 }
@@ -1,10 +1,16 @@
+use crate::cla::file;
 use crate::core::bundlepack::*;
+use crate::core::events::{self, Event};
+use crate::core::extension_blocks::BlockAction;
+use crate::core::status_reports::{reason_str, BundleStatus, StatusReportEntry};
 use crate::core::*;
+use crate::peers_retire;
 use crate::routing::RoutingNotifcation;
 use crate::store_push_bundle;
 use crate::store_remove;
 use crate::CONFIG;
 use crate::DTNCORE;
+use crate::STATUS_REPORTS;
 use crate::{is_local_node_id, STATS};
 use crate::{routing_notify, routing_sender_for_bundle, store_add_bundle_if_unknown};
 
@@ -88,6 +94,11 @@ pub async fn transmit(mut bp: BundlePack) -> Result<()> {
 
         delete(bp, NO_INFORMATION).await?;
     } else {
+        events::emit(Event::BundleCreated {
+            bid: bp.id().to_string(),
+            source: bp.source.to_string(),
+            destination: bp.destination.to_string(),
+        });
         dispatch(bp).await?;
     }
     Ok(())
@@ -98,6 +109,12 @@ pub async fn receive(mut bndl: Bundle) -> Result<()> {
     if store_add_bundle_if_unknown(&bndl)? {
         info!("Received new bundle: {}", bndl.id());
         STATS.lock().incoming += 1;
+        events::emit(Event::BundleReceived {
+            bid: bndl.id(),
+            source: bndl.primary.source.to_string(),
+            destination: bndl.primary.destination.to_string(),
+            previous_node: bndl.previous_node().map(|eid| eid.to_string()),
+        });
     } else {
         debug!(
             "Received an already known bundle, skip processing: {}",
@@ -123,11 +140,34 @@ pub async fn receive(mut bndl: Bundle) -> Result<()> {
     {
         send_status_report(&bp, RECEIVED_BUNDLE, NO_INFORMATION).await;
     }
-    let mut remove_idx = Vec::new();
-    let mut index = 0;
+    let extensions = (*crate::EXTENSION_BLOCKS.lock()).clone();
+    let mut remove = Vec::new();
     for cb in bndl.canonicals.iter() {
-        if cb.block_type < 11 {
-            // TODO: fix magic number to check for a known block type
+        if let Some(handler) = extensions.get(cb.block_type) {
+            match handler.on_receive(&bndl, cb) {
+                BlockAction::Keep => {}
+                BlockAction::RemoveBlock => {
+                    info!(
+                        "Removing {} block from bundle: {} {}",
+                        handler.name(),
+                        bp.id(),
+                        cb.block_number
+                    );
+                    remove.push(cb.block_number);
+                }
+                BlockAction::DeleteBundle(reason) => {
+                    info!(
+                        "Bundle's {} block requested bundle deletion: {}",
+                        handler.name(),
+                        bp.id()
+                    );
+                    delete(bp, reason).await?;
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+        if extensions.is_known(cb.block_type) {
             continue;
         }
         warn!(
@@ -164,14 +204,12 @@ pub async fn receive(mut bndl: Bundle) -> Result<()> {
                 cb.block_number,
                 cb.block_type
             );
-            remove_idx.push(index);
+            remove.push(cb.block_number);
         }
-        index += 1;
-    }
-    for i in remove_idx {
-        // Remove canoncial blocks marked for deletion
-        bndl.canonicals.remove(i);
     }
+    // Remove canoncial blocks marked for deletion
+    bndl.canonicals
+        .retain(|cb| !remove.contains(&cb.block_number));
     if let Err(err) = store_push_bundle(&bndl) {
         bail!("error adding received bundle: {} {}", bndl.id(), err);
     }
@@ -206,29 +244,6 @@ pub async fn dispatch(bp: BundlePack) -> Result<()> {
     Ok(())
 }
 
-async fn handle_hop_count_block(mut bundle: Bundle) -> Result<Bundle> {
-    let bid = bundle.id();
-    if let Some(hc) = bundle.extension_block_by_type_mut(bp7::canonical::HOP_COUNT_BLOCK) {
-        if hc.hop_count_increase() {
-            let (hc_limit, hc_count) = hc
-                .hop_count_get()
-                .expect("hop count data missing from hop count block");
-            debug!(
-                "Bundle contains an hop count block: {} {} {}",
-                &bid, hc_limit, hc_count
-            );
-            if hc.hop_count_exceeded() {
-                warn!(
-                    "Bundle contains an exceeded hop count block: {} {} {}",
-                    &bid, hc_limit, hc_count
-                );
-                delete(bundle.into(), HOP_LIMIT_EXCEEDED).await?;
-                bail!("hop count exceeded");
-            }
-        }
-    }
-    Ok(bundle)
-}
 async fn handle_primary_lifetime(bundle: &Bundle) -> Result<()> {
     if bundle.primary.is_lifetime_exceeded() {
         warn!(
@@ -264,39 +279,24 @@ pub fn update_bundle_age(bundle: &mut Bundle) -> Option<u64> {
     }
     None
 }
-async fn handle_bundle_age_block(mut bundle: Bundle) -> Result<Bundle> {
-    if let Some(age) = update_bundle_age(&mut bundle) {
-        if std::time::Duration::from_micros(age) >= bundle.primary.lifetime {
-            warn!("Dropping bundle, age exceeds lifetime: {}", bundle.id());
-            delete(bundle.into(), LIFETIME_EXPIRED).await?;
-            bail!("age block lifetime exceeded");
+/// Lets the extension block handlers update the bundle before it is forwarded.
+async fn handle_extension_blocks(mut bundle: Bundle) -> Result<Bundle> {
+    let handlers = (*crate::EXTENSION_BLOCKS.lock()).handlers();
+    for handler in handlers {
+        match handler.on_forward(&mut bundle) {
+            BlockAction::Keep => {}
+            BlockAction::RemoveBlock => {
+                let block_type = handler.block_type();
+                bundle.canonicals.retain(|cb| cb.block_type != block_type);
+            }
+            BlockAction::DeleteBundle(reason) => {
+                delete(bundle.into(), reason).await?;
+                bail!("{} block requested bundle deletion", handler.name());
+            }
         }
     }
     Ok(bundle)
 }
-
-async fn handle_previous_node_block(mut bundle: Bundle) -> Result<Bundle> {
-    if let Some(pnb) = bundle.extension_block_by_type_mut(bp7::canonical::PREVIOUS_NODE_BLOCK) {
-        let prev_eid = &pnb
-            .previous_node_get()
-            .expect("no previoud node EID found!")
-            .clone();
-        let local_eid = CONFIG.lock().host_eid.clone();
-        pnb.previous_node_update(local_eid.clone());
-        debug!(
-            "Previous Node Block was updated: {} {} {}",
-            bundle.id(),
-            prev_eid,
-            local_eid
-        );
-    } else {
-        // according to rfc always add a previous node block
-        let local_eid = CONFIG.lock().host_eid.clone();
-        let pnb = bp7::canonical::new_previous_node_block(0, BlockControlFlags::empty(), local_eid);
-        bundle.add_canonical_block(pnb);
-    }
-    Ok(bundle)
-}
 // forward a bundle pack's bundle to another node.
 pub async fn forward(mut bp: BundlePack) -> Result<()> {
     let bpid = bp.id().to_string();
@@ -312,7 +312,11 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
 
     trace!("Check delivery");
 
-    let (nodes, delete_afterwards) = routing_sender_for_bundle(bp.clone()).await?;
+    let (mut nodes, delete_afterwards) = routing_sender_for_bundle(bp.clone()).await?;
+    topics::filter_senders(&bp.destination, &mut nodes);
+    file::filter_senders(&bp.destination, &mut nodes);
+    // group bundles keep being forwarded to other members until their lifetime expires
+    let delete_afterwards = delete_afterwards && !bp.destination.is_non_singleton();
     if !nodes.is_empty() {
         debug!("Attempting forwarding of {} to nodes: {:?}", bp.id(), nodes);
     }
@@ -321,6 +325,10 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
         trace!("No new peers for forwarding of bundle {}", &bp.id());
         if delete_afterwards {
             store_remove(&bpid)?;
+            events::emit(Event::BundleDeleted {
+                bid: bpid,
+                reason: "released by routing agent".into(),
+            });
         }
     } else {
         debug!("Attempting forwarding of {} to nodes: {:?}", bp.id(), nodes);
@@ -335,20 +343,14 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
         trace!("Handle lifetime");
         handle_primary_lifetime(&bndl).await?;
 
-        trace!("Handle hop count block");
-        bndl = handle_hop_count_block(bndl).await?;
-
-        trace!("Handle previous node block");
-        // Handle previous node block
-        bndl = handle_previous_node_block(bndl).await?;
-        trace!("Handle bundle age block");
-        // Handle bundle age block
-        bndl = handle_bundle_age_block(bndl).await?;
+        trace!("Handle extension blocks");
+        bndl = handle_extension_blocks(bndl).await?;
 
         let mut wg = Vec::new();
         let bundle_data = bndl.to_cbor();
         for n in nodes {
             let bd = bundle_data.clone(); // TODO: optimize cloning away, reference should do
+            let bd_len = bd.len() as u64;
             let bpid = bpid.clone();
             let bundle_sent = std::sync::Arc::clone(&bundle_sent);
             let n = n.clone();
@@ -369,6 +371,12 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
                     );
                     STATS.lock().failed += 1;
                     debug!("Error while transferring bundle {}: {}", &bpid, err);
+                    events::emit(Event::TransferFailed {
+                        bid: bpid.clone(),
+                        peer: n.next_hop.node().unwrap_or_default(),
+                        cla: n.cla_name.clone(),
+                        error: err.to_string(),
+                    });
                     let mut failed_peer = None;
 
                     if let Err(err) = routing_notify(RoutingNotifcation::SendingFailed(
@@ -386,7 +394,12 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
                             "Reporting failed sending to peer: {}",
                             &n.next_hop.node().unwrap()
                         );
-                        peer_entry.report_fail();
+                        peer_entry.report_transfer(
+                            &n.cla_name,
+                            bd_len,
+                            start_time.elapsed(),
+                            false,
+                        );
                         if peer_entry.failed_too_much() && peer_entry.con_type == PeerType::Dynamic
                         {
                             failed_peer = Some(peer_entry.node_name());
@@ -394,7 +407,10 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
                     }
                     if let Some(peer) = failed_peer {
                         let peers_before = (*PEERS.lock()).len();
-                        (*PEERS.lock()).remove(&peer);
+                        let removed = (*PEERS.lock()).remove(&peer);
+                        if let Some(removed) = removed {
+                            peers_retire(removed);
+                        }
                         let peers_after = (*PEERS.lock()).len();
                         debug!("Removing peer {} from list of neighbors due to too many failed transmissions ({}/{})", peer, peers_before, peers_after);
                     }
@@ -411,6 +427,16 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
                         start_time.elapsed()
                     );
                     STATS.lock().outgoing += 1;
+                    events::emit(Event::BundleForwarded {
+                        bid: bpid.clone(),
+                        peer: n.next_hop.node().unwrap_or_default(),
+                        cla: n.cla_name.clone(),
+                        bytes: bd_len,
+                        duration: start_time.elapsed().as_millis() as u64,
+                    });
+                    if let Some(peer_entry) = (*PEERS.lock()).get_mut(&n.next_hop.node().unwrap()) {
+                        peer_entry.report_transfer(&n.cla_name, bd_len, start_time.elapsed(), true);
+                    }
                     bundle_sent.store(true, Ordering::Relaxed);
                     if let Err(err) = routing_notify(RoutingNotifcation::SendingSucceeded(
                         bpid,
@@ -451,6 +477,10 @@ pub async fn forward(mut bp: BundlePack) -> Result<()> {
             }
             if delete_afterwards {
                 store_remove(&bpid)?;
+                events::emit(Event::BundleDeleted {
+                    bid: bpid,
+                    reason: "forwarded".into(),
+                });
             } else if bndl.is_administrative_record() {
                 // TODO: always inspect all bundles, should be configurable
                 is_administrative_record_valid(&bndl);
@@ -473,7 +503,7 @@ pub async fn local_delivery(mut bp: BundlePack) -> Result<()> {
     if bndl.is_none() {
         bail!("bundle not found");
     }
-    let bndl = bndl.unwrap();
+    let mut bndl = bndl.unwrap();
 
     if bp.administrative && !is_administrative_record_valid(&bndl) {
         delete(bp, NO_INFORMATION).await?;
@@ -501,13 +531,57 @@ pub async fn local_delivery(mut bp: BundlePack) -> Result<()> {
         bp.add_constraint(Constraint::ForwardPending);
     }
     bp.sync()?;
+    if let Some(info) = transfer::TransferInfo::from_bundle(&bndl) {
+        let dir = transfer::transfer_dir();
+        // reassembly writes to disk, keep it off the async workers
+        let (chunk, res) = tokio::task::spawn_blocking(move || {
+            let res = transfer::receive_chunk(&dir, &bndl, &info);
+            (bndl, res)
+        })
+        .await?;
+        bndl = chunk;
+        match res {
+            Ok(progress) => {
+                debug!(
+                    "Received {} of transfer {}: {} bytes in {} chunks",
+                    bp.id(),
+                    progress.id,
+                    progress.bytes,
+                    progress.chunks
+                );
+                crate::store_mark_delivered(bp.id());
+                STATS.lock().delivered += 1;
+                delivered(&bp);
+                return Ok(());
+            }
+            Err(err) => warn!(
+                "Reassembly of {} failed, delivering it as a single bundle: {}",
+                bp.id(),
+                err
+            ),
+        }
+    }
+    if rpc::on_delivery(&bndl) {
+        debug!("Handed response {} to waiting request", bp.id());
+        crate::store_mark_delivered(bp.id());
+        STATS.lock().delivered += 1;
+        delivered(&bp);
+        return Ok(());
+    }
     if let Some(aa) = (*DTNCORE.lock()).get_endpoint_mut(&bp.destination) {
         info!("Delivering {}", bp.id());
         aa.push(&bndl);
         STATS.lock().delivered += 1;
+        delivered(&bp);
     }
     Ok(())
 }
+fn delivered(bp: &BundlePack) {
+    events::emit(Event::BundleDelivered {
+        bid: bp.id().to_string(),
+        endpoint: bp.destination.to_string(),
+    });
+}
 pub fn contraindicated(mut bp: BundlePack) -> Result<()> {
     info!("Bundle marked for contraindication: {}", bp.id());
     bp.add_constraint(Constraint::Contraindicated);
@@ -520,7 +594,7 @@ pub async fn delete(mut bp: BundlePack, reason: StatusReportReason) -> Result<()
     if bndl.is_none() {
         bail!("bundle not found");
     }
-    (*STATS.lock()).node.error_info.discarded_bundle_count += 1;
+    STATS.lock().node.error_info.discarded_bundle_count += 1;
     let bndl = bndl.unwrap();
     if bndl
         .primary
@@ -534,6 +608,10 @@ pub async fn delete(mut bp: BundlePack, reason: StatusReportReason) -> Result<()
     bp.clear_constraints();
     info!("Bundle marked for deletion: {}", bp.id());
     bp.sync()?;
+    events::emit(Event::BundleDeleted {
+        bid: bp.id().to_string(),
+        reason: reason_str(reason).into(),
+    });
     Ok(())
 }
 
@@ -565,7 +643,7 @@ fn is_administrative_record_valid(bundle: &Bundle) -> bool {
                     );
                     // Currently there are only status reports. This must be changed if more
                     // types of administrative records are introduced.
-                    inspect_status_report(&bundle.id(), ar);
+                    inspect_status_report(bundle, ar);
                     true
                 }
                 Err(ar) => {
@@ -588,7 +666,8 @@ fn is_administrative_record_valid(bundle: &Bundle) -> bool {
     }
 }
 
-fn inspect_status_report(bid: &str, ar: AdministrativeRecord) {
+fn inspect_status_report(bundle: &Bundle, ar: AdministrativeRecord) {
+    let bid = bundle.id();
     if let AdministrativeRecord::BundleStatusReport(bsr) = &ar {
         let sips = &bsr.status_information;
         if sips.is_empty() {
@@ -598,7 +677,9 @@ fn inspect_status_report(bid: &str, ar: AdministrativeRecord) {
             );
             return;
         }
-        if !store_has_item(&bsr.refbundle()) {
+        let refbundle = bsr.refbundle();
+        // reports for bundles that already left the store are still recorded if they are tracked
+        if !store_has_item(&refbundle) && !STATUS_REPORTS.lock().is_tracked(&refbundle) {
             warn!("Status Report's bundle is unknown: {} {:?}", bid, ar);
             return;
         }
@@ -612,6 +693,20 @@ fn inspect_status_report(bid: &str, ar: AdministrativeRecord) {
         }
         for (i, sip) in sips.iter().enumerate() {
             debug!("Parsing Status Report: {} #{} {:?} {:?}", bid, i, bsr, sip);
+            if !sip.asserted {
+                continue;
+            }
+            if let Some(status) = BundleStatus::from_pos(i as u32) {
+                STATUS_REPORTS.lock().add(
+                    &refbundle,
+                    StatusReportEntry::new(
+                        bundle.primary.source.to_string(),
+                        status,
+                        sip.time,
+                        bsr.report_reason,
+                    ),
+                );
+            }
             match i as u32 {
                 bp7::administrative_record::RECEIVED_BUNDLE => {}
                 bp7::administrative_record::FORWARDED_BUNDLE => {}
@@ -619,15 +714,18 @@ fn inspect_status_report(bid: &str, ar: AdministrativeRecord) {
                 bp7::administrative_record::DELIVERED_BUNDLE => {
                     info!(
                         "Status Report indicated bundle delivery: {} {}",
-                        bid,
-                        bsr.refbundle()
+                        bid, refbundle
                     );
-                    if store_remove(&bsr.refbundle()).is_err() {
+                    if store_remove(&refbundle).is_err() {
                         warn!(
                             "Status Report could not remove bundle: {} {}",
-                            bid,
-                            bsr.refbundle()
+                            bid, refbundle
                         );
+                    } else {
+                        events::emit(Event::BundleDeleted {
+                            bid: refbundle.clone(),
+                            reason: "delivery reported".into(),
+                        });
                     }
                 }
                 _ => {
@@ -0,0 +1,173 @@
+//! Range-based set reconciliation of bundle IDs.
+//!
+//! Both sides keep their bundle IDs sorted. The puller sends a digest of a range of IDs,
+//! the remote compares it with the digest of its own IDs in that range. Equal ranges are done,
+//! small differing ranges are answered with the remote's IDs, large ones are split into
+//! subranges with their digests, which the puller compares with its own in the next round.
+//! Thus, the transferred data grows with the number of differing bundles instead of the
+//! total number of bundles.
+
+use crate::core::bundlepack::Constraint;
+use crate::core::helpers::get_digest_of_bids;
+use crate::core::store::BundleStore;
+use crate::STORE;
+use serde::{Deserialize, Serialize};
+
+/// Ranges with at most this many remote bundles are answered with the bundle IDs.
+pub const LEAF_SIZE: usize = 32;
+/// Number of subranges a differing range is split into.
+pub const FANOUT: usize = 16;
+
+/// Restricts the set of bundles to reconcile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncFilter {
+    /// only bundles whose destination contains this string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dst: Option<String>,
+    /// only bundles with at least this remaining lifetime in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_lifetime: Option<u64>,
+}
+
+/// Digest of all bundle IDs `lo <= bid < hi`, `hi` being unbounded if `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeDigest {
+    pub lo: String,
+    pub hi: Option<String>,
+    pub count: usize,
+    pub digest: String,
+    /// the bundle IDs of the range, only included for small ranges in replies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRequest {
+    #[serde(default)]
+    pub filter: SyncFilter,
+    pub ranges: Vec<RangeDigest>,
+}
+
+/// Sorted IDs of all bundles in the local store matching `filter`.
+///
+/// Deleted bundles are left out, as they cannot be served to pullers. Pullers skip the bundles
+/// they deleted themselves by their tombstones instead.
+pub fn local_bids(filter: &SyncFilter) -> Vec<String> {
+    let now = bp7::dtn_time_now();
+    let mut bids: Vec<String> = (*STORE.lock())
+        .bundles()
+        .iter()
+        .filter(|bp| !bp.has_constraint(Constraint::Deleted))
+        .filter(|bp| {
+            filter
+                .dst
+                .as_ref()
+                .is_none_or(|dst| bp.destination.to_string().contains(dst))
+        })
+        .filter(|bp| match filter.min_lifetime {
+            // bundles without a clock cannot be checked
+            Some(min) if bp.creation_time > 0 => bp.creation_time + bp.lifetime >= now + min * 1000,
+            _ => true,
+        })
+        .map(|bp| bp.id.to_string())
+        .collect();
+    bids.sort_unstable();
+    bids
+}
+
+fn range_slice<'a>(bids: &'a [String], lo: &str, hi: Option<&str>) -> &'a [String] {
+    let start = bids.partition_point(|b| b.as_str() < lo);
+    let end = match hi {
+        Some(hi) => bids.partition_point(|b| b.as_str() < hi),
+        None => bids.len(),
+    };
+    &bids[start..end.max(start)]
+}
+
+/// Computes the digest of a range of the sorted bundle IDs `bids`.
+pub fn range_digest(bids: &[String], lo: &str, hi: Option<&str>) -> RangeDigest {
+    let slice = range_slice(bids, lo, hi);
+    RangeDigest {
+        lo: lo.to_string(),
+        hi: hi.map(|h| h.to_string()),
+        count: slice.len(),
+        digest: get_digest_of_bids(slice),
+        bids: None,
+    }
+}
+
+/// Digest over all bundle IDs, the starting point of a reconciliation.
+pub fn full_range_digest(bids: &[String]) -> RangeDigest {
+    range_digest(bids, "", None)
+}
+
+/// Answers the ranges of a puller with the local sorted bundle IDs `bids`.
+///
+/// # Example
+///
+/// ```
+/// use dtn7::core::reconciliation::*;
+///
+/// let remote: Vec<String> = (0..1000).map(|i| format!("dtn://node1/-{:06}-0", i)).collect();
+/// let mut local = remote.clone();
+/// local.remove(500);
+///
+/// let mut ranges = vec![full_range_digest(&local)];
+/// let mut missing = Vec::new();
+/// while !ranges.is_empty() {
+///     let mut next = Vec::new();
+///     for reply in answer_ranges(&remote, &ranges) {
+///         if let Some(bids) = reply.bids {
+///             missing.extend(bids.into_iter().filter(|b| local.binary_search(b).is_err()));
+///         } else {
+///             let own = range_digest(&local, &reply.lo, reply.hi.as_deref());
+///             if own.digest != reply.digest {
+///                 next.push(own);
+///             }
+///         }
+///     }
+///     ranges = next;
+/// }
+/// assert_eq!(missing, vec![remote[500].clone()]);
+/// ```
+pub fn answer_ranges(bids: &[String], ranges: &[RangeDigest]) -> Vec<RangeDigest> {
+    let mut replies = Vec::new();
+    for range in ranges {
+        let slice = range_slice(bids, &range.lo, range.hi.as_deref());
+        let digest = get_digest_of_bids(slice);
+        if slice.len() == range.count && digest == range.digest {
+            continue;
+        }
+        if slice.len() <= LEAF_SIZE {
+            replies.push(RangeDigest {
+                lo: range.lo.clone(),
+                hi: range.hi.clone(),
+                count: slice.len(),
+                digest,
+                bids: Some(slice.to_vec()),
+            });
+            continue;
+        }
+        let chunk_size = slice.len().div_ceil(FANOUT);
+        let chunks: Vec<&[String]> = slice.chunks(chunk_size).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let lo = if i == 0 {
+                range.lo.clone()
+            } else {
+                chunk[0].clone()
+            };
+            let hi = match chunks.get(i + 1) {
+                Some(next) => Some(next[0].clone()),
+                None => range.hi.clone(),
+            };
+            replies.push(RangeDigest {
+                lo,
+                hi,
+                count: chunk.len(),
+                digest: get_digest_of_bids(chunk),
+                bids: None,
+            });
+        }
+    }
+    replies
+}
@@ -0,0 +1,385 @@
+//! Request/response exchanges between applications.
+//!
+//! Requests carry a correlation block with a correlation ID and the endpoint replies are sent to.
+//! The requesting node keeps the request pending until the response arrives or the deadline is
+//! reached. Responding nodes remember received requests, so applications reply by bundle ID.
+
+use crate::core::extension_blocks::{BlockAction, ExtensionBlockHandler};
+use crate::{CONFIG, RPC, STATS};
+use anyhow::{bail, Context, Result};
+use bp7::canonical::{new_canonical_block, CanonicalBlock, CanonicalBlockType};
+use bp7::dtntime::DtnTimeHelpers;
+use bp7::flags::{BlockControlFlags, BundleControlFlags};
+use bp7::{Bundle, CanonicalData, CreationTimestamp, EndpointID};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+
+/// Block type of the correlation block, taken from the private use range.
+pub const CORRELATION_BLOCK: CanonicalBlockType = 194;
+/// Time to wait for a response unless requested otherwise.
+pub const RPC_TIMEOUT_DEFAULT: Duration = Duration::from_secs(30);
+/// Maximum number of received requests kept for replies, the oldest ones are dropped.
+pub const RPC_MAX_RECEIVED: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    Request,
+    Response,
+}
+
+/// Content of the correlation block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Correlation {
+    pub id: String,
+    pub kind: MessageKind,
+    /// endpoint responses are sent to
+    pub reply_to: String,
+}
+
+impl Correlation {
+    pub fn from_bundle(bundle: &Bundle) -> Option<Correlation> {
+        Correlation::from_block(bundle.extension_block_by_type(CORRELATION_BLOCK)?)
+    }
+    fn from_block(block: &CanonicalBlock) -> Option<Correlation> {
+        match block.data() {
+            CanonicalData::Unknown(data) => serde_cbor::from_slice(data).ok(),
+            _ => None,
+        }
+    }
+    pub fn to_block(&self) -> CanonicalBlock {
+        new_canonical_block(
+            CORRELATION_BLOCK,
+            0,
+            BlockControlFlags::empty().bits(),
+            CanonicalData::Unknown(
+                serde_cbor::to_vec(self).expect("correlation block encoding error"),
+            ),
+        )
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn new_bundle(
+    src: EndpointID,
+    dst: EndpointID,
+    correlation: &Correlation,
+    data: Vec<u8>,
+    lifetime: Duration,
+) -> Bundle {
+    let pblock = bp7::primary::PrimaryBlockBuilder::default()
+        .bundle_control_flags(BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED.bits())
+        .destination(dst)
+        .source(src.clone())
+        .report_to(src)
+        .creation_timestamp(CreationTimestamp::now())
+        .lifetime(lifetime)
+        .build()
+        .unwrap();
+    let mut bndl = Bundle::new(
+        pblock,
+        vec![
+            bp7::canonical::new_payload_block(BlockControlFlags::empty(), data),
+            bp7::canonical::new_hop_count_block(2, BlockControlFlags::empty(), 32),
+        ],
+    );
+    bndl.add_canonical_block(correlation.to_block());
+    bndl.set_crc(bp7::crc::CRC_NO);
+    bndl
+}
+
+/// Request waiting for its response on this node.
+#[derive(Debug)]
+struct PendingRequest {
+    bid: String,
+    dst: String,
+    deadline: u64,
+    tx: oneshot::Sender<Bundle>,
+}
+
+/// Request received on this node that has not been answered yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceivedRequest {
+    pub bid: String,
+    pub correlation: Correlation,
+    /// endpoint the request was delivered to, the source of the response
+    pub destination: String,
+    /// unix time the request bundle expires
+    pub expires: u64,
+}
+
+impl ReceivedRequest {
+    pub fn from_bundle(bundle: &Bundle) -> Option<ReceivedRequest> {
+        let correlation = Correlation::from_bundle(bundle)?;
+        if correlation.kind != MessageKind::Request {
+            return None;
+        }
+        let created = match bundle.primary.creation_timestamp.dtntime() {
+            // nodes without a clock do not set the creation time
+            0 => now(),
+            dtntime => dtntime.unix(),
+        };
+        Some(ReceivedRequest {
+            bid: bundle.id(),
+            correlation,
+            destination: bundle.primary.destination.to_string(),
+            expires: created + bundle.primary.lifetime.as_secs(),
+        })
+    }
+    /// Builds the response bundle, which lives as long as the request.
+    pub fn response(&self, data: Vec<u8>) -> Result<Bundle> {
+        let dst = EndpointID::try_from(self.correlation.reply_to.as_str())?;
+        let mut src = EndpointID::try_from(self.destination.as_str())?;
+        if src.is_non_singleton() {
+            // group endpoints are no valid bundle source
+            src = CONFIG.lock().host_eid.clone();
+        }
+        let correlation = Correlation {
+            kind: MessageKind::Response,
+            ..self.correlation.clone()
+        };
+        let lifetime = Duration::from_secs(self.expires.saturating_sub(now()).max(60));
+        Ok(new_bundle(src, dst, &correlation, data, lifetime))
+    }
+}
+
+/// Pending and received requests of this node.
+#[derive(Debug, Default)]
+pub struct RpcState {
+    pending: HashMap<String, PendingRequest>,
+    received: HashMap<String, ReceivedRequest>,
+    /// bundle IDs of received requests in order of arrival, may contain answered ones
+    arrivals: VecDeque<String>,
+}
+
+impl RpcState {
+    /// Drops the oldest received requests beyond `RPC_MAX_RECEIVED`, so peers sending
+    /// long-lived requests cannot grow the state without bound.
+    fn limit_received(&mut self) {
+        while self.received.len() > RPC_MAX_RECEIVED {
+            let Some(bid) = self.arrivals.pop_front() else {
+                break;
+            };
+            if self.received.remove(&bid).is_some() {
+                debug!("Dropping unanswered request {}", bid);
+            }
+        }
+        if self.arrivals.len() > 2 * RPC_MAX_RECEIVED {
+            let received = &self.received;
+            self.arrivals.retain(|bid| received.contains_key(bid));
+        }
+    }
+}
+
+/// Request as listed by `/rpc/pending`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingInfo {
+    pub id: String,
+    pub bid: String,
+    pub dst: String,
+    /// unix time the request times out
+    pub deadline: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcStatus {
+    pub pending: Vec<PendingInfo>,
+    pub received: Vec<ReceivedRequest>,
+}
+
+pub fn status() -> RpcStatus {
+    let rpc = RPC.lock();
+    let mut pending: Vec<PendingInfo> = rpc
+        .pending
+        .iter()
+        .map(|(id, p)| PendingInfo {
+            id: id.clone(),
+            bid: p.bid.clone(),
+            dst: p.dst.clone(),
+            deadline: p.deadline,
+        })
+        .collect();
+    pending.sort_unstable_by_key(|p| p.deadline);
+    let mut received: Vec<ReceivedRequest> = rpc.received.values().cloned().collect();
+    received.sort_unstable_by_key(|r| r.expires);
+    RpcStatus { pending, received }
+}
+
+/// Request sent by this node, resolved by `wait`.
+#[derive(Debug)]
+pub struct Request {
+    pub id: String,
+    pub bid: String,
+    timeout: Duration,
+    rx: oneshot::Receiver<Bundle>,
+}
+
+/// Forgets the pending request once nobody waits for it anymore, also if the waiting future is
+/// dropped, e.g. because the HTTP client disconnected.
+struct PendingGuard<'a>(&'a str);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        RPC.lock().pending.remove(self.0);
+    }
+}
+
+impl Request {
+    /// Waits for the response, returns none once the request timed out.
+    pub async fn wait(self) -> Option<Bundle> {
+        let _pending = PendingGuard(&self.id);
+        match tokio::time::timeout(self.timeout, self.rx).await {
+            Ok(Ok(response)) => Some(response),
+            _ => {
+                debug!("Request {} timed out", self.id);
+                None
+            }
+        }
+    }
+}
+
+/// Sends a request from this node, responses are addressed to `reply_to` or the node ID.
+pub async fn request(
+    dst: EndpointID,
+    data: Vec<u8>,
+    timeout: Duration,
+    reply_to: Option<EndpointID>,
+) -> Result<Request> {
+    if dst == EndpointID::none() {
+        bail!("requests to dtn:none can not be answered");
+    }
+    let src = CONFIG.lock().host_eid.clone();
+    let correlation = Correlation {
+        id: format!("{:016x}", rand::random::<u64>()),
+        kind: MessageKind::Request,
+        reply_to: reply_to.unwrap_or_else(|| src.clone()).to_string(),
+    };
+    // the request is useless to the responder after the deadline
+    let lifetime = timeout.max(Duration::from_secs(1));
+    let bndl = new_bundle(src, dst.clone(), &correlation, data, lifetime);
+    let bid = bndl.id();
+    let (tx, rx) = oneshot::channel();
+    RPC.lock().pending.insert(
+        correlation.id.clone(),
+        PendingRequest {
+            bid: bid.clone(),
+            dst: dst.to_string(),
+            // rounded up, the janitor must not expire requests still being waited for
+            deadline: now() + timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0),
+            tx,
+        },
+    );
+    debug!("Sending request {} in {} to {}", correlation.id, bid, dst);
+    crate::core::processing::send_bundle(bndl).await;
+    STATS.lock().node.bundles.bundles_created += 1;
+    Ok(Request {
+        id: correlation.id,
+        bid,
+        timeout,
+        rx,
+    })
+}
+
+/// Sends the response to a received request and returns its bundle ID.
+pub async fn reply(request_bid: &str, data: Vec<u8>) -> Result<String> {
+    let request = RPC
+        .lock()
+        .received
+        .get(request_bid)
+        .cloned()
+        .context("unknown request")?;
+    let bndl = request.response(data)?;
+    // only answered requests are forgotten, failed replies can be retried
+    if RPC.lock().received.remove(request_bid).is_none() {
+        bail!("request already answered");
+    }
+    let bid = bndl.id();
+    debug!(
+        "Sending response to {} in {} to {}",
+        request.correlation.id, bid, request.correlation.reply_to
+    );
+    crate::core::processing::send_bundle(bndl).await;
+    STATS.lock().node.bundles.bundles_created += 1;
+    Ok(bid)
+}
+
+/// Removes pending requests past their deadline and received requests that expired, returns
+/// the number of removed requests.
+pub fn expire() -> usize {
+    let now = now();
+    let mut rpc = RPC.lock();
+    let before = rpc.pending.len() + rpc.received.len();
+    rpc.pending.retain(|_, p| p.deadline >= now);
+    rpc.received.retain(|_, r| r.expires > now);
+    before - rpc.pending.len() - rpc.received.len()
+}
+
+/// Tracks requests and matches responses on local delivery, returns true if the bundle was
+/// handed to a waiting client.
+pub fn on_delivery(bundle: &Bundle) -> bool {
+    let Some(correlation) = Correlation::from_bundle(bundle) else {
+        return false;
+    };
+    let mut rpc = RPC.lock();
+    match correlation.kind {
+        MessageKind::Request => {
+            let now = now();
+            rpc.received.retain(|_, r| r.expires > now);
+            if let Some(request) = ReceivedRequest::from_bundle(bundle) {
+                let bid = request.bid.clone();
+                if rpc.received.insert(bid.clone(), request).is_none() {
+                    rpc.arrivals.push_back(bid);
+                }
+            }
+            rpc.limit_received();
+            false
+        }
+        MessageKind::Response => match rpc.pending.remove(&correlation.id) {
+            Some(pending) => {
+                debug!(
+                    "Received response {} to request {}",
+                    bundle.id(),
+                    pending.bid
+                );
+                pending.tx.send(bundle.clone()).is_ok()
+            }
+            None => {
+                debug!("Response {} has no pending request", bundle.id());
+                false
+            }
+        },
+    }
+}
+
+/// Correlation blocks of received bundles must be decodable.
+pub struct CorrelationBlock;
+
+impl ExtensionBlockHandler for CorrelationBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        CORRELATION_BLOCK
+    }
+    fn name(&self) -> &str {
+        "correlation"
+    }
+    fn on_receive(&self, bundle: &Bundle, block: &CanonicalBlock) -> BlockAction {
+        match Correlation::from_block(block) {
+            Some(c) if EndpointID::try_from(c.reply_to.as_str()).is_ok() => BlockAction::Keep,
+            _ => {
+                warn!("Removing invalid correlation block from {}", bundle.id());
+                BlockAction::RemoveBlock
+            }
+        }
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        serde_json::to_value(Correlation::from_block(block)).unwrap_or_default()
+    }
+}
@@ -23,7 +23,7 @@ impl NodeStats {
         mib.node_state.bp_versions = vec![7]; // Bundle Protocol version - fixed to 7 for now
 
         // mib.error_info.failed_forwards_bundle_count = (*STATS.lock()).failed;
-        return mib;
+        mib
     }
 }
 
@@ -33,6 +33,18 @@ pub enum FailureAction {
     Defer = 1,
 }
 
+impl std::str::FromStr for FailureAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "abandon" => Ok(FailureAction::Abandon),
+            "defer" => Ok(FailureAction::Defer),
+            _ => anyhow::bail!("unknown failure action {}, expected abandon or defer", s),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 /// CCSDS Bundle Protocol Orange Book - Annex C Bundle State Information Table C-1
 pub struct BundleStateInformation {
@@ -0,0 +1,161 @@
+use bp7::administrative_record::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Maximum number of bundles for which status reports are kept.
+pub const MAX_TRACKED_BUNDLES: usize = 4096;
+/// Maximum number of reports kept per bundle.
+pub const MAX_REPORTS_PER_BUNDLE: usize = 64;
+
+/// The status asserted by a bundle status report.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleStatus {
+    Received,
+    Forwarded,
+    Delivered,
+    Deleted,
+}
+
+impl BundleStatus {
+    pub fn from_pos(pos: StatusInformationPos) -> Option<BundleStatus> {
+        match pos {
+            RECEIVED_BUNDLE => Some(BundleStatus::Received),
+            FORWARDED_BUNDLE => Some(BundleStatus::Forwarded),
+            DELIVERED_BUNDLE => Some(BundleStatus::Delivered),
+            DELETED_BUNDLE => Some(BundleStatus::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// Human readable description of a status report reason code.
+pub fn reason_str(reason: StatusReportReason) -> &'static str {
+    match reason {
+        NO_INFORMATION => "no additional information",
+        LIFETIME_EXPIRED => "lifetime expired",
+        FORWARD_UNIDIRECTIONAL_LINK => "forwarded over unidirectional link",
+        TRANSMISSION_CANCELED => "transmission canceled",
+        DEPLETED_STORAGE => "depleted storage",
+        DEST_ENDPOINT_UNINTELLIGIBLE => "destination endpoint ID unintelligible",
+        NO_ROUTE_TO_DESTINATION => "no known route to destination from here",
+        NO_NEXT_NODE_CONTACT => "no timely contact with next node on route",
+        BLOCK_UNINTELLIGIBLE => "block unintelligible",
+        HOP_LIMIT_EXCEEDED => "hop limit exceeded",
+        TRAFFIC_PARED => "traffic pared",
+        BLOCK_UNSUPPORTED => "block unsupported",
+        _ => "unknown",
+    }
+}
+
+/// A single status assertion about a bundle by some node.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StatusReportEntry {
+    /// node that sent the status report
+    pub reporter: String,
+    pub status: BundleStatus,
+    /// time of the status assertion as DTN time in milliseconds, 0 if not reported
+    pub time: u64,
+    /// local reception time of the report in unix time as seconds
+    pub received: u64,
+    pub reason: StatusReportReason,
+    pub reason_text: String,
+}
+
+impl StatusReportEntry {
+    pub fn new(
+        reporter: String,
+        status: BundleStatus,
+        time: u64,
+        reason: StatusReportReason,
+    ) -> StatusReportEntry {
+        StatusReportEntry {
+            reporter,
+            status,
+            time,
+            received: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time went backwards")
+                .as_secs(),
+            reason,
+            reason_text: reason_str(reason).to_string(),
+        }
+    }
+}
+
+/// Notification sent to the application that created the referenced bundle.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StatusReportNotification {
+    pub bid: String,
+    #[serde(flatten)]
+    pub report: StatusReportEntry,
+}
+
+/// Status report timelines of bundles, keyed by the referenced bundle ID.
+///
+/// # Example
+///
+/// ```
+/// use dtn7::core::status_reports::*;
+///
+/// let mut log = StatusReportLog::default();
+/// let bid = "dtn://node1/-683555464000-0";
+/// log.add(bid, StatusReportEntry::new("dtn://node2/".into(), BundleStatus::Received, 0, 0));
+/// log.add(bid, StatusReportEntry::new("dtn://node3/".into(), BundleStatus::Delivered, 0, 0));
+///
+/// let timeline = log.get(bid).unwrap();
+/// assert_eq!(timeline.len(), 2);
+/// assert_eq!(timeline[1].status, BundleStatus::Delivered);
+/// ```
+#[derive(Debug, Default)]
+pub struct StatusReportLog {
+    reports: HashMap<String, Vec<StatusReportEntry>>,
+    /// bundle IDs in order of their first report, oldest first
+    order: VecDeque<String>,
+    watchers: HashMap<String, mpsc::Sender<StatusReportNotification>>,
+}
+
+impl StatusReportLog {
+    pub fn is_tracked(&self, bid: &str) -> bool {
+        self.reports.contains_key(bid) || self.watchers.contains_key(bid)
+    }
+
+    pub fn add(&mut self, bid: &str, report: StatusReportEntry) {
+        if let Some(tx) = self.watchers.get(bid) {
+            let notification = StatusReportNotification {
+                bid: bid.to_string(),
+                report: report.clone(),
+            };
+            if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(notification) {
+                self.watchers.remove(bid);
+            }
+        }
+        if !self.reports.contains_key(bid) {
+            if self.order.len() >= MAX_TRACKED_BUNDLES {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.reports.remove(&oldest);
+                    self.watchers.remove(&oldest);
+                }
+            }
+            self.order.push_back(bid.to_string());
+        }
+        let timeline = self.reports.entry(bid.to_string()).or_default();
+        if timeline.len() < MAX_REPORTS_PER_BUNDLE {
+            timeline.push(report);
+        }
+    }
+
+    pub fn get(&self, bid: &str) -> Option<&Vec<StatusReportEntry>> {
+        self.reports.get(bid)
+    }
+
+    /// Sends all future reports about bundle `bid` to `tx`.
+    pub fn watch(&mut self, bid: &str, tx: mpsc::Sender<StatusReportNotification>) {
+        self.watchers.retain(|_, tx| !tx.is_closed());
+        if self.watchers.len() < MAX_TRACKED_BUNDLES {
+            self.watchers.insert(bid.to_string(), tx);
+        }
+    }
+}
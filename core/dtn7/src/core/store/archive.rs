@@ -0,0 +1,195 @@
+//! Portable archive of bundle store contents.
+//!
+//! An archive is a sequence of CBOR items: an [`ArchiveHeader`] followed by one
+//! [`ArchiveEntry`] per bundle. Entries carry the bundle metadata including its constraints,
+//! tombstones of deleted bundles have no bundle data. As every item is encoded on its own,
+//! archives are written and read in a streaming fashion independent of the store size.
+
+use super::BundleStore;
+use crate::core::bundlepack::{BundlePack, Constraint};
+use anyhow::{bail, Result};
+use bp7::Bundle;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const ARCHIVE_MAGIC: &str = "dtn7-store-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub magic: String,
+    pub version: u32,
+    /// node ID of the exporting node
+    pub node: String,
+    /// unix time in seconds
+    pub created: u64,
+}
+
+impl ArchiveHeader {
+    pub fn new(node: &str) -> ArchiveHeader {
+        ArchiveHeader {
+            magic: ARCHIVE_MAGIC.into(),
+            version: ARCHIVE_VERSION,
+            node: node.into(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time went backwards")
+                .as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub metadata: BundlePack,
+    /// encoded bundle, `None` for tombstones
+    pub bundle: Option<ByteBuf>,
+}
+
+/// Number of entries processed by an export or import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveSummary {
+    pub bundles: usize,
+    pub tombstones: usize,
+    /// entries already present in the target store, without usable bundle data or tombstones
+    /// the target store does not keep
+    pub skipped: usize,
+}
+
+fn archive_entry<S: BundleStore + ?Sized>(
+    store: &S,
+    bid: &str,
+    summary: &mut ArchiveSummary,
+) -> Option<ArchiveEntry> {
+    let metadata = match store.get_metadata(bid) {
+        Some(metadata) => metadata,
+        None => {
+            warn!("Not exporting {}, metadata unreadable", bid);
+            summary.skipped += 1;
+            return None;
+        }
+    };
+    if metadata.has_constraint(Constraint::Deleted) {
+        summary.tombstones += 1;
+        return Some(ArchiveEntry {
+            metadata,
+            bundle: None,
+        });
+    }
+    match store.get_raw(bid) {
+        Some(buf) => {
+            summary.bundles += 1;
+            Some(ArchiveEntry {
+                metadata,
+                bundle: Some(ByteBuf::from(buf)),
+            })
+        }
+        None => {
+            warn!("Not exporting {}, bundle missing", bid);
+            summary.skipped += 1;
+            None
+        }
+    }
+}
+
+fn restore_entry<S: BundleStore + ?Sized>(
+    store: &mut S,
+    entry: ArchiveEntry,
+    summary: &mut ArchiveSummary,
+) -> Result<()> {
+    let bid = entry.metadata.id.clone();
+    if store.has_item(&bid) || store.get_metadata(&bid).is_some() {
+        debug!("Skipping {}, already in store", bid);
+        summary.skipped += 1;
+        return Ok(());
+    }
+    match entry.bundle {
+        Some(buf) => match Bundle::try_from(buf.into_vec()) {
+            Ok(bndl) => {
+                store.push(&bndl)?;
+                store.update_metadata(&entry.metadata)?;
+                summary.bundles += 1;
+            }
+            Err(err) => {
+                warn!("Skipping {}, invalid bundle: {}", bid, err);
+                summary.skipped += 1;
+            }
+        },
+        None => {
+            store.insert_metadata(&entry.metadata)?;
+            // some backends, e.g., sneakers, do not keep tombstones
+            if store.get_metadata(&bid).is_some() {
+                summary.tombstones += 1;
+            } else {
+                debug!("Skipping tombstone {}, not supported by the store", bid);
+                summary.skipped += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes all bundles and tombstones of `store` to `w`.
+pub fn export_store<S: BundleStore + ?Sized>(
+    store: &S,
+    w: &mut dyn Write,
+    node: &str,
+) -> Result<ArchiveSummary> {
+    let mut summary = ArchiveSummary::default();
+    serde_cbor::to_writer(&mut *w, &ArchiveHeader::new(node))?;
+    for bid in store.metadata_ids() {
+        if let Some(entry) = archive_entry(store, &bid, &mut summary) {
+            serde_cbor::to_writer(&mut *w, &entry)?;
+        }
+    }
+    w.flush()?;
+    Ok(summary)
+}
+
+/// Reads an archive from `r` into `store`, returning the header and the processed entries.
+///
+/// Bundles already known to `store` are left untouched.
+pub fn import_store<S: BundleStore + ?Sized>(
+    store: &mut S,
+    r: &mut dyn Read,
+) -> Result<(ArchiveHeader, ArchiveSummary)> {
+    let mut items = serde_cbor::Deserializer::from_reader(r).into_iter::<serde_cbor::Value>();
+    let header: ArchiveHeader = match items.next() {
+        Some(item) => serde_cbor::value::from_value(item?)?,
+        None => bail!("empty archive"),
+    };
+    if header.magic != ARCHIVE_MAGIC {
+        bail!("not a bundle store archive");
+    }
+    if header.version > ARCHIVE_VERSION {
+        bail!("unsupported archive version {}", header.version);
+    }
+
+    let mut summary = ArchiveSummary::default();
+    for item in items {
+        let entry: ArchiveEntry = serde_cbor::value::from_value(item?)?;
+        restore_entry(store, entry, &mut summary)?;
+    }
+    Ok((header, summary))
+}
+
+/// Copies all bundles and tombstones from one store to another without an intermediate archive.
+pub fn migrate_store<S: BundleStore + ?Sized, T: BundleStore + ?Sized>(
+    from: &S,
+    to: &mut T,
+) -> Result<ArchiveSummary> {
+    let mut exported = ArchiveSummary::default();
+    let mut summary = ArchiveSummary::default();
+    for bid in from.metadata_ids() {
+        if let Some(entry) = archive_entry(from, &bid, &mut exported) {
+            restore_entry(to, entry, &mut summary)?;
+        }
+    }
+    summary.skipped += exported.skipped;
+    to.compact()?;
+    Ok(summary)
+}
@@ -0,0 +1,635 @@
+//! Transparent at-rest encryption for any bundle store backend.
+//!
+//! [`EncryptedBundleStore`] wraps another store and keeps nothing but opaque carrier bundles
+//! in it. Every bundle is stored as two carriers, one holding the sealed bundle and one holding
+//! the sealed metadata, both addressed by a random tag instead of the bundle ID:
+//!
+//! ```text
+//! dtn://<tag>/-0-<generation>   sealed bundle
+//! dtn://<tag>/-1-<generation>   sealed metadata
+//! ```
+//!
+//! Carriers are sealed with ChaCha20-Poly1305, the carrier ID serves as associated data.
+//! Changes are written as a new generation before the previous one is purged, so a crash
+//! never leaves a bundle without a readable carrier, independent of the backend.
+//! Superseded generations are purged in batches, leftovers of a crash are purged on the next start.
+//! The metadata of all bundles is decrypted once when opening the store and served from memory.
+//!
+//! Keys are given as `<id>:<64 hex digits>` entries, separated by newlines or commas, the first
+//! entry being the current key used for sealing. All other keys are only used for opening.
+//! For a key rotation, a new key is put in front of the old ones and [`EncryptedBundleStore::rotate`]
+//! reseals all carriers still sealed with an old key, after which the old key can be dropped.
+
+use super::{BundleStore, BundleStoresEnum};
+use crate::core::bundlepack::{BundlePack, Constraint};
+use anyhow::{anyhow, bail, Result};
+use bp7::{Bundle, CreationTimestamp, EndpointID};
+use log::{debug, info, warn};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt::{Debug, Write as _};
+use std::path::Path;
+
+/// Environment variable holding the store keys if no key file is configured.
+pub const STORE_KEY_ENV: &str = "DTN7_STORE_KEY";
+
+const SEAL_VERSION: u8 = 1;
+const HEADER_LEN: usize = 1 + 4 + NONCE_LEN;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const KIND_BUNDLE: u64 = 0;
+const KIND_METADATA: u64 = 1;
+const CARRIER_DESTINATION: &str = "dtn://encrypted/";
+/// Number of superseded carriers collected before they are purged from the inner store
+const PURGE_BATCH: usize = 64;
+
+fn to_hex(buf: &[u8]) -> String {
+    buf.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        bail!("invalid hex string");
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| e.into()))
+        .collect()
+}
+
+/// Set of keys for an [`EncryptedBundleStore`].
+pub struct StoreKeys {
+    current: u32,
+    keys: BTreeMap<u32, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl Debug for StoreKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StoreKeys")
+            .field("current", &self.current)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl StoreKeys {
+    /// Parses `<id>:<64 hex digits>` entries separated by newlines or commas.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use dtn7::core::store::StoreKeys;
+    ///
+    /// let new = StoreKeys::generate_entry(2).unwrap();
+    /// let old = StoreKeys::generate_entry(1).unwrap();
+    /// let keys = StoreKeys::parse(&format!("{}\n{}", new, old)).unwrap();
+    /// assert_eq!(keys.current(), 2);
+    /// assert_eq!(keys.ids(), vec![1, 2]);
+    /// ```
+    pub fn parse(s: &str) -> Result<StoreKeys> {
+        let mut current = None;
+        let mut keys = BTreeMap::new();
+        for entry in s.split([',', '\n']).map(str::trim) {
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("store key entry must be <id>:<hex key>"))?;
+            let id: u32 = id.trim().parse()?;
+            let key = from_hex(key.trim())?;
+            if key.len() != KEY_LEN {
+                bail!("store key {} must be {} bytes", id, KEY_LEN);
+            }
+            let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+                .map_err(|_| anyhow!("invalid store key {}", id))?;
+            if keys.insert(id, LessSafeKey::new(key)).is_some() {
+                bail!("duplicate store key {}", id);
+            }
+            current.get_or_insert(id);
+        }
+        let current = current.ok_or_else(|| anyhow!("no store key given"))?;
+        Ok(StoreKeys {
+            current,
+            keys,
+            rng: SystemRandom::new(),
+        })
+    }
+    pub fn from_file(path: &Path) -> Result<StoreKeys> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("reading store key file {}: {}", path.display(), e))?;
+        StoreKeys::parse(&content)
+    }
+    /// Loads the keys from `key_file` if given, otherwise from the environment.
+    ///
+    /// Returns `None` if no keys are configured at all.
+    pub fn load(key_file: Option<&Path>) -> Result<Option<StoreKeys>> {
+        if let Some(path) = key_file {
+            return StoreKeys::from_file(path).map(Some);
+        }
+        match std::env::var(STORE_KEY_ENV) {
+            Ok(keys) => StoreKeys::parse(&keys).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+    /// Creates a new random key entry for a key file.
+    pub fn generate_entry(id: u32) -> Result<String> {
+        let mut key = [0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| anyhow!("no randomness available"))?;
+        Ok(format!("{}:{}", id, to_hex(&key)))
+    }
+    /// ID of the key used for sealing.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+    pub fn ids(&self) -> Vec<u32> {
+        self.keys.keys().copied().collect()
+    }
+    fn random(&self, buf: &mut [u8]) -> Result<()> {
+        self.rng
+            .fill(buf)
+            .map_err(|_| anyhow!("no randomness available"))
+    }
+    fn seal(&self, aad: &str, plain: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random(&mut nonce)?;
+        let mut buf = Vec::with_capacity(HEADER_LEN + plain.len() + CHACHA20_POLY1305.tag_len());
+        buf.push(SEAL_VERSION);
+        buf.extend_from_slice(&self.current.to_be_bytes());
+        buf.extend_from_slice(&nonce);
+        let mut in_out = plain.to_vec();
+        self.keys[&self.current]
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("sealing failed"))?;
+        buf.extend_from_slice(&in_out);
+        Ok(buf)
+    }
+    /// Key ID a sealed buffer was sealed with.
+    fn sealed_with(sealed: &[u8]) -> Result<u32> {
+        if sealed.len() < HEADER_LEN || sealed[0] != SEAL_VERSION {
+            bail!("unknown sealed format");
+        }
+        Ok(u32::from_be_bytes(sealed[1..5].try_into()?))
+    }
+    fn open(&self, aad: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let key_id = StoreKeys::sealed_with(sealed)?;
+        let key = self
+            .keys
+            .get(&key_id)
+            .ok_or_else(|| anyhow!("unknown store key {}", key_id))?;
+        let nonce = Nonce::try_assume_unique_for_key(&sealed[5..HEADER_LEN])
+            .map_err(|_| anyhow!("invalid nonce"))?;
+        let mut in_out = sealed[HEADER_LEN..].to_vec();
+        let plain_len = key
+            .open_in_place(nonce, Aad::from(aad.as_bytes()), &mut in_out)
+            .map_err(|_| anyhow!("authentication failed"))?
+            .len();
+        in_out.truncate(plain_len);
+        Ok(in_out)
+    }
+}
+
+/// Content of a sealed metadata carrier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MetadataRecord {
+    metadata: BundlePack,
+    /// generation of the bundle carrier, `None` for tombstones
+    bundle: Option<u64>,
+    /// key the bundle carrier is sealed with
+    bundle_key: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    tag: String,
+    /// generation of the metadata carrier
+    generation: u64,
+    /// highest generation in use for this tag
+    last_generation: u64,
+    key: u32,
+    record: MetadataRecord,
+}
+
+impl Entry {
+    fn bundle_is_stale(&self, current: u32) -> bool {
+        self.record.bundle.is_some() && self.record.bundle_key != current
+    }
+    fn is_stale(&self, current: u32) -> bool {
+        self.key != current || self.bundle_is_stale(current)
+    }
+}
+
+fn carrier_id(tag: &str, kind: u64, generation: u64) -> String {
+    format!("dtn://{}/-{}-{}", tag, kind, generation)
+}
+
+/// Splits a carrier ID into tag, kind and generation.
+fn parse_carrier_id(id: &str) -> Option<(String, u64, u64)> {
+    let rest = id.strip_prefix("dtn://")?;
+    let (tag, rest) = rest.split_once("/-")?;
+    if tag.len() != 2 * TAG_LEN || !tag.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let (kind, generation) = rest.split_once('-')?;
+    let kind: u64 = kind.parse().ok()?;
+    if kind != KIND_BUNDLE && kind != KIND_METADATA {
+        return None;
+    }
+    Some((tag.to_string(), kind, generation.parse().ok()?))
+}
+
+/// Store wrapper encrypting bundles and metadata before handing them to the inner store.
+#[derive(Debug)]
+pub struct EncryptedBundleStore {
+    inner: Box<BundleStoresEnum>,
+    keys: StoreKeys,
+    entries: HashMap<String, Entry>,
+    /// carriers replaced by a newer generation, not yet purged
+    superseded: Vec<String>,
+}
+
+impl EncryptedBundleStore {
+    /// Opens an encrypted store on top of `inner`.
+    ///
+    /// Plaintext bundles found in `inner` are encrypted, stale carrier generations are purged.
+    /// Carriers that cannot be opened with `keys` are left untouched and ignored.
+    pub fn new(inner: BundleStoresEnum, keys: StoreKeys) -> Result<EncryptedBundleStore> {
+        let mut store = EncryptedBundleStore {
+            inner: Box::new(inner),
+            keys,
+            entries: HashMap::new(),
+            superseded: Vec::new(),
+        };
+        store.load(true)?;
+        Ok(store)
+    }
+    /// Opens the store for reading only, e.g., to export it.
+    ///
+    /// Unlike [`new`](Self::new), stale carriers are kept and plaintext bundles are neither
+    /// encrypted nor visible.
+    pub fn read_only(inner: BundleStoresEnum, keys: StoreKeys) -> Result<EncryptedBundleStore> {
+        let mut store = EncryptedBundleStore {
+            inner: Box::new(inner),
+            keys,
+            entries: HashMap::new(),
+            superseded: Vec::new(),
+        };
+        store.load(false)?;
+        Ok(store)
+    }
+    pub fn keys(&self) -> &StoreKeys {
+        &self.keys
+    }
+    pub fn into_inner(mut self) -> BundleStoresEnum {
+        if let Err(err) = self.purge_superseded() {
+            warn!("Error purging superseded carriers: {}", err);
+        }
+        *self.inner
+    }
+
+    /// Purges all carriers replaced by a newer generation.
+    fn purge_superseded(&mut self) -> Result<()> {
+        if !self.superseded.is_empty() {
+            debug!("Purging {} superseded carriers", self.superseded.len());
+        }
+        for id in std::mem::take(&mut self.superseded) {
+            self.inner.purge(&id)?;
+        }
+        Ok(())
+    }
+
+    /// Decrypts the metadata of all bundles, `repair` purges stale carriers and encrypts
+    /// plaintext bundles.
+    fn load(&mut self, repair: bool) -> Result<()> {
+        let mut carriers: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+        let mut plaintext = Vec::new();
+        for id in self.inner.metadata_ids() {
+            match parse_carrier_id(&id) {
+                Some((tag, kind, generation)) => {
+                    carriers.entry(tag).or_default().push((kind, generation))
+                }
+                None => plaintext.push(id),
+            }
+        }
+        let mut unreadable = 0;
+        for (tag, mut gens) in carriers {
+            gens.sort_unstable_by_key(|(_, g)| std::cmp::Reverse(*g));
+            let last_generation = gens[0].1;
+            let mut found = None;
+            for (kind, generation) in gens.iter().filter(|(k, _)| *k == KIND_METADATA) {
+                let id = carrier_id(&tag, *kind, *generation);
+                match self.read_carrier(&id) {
+                    Ok((key, buf)) => match serde_cbor::from_slice::<MetadataRecord>(&buf) {
+                        Ok(record) => {
+                            found = Some((*generation, key, record));
+                            break;
+                        }
+                        Err(err) => warn!("Invalid metadata in carrier {}: {}", id, err),
+                    },
+                    Err(err) => warn!("Could not open carrier {}: {}", id, err),
+                }
+            }
+            let Some((generation, key, record)) = found else {
+                unreadable += 1;
+                continue;
+            };
+            // purge carriers left over from interrupted updates, newer ones only if
+            // they are not referenced by a newer metadata generation sealed with an unknown key
+            let newer_metadata = gens
+                .iter()
+                .any(|(kind, g)| *kind == KIND_METADATA && *g > generation);
+            for (kind, g) in gens {
+                let in_use = if kind == KIND_METADATA {
+                    g == generation
+                } else {
+                    record.bundle == Some(g)
+                };
+                if repair && !in_use && (g < generation || !newer_metadata) {
+                    let id = carrier_id(&tag, kind, g);
+                    debug!("Purging stale carrier {}", id);
+                    self.inner.purge(&id)?;
+                }
+            }
+            let bid = record.metadata.id.clone();
+            self.entries.insert(
+                bid,
+                Entry {
+                    tag,
+                    generation,
+                    last_generation,
+                    key,
+                    record,
+                },
+            );
+        }
+        if unreadable > 0 {
+            warn!(
+                "{} encrypted bundles could not be opened with the configured store keys",
+                unreadable
+            );
+        }
+        if !plaintext.is_empty() && !repair {
+            warn!(
+                "Skipping {} plaintext bundles of read-only store",
+                plaintext.len()
+            );
+        } else if !plaintext.is_empty() {
+            info!("Encrypting {} plaintext bundles in store", plaintext.len());
+            for bid in plaintext {
+                if let Some(bp) = self.inner.get_metadata(&bid) {
+                    match self.inner.get_bundle(&bid) {
+                        Some(bndl) => self.write(&bp, Some(&bndl))?,
+                        None => self.write(&bp, None)?,
+                    }
+                }
+                self.inner.purge(&bid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the carrier `id` returning the key ID and the plaintext.
+    fn read_carrier(&self, id: &str) -> Result<(u32, Vec<u8>)> {
+        let carrier = self
+            .inner
+            .get_bundle(id)
+            .ok_or_else(|| anyhow!("carrier {} missing", id))?;
+        let sealed = carrier
+            .payload()
+            .ok_or_else(|| anyhow!("carrier {} without payload", id))?;
+        let key = StoreKeys::sealed_with(sealed)?;
+        Ok((key, self.keys.open(id, sealed)?))
+    }
+
+    fn write_carrier(&mut self, tag: &str, kind: u64, generation: u64, plain: &[u8]) -> Result<()> {
+        let sealed = self.keys.seal(&carrier_id(tag, kind, generation), plain)?;
+        let source = EndpointID::try_from(format!("dtn://{}/", tag))?;
+        let pblock = bp7::primary::PrimaryBlockBuilder::default()
+            .destination(EndpointID::try_from(CARRIER_DESTINATION)?)
+            .source(source.clone())
+            .report_to(source)
+            .creation_timestamp(CreationTimestamp::with_time_and_seq(kind, generation))
+            .lifetime(std::time::Duration::ZERO)
+            .build()
+            .map_err(|e| anyhow!("building carrier failed: {:?}", e))?;
+        let mut carrier = Bundle::new(
+            pblock,
+            vec![bp7::canonical::new_payload_block(
+                bp7::flags::BlockControlFlags::empty(),
+                sealed,
+            )],
+        );
+        carrier.set_crc(bp7::crc::CRC_NO);
+        self.inner.push(&carrier)
+    }
+
+    /// Writes metadata and, if given, the bundle as new carrier generations.
+    ///
+    /// Without a bundle, an existing bundle carrier is kept unless the metadata marks it as deleted.
+    fn write(&mut self, bp: &BundlePack, bndl: Option<&Bundle>) -> Result<()> {
+        let old = self.entries.get(bp.id()).cloned();
+        let tag = match &old {
+            Some(entry) => entry.tag.clone(),
+            None => {
+                let mut tag = [0u8; TAG_LEN];
+                self.keys.random(&mut tag)?;
+                to_hex(&tag)
+            }
+        };
+        let mut generation = old.as_ref().map_or(0, |e| e.last_generation + 1);
+        let deleted = bp.has_constraint(Constraint::Deleted);
+        let (bundle, bundle_key) = match (bndl, &old) {
+            (Some(bndl), _) => {
+                self.write_carrier(&tag, KIND_BUNDLE, generation, &bndl.clone().to_cbor())?;
+                generation += 1;
+                (Some(generation - 1), self.keys.current)
+            }
+            (None, Some(old)) if !deleted => (old.record.bundle, old.record.bundle_key),
+            (None, _) => (None, self.keys.current),
+        };
+        let record = MetadataRecord {
+            metadata: bp.clone(),
+            bundle,
+            bundle_key,
+        };
+        self.write_carrier(
+            &tag,
+            KIND_METADATA,
+            generation,
+            &serde_cbor::to_vec(&record)?,
+        )?;
+        if let Some(old) = &old {
+            self.superseded
+                .push(carrier_id(&tag, KIND_METADATA, old.generation));
+            if let Some(g) = old.record.bundle {
+                if record.bundle != Some(g) {
+                    self.superseded.push(carrier_id(&tag, KIND_BUNDLE, g));
+                }
+            }
+        }
+        self.entries.insert(
+            bp.id().to_string(),
+            Entry {
+                tag,
+                generation,
+                last_generation: generation,
+                key: self.keys.current,
+                record,
+            },
+        );
+        if self.superseded.len() >= PURGE_BATCH {
+            self.purge_superseded()?;
+        }
+        Ok(())
+    }
+
+    /// Number of bundles sealed with a key other than the current one.
+    pub fn stale_count(&self) -> usize {
+        let current = self.keys.current;
+        self.entries
+            .values()
+            .filter(|e| e.is_stale(current))
+            .count()
+    }
+
+    /// Reseals all bundles not sealed with the current key and returns their number.
+    pub fn rotate(&mut self) -> Result<usize> {
+        let current = self.keys.current;
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.is_stale(current))
+            .map(|(bid, _)| bid.clone())
+            .collect();
+        for bid in &stale {
+            let entry = &self.entries[bid];
+            let bp = entry.record.metadata.clone();
+            if entry.bundle_is_stale(current) {
+                let bndl = self
+                    .get_bundle(bid)
+                    .ok_or_else(|| anyhow!("could not open bundle {}", bid))?;
+                self.write(&bp, Some(&bndl))?;
+            } else {
+                self.write(&bp, None)?;
+            }
+        }
+        if !stale.is_empty() {
+            self.purge_superseded()?;
+            self.inner.compact()?;
+        }
+        Ok(stale.len())
+    }
+}
+
+impl BundleStore for EncryptedBundleStore {
+    fn push(&mut self, bndl: &Bundle) -> Result<()> {
+        let bid = bndl.id();
+        let bp = match self.entries.get(&bid) {
+            Some(entry) => {
+                debug!("Bundle {} already in store, updating it!", bid);
+                entry.record.metadata.clone()
+            }
+            None => BundlePack::from(bndl),
+        };
+        self.write(&bp, Some(bndl))
+    }
+    fn update_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        if !self.entries.contains_key(bp.id()) {
+            bail!("Bundle not in store!");
+        }
+        self.write(bp, None)
+    }
+    fn remove(&mut self, bid: &str) -> Result<()> {
+        let Some(mut meta) = self.get_metadata(bid) else {
+            bail!("Bundle meta data not in store!");
+        };
+        meta.clear_constraints();
+        meta.add_constraint(Constraint::Deleted);
+        self.write(&meta, None)
+    }
+    fn count(&self) -> u64 {
+        self.entries
+            .values()
+            .filter(|e| e.record.bundle.is_some())
+            .count() as u64
+    }
+    fn all_ids(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(_, e)| e.record.bundle.is_some())
+            .map(|(bid, _)| bid.clone())
+            .collect()
+    }
+    fn has_item(&self, bid: &str) -> bool {
+        self.entries
+            .get(bid)
+            .is_some_and(|e| e.record.bundle.is_some())
+    }
+    fn bundles(&self) -> Vec<BundlePack> {
+        self.entries
+            .values()
+            .map(|e| e.record.metadata.clone())
+            .collect()
+    }
+    fn get_bundle(&self, bpid: &str) -> Option<Bundle> {
+        let buf = self.get_raw(bpid)?;
+        match Bundle::try_from(buf) {
+            Ok(bndl) => Some(bndl),
+            Err(err) => {
+                warn!("Decrypted bundle {} is invalid: {}", bpid, err);
+                None
+            }
+        }
+    }
+    fn get_metadata(&self, bpid: &str) -> Option<BundlePack> {
+        self.entries.get(bpid).map(|e| e.record.metadata.clone())
+    }
+    fn metadata_ids(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+    fn get_raw(&self, bpid: &str) -> Option<Vec<u8>> {
+        let entry = self.entries.get(bpid)?;
+        let id = carrier_id(&entry.tag, KIND_BUNDLE, entry.record.bundle?);
+        match self.read_carrier(&id) {
+            Ok((_, buf)) => Some(buf),
+            Err(err) => {
+                warn!("Could not open bundle {}: {}", bpid, err);
+                None
+            }
+        }
+    }
+    fn purge(&mut self, bid: &str) -> Result<()> {
+        if let Some(entry) = self.entries.remove(bid) {
+            // older generations must not outlive the current one, they would be restored
+            self.purge_superseded()?;
+            self.inner
+                .purge(&carrier_id(&entry.tag, KIND_METADATA, entry.generation))?;
+            if let Some(g) = entry.record.bundle {
+                self.inner.purge(&carrier_id(&entry.tag, KIND_BUNDLE, g))?;
+            }
+        }
+        Ok(())
+    }
+    fn compact(&mut self) -> Result<()> {
+        self.purge_superseded()?;
+        self.inner.compact()
+    }
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        self.write(bp, None)
+    }
+}
@@ -0,0 +1,226 @@
+//! Integrity checks and repair of bundle stores.
+//!
+//! After power loss or crashes a store can contain metadata without bundles, bundles without
+//! metadata or bundles that do not decode anymore. Additionally, tombstones of deleted bundles
+//! are kept forever by some backends. [`check`] finds all of these, [`repair`] removes them.
+
+use super::BundleStore;
+use crate::core::bundlepack::{BundlePack, Constraint};
+use crate::core::events::EvictionReason;
+use anyhow::Result;
+use bp7::Bundle;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Findings of a store integrity check.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreCheckReport {
+    /// number of stored bundles that were decoded and verified
+    pub checked: usize,
+    /// tombstones of deleted bundles older than the tombstone horizon
+    pub expired_tombstones: Vec<String>,
+    /// metadata of bundles that are not deleted but have no stored bundle
+    pub missing_bundles: Vec<String>,
+    /// stored bundles without metadata
+    pub orphaned_bundles: Vec<String>,
+    /// metadata entries that cannot be decoded
+    pub corrupt_metadata: Vec<String>,
+    /// stored bundles that fail decoding, validation or CRC verification
+    pub corrupt_bundles: Vec<String>,
+}
+
+impl StoreCheckReport {
+    /// Returns true if no repair is needed.
+    pub fn is_clean(&self) -> bool {
+        self.expired_tombstones.is_empty()
+            && self.missing_bundles.is_empty()
+            && self.orphaned_bundles.is_empty()
+            && self.corrupt_metadata.is_empty()
+            && self.corrupt_bundles.is_empty()
+    }
+
+    /// IDs of all entries to be purged on repair.
+    pub fn affected(&self) -> Vec<String> {
+        let mut bids: Vec<String> = self
+            .expired_tombstones
+            .iter()
+            .chain(self.missing_bundles.iter())
+            .chain(self.orphaned_bundles.iter())
+            .chain(self.corrupt_metadata.iter())
+            .chain(self.corrupt_bundles.iter())
+            .cloned()
+            .collect();
+        bids.sort_unstable();
+        bids.dedup();
+        bids
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_millis() as u64
+}
+
+/// A tombstone must outlive the bundle it stands for, otherwise the bundle could be
+/// received again. Thus, it expires once the bundle lifetime and the horizon have passed.
+fn tombstone_expired(bp: &BundlePack, horizon: Duration, now: u64) -> bool {
+    let mut keep_until = bp.received_time;
+    if bp.creation_time > 0 {
+        let expiry = bp.creation_time + bp.lifetime + bp7::dtntime::SECONDS1970_TO2K * 1000;
+        keep_until = keep_until.max(expiry);
+    }
+    keep_until + (horizon.as_millis() as u64) < now
+}
+
+/// Decodes a stored bundle and verifies its CRCs.
+pub fn verify_bundle(bid: &str, buf: Vec<u8>) -> Result<(), String> {
+    let mut bndl = Bundle::try_from(buf).map_err(|err| format!("invalid CBOR: {}", err))?;
+    if bndl.id() != bid {
+        return Err(format!("stored under wrong ID {}", bndl.id()));
+    }
+    if let Err(errs) = bndl.validate() {
+        return Err(format!("invalid bundle: {:?}", errs));
+    }
+    if !bndl.crc_valid() {
+        return Err("CRC mismatch".into());
+    }
+    Ok(())
+}
+
+/// Incremental store check, one entry at a time.
+///
+/// Used by [`check`] and to check the global store without holding its lock for the whole
+/// run: the caller fetches each entry under a short lock and hands it to the checker.
+/// All metadata entries must be checked before the stored bundles.
+#[derive(Debug)]
+pub struct StoreChecker {
+    horizon: Duration,
+    now: u64,
+    blob_ids: HashSet<String>,
+    meta_ids: HashSet<String>,
+    report: StoreCheckReport,
+}
+
+impl StoreChecker {
+    /// Starts a check of a store containing the bundles `blob_ids`.
+    pub fn new(blob_ids: &[String], horizon: Duration) -> StoreChecker {
+        StoreChecker {
+            horizon,
+            now: unix_millis(),
+            blob_ids: blob_ids.iter().cloned().collect(),
+            meta_ids: HashSet::new(),
+            report: StoreCheckReport::default(),
+        }
+    }
+
+    /// Checks a metadata entry, `meta` is `None` if it cannot be decoded.
+    pub fn check_metadata(&mut self, bid: &str, meta: Option<BundlePack>) {
+        match meta {
+            Some(bp) => {
+                if bp.has_constraint(Constraint::Deleted) {
+                    if !self.horizon.is_zero() && tombstone_expired(&bp, self.horizon, self.now) {
+                        self.report.expired_tombstones.push(bid.to_string());
+                    }
+                } else if !self.blob_ids.contains(bid) {
+                    self.report.missing_bundles.push(bid.to_string());
+                }
+            }
+            None => self.report.corrupt_metadata.push(bid.to_string()),
+        }
+        self.meta_ids.insert(bid.to_string());
+    }
+
+    /// Checks a stored bundle, `get_raw` is only called for bundles with metadata.
+    ///
+    /// A bundle `get_raw` does not return anymore was deleted since the check started and is
+    /// skipped.
+    pub fn check_blob<F: FnOnce() -> Option<Vec<u8>>>(&mut self, bid: &str, get_raw: F) {
+        if !self.meta_ids.contains(bid) {
+            self.report.orphaned_bundles.push(bid.to_string());
+            return;
+        }
+        let Some(buf) = get_raw() else {
+            debug!("Bundle {} is gone, skipping it", bid);
+            return;
+        };
+        self.report.checked += 1;
+        if let Err(err) = verify_bundle(bid, buf) {
+            warn!("Corrupt bundle {} in store: {}", bid, err);
+            self.report.corrupt_bundles.push(bid.to_string());
+        }
+    }
+
+    pub fn finish(self) -> StoreCheckReport {
+        debug!("store check: {:?}", self.report);
+        self.report
+    }
+}
+
+/// Checks again whether the finding `reason` still applies to `bid`.
+///
+/// Stores checked entry by entry change during the check, e.g., bundles are deleted and leave
+/// a tombstone. Call this under the store lock right before purging an entry.
+pub fn confirm<S: BundleStore + ?Sized>(
+    store: &S,
+    bid: &str,
+    reason: EvictionReason,
+    horizon: Duration,
+) -> bool {
+    let meta = store.get_metadata(bid);
+    let deleted = meta
+        .as_ref()
+        .is_some_and(|bp| bp.has_constraint(Constraint::Deleted));
+    match reason {
+        EvictionReason::TombstoneExpired => meta.is_some_and(|bp| {
+            bp.has_constraint(Constraint::Deleted) && tombstone_expired(&bp, horizon, unix_millis())
+        }),
+        EvictionReason::MissingBundle => meta.is_some() && !deleted && store.get_raw(bid).is_none(),
+        EvictionReason::OrphanedBundle => meta.is_none() && store.get_raw(bid).is_some(),
+        EvictionReason::Corrupt => match meta {
+            // metadata that does not decode
+            None => true,
+            Some(_) if deleted => false,
+            Some(_) => store
+                .get_raw(bid)
+                .is_some_and(|buf| verify_bundle(bid, buf).is_err()),
+        },
+        EvictionReason::Expired => false,
+    }
+}
+
+/// Checks the integrity of `store`.
+///
+/// Tombstones are reported as expired `horizon` after the lifetime of their bundle ended,
+/// a zero `horizon` keeps them forever.
+pub fn check<S: BundleStore + ?Sized>(store: &S, horizon: Duration) -> StoreCheckReport {
+    let blob_ids = store.blob_ids();
+    let mut checker = StoreChecker::new(&blob_ids, horizon);
+    for bid in store.metadata_ids() {
+        checker.check_metadata(&bid, store.get_metadata(&bid));
+    }
+    for bid in blob_ids {
+        checker.check_blob(&bid, || store.get_raw(&bid));
+    }
+    checker.finish()
+}
+
+/// Purges all entries found by `check` and compacts the store.
+///
+/// Broken entries are removed without leaving a tombstone, so the bundles can be received again.
+/// Returns the number of purged entries.
+pub fn repair<S: BundleStore + ?Sized>(store: &mut S, report: &StoreCheckReport) -> Result<usize> {
+    let affected = report.affected();
+    for bid in &affected {
+        store.purge(bid)?;
+    }
+    store.compact()?;
+    if !affected.is_empty() {
+        info!("Store repair purged {} entries", affected.len());
+    }
+    Ok(affected.len())
+}
@@ -96,6 +96,23 @@ impl BundleStore for InMemoryBundleStore {
     fn get_metadata(&self, bpid: &str) -> Option<BundlePack> {
         self.metadata.get(bpid).cloned()
     }
+
+    fn purge(&mut self, bid: &str) -> Result<()> {
+        self.bundles.remove(bid);
+        self.metadata.remove(bid);
+        Ok(())
+    }
+
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        self.metadata.insert(bp.id().to_string(), bp.clone());
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        self.bundles.shrink_to_fit();
+        self.metadata.shrink_to_fit();
+        Ok(())
+    }
 }
 
 impl InMemoryBundleStore {
@@ -5,7 +5,13 @@ use bp7::Bundle;
 use enum_dispatch::enum_dispatch;
 use std::collections::HashSet;
 use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::path::Path;
 
+pub mod archive;
+#[cfg(feature = "store_encryption")]
+mod encrypted;
+pub mod maintenance;
 mod mem;
 pub use mem::InMemoryBundleStore;
 
@@ -15,12 +21,21 @@ mod sled;
 #[cfg(feature = "store_sneakers")]
 mod sneakers;
 
+#[cfg(feature = "store_sqlite")]
+mod sqlite;
+
 #[cfg(feature = "store_sled")]
 pub use self::sled::SledBundleStore;
 
 #[cfg(feature = "store_sneakers")]
 pub use sneakers::SneakersBundleStore;
 
+#[cfg(feature = "store_sqlite")]
+pub use self::sqlite::SqliteBundleStore;
+
+#[cfg(feature = "store_encryption")]
+pub use encrypted::{EncryptedBundleStore, StoreKeys, STORE_KEY_ENV};
+
 #[enum_dispatch]
 #[derive(Debug)]
 pub enum BundleStoresEnum {
@@ -29,6 +44,10 @@ pub enum BundleStoresEnum {
     InMemoryBundleStore,
     #[cfg(feature = "store_sneakers")]
     SneakersBundleStore,
+    #[cfg(feature = "store_sqlite")]
+    SqliteBundleStore,
+    #[cfg(feature = "store_encryption")]
+    EncryptedBundleStore,
 }
 
 #[enum_dispatch(BundleStoresEnum)]
@@ -39,12 +58,33 @@ pub trait BundleStore: Debug {
     fn count(&self) -> u64;
     fn all_ids(&self) -> Vec<String>;
     fn has_item(&self, bid: &str) -> bool;
-    fn pending(&self) -> Vec<String>;
+    fn pending(&self) -> Vec<String> {
+        let reassembly: HashSet<String> = self
+            .query_constraint(Constraint::ReassemblyPending)
+            .into_iter()
+            .collect();
+        let mut bids = self.query_constraint(Constraint::ForwardPending);
+        bids.extend(self.query_constraint(Constraint::Contraindicated));
+        bids.sort_unstable();
+        bids.dedup();
+        bids.retain(|bid| !reassembly.contains(bid));
+        bids
+    }
     fn forwarding(&self) -> Vec<String> {
-        let criteria: HashSet<Constraint> = vec![Constraint::ForwardPending].into_iter().collect();
-        self.filter(&criteria)
+        self.query_constraint(Constraint::ForwardPending)
+    }
+    fn filter(&self, criteria: &HashSet<Constraint>) -> Vec<String> {
+        let mut criteria = criteria.iter();
+        let mut bids = match criteria.next() {
+            Some(c) => self.query_constraint(*c),
+            None => return self.metadata_ids(),
+        };
+        for c in criteria {
+            let matching: HashSet<String> = self.query_constraint(*c).into_iter().collect();
+            bids.retain(|bid| matching.contains(bid));
+        }
+        bids
     }
-    fn filter(&self, criteria: &HashSet<Constraint>) -> Vec<String>;
     fn bundles(&self) -> Vec<BundlePack>;
     fn bundles_status(&self) -> Vec<String> {
         self.bundles().iter().map(|bp| bp.to_string()).collect()
@@ -61,18 +101,86 @@ pub trait BundleStore: Debug {
             .collect()
     }
     fn filter_addr(&self, criteria: &str) -> Vec<String> {
+        self.query_address(criteria)
+    }
+    fn get_bundle(&self, bpid: &str) -> Option<Bundle>;
+    fn get_metadata(&self, bpid: &str) -> Option<BundlePack>;
+
+    /// IDs of all bundles with `constraint`.
+    fn query_constraint(&self, constraint: Constraint) -> Vec<String> {
         self.bundles()
-            .iter()
+            .into_iter()
+            .filter(|bp| bp.has_constraint(constraint))
+            .map(|bp| bp.id)
+            .collect()
+    }
+    /// IDs of all bundles not deleted with destination `dst`.
+    fn query_destination(&self, dst: &str) -> Vec<String> {
+        self.bundles()
+            .into_iter()
+            .filter(|bp| !bp.has_constraint(Constraint::Deleted))
+            .filter(|bp| bp.destination.to_string() == dst)
+            .map(|bp| bp.id)
+            .collect()
+    }
+    /// IDs of all bundles not deleted with a source or destination containing `addr`.
+    fn query_address(&self, addr: &str) -> Vec<String> {
+        self.bundles()
+            .into_iter()
             .filter(|bp| !bp.has_constraint(Constraint::Deleted))
             .filter(|bp| {
-                bp.source.to_string().contains(criteria)
-                    || bp.destination.to_string().contains(criteria)
+                bp.source.to_string().contains(addr) || bp.destination.to_string().contains(addr)
             })
-            .map(|bp| bp.id.clone())
+            .map(|bp| bp.id)
             .collect()
     }
-    fn get_bundle(&self, bpid: &str) -> Option<Bundle>;
-    fn get_metadata(&self, bpid: &str) -> Option<BundlePack>;
+    /// IDs of all bundles not deleted whose lifetime ends before `time` as DTN time in ms.
+    ///
+    /// Bundles with a lifetime of zero never expire.
+    fn query_expiring_before(&self, time: u64) -> Vec<String> {
+        self.bundles()
+            .into_iter()
+            .filter(|bp| !bp.has_constraint(Constraint::Deleted))
+            .filter(|bp| bp.lifetime != 0 && bp.creation_time + bp.lifetime < time)
+            .map(|bp| bp.id)
+            .collect()
+    }
+    /// Metadata of up to `limit` bundles ordered by creation time and ID, skipping `offset` bundles.
+    fn query_page(&self, offset: usize, limit: usize) -> Vec<BundlePack> {
+        let mut bundles = self.bundles();
+        bundles.sort_unstable_by(|a, b| (a.creation_time, &a.id).cmp(&(b.creation_time, &b.id)));
+        bundles.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// IDs of all stored bundles, independent of their metadata.
+    fn blob_ids(&self) -> Vec<String> {
+        self.all_ids()
+    }
+    /// IDs of all metadata entries, including tombstones of deleted bundles.
+    fn metadata_ids(&self) -> Vec<String> {
+        self.bundles().into_iter().map(|bp| bp.id).collect()
+    }
+    /// The encoded bundle as it is stored.
+    fn get_raw(&self, bpid: &str) -> Option<Vec<u8>> {
+        self.get_bundle(bpid).map(|mut bndl| bndl.to_cbor())
+    }
+    /// Removes bundle and metadata without leaving a tombstone.
+    fn purge(&mut self, bid: &str) -> Result<()>;
+    /// Reclaims space freed by previous removals.
+    fn compact(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Stores metadata without a bundle, used to restore tombstones.
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()>;
+
+    /// Writes all bundles including their metadata to an archive.
+    fn export(&self, w: &mut dyn Write, node: &str) -> Result<archive::ArchiveSummary> {
+        archive::export_store(self, w, node)
+    }
+    /// Adds all bundles from an archive that are not yet in the store.
+    fn import(&mut self, r: &mut dyn Read) -> Result<archive::ArchiveSummary> {
+        Ok(archive::import_store(self, r)?.1)
+    }
 }
 
 pub fn bundle_stores() -> Vec<&'static str> {
@@ -82,6 +190,8 @@ pub fn bundle_stores() -> Vec<&'static str> {
         "sled",
         #[cfg(feature = "store_sneakers")]
         "sneakers",
+        #[cfg(feature = "store_sqlite")]
+        "sqlite",
     ]
 }
 
@@ -92,6 +202,48 @@ pub fn new(bundlestore: &str) -> BundleStoresEnum {
         "sled" => sled::SledBundleStore::new().into(),
         #[cfg(feature = "store_sneakers")]
         "sneakers" => sneakers::SneakersBundleStore::new().into(),
+        #[cfg(feature = "store_sqlite")]
+        "sqlite" => sqlite::SqliteBundleStore::new().into(),
         _ => panic!("Unknown bundle store {}", bundlestore),
     }
 }
+
+/// Creates the store `bundlestore`, encrypted if store keys are configured.
+///
+/// Keys are taken from `key_file` or the `DTN7_STORE_KEY` environment variable,
+/// bundles sealed with an old key are resealed with the current one.
+pub fn open(bundlestore: &str, key_file: Option<&Path>) -> Result<BundleStoresEnum> {
+    #[cfg(feature = "store_encryption")]
+    if let Some(keys) = StoreKeys::load(key_file)? {
+        let mut store = EncryptedBundleStore::new(new(bundlestore), keys)?;
+        let rotated = store.rotate()?;
+        if rotated > 0 {
+            log::info!(
+                "Resealed {} bundles with store key {}",
+                rotated,
+                store.keys().current()
+            );
+        }
+        return Ok(store.into());
+    }
+    #[cfg(not(feature = "store_encryption"))]
+    if key_file.is_some() {
+        anyhow::bail!("store encryption is not supported by this build");
+    }
+    Ok(new(bundlestore))
+}
+
+/// Opens the store `bundlestore` for reading only, e.g., to export or migrate it.
+///
+/// Unlike [`open`], encrypted stores are neither resealed nor repaired.
+pub fn open_read_only(bundlestore: &str, key_file: Option<&Path>) -> Result<BundleStoresEnum> {
+    #[cfg(feature = "store_encryption")]
+    if let Some(keys) = StoreKeys::load(key_file)? {
+        return Ok(EncryptedBundleStore::read_only(new(bundlestore), keys)?.into());
+    }
+    #[cfg(not(feature = "store_encryption"))]
+    if key_file.is_some() {
+        anyhow::bail!("store encryption is not supported by this build");
+    }
+    Ok(new(bundlestore))
+}
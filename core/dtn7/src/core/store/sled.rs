@@ -120,22 +120,57 @@ impl BundleStore for SledBundleStore {
             .iter()
             .values()
             .filter_map(Result::ok)
-            .map(|k| BundlePack::from(k.as_ref()))
+            .filter_map(|k| serde_cbor::from_slice(k.as_ref()).ok())
             .collect()
     }
 
     fn get_bundle(&self, bpid: &str) -> Option<bp7::Bundle> {
-        self.bundles
+        self.get_raw(bpid)
+            .and_then(|buf| Bundle::try_from(buf).ok())
+    }
+
+    fn get_metadata(&self, bpid: &str) -> Option<BundlePack> {
+        self.metadata
             .get(bpid)
-            .map(|b| Bundle::try_from(b.unwrap().as_ref().to_vec()).unwrap())
             .ok()
+            .flatten()
+            .and_then(|buf| serde_cbor::from_slice(buf.as_ref()).ok())
     }
 
-    fn get_metadata(&self, bpid: &str) -> Option<BundlePack> {
+    fn metadata_ids(&self) -> Vec<String> {
         self.metadata
+            .iter()
+            .keys()
+            .filter_map(Result::ok)
+            .map(|k| String::from_utf8_lossy(k.as_ref()).into())
+            .collect()
+    }
+
+    fn get_raw(&self, bpid: &str) -> Option<Vec<u8>> {
+        self.bundles
             .get(bpid)
-            .map(|b| b.unwrap().as_ref().into())
             .ok()
+            .flatten()
+            .map(|buf| buf.to_vec())
+    }
+
+    fn purge(&mut self, bid: &str) -> Result<()> {
+        self.bundles.remove(bid)?;
+        self.metadata.remove(bid)?;
+        Ok(())
+    }
+
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        self.metadata.insert(bp.id(), bp.to_cbor())?;
+        self.metadata.flush()?;
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        // sled reclaims space of removed entries in the background when segments are flushed
+        self.bundles.flush()?;
+        self.metadata.flush()?;
+        Ok(())
     }
 }
 
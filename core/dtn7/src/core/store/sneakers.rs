@@ -5,9 +5,11 @@ use anyhow::{bail, Result};
 use bp7::{Bundle, EndpointID};
 use d7sneakers::{Constraints, SneakerWorld};
 use log::debug;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct SneakersBundleStore {
@@ -149,6 +151,86 @@ impl BundleStore for SneakersBundleStore {
         Some(bp)
         //}
     }
+
+    fn blob_ids(&self) -> Vec<String> {
+        let known: HashMap<String, String> = self
+            .store
+            .db
+            .ids()
+            .into_iter()
+            .filter_map(|bid| {
+                let path = self.store.db.path_for_bundle(&bid)?;
+                let name = Path::new(&path).file_name()?.to_str()?.to_string();
+                Some((name, bid))
+            })
+            .collect();
+        let dirs = [
+            self.store.fs.path_single(),
+            self.store.fs.path_administrative(),
+            self.store.fs.path_group(),
+        ];
+        let mut files = Vec::new();
+        for dir in dirs.iter() {
+            bundle_files(dir, &mut files);
+        }
+        files
+            .into_iter()
+            .filter_map(|file| {
+                let name = file.file_name()?.to_str()?;
+                if let Some(bid) = known.get(name) {
+                    return Some(bid.clone());
+                }
+                // files without database entry are identified by their content if possible,
+                // otherwise by their name, which is enough to purge them
+                let bid = fs::read(&file)
+                    .ok()
+                    .and_then(|buf| Bundle::try_from(buf).ok())
+                    .map(|bndl| bndl.id())
+                    .unwrap_or_else(|| name.trim_end_matches(".bundle").to_string());
+                Some(bid)
+            })
+            .collect()
+    }
+
+    fn metadata_ids(&self) -> Vec<String> {
+        self.store.db.ids()
+    }
+
+    fn get_raw(&self, bpid: &str) -> Option<Vec<u8>> {
+        self.store
+            .db
+            .path_for_bundle(bpid)
+            .and_then(|path| std::fs::read(path).ok())
+    }
+
+    fn purge(&mut self, bid: &str) -> Result<()> {
+        // looking up the file by its ID walks the whole store directory
+        let removed = match self.store.db.path_for_bundle(bid) {
+            Some(path) => fs::remove_file(path).map_err(|err| err.into()),
+            None => self.store.fs.remove_bundle(bid),
+        };
+        if let Err(err) = removed {
+            debug!("no bundle file to purge for {}: {}", bid, err);
+        }
+        if self.store.db.exists(bid) {
+            self.store.db.delete(bid)?;
+        }
+        Ok(())
+    }
+
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        if self.store.db.exists(bp.id()) {
+            self.update_metadata(bp)
+        } else {
+            // sneakers removes deleted bundles completely, thus, there are no tombstones
+            debug!("Not storing metadata of {} without bundle", bp.id());
+            Ok(())
+        }
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        self.store.sync()
+    }
 }
 
 impl SneakersBundleStore {
@@ -162,6 +244,21 @@ impl SneakersBundleStore {
     }
 }
 
+/// Collects all bundle files below `dir`.
+fn bundle_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            bundle_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "bundle") {
+            files.push(path);
+        }
+    }
+}
+
 impl Default for SneakersBundleStore {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,388 @@
+use super::BundleStore;
+use crate::core::bundlepack::{BundlePack, Constraint};
+use crate::CONFIG;
+use anyhow::{bail, Result};
+use bp7::{Bundle, EndpointID};
+use log::{debug, error};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bundles (
+    id TEXT PRIMARY KEY NOT NULL,
+    source TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    creation_time INTEGER NOT NULL,
+    lifetime INTEGER NOT NULL,
+    expires INTEGER,
+    received_time INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    administrative INTEGER NOT NULL,
+    payload BLOB
+);
+CREATE INDEX IF NOT EXISTS bundles_source ON bundles(source);
+CREATE INDEX IF NOT EXISTS bundles_destination ON bundles(destination);
+CREATE INDEX IF NOT EXISTS bundles_creation ON bundles(creation_time, id);
+CREATE INDEX IF NOT EXISTS bundles_expires ON bundles(expires);
+CREATE INDEX IF NOT EXISTS bundles_size ON bundles(size);
+CREATE TABLE IF NOT EXISTS constraints (
+    id TEXT NOT NULL REFERENCES bundles(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (id, name)
+);
+CREATE INDEX IF NOT EXISTS constraints_name ON constraints(name);
+";
+
+const METADATA_COLUMNS: &str =
+    "id, source, destination, creation_time, lifetime, received_time, size, administrative";
+
+const ALL_CONSTRAINTS: [Constraint; 6] = [
+    Constraint::DispatchPending,
+    Constraint::ForwardPending,
+    Constraint::ReassemblyPending,
+    Constraint::Contraindicated,
+    Constraint::LocalEndpoint,
+    Constraint::Deleted,
+];
+
+fn constraint_name(c: Constraint) -> String {
+    format!("{:?}", c)
+}
+
+fn constraint_from_name(name: &str) -> Option<Constraint> {
+    ALL_CONSTRAINTS
+        .iter()
+        .find(|c| constraint_name(**c) == name)
+        .copied()
+}
+
+/// Decodes a row selected with `METADATA_COLUMNS`, constraints are added by the caller.
+fn metadata_from_row(row: &Row) -> rusqlite::Result<BundlePack> {
+    let eid = |idx: usize| -> rusqlite::Result<EndpointID> {
+        let s: String = row.get(idx)?;
+        Ok(EndpointID::try_from(s).unwrap_or_default())
+    };
+    Ok(BundlePack {
+        id: row.get(0)?,
+        source: eid(1)?,
+        destination: eid(2)?,
+        creation_time: row.get::<_, i64>(3)? as u64,
+        lifetime: row.get::<_, i64>(4)? as u64,
+        received_time: row.get::<_, i64>(5)? as u64,
+        size: row.get::<_, i64>(6)? as usize,
+        administrative: row.get(7)?,
+        constraints: HashSet::new(),
+    })
+}
+
+/// Bundle store keeping metadata in indexed columns and bundles as blobs of a SQLite database.
+#[derive(Debug)]
+pub struct SqliteBundleStore {
+    conn: Connection,
+}
+
+impl SqliteBundleStore {
+    pub fn new() -> SqliteBundleStore {
+        let mut wd = CONFIG.lock().workdir.clone();
+        wd.push("store.sqlite3");
+        SqliteBundleStore::open(&wd).expect("open sqlite bundle store")
+    }
+
+    pub fn open(path: &Path) -> Result<SqliteBundleStore> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteBundleStore { conn })
+    }
+
+    fn ids(&self, sql: &str, params: impl rusqlite::Params) -> Vec<String> {
+        let res = self.conn.prepare_cached(sql).and_then(|mut stmt| {
+            stmt.query_map(params, |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+        });
+        res.unwrap_or_else(|err| {
+            error!("could not query sqlite database: {}", err);
+            Vec::new()
+        })
+    }
+
+    fn constraints_of(&self, bid: &str) -> rusqlite::Result<HashSet<Constraint>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT name FROM constraints WHERE id = ?1")?;
+        let names = stmt.query_map([bid], |row| row.get::<_, String>(0))?;
+        Ok(names
+            .filter_map(|name| name.ok())
+            .filter_map(|name| constraint_from_name(&name))
+            .collect())
+    }
+
+    fn all_constraints(&self) -> rusqlite::Result<HashMap<String, HashSet<Constraint>>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id, name FROM constraints")?;
+        let mut constraints: HashMap<String, HashSet<Constraint>> = HashMap::new();
+        for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))? {
+            let (bid, name): (String, String) = row?;
+            if let Some(c) = constraint_from_name(&name) {
+                constraints.entry(bid).or_default().insert(c);
+            }
+        }
+        Ok(constraints)
+    }
+
+    fn metadata_where(&self, condition: &str, params: impl rusqlite::Params) -> Vec<BundlePack> {
+        let res = (|| {
+            // a single query for all constraints is cheaper than one per bundle for full scans
+            let mut constraints = if condition.is_empty() {
+                Some(self.all_constraints()?)
+            } else {
+                None
+            };
+            let mut stmt = self.conn.prepare_cached(&format!(
+                "SELECT {} FROM bundles {}",
+                METADATA_COLUMNS, condition
+            ))?;
+            let bundles = stmt
+                .query_map(params, metadata_from_row)?
+                .map(|bp| {
+                    let mut bp = bp?;
+                    bp.constraints = match constraints.as_mut() {
+                        Some(constraints) => constraints.remove(&bp.id).unwrap_or_default(),
+                        None => self.constraints_of(&bp.id)?,
+                    };
+                    Ok(bp)
+                })
+                .collect::<rusqlite::Result<Vec<BundlePack>>>();
+            bundles
+        })();
+        res.unwrap_or_else(|err| {
+            error!("could not query sqlite database: {}", err);
+            Vec::new()
+        })
+    }
+
+    fn write_metadata(&mut self, bp: &BundlePack, payload: Option<Option<&[u8]>>) -> Result<()> {
+        let expires = if bp.lifetime == 0 {
+            None
+        } else {
+            Some((bp.creation_time + bp.lifetime) as i64)
+        };
+        let tx = self.conn.transaction()?;
+        let changed = tx.execute(
+            "UPDATE bundles SET source = ?2, destination = ?3, creation_time = ?4, lifetime = ?5,
+                expires = ?6, received_time = ?7, size = ?8, administrative = ?9 WHERE id = ?1",
+            params![
+                bp.id,
+                bp.source.to_string(),
+                bp.destination.to_string(),
+                bp.creation_time as i64,
+                bp.lifetime as i64,
+                expires,
+                bp.received_time as i64,
+                bp.size as i64,
+                bp.administrative,
+            ],
+        )?;
+        if changed == 0 {
+            tx.execute(
+                "INSERT INTO bundles (id, source, destination, creation_time, lifetime, expires,
+                    received_time, size, administrative) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    bp.id,
+                    bp.source.to_string(),
+                    bp.destination.to_string(),
+                    bp.creation_time as i64,
+                    bp.lifetime as i64,
+                    expires,
+                    bp.received_time as i64,
+                    bp.size as i64,
+                    bp.administrative,
+                ],
+            )?;
+        }
+        if let Some(payload) = payload {
+            tx.execute(
+                "UPDATE bundles SET payload = ?2 WHERE id = ?1",
+                params![bp.id, payload],
+            )?;
+        }
+        tx.execute("DELETE FROM constraints WHERE id = ?1", [&bp.id])?;
+        for c in bp.constraints.iter() {
+            tx.execute(
+                "INSERT INTO constraints (id, name) VALUES (?1, ?2)",
+                params![bp.id, constraint_name(*c)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Default for SqliteBundleStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BundleStore for SqliteBundleStore {
+    fn push(&mut self, bndl: &Bundle) -> Result<()> {
+        let bid = bndl.id();
+        let buf = bndl.clone().to_cbor();
+        if self.has_item(&bid) {
+            debug!("Bundle {} already in store, updating it!", bid);
+            self.conn.execute(
+                "UPDATE bundles SET payload = ?2 WHERE id = ?1",
+                params![bid, buf],
+            )?;
+            return Ok(());
+        }
+        let bp = BundlePack::from(bndl);
+        self.write_metadata(&bp, Some(Some(&buf)))
+    }
+    fn update_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        if self.get_metadata(bp.id()).is_none() {
+            bail!("Bundle not in store!");
+        }
+        self.write_metadata(bp, None)
+    }
+    fn remove(&mut self, bid: &str) -> Result<()> {
+        if let Some(mut meta) = self.get_metadata(bid) {
+            meta.clear_constraints();
+            meta.add_constraint(Constraint::Deleted);
+            self.write_metadata(&meta, Some(None))
+        } else {
+            bail!("Bundle meta data not in store!");
+        }
+    }
+    fn count(&self) -> u64 {
+        self.conn
+            .query_row(
+                "SELECT COUNT(*) FROM bundles WHERE payload IS NOT NULL",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap_or(0) as u64
+    }
+    fn all_ids(&self) -> Vec<String> {
+        self.ids("SELECT id FROM bundles WHERE payload IS NOT NULL", [])
+    }
+    fn has_item(&self, bid: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM bundles WHERE id = ?1 AND payload IS NOT NULL",
+                [bid],
+                |_| Ok(()),
+            )
+            .optional()
+            .unwrap_or_else(|err| {
+                error!("could not query sqlite database: {}", err);
+                None
+            })
+            .is_some()
+    }
+    fn bundles(&self) -> Vec<BundlePack> {
+        self.metadata_where("", [])
+    }
+    fn src_dst_ts(&self) -> Vec<String> {
+        let res = self
+            .conn
+            .prepare_cached("SELECT source, destination, creation_time, size FROM bundles")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| {
+                    Ok(format!(
+                        "{} {} {} {}",
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?
+                    ))
+                })?
+                .collect()
+            });
+        res.unwrap_or_default()
+    }
+    fn get_bundle(&self, bpid: &str) -> Option<Bundle> {
+        self.get_raw(bpid)
+            .and_then(|buf| Bundle::try_from(buf).ok())
+    }
+    fn get_metadata(&self, bpid: &str) -> Option<BundlePack> {
+        let mut bp = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM bundles WHERE id = ?1", METADATA_COLUMNS),
+                [bpid],
+                metadata_from_row,
+            )
+            .optional()
+            .ok()
+            .flatten()?;
+        bp.constraints = self.constraints_of(bpid).ok()?;
+        Some(bp)
+    }
+
+    fn metadata_ids(&self) -> Vec<String> {
+        self.ids("SELECT id FROM bundles", [])
+    }
+    fn get_raw(&self, bpid: &str) -> Option<Vec<u8>> {
+        self.conn
+            .query_row(
+                "SELECT payload FROM bundles WHERE id = ?1 AND payload IS NOT NULL",
+                [bpid],
+                |row| row.get(0),
+            )
+            .optional()
+            .ok()
+            .flatten()
+    }
+    fn purge(&mut self, bid: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM bundles WHERE id = ?1", [bid])?;
+        Ok(())
+    }
+    fn compact(&mut self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+    fn insert_metadata(&mut self, bp: &BundlePack) -> Result<()> {
+        self.write_metadata(bp, None)
+    }
+
+    fn query_constraint(&self, constraint: Constraint) -> Vec<String> {
+        self.ids(
+            "SELECT id FROM constraints WHERE name = ?1",
+            [constraint_name(constraint)],
+        )
+    }
+    fn query_destination(&self, dst: &str) -> Vec<String> {
+        self.ids(
+            "SELECT id FROM bundles WHERE destination = ?1 AND payload IS NOT NULL",
+            [dst],
+        )
+    }
+    fn query_address(&self, addr: &str) -> Vec<String> {
+        self.ids(
+            "SELECT id FROM bundles WHERE payload IS NOT NULL
+                AND (instr(source, ?1) > 0 OR instr(destination, ?1) > 0)",
+            [addr],
+        )
+    }
+    fn query_expiring_before(&self, time: u64) -> Vec<String> {
+        self.ids(
+            "SELECT id FROM bundles WHERE expires < ?1 AND payload IS NOT NULL",
+            [time as i64],
+        )
+    }
+    fn query_page(&self, offset: usize, limit: usize) -> Vec<BundlePack> {
+        let limit = limit.min(i64::MAX as usize) as i64;
+        let page: Vec<BundlePack> = self.metadata_where(
+            "ORDER BY creation_time, id LIMIT ?1 OFFSET ?2",
+            params![limit, offset as i64],
+        );
+        page
+    }
+}
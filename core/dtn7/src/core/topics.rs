@@ -0,0 +1,180 @@
+//! Publish/subscribe on top of group endpoints.
+//!
+//! A topic such as `sensors/temperature` is the group endpoint `dtn://topics/~sensors/temperature`,
+//! registering that endpoint subscribes the node. Nodes advertise their topics in IPND beacons and
+//! relay the topics of their neighbours with an increased hop count, so topic bundles are only
+//! forwarded towards peers with subscribers somewhere behind them.
+
+use crate::cla::ClaSenderTask;
+use crate::core::application_agent::ApplicationAgent;
+use crate::core::DtnPeer;
+use crate::ipnd::services::Service;
+use crate::{CONFIG, DTNCORE, PEERS, STATS};
+use anyhow::{bail, Result};
+use bp7::flags::BundleControlFlags;
+use bp7::EndpointID;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// Node name of all topic endpoints.
+pub const TOPIC_NODE: &str = "topics";
+/// Topics learned from peers are advertised up to this distance in hops.
+pub const TOPIC_MAX_HOPS: u8 = 8;
+/// Upper bound for the topic advertisement in beacons, closer topics take precedence.
+pub const TOPIC_ADVERTISEMENT_MAX: usize = 4096;
+
+/// Topics are made of alphanumeric characters, `-`, `_` and `.` with `/` separating levels.
+pub fn is_valid_topic(topic: &str) -> bool {
+    topic.len() <= 64
+        && topic
+            .split('/')
+            .all(|level| !level.is_empty() && level != "." && level != "..")
+        && topic
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+}
+
+/// Group endpoint of a topic.
+///
+/// ```
+/// use dtn7::core::topics::{topic_eid, topic_of};
+///
+/// let eid = topic_eid("sensors/temperature").unwrap();
+/// assert_eq!(eid.to_string(), "dtn://topics/~sensors/temperature");
+/// assert_eq!(topic_of(&eid).as_deref(), Some("sensors/temperature"));
+/// ```
+pub fn topic_eid(topic: &str) -> Result<EndpointID> {
+    if !is_valid_topic(topic) {
+        bail!("invalid topic {:?}", topic);
+    }
+    Ok(EndpointID::try_from(format!(
+        "dtn://{}/~{}",
+        TOPIC_NODE, topic
+    ))?)
+}
+
+/// Topic of a topic endpoint, none for all other endpoints.
+pub fn topic_of(eid: &EndpointID) -> Option<String> {
+    if eid.node()? != TOPIC_NODE {
+        return None;
+    }
+    let topic = eid.service_name()?.strip_prefix('~')?.to_string();
+    is_valid_topic(&topic).then_some(topic)
+}
+
+/// Sends data to all subscribers of a topic and returns the bundle ID.
+pub async fn publish(topic: &str, data: Vec<u8>, lifetime: Duration) -> Result<String> {
+    let dst = topic_eid(topic)?;
+    let src = CONFIG.lock().host_eid.clone();
+    let mut bndl = bp7::bundle::new_std_payload_bundle(src, dst, data);
+    // delivery reports of every subscriber would flood the publisher
+    bndl.primary.bundle_control_flags = BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED.bits();
+    bndl.primary.lifetime = lifetime;
+    let bid = bndl.id();
+    crate::core::processing::send_bundle(bndl).await;
+    STATS.lock().node.bundles.bundles_created += 1;
+    Ok(bid)
+}
+
+/// Topics with a registered endpoint on this node.
+pub fn local_topics() -> BTreeSet<String> {
+    DTNCORE
+        .lock()
+        .endpoints
+        .iter()
+        .filter_map(|aa| topic_of(aa.eid()))
+        .collect()
+}
+
+/// Parses a topic advertisement of the form `<topic>:<hops> ...`.
+pub fn parse_interest(payload: &str) -> BTreeMap<String, u8> {
+    payload
+        .split_whitespace()
+        .filter_map(|entry| {
+            let (topic, hops) = entry.rsplit_once(':')?;
+            Some((topic.to_string(), hops.parse().ok()?))
+        })
+        .filter(|(topic, _)| is_valid_topic(topic))
+        .collect()
+}
+
+/// Formats topics for an advertisement, dropping the most distant ones beyond `TOPIC_ADVERTISEMENT_MAX`.
+pub fn format_interest(topics: &BTreeMap<String, u8>) -> String {
+    let mut entries: Vec<(&String, &u8)> = topics.iter().collect();
+    entries.sort_by_key(|(_, hops)| **hops);
+    let mut payload = String::new();
+    for (topic, hops) in entries {
+        let entry = format!("{}:{}", topic, hops);
+        if payload.len() + entry.len() + 1 > TOPIC_ADVERTISEMENT_MAX {
+            break;
+        }
+        if !payload.is_empty() {
+            payload.push(' ');
+        }
+        payload.push_str(&entry);
+    }
+    payload
+}
+
+/// Topics advertised by a peer, none if the peer does not take part in topic advertisement.
+pub fn peer_interest(peer: &DtnPeer) -> Option<BTreeMap<String, u8>> {
+    peer.services
+        .get(&Service::TOPICS)
+        .map(|payload| parse_interest(payload))
+}
+
+/// Topics to advertise in beacons: local subscriptions and those relayed from peers.
+pub fn advertisement() -> BTreeMap<String, u8> {
+    let mut topics: BTreeMap<String, u8> = local_topics().into_iter().map(|t| (t, 0)).collect();
+    for peer in PEERS.lock().values() {
+        for (topic, hops) in peer_interest(peer).unwrap_or_default() {
+            if hops >= TOPIC_MAX_HOPS {
+                continue;
+            }
+            let known = topics.entry(topic).or_insert(hops + 1);
+            *known = (*known).min(hops + 1);
+        }
+    }
+    topics
+}
+
+/// Peers without topic advertisement get all topic bundles, others only those they are interested in.
+pub fn is_interested(next_hop: &EndpointID, topic: &str) -> bool {
+    let Some(node) = next_hop.node() else {
+        return true;
+    };
+    match PEERS.lock().get(&node).and_then(peer_interest) {
+        Some(interest) => interest.contains_key(topic),
+        None => true,
+    }
+}
+
+/// Drops senders towards peers without interest in the topic of a bundle.
+pub fn filter_senders(destination: &EndpointID, senders: &mut Vec<ClaSenderTask>) {
+    if let Some(topic) = topic_of(destination) {
+        senders.retain(|sender| is_interested(&sender.next_hop, &topic));
+    }
+}
+
+/// Topic state of the node as shown by `/topics`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicStatus {
+    pub local: BTreeSet<String>,
+    pub advertised: BTreeMap<String, u8>,
+    /// topics advertised by peers taking part in topic advertisement
+    pub peers: BTreeMap<String, BTreeMap<String, u8>>,
+}
+
+pub fn status() -> TopicStatus {
+    let peers = PEERS
+        .lock()
+        .iter()
+        .filter_map(|(name, peer)| Some((name.clone(), peer_interest(peer)?)))
+        .collect();
+    TopicStatus {
+        local: local_topics(),
+        advertised: advertisement(),
+        peers,
+    }
+}
@@ -0,0 +1,474 @@
+//! Transfer of large ADUs as a sequence of bundles.
+//!
+//! Every bundle of a transfer carries a transfer block with the transfer ID, its index and the
+//! byte offset of its payload. Receiving nodes write the payloads of locally delivered bundles
+//! into a spool file in `<workdir>/transfers`, so neither side has to keep the whole ADU in memory
+//! and a transfer survives restarts of dtnd. Transfers are identified by their source and ID.
+
+use crate::core::extension_blocks::{BlockAction, ExtensionBlockHandler};
+use crate::{store_push_bundle, CONFIG, STATS};
+use anyhow::{bail, Context, Result};
+use bp7::canonical::{new_canonical_block, CanonicalBlock, CanonicalBlockType};
+use bp7::flags::{BlockControlFlags, BundleControlFlags};
+use bp7::{Bundle, CanonicalData, CreationTimestamp, EndpointID};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Block type of the transfer block, taken from the private use range.
+pub const TRANSFER_BLOCK: CanonicalBlockType = 193;
+/// Payload size of the bundles of a transfer unless requested otherwise.
+pub const TRANSFER_CHUNK_SIZE_DEFAULT: usize = 1024 * 1024;
+/// Largest payload size of the bundles of a transfer.
+pub const TRANSFER_CHUNK_SIZE_MAX: usize = 64 * 1024 * 1024;
+/// Largest ADU accepted for reassembly, chunks reaching beyond it are rejected.
+pub const TRANSFER_SIZE_MAX: u64 = 1024 * 1024 * 1024 * 1024;
+
+/// Content of the transfer block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferInfo {
+    pub id: String,
+    pub index: u64,
+    /// position of the payload within the ADU
+    pub offset: u64,
+    /// set on the final bundle of a transfer
+    pub last: bool,
+    /// optional file name of the ADU
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl TransferInfo {
+    pub fn from_bundle(bundle: &Bundle) -> Option<TransferInfo> {
+        TransferInfo::from_block(bundle.extension_block_by_type(TRANSFER_BLOCK)?)
+    }
+    fn from_block(block: &CanonicalBlock) -> Option<TransferInfo> {
+        match block.data() {
+            CanonicalData::Unknown(data) => serde_cbor::from_slice(data).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a new random transfer ID.
+pub fn new_transfer_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Transfer IDs end up in file names, so only a safe subset of characters is accepted.
+pub fn is_valid_transfer_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Splits an ADU that is written in pieces into bundles of `chunk_size` payload bytes.
+///
+/// ```
+/// use bp7::EndpointID;
+/// use dtn7::core::transfer::{TransferInfo, Upload};
+/// use std::time::Duration;
+///
+/// let src = EndpointID::try_from("dtn://node1/").unwrap();
+/// let dst = EndpointID::try_from("dtn://node2/incoming").unwrap();
+/// let mut upload = Upload::new("t1".into(), src, dst, Duration::from_secs(60), 4, None);
+/// assert_eq!(upload.write(b"0123456789").len(), 2);
+/// assert_eq!(upload.offset(), 10);
+/// let last = upload.finish();
+/// let info = TransferInfo::from_bundle(&last).unwrap();
+/// assert_eq!((info.index, info.offset, info.last), (2, 8, true));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Upload {
+    id: String,
+    src: EndpointID,
+    dst: EndpointID,
+    lifetime: Duration,
+    chunk_size: usize,
+    name: Option<String>,
+    index: u64,
+    /// bytes already sent in bundles
+    sent: u64,
+    buf: Vec<u8>,
+    /// time data was last written
+    touched: Instant,
+}
+
+impl Upload {
+    pub fn new(
+        id: String,
+        src: EndpointID,
+        dst: EndpointID,
+        lifetime: Duration,
+        chunk_size: usize,
+        name: Option<String>,
+    ) -> Upload {
+        // chunk sizes come from clients, bundles beyond the maximum would not get far
+        Upload {
+            id,
+            src,
+            dst,
+            lifetime,
+            chunk_size: chunk_size.clamp(1, TRANSFER_CHUNK_SIZE_MAX),
+            name,
+            index: 0,
+            sent: 0,
+            buf: Vec::new(),
+            touched: Instant::now(),
+        }
+    }
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    /// Number of ADU bytes accepted so far, a resumed upload continues here.
+    pub fn offset(&self) -> u64 {
+        self.sent + self.buf.len() as u64
+    }
+    /// Number of bundles created so far.
+    pub fn bundles(&self) -> u64 {
+        self.index
+    }
+    /// Time since data was last written to the upload.
+    pub fn idle(&self) -> Duration {
+        self.touched.elapsed()
+    }
+    pub fn status(&self, complete: bool) -> UploadStatus {
+        UploadStatus {
+            transfer: self.id.clone(),
+            offset: self.offset(),
+            bundles: self.index,
+            complete,
+        }
+    }
+    /// Adds data to the ADU and returns the bundles for all completed chunks.
+    pub fn write(&mut self, mut data: &[u8]) -> Vec<Bundle> {
+        self.touched = Instant::now();
+        let mut bundles = Vec::new();
+        while !data.is_empty() {
+            let take = (self.chunk_size - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buf.len() == self.chunk_size {
+                bundles.push(self.chunk(false));
+            }
+        }
+        bundles
+    }
+    /// Returns the final bundle with the remaining data, which might be empty.
+    pub fn finish(mut self) -> Bundle {
+        self.chunk(true)
+    }
+    fn chunk(&mut self, last: bool) -> Bundle {
+        let payload = std::mem::take(&mut self.buf);
+        let info = TransferInfo {
+            id: self.id.clone(),
+            index: self.index,
+            offset: self.sent,
+            last,
+            name: self.name.clone(),
+        };
+        self.index += 1;
+        self.sent += payload.len() as u64;
+
+        let pblock = bp7::primary::PrimaryBlockBuilder::default()
+            .bundle_control_flags(BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED.bits())
+            .destination(self.dst.clone())
+            .source(self.src.clone())
+            .report_to(self.src.clone())
+            .creation_timestamp(CreationTimestamp::now())
+            .lifetime(self.lifetime)
+            .build()
+            .unwrap();
+        let mut bndl = Bundle::new(
+            pblock,
+            vec![
+                bp7::canonical::new_payload_block(BlockControlFlags::empty(), payload),
+                bp7::canonical::new_hop_count_block(2, BlockControlFlags::empty(), 32),
+                new_canonical_block(
+                    TRANSFER_BLOCK,
+                    3,
+                    BlockControlFlags::empty().bits(),
+                    CanonicalData::Unknown(
+                        serde_cbor::to_vec(&info).expect("transfer block encoding error"),
+                    ),
+                ),
+            ],
+        );
+        bndl.set_crc(bp7::crc::CRC_NO);
+        bndl
+    }
+}
+
+/// State of an upload as reported to senders.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub transfer: String,
+    /// bytes accepted so far, an interrupted upload has to be resumed from here
+    pub offset: u64,
+    pub bundles: u64,
+    pub complete: bool,
+}
+
+/// Stores a bundle of an upload and hands it to routing. The bundle is stored before this
+/// returns, so uploads can not outrun the store.
+pub async fn send_chunk(bndl: Bundle) -> Result<()> {
+    store_push_bundle(&bndl)?;
+    STATS.lock().node.bundles.bundles_created += 1;
+    tokio::spawn(async move {
+        if let Err(err) = crate::core::processing::transmit(bndl.into()).await {
+            warn!("Transmission failed: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Reassembly state of a transfer as shown to receivers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferProgress {
+    pub id: String,
+    pub src: String,
+    pub dst: String,
+    pub name: Option<String>,
+    /// bundles received so far
+    pub chunks: u64,
+    /// payload bytes received so far
+    pub bytes: u64,
+    /// number of bundles, known once the last one arrived
+    pub total_chunks: Option<u64>,
+    /// size of the ADU, known once the last bundle arrived
+    pub total_bytes: Option<u64>,
+    pub complete: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReassemblyState {
+    progress: TransferProgress,
+    received: BTreeSet<u64>,
+    /// end of the payload reaching furthest into the ADU
+    #[serde(default)]
+    end: u64,
+}
+
+/// Directory with the partial and complete ADUs of incoming transfers.
+pub fn transfer_dir() -> PathBuf {
+    CONFIG.lock().workdir.join("transfers")
+}
+
+/// File name of a transfer, the source is escaped so IDs of different sources never collide.
+fn transfer_key(src: &str, id: &str) -> String {
+    let mut key = String::new();
+    for b in src.bytes() {
+        if b.is_ascii_alphanumeric() {
+            key.push(b as char);
+        } else {
+            key.push_str(&format!("_{:02x}", b));
+        }
+    }
+    format!("{}-{}", key, id)
+}
+
+fn state_path(dir: &Path, src: &str, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", transfer_key(src, id)))
+}
+
+/// Path of the reassembled ADU, only complete once the progress says so.
+pub fn adu_path(dir: &Path, src: &str, id: &str) -> PathBuf {
+    dir.join(format!("{}.adu", transfer_key(src, id)))
+}
+
+/// Number of locks transfers are spread over, chunks of one transfer always share a lock.
+const REASSEMBLY_LOCKS: usize = 64;
+
+lazy_static! {
+    /// Serializes the state updates of chunks of the same transfer received at the same time
+    static ref REASSEMBLY: Vec<Mutex<()>> = (0..REASSEMBLY_LOCKS).map(|_| Mutex::new(())).collect();
+}
+
+fn lock_transfer(dir: &Path, src: &str, id: &str) -> MutexGuard<'static, ()> {
+    let mut hasher = DefaultHasher::new();
+    state_path(dir, src, id).hash(&mut hasher);
+    REASSEMBLY[hasher.finish() as usize % REASSEMBLY_LOCKS].lock()
+}
+
+fn load_state(path: &Path) -> Option<ReassemblyState> {
+    let buf = std::fs::read(path).ok()?;
+    serde_json::from_slice(&buf).ok()
+}
+
+fn save_state(dir: &Path, state: &ReassemblyState) -> Result<()> {
+    let path = state_path(dir, &state.progress.src, &state.progress.id);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Writes the payload of a bundle into the ADU of its transfer. Duplicates are ignored.
+///
+/// Chunks reaching beyond `TRANSFER_SIZE_MAX` or beyond the end of the transfer given by its
+/// last chunk are rejected. The file operations block, call this from a blocking task.
+pub fn receive_chunk(dir: &Path, bundle: &Bundle, info: &TransferInfo) -> Result<TransferProgress> {
+    if !is_valid_transfer_id(&info.id) {
+        bail!("invalid transfer id {:?}", info.id);
+    }
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("creating transfer directory {}", dir.display()))?;
+    let src = bundle.primary.source.to_string();
+    let _lock = lock_transfer(dir, &src, &info.id);
+    let mut state =
+        load_state(&state_path(dir, &src, &info.id)).unwrap_or_else(|| ReassemblyState {
+            progress: TransferProgress {
+                id: info.id.clone(),
+                src: src.clone(),
+                dst: bundle.primary.destination.to_string(),
+                name: info.name.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    if state.received.contains(&info.index) {
+        debug!("Ignoring duplicate chunk {} of {}", info.index, info.id);
+        return Ok(state.progress);
+    }
+
+    let payload = bundle.payload().map(|p| p.as_slice()).unwrap_or_default();
+    let end = info
+        .offset
+        .checked_add(payload.len() as u64)
+        .filter(|end| *end <= TRANSFER_SIZE_MAX)
+        .with_context(|| {
+            format!(
+                "chunk {} of {} exceeds the maximum transfer size",
+                info.index, info.id
+            )
+        })?;
+    if let (Some(total_chunks), Some(total_bytes)) =
+        (state.progress.total_chunks, state.progress.total_bytes)
+    {
+        if info.index >= total_chunks || end > total_bytes {
+            bail!(
+                "chunk {} of {} lies beyond the end of the transfer",
+                info.index,
+                info.id
+            );
+        }
+    }
+    if info.last && (end < state.end || state.received.last().is_some_and(|i| *i > info.index)) {
+        bail!(
+            "final chunk {} of {} ends before chunks already received",
+            info.index,
+            info.id
+        );
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(adu_path(dir, &src, &info.id))?;
+    file.seek(SeekFrom::Start(info.offset))?;
+    file.write_all(payload)?;
+    file.sync_data()?;
+
+    let progress = &mut state.progress;
+    state.received.insert(info.index);
+    state.end = state.end.max(end);
+    progress.chunks = state.received.len() as u64;
+    progress.bytes += payload.len() as u64;
+    if info.last {
+        progress.total_chunks = Some(info.index + 1);
+        progress.total_bytes = Some(end);
+    }
+    progress.complete = progress.total_chunks == Some(progress.chunks);
+    save_state(dir, &state)?;
+    Ok(state.progress)
+}
+
+/// Progress of all incoming transfers.
+pub fn transfers(dir: &Path) -> Vec<TransferProgress> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut transfers: Vec<TransferProgress> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".json"))
+        .filter_map(|e| Some(load_state(&e.path())?.progress))
+        .collect();
+    transfers.sort_unstable_by(|a, b| (&a.id, &a.src).cmp(&(&b.id, &b.src)));
+    transfers
+}
+
+pub fn progress(dir: &Path, src: &str, id: &str) -> Option<TransferProgress> {
+    if !is_valid_transfer_id(id) {
+        return None;
+    }
+    load_state(&state_path(dir, src, id)).map(|state| state.progress)
+}
+
+/// Removes a transfer with its ADU, returns false if it is unknown.
+pub fn remove(dir: &Path, src: &str, id: &str) -> Result<bool> {
+    let _lock = lock_transfer(dir, src, id);
+    if progress(dir, src, id).is_none() {
+        return Ok(false);
+    }
+    let adu = adu_path(dir, src, id);
+    if adu.exists() {
+        std::fs::remove_file(adu)?;
+    }
+    std::fs::remove_file(state_path(dir, src, id))?;
+    Ok(true)
+}
+
+/// Removes incomplete transfers that did not receive a chunk for `max_age`,
+/// returns the number of removed transfers.
+pub fn remove_stale(dir: &Path, max_age: Duration) -> usize {
+    let now = SystemTime::now();
+    transfers(dir)
+        .into_iter()
+        .filter(|t| !t.complete)
+        .filter(|t| {
+            std::fs::metadata(state_path(dir, &t.src, &t.id))
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > max_age)
+        })
+        .filter(|t| match remove(dir, &t.src, &t.id) {
+            Ok(removed) => removed,
+            Err(err) => {
+                warn!("Removing stale transfer {} failed: {}", t.id, err);
+                false
+            }
+        })
+        .count()
+}
+
+/// Transfer blocks of received bundles must be decodable.
+pub struct TransferBlock;
+
+impl ExtensionBlockHandler for TransferBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        TRANSFER_BLOCK
+    }
+    fn name(&self) -> &str {
+        "transfer"
+    }
+    fn on_receive(&self, bundle: &Bundle, block: &CanonicalBlock) -> BlockAction {
+        match TransferInfo::from_block(block) {
+            Some(info) if is_valid_transfer_id(&info.id) => BlockAction::Keep,
+            _ => {
+                warn!("Removing invalid transfer block from {}", bundle.id());
+                BlockAction::RemoveBlock
+            }
+        }
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        serde_json::to_value(TransferInfo::from_block(block)).unwrap_or_default()
+    }
+}
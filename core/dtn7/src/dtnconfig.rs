@@ -29,7 +29,15 @@ pub struct DtnConfig {
     pub discovery_destinations: BTreeMap<String, u32>,
     pub discovery_listen_port: u16,
     pub janitor_interval: Duration,
+    /// interval of store integrity checks and repairs, 0 disables them
+    pub store_check_interval: Duration,
+    /// time tombstones of deleted bundles are kept after their lifetime ended, 0 keeps them forever
+    pub tombstone_horizon: Duration,
+    /// time incomplete transfers and interrupted uploads are kept without progress, 0 keeps them forever
+    pub transfer_expiry: Duration,
     pub endpoints: Vec<String>,
+    /// settings of application agents handling bundles inside dtnd, by agent name
+    pub agents: BTreeMap<String, HashMap<String, String>>,
     pub clas: Vec<(CLAsAvailable, HashMap<String, String>)>,
     pub cla_global_settings: HashMap<CLAsAvailable, HashMap<String, String>>,
     pub services: BTreeMap<u8, String>,
@@ -39,6 +47,10 @@ pub struct DtnConfig {
     pub statics: Vec<DtnPeer>,
     pub workdir: PathBuf,
     pub db: String,
+    /// file with the keys for bundle store encryption
+    pub store_key_file: Option<PathBuf>,
+    /// file node events are appended to as newline-delimited JSON
+    pub event_log: Option<PathBuf>,
     pub generate_status_reports: bool,
     pub ecla_tcp_port: u16,
     pub ecla_enable: bool,
@@ -134,6 +146,12 @@ impl From<PathBuf> for DtnConfig {
         dtncfg.db = s.get_string("db").unwrap_or_else(|_| "mem".into());
         debug!("db: {:?}", dtncfg.db);
 
+        dtncfg.store_key_file = s.get_string("store-key-file").ok().map(PathBuf::from);
+        debug!("store-key-file: {:?}", dtncfg.store_key_file);
+
+        dtncfg.event_log = s.get_string("event-log").ok().map(PathBuf::from);
+        debug!("event-log: {:?}", dtncfg.event_log);
+
         dtncfg.webport = s
             .get_int("webport")
             .unwrap_or_else(|_| i64::from(dtncfg.webport)) as u16;
@@ -149,6 +167,24 @@ impl From<PathBuf> for DtnConfig {
         };
         debug!("janitor: {:?}", dtncfg.janitor_interval);
 
+        if let Ok(interval) = s.get_string("core.store-check") {
+            dtncfg.store_check_interval =
+                humantime::parse_duration(&interval).unwrap_or_else(|_| Duration::new(0, 0));
+        }
+        debug!("store-check: {:?}", dtncfg.store_check_interval);
+
+        if let Ok(horizon) = s.get_string("core.tombstone-horizon") {
+            dtncfg.tombstone_horizon =
+                humantime::parse_duration(&horizon).unwrap_or_else(|_| Duration::new(0, 0));
+        }
+        debug!("tombstone-horizon: {:?}", dtncfg.tombstone_horizon);
+
+        if let Ok(expiry) = s.get_string("core.transfer-expiry") {
+            dtncfg.transfer_expiry =
+                humantime::parse_duration(&expiry).unwrap_or_else(|_| Duration::new(0, 0));
+        }
+        debug!("transfer-expiry: {:?}", dtncfg.transfer_expiry);
+
         dtncfg.announcement_interval = if let Ok(interval) = s.get_string("discovery.interval") {
             humantime::parse_duration(&interval).unwrap_or_else(|_| Duration::new(0, 0))
         } else {
@@ -179,6 +215,17 @@ impl From<PathBuf> for DtnConfig {
                 dtncfg.endpoints.push(eid);
             }
         }
+        if let Ok(agents) = s.get_table("endpoints.agent") {
+            for (k, v) in agents.iter() {
+                let tab = v.clone().into_table().unwrap();
+                let mut agent_settings = HashMap::new();
+                for (k, v) in tab {
+                    agent_settings.insert(k, v.into_string().unwrap());
+                }
+                debug!("Agent {}: {:?}", k, agent_settings);
+                dtncfg.agents.insert(k.to_string(), agent_settings);
+            }
+        }
         if let Ok(clas) = s.get_table("convergencylayers.cla") {
             for (_k, v) in clas.iter() {
                 let mut tab = v.clone().into_table().unwrap();
@@ -281,7 +328,11 @@ impl DtnConfig {
             discovery_listen_port: 3003,
             webport: 3000,
             janitor_interval: "10s".parse::<humantime::Duration>().unwrap().into(),
+            store_check_interval: "1h".parse::<humantime::Duration>().unwrap().into(),
+            tombstone_horizon: "24h".parse::<humantime::Duration>().unwrap().into(),
+            transfer_expiry: "24h".parse::<humantime::Duration>().unwrap().into(),
             endpoints: Vec::new(),
+            agents: BTreeMap::new(),
             clas: Vec::new(),
             cla_global_settings: HashMap::new(),
             services: BTreeMap::new(),
@@ -291,6 +342,8 @@ impl DtnConfig {
             statics: Vec::new(),
             workdir: std::env::current_dir().unwrap(),
             db: String::from("mem"),
+            store_key_file: None,
+            event_log: None,
             generate_status_reports: false,
             ecla_enable: false,
             ecla_tcp_port: 0,
@@ -312,16 +365,23 @@ impl DtnConfig {
         self.discovery_destinations = cfg.discovery_destinations;
         self.discovery_listen_port = cfg.discovery_listen_port;
         self.janitor_interval = cfg.janitor_interval;
+        self.store_check_interval = cfg.store_check_interval;
+        self.tombstone_horizon = cfg.tombstone_horizon;
+        self.transfer_expiry = cfg.transfer_expiry;
         self.endpoints = cfg.endpoints;
+        self.agents = cfg.agents;
         self.clas = cfg.clas;
         self.cla_global_settings = cfg.cla_global_settings;
         self.services = cfg.services;
         self.routing = cfg.routing;
         self.routing_settings = cfg.routing_settings;
+        crate::routing::external::validate_settings(&mut self.routing_settings);
         self.peer_timeout = cfg.peer_timeout;
         self.statics = cfg.statics;
         self.workdir = cfg.workdir;
         self.db = cfg.db;
+        self.store_key_file = cfg.store_key_file;
+        self.event_log = cfg.event_log;
         self.generate_status_reports = cfg.generate_status_reports;
         self.ecla_enable = cfg.ecla_enable;
         self.ecla_tcp_port = cfg.ecla_tcp_port;
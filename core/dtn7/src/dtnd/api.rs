@@ -0,0 +1,711 @@
+//! Versioned REST/JSON management API served under `/api/v2`.
+//!
+//! Resources are addressed by path, read with GET, created with POST and removed with DELETE.
+//! Bundle and endpoint IDs in paths are percent-encoded. Errors are JSON objects with status and
+//! message, the contract is described by the OpenAPI document at `/api/v2/openapi.json`.
+
+use crate::core::application_agent::{self, ApplicationAgent, ApplicationAgentEnum};
+use crate::core::bundlepack::{BundlePack, Constraint};
+use crate::core::helpers::{is_valid_service_name, parse_peer_url};
+use crate::core::linkstats::{ClaLinkStats, LinkStats};
+use crate::core::peer::{DtnPeer, PeerType};
+use crate::core::processing;
+use crate::core::status_reports::StatusReportEntry;
+use crate::core::store::BundleStore;
+use crate::ipnd::services::Service;
+use crate::{
+    cla_names, cla_settings, peers_add, peers_remove, routing_cmd, routing_get_data,
+    status_reports_get, store_get_bundle, DtnStatistics, CONFIG, DTNCORE, EXTENSION_BLOCKS, PEERS,
+    STATS, STORE,
+};
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{DefaultBodyLimit, Path, Query};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use bp7::administrative_record::TRANSMISSION_CANCELED;
+use bp7::dtntime::DtnTimeHelpers;
+use bp7::flags::{BlockControlFlags, BundleControlFlags};
+use bp7::{CreationTimestamp, EndpointID};
+use http::StatusCode;
+use log::{debug, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Path prefix of the API
+pub const API_PREFIX: &str = "/api/v2";
+/// Page size of bundle listings unless requested otherwise
+pub const PAGE_LIMIT_DEFAULT: usize = 100;
+/// Largest page size of bundle listings
+pub const PAGE_LIMIT_MAX: usize = 1000;
+
+/// OpenAPI description of the API
+pub const OPENAPI: &str = include_str!("openapi.json");
+
+/// Error body of all failed requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status: status.as_u16(),
+            message: message.into(),
+        }
+    }
+    fn bad_request(message: impl Into<String>) -> ApiError {
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    }
+    fn not_found(message: impl Into<String>) -> ApiError {
+        ApiError::new(StatusCode::NOT_FOUND, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, Json(self)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Parses a JSON request body, axum's own extractor answers malformed bodies in plain text.
+fn json_body<T: DeserializeOwned>(body: &[u8]) -> ApiResult<T> {
+    serde_json::from_slice(body).map_err(|err| ApiError::bad_request(err.to_string()))
+}
+
+/// Query parameters, malformed ones are answered with an error body as well.
+fn query<T>(query: Result<Query<T>, QueryRejection>) -> ApiResult<T> {
+    query
+        .map(|Query(q)| q)
+        .map_err(|err| ApiError::bad_request(err.to_string()))
+}
+
+fn parse_eid(eid: &str) -> ApiResult<EndpointID> {
+    EndpointID::try_from(eid).map_err(|_| ApiError::bad_request("Malformed endpoint ID"))
+}
+
+/// One page of a listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    /// number of items matching the filter
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub version: String,
+    pub routing: String,
+    pub clas: Vec<String>,
+    pub endpoints: usize,
+    pub peers: usize,
+    pub bundles: usize,
+    /// location announced by the geolocation service
+    pub location: Option<Location>,
+}
+
+/// Geographical location in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+impl Location {
+    /// Parses the `<latitude> <longitude>` payload of the IPND geolocation service.
+    pub fn from_service(payload: &str) -> Option<Location> {
+        let mut coords = payload.split_whitespace().map(|c| c.parse::<f32>());
+        let latitude = coords.next()?.ok()?;
+        let longitude = coords.next()?.ok()?;
+        Some(Location {
+            latitude,
+            longitude,
+        })
+    }
+}
+
+pub fn node_info() -> NodeInfo {
+    let (node_id, routing) = {
+        let config = CONFIG.lock();
+        (config.host_eid.to_string(), config.routing.clone())
+    };
+    let (endpoints, bundles, location) = {
+        let core = DTNCORE.lock();
+        let location = core
+            .service_list
+            .get(&Service::GEO_LOCATION)
+            .and_then(|l| Location::from_service(l));
+        (core.endpoints.len(), core.bundle_count(), location)
+    };
+    NodeInfo {
+        node_id,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        routing,
+        clas: cla_names(),
+        endpoints,
+        peers: PEERS.lock().len(),
+        bundles,
+        location,
+    }
+}
+
+async fn node() -> Json<NodeInfo> {
+    Json(node_info())
+}
+
+/// Bundle as listed by `/bundles`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSummary {
+    pub id: String,
+    pub source: String,
+    pub destination: String,
+    /// creation time in unix time as seconds, 0 if the source has no clock
+    pub creation_time: u64,
+    /// reception time in unix time as milliseconds
+    pub received_time: u64,
+    /// lifetime in milliseconds
+    pub lifetime: u64,
+    pub size: usize,
+    pub administrative: bool,
+    pub constraints: Vec<String>,
+}
+
+impl From<&BundlePack> for BundleSummary {
+    fn from(bp: &BundlePack) -> Self {
+        let mut constraints: Vec<String> = bp.constraints.iter().map(|c| c.to_string()).collect();
+        constraints.sort_unstable();
+        BundleSummary {
+            id: bp.id.clone(),
+            source: bp.source.to_string(),
+            destination: bp.destination.to_string(),
+            creation_time: match bp.creation_time {
+                0 => 0,
+                dtntime => dtntime.unix(),
+            },
+            received_time: bp.received_time,
+            lifetime: bp.lifetime,
+            size: bp.size,
+            administrative: bp.administrative,
+            constraints,
+        }
+    }
+}
+
+/// Filter and pagination of `/bundles`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BundleQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// prefix of the source endpoint
+    pub src: Option<String>,
+    /// prefix of the destination endpoint
+    pub dst: Option<String>,
+    /// retention constraint the bundles must have, e.g. `ForwardPending`
+    pub constraint: Option<String>,
+    /// include deleted bundles whose metadata is still kept
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl BundleQuery {
+    fn matches(&self, bp: &BundlePack) -> bool {
+        (self.deleted || !bp.has_constraint(Constraint::Deleted))
+            && self
+                .src
+                .as_ref()
+                .is_none_or(|src| bp.source.to_string().starts_with(src.as_str()))
+            && self
+                .dst
+                .as_ref()
+                .is_none_or(|dst| bp.destination.to_string().starts_with(dst.as_str()))
+            && self
+                .constraint
+                .as_ref()
+                .is_none_or(|c| bp.constraints.iter().any(|bc| bc.to_string() == *c))
+    }
+}
+
+/// Filters and pages bundles, oldest first.
+///
+/// `bundles` is a snapshot of the store metadata ordered by creation time and ID, as returned
+/// by [`BundleStore::query_page`], so all pages of a request see the same state.
+pub fn bundle_page(bundles: &[BundlePack], query: &BundleQuery) -> ApiResult<Page<BundleSummary>> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(PAGE_LIMIT_DEFAULT);
+    if limit == 0 || limit > PAGE_LIMIT_MAX {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            PAGE_LIMIT_MAX
+        )));
+    }
+    let mut total = 0;
+    let mut items = Vec::new();
+    for bp in bundles.iter().filter(|bp| query.matches(bp)) {
+        if total >= offset && items.len() < limit {
+            items.push(BundleSummary::from(bp));
+        }
+        total += 1;
+    }
+    Ok(Page {
+        total,
+        offset,
+        limit,
+        items,
+    })
+}
+
+async fn bundles(
+    params: Result<Query<BundleQuery>, QueryRejection>,
+) -> ApiResult<Json<Page<BundleSummary>>> {
+    let params = query(params)?;
+    // one snapshot, the store is locked once and not for the filtering
+    let bundles = STORE.lock().query_page(0, usize::MAX);
+    bundle_page(&bundles, &params).map(Json)
+}
+
+/// Parameters of a bundle created from a payload.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewBundle {
+    pub dst: String,
+    /// lifetime such as `1h`, one hour by default
+    pub lifetime: Option<String>,
+    /// bundle processing control flags
+    pub flags: Option<u64>,
+}
+
+async fn bundle_create(
+    params: Result<Query<NewBundle>, QueryRejection>,
+    body: bytes::Bytes,
+) -> ApiResult<(StatusCode, Json<BundleSummary>)> {
+    let params = query(params)?;
+    let dst = parse_eid(&params.dst)?;
+    if dst == EndpointID::none() {
+        return Err(ApiError::bad_request("Destination must not be dtn:none"));
+    }
+    let lifetime = match &params.lifetime {
+        Some(l) => {
+            humantime::parse_duration(l).map_err(|_| ApiError::bad_request("Malformed lifetime"))?
+        }
+        None => Duration::from_secs(60 * 60),
+    };
+    let flags = match params.flags {
+        Some(f) => BundleControlFlags::from_bits(f)
+            .ok_or_else(|| ApiError::bad_request("Invalid bundle processing control flags"))?,
+        None => BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED,
+    };
+    let src = CONFIG.lock().host_eid.clone();
+    let pblock = bp7::primary::PrimaryBlockBuilder::default()
+        .bundle_control_flags(flags.bits())
+        .destination(dst)
+        .source(src.clone())
+        .report_to(src)
+        .creation_timestamp(CreationTimestamp::now())
+        .lifetime(lifetime)
+        .build()
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+    let mut bndl = bp7::Bundle::new(
+        pblock,
+        vec![
+            bp7::canonical::new_payload_block(BlockControlFlags::empty(), body.to_vec()),
+            bp7::canonical::new_hop_count_block(2, BlockControlFlags::empty(), 32),
+        ],
+    );
+    bndl.set_crc(bp7::crc::CRC_NO);
+    let summary = BundleSummary::from(&BundlePack::from(bndl.clone()));
+    debug!("Sending bundle {} to {}", summary.id, summary.destination);
+    crate::core::processing::send_bundle(bndl).await;
+    STATS.lock().node.bundles.bundles_created += 1;
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleDetail {
+    #[serde(flatten)]
+    pub summary: BundleSummary,
+    /// canonical blocks as described by their extension block handlers
+    pub blocks: serde_json::Value,
+    pub reports: Vec<StatusReportEntry>,
+}
+
+/// Metadata of bundles in the store, deleted ones are only found by listings.
+fn bundle_meta(bid: &str) -> ApiResult<BundlePack> {
+    crate::store_get_metadata(bid)
+        .filter(|bp| !bp.has_constraint(Constraint::Deleted))
+        .ok_or_else(|| ApiError::not_found("Bundle not found"))
+}
+
+fn bundle_data(bid: &str) -> ApiResult<bp7::Bundle> {
+    store_get_bundle(bid).ok_or_else(|| ApiError::not_found("Bundle not found"))
+}
+
+async fn bundle(Path(bid): Path<String>) -> ApiResult<Json<BundleDetail>> {
+    let meta = bundle_meta(&bid)?;
+    let bundle = bundle_data(&bid)?;
+    let blocks = EXTENSION_BLOCKS.lock().describe(&bundle);
+    Ok(Json(BundleDetail {
+        summary: BundleSummary::from(&meta),
+        blocks: serde_json::to_value(blocks).unwrap_or_default(),
+        reports: status_reports_get(&bid),
+    }))
+}
+
+async fn bundle_payload(Path(bid): Path<String>) -> ApiResult<Response> {
+    let bundle = bundle_data(&bid)?;
+    let payload = bundle
+        .payload()
+        .cloned()
+        .ok_or_else(|| ApiError::not_found("Bundle has no payload"))?;
+    Ok((
+        [(http::header::CONTENT_TYPE, "application/octet-stream")],
+        payload,
+    )
+        .into_response())
+}
+
+async fn bundle_cbor(Path(bid): Path<String>) -> ApiResult<Response> {
+    let mut bundle = bundle_data(&bid)?;
+    Ok((
+        [(http::header::CONTENT_TYPE, "application/cbor")],
+        bundle.to_cbor(),
+    )
+        .into_response())
+}
+
+async fn bundle_delete(Path(bid): Path<String>) -> ApiResult<StatusCode> {
+    let bp = bundle_meta(&bid)?;
+    info!("Requested deleting of bundle {}", bid);
+    processing::delete(bp, TRANSMISSION_CANCELED)
+        .await
+        .map_err(|err| ApiError::not_found(err.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointInfo {
+    pub eid: String,
+    /// `singleton`, `group` or `handler`
+    pub kind: String,
+    /// whether a websocket client receives the bundles of the endpoint
+    pub subscribed: bool,
+}
+
+impl From<&ApplicationAgentEnum> for EndpointInfo {
+    fn from(aa: &ApplicationAgentEnum) -> Self {
+        let kind = match aa {
+            ApplicationAgentEnum::SimpleApplicationAgent(_) => "singleton",
+            ApplicationAgentEnum::GroupApplicationAgent(_) => "group",
+            ApplicationAgentEnum::HandlerApplicationAgent(_) => "handler",
+        };
+        EndpointInfo {
+            eid: aa.eid().to_string(),
+            kind: kind.into(),
+            subscribed: aa.delivery_addr().is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewEndpoint {
+    /// endpoint ID or service name on this node
+    pub eid: String,
+}
+
+/// Endpoint IDs or service names of this node.
+fn endpoint_eid(eid: &str) -> ApiResult<EndpointID> {
+    if is_valid_service_name(eid) {
+        CONFIG
+            .lock()
+            .host_eid
+            .new_endpoint(eid)
+            .map_err(|_| ApiError::bad_request("Malformed endpoint ID"))
+    } else {
+        parse_eid(eid)
+    }
+}
+
+async fn endpoints() -> Json<Vec<EndpointInfo>> {
+    Json(
+        DTNCORE
+            .lock()
+            .endpoints
+            .iter()
+            .map(EndpointInfo::from)
+            .collect(),
+    )
+}
+
+async fn endpoint(Path(eid): Path<String>) -> ApiResult<Json<EndpointInfo>> {
+    let eid = endpoint_eid(&eid)?;
+    DTNCORE
+        .lock()
+        .get_endpoint(&eid)
+        .map(|aa| Json(EndpointInfo::from(aa)))
+        .ok_or_else(|| ApiError::not_found("Endpoint not registered"))
+}
+
+async fn endpoint_register(body: bytes::Bytes) -> ApiResult<(StatusCode, Json<EndpointInfo>)> {
+    let eid = endpoint_eid(&json_body::<NewEndpoint>(&body)?.eid)?;
+    let mut core = DTNCORE.lock();
+    if core.is_in_endpoints(&eid) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Endpoint already registered",
+        ));
+    }
+    core.register_application_agent(application_agent::new(eid.clone()));
+    let info = core
+        .get_endpoint(&eid)
+        .map(EndpointInfo::from)
+        .ok_or_else(|| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Registration failed"))?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn endpoint_unregister(Path(eid): Path<String>) -> ApiResult<StatusCode> {
+    let eid = endpoint_eid(&eid)?;
+    let mut core = DTNCORE.lock();
+    if !core.is_in_endpoints(&eid) {
+        return Err(ApiError::not_found("Endpoint not registered"));
+    }
+    core.unregister_application_agent(application_agent::new(eid));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaInfo {
+    pub name: String,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerInfo {
+    /// node name the peer is addressed by
+    pub node: String,
+    pub eid: String,
+    pub addr: String,
+    /// `static` or `dynamic`
+    #[serde(rename = "type")]
+    pub peer_type: String,
+    /// announcement period in seconds
+    pub period: Option<u64>,
+    pub clas: Vec<ClaInfo>,
+    pub services: BTreeMap<u8, String>,
+    /// unix time of the last contact
+    pub last_contact: u64,
+    pub fails: u16,
+    pub link: LinkState,
+    /// location announced by the geolocation service
+    pub location: Option<Location>,
+}
+
+/// Contact and link quality of a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkState {
+    pub in_contact: bool,
+    /// duration of the ongoing contact in seconds
+    pub contact_duration: Option<u64>,
+    /// share of successful recent transfers
+    pub success_ratio: Option<f64>,
+    /// average throughput of recent transfers in bytes per second
+    pub throughput: Option<u64>,
+    /// traffic counters per convergence layer
+    pub clas: BTreeMap<String, ClaLinkStats>,
+}
+
+impl From<&LinkStats> for LinkState {
+    fn from(stats: &LinkStats) -> Self {
+        LinkState {
+            in_contact: stats.in_contact(),
+            contact_duration: stats.current_contact_duration(),
+            success_ratio: stats.success_ratio(),
+            throughput: stats.throughput(),
+            clas: stats.clas.clone(),
+        }
+    }
+}
+
+impl From<&DtnPeer> for PeerInfo {
+    fn from(peer: &DtnPeer) -> Self {
+        PeerInfo {
+            node: peer.node_name(),
+            eid: peer.eid.to_string(),
+            addr: peer.addr.to_string(),
+            peer_type: format!("{:?}", peer.con_type).to_lowercase(),
+            period: peer.period.map(|p| p.as_secs()),
+            clas: peer
+                .cla_list
+                .iter()
+                .map(|(name, port)| ClaInfo {
+                    name: name.clone(),
+                    port: *port,
+                })
+                .collect(),
+            services: peer.services.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            last_contact: peer.last_contact,
+            fails: peer.fails,
+            link: LinkState::from(&peer.link_stats),
+            location: peer
+                .services
+                .get(&Service::GEO_LOCATION)
+                .and_then(|l| Location::from_service(l)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewPeer {
+    /// peer URL such as `mtcp://192.168.2.1:2342/node2`
+    pub url: String,
+    /// `static` or `dynamic`, dynamic peers are removed after the peer timeout
+    #[serde(rename = "type")]
+    pub peer_type: Option<String>,
+}
+
+pub fn peer_infos() -> Vec<PeerInfo> {
+    PEERS.lock().values().map(PeerInfo::from).collect()
+}
+
+async fn peers() -> Json<Vec<PeerInfo>> {
+    Json(peer_infos())
+}
+
+async fn peer(Path(node): Path<String>) -> ApiResult<Json<PeerInfo>> {
+    PEERS
+        .lock()
+        .get(&node)
+        .map(|p| Json(PeerInfo::from(p)))
+        .ok_or_else(|| ApiError::not_found("Peer not found"))
+}
+
+async fn peer_add(body: bytes::Bytes) -> ApiResult<(StatusCode, Json<PeerInfo>)> {
+    let new: NewPeer = json_body(&body)?;
+    let mut peer =
+        parse_peer_url(&new.url).map_err(|_| ApiError::bad_request("Malformed peer URL"))?;
+    peer.con_type = match new.peer_type.as_deref() {
+        Some(t) => PeerType::try_from(t).map_err(|_| ApiError::bad_request("Unknown peer type"))?,
+        None => PeerType::Static,
+    };
+    let info = PeerInfo::from(&peer);
+    let status = if peers_add(peer) {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(info)))
+}
+
+async fn peer_delete(Path(node): Path<String>) -> ApiResult<StatusCode> {
+    if !PEERS.lock().contains_key(&node) {
+        return Err(ApiError::not_found("Peer not found"));
+    }
+    peers_remove(&node);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaSettings {
+    pub name: String,
+    pub settings: HashMap<String, String>,
+}
+
+async fn clas() -> Json<Vec<ClaSettings>> {
+    Json(
+        cla_names()
+            .into_iter()
+            .map(|name| ClaSettings {
+                settings: cla_settings(name.clone()).unwrap_or_default(),
+                name,
+            })
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingInfo {
+    pub algorithm: String,
+    pub settings: BTreeMap<String, HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutingCommand {
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingData {
+    pub data: String,
+}
+
+async fn routing() -> Json<RoutingInfo> {
+    let config = CONFIG.lock();
+    Json(RoutingInfo {
+        algorithm: config.routing.clone(),
+        settings: config.routing_settings.clone(),
+    })
+}
+
+async fn routing_command(body: bytes::Bytes) -> ApiResult<StatusCode> {
+    let cmd: RoutingCommand = json_body(&body)?;
+    routing_cmd(cmd.command)
+        .await
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn routing_data(
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult<Json<RoutingData>> {
+    let param = params.get("param").cloned().unwrap_or_default();
+    let data = routing_get_data(param)
+        .await
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(RoutingData { data }))
+}
+
+async fn stats() -> Json<DtnStatistics> {
+    STATS.lock().update_node_stats();
+    Json(STATS.lock().clone())
+}
+
+async fn openapi() -> Response {
+    ([(http::header::CONTENT_TYPE, "application/json")], OPENAPI).into_response()
+}
+
+/// Fallback of the web server, nested routers can not have their own.
+pub async fn fallback(uri: http::Uri) -> Response {
+    if uri.path().starts_with(API_PREFIX) {
+        ApiError::not_found("No such resource").into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+/// Routes of the API, to be nested under `API_PREFIX`.
+pub fn router() -> Router {
+    Router::new()
+        .route("/openapi.json", get(openapi))
+        .route("/node", get(node))
+        .route("/bundles", get(bundles).post(bundle_create))
+        .layer(DefaultBodyLimit::disable())
+        .route("/bundles/:bid", get(bundle).delete(bundle_delete))
+        .route("/bundles/:bid/payload", get(bundle_payload))
+        .route("/bundles/:bid/cbor", get(bundle_cbor))
+        .route("/endpoints", get(endpoints).post(endpoint_register))
+        .route("/endpoints/:eid", get(endpoint).delete(endpoint_unregister))
+        .route("/peers", get(peers).post(peer_add))
+        .route("/peers/:node", get(peer).delete(peer_delete))
+        .route("/clas", get(clas))
+        .route("/routing", get(routing))
+        .route("/routing/commands", axum::routing::post(routing_command))
+        .route("/routing/data", get(routing_data))
+        .route("/stats", get(stats))
+}
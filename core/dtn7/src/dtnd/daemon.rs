@@ -3,11 +3,13 @@ use std::convert::TryFrom;
 use super::{httpd, janitor};
 use crate::cla::ecla::processing::start_ecla;
 use crate::cla::ConvergenceLayerAgent;
-use crate::core::application_agent::SimpleApplicationAgent;
+use crate::core::application_agent::{self, ApplicationAgent, SimpleApplicationAgent};
+use crate::core::handler_agent::HandlerApplicationAgent;
 use crate::dtnconfig::DtnConfig;
 use crate::ipnd::neighbour_discovery;
-use crate::{cla_add, peers_add, STATS};
-use crate::{CLAS, CONFIG, DTNCORE, STORE};
+use crate::{cla_add, peers_add, service_add, STATS};
+use crate::{CLAS, CONFIG, DTNCORE, EVENTS, STORE};
+use anyhow::Context;
 use bp7::EndpointID;
 use log::{error, info, warn};
 
@@ -74,13 +76,21 @@ pub async fn start_dtnd(cfg: DtnConfig) -> anyhow::Result<()> {
         .as_secs();
 
     info!("Local Node ID: {}", CONFIG.lock().host_eid);
+    EVENTS.set_node(&CONFIG.lock().host_eid.to_string());
 
     info!("Work Dir: {:?}", CONFIG.lock().workdir);
 
     let db = CONFIG.lock().db.clone();
     info!("DB Backend: {}", db);
 
-    (*STORE.lock()) = crate::core::store::new(&db);
+    let store_key_file = CONFIG.lock().store_key_file.clone();
+    (*STORE.lock()) = crate::core::store::open(&db, store_key_file.as_deref())?;
+
+    let event_log = CONFIG.lock().event_log.clone();
+    if let Some(event_log) = event_log {
+        info!("Event Log: {:?}", event_log);
+        EVENTS.open_log(&event_log)?;
+    }
 
     info!(
         "Announcement Interval: {}",
@@ -138,9 +148,22 @@ pub async fn start_dtnd(cfg: DtnConfig) -> anyhow::Result<()> {
         peers_add(s.clone());
     }
 
+    let services = CONFIG.lock().services.clone();
+    for (tag, payload) in services {
+        info!("Adding service: {} {}", tag, payload);
+        service_add(tag, payload);
+    }
+
     let local_host_id = CONFIG.lock().host_eid.clone();
     (*DTNCORE.lock())
         .register_application_agent(SimpleApplicationAgent::with(local_host_id.clone()).into());
+    let agents = CONFIG.lock().agents.clone();
+    for (name, settings) in agents {
+        let aa = HandlerApplicationAgent::from_settings(&settings)
+            .with_context(|| format!("invalid settings of agent {}", name))?;
+        info!("Agent {} handles {} ({:?})", name, aa.eid(), aa.handler());
+        (*DTNCORE.lock()).register_application_agent(aa.into());
+    }
     for e in &CONFIG.lock().endpoints {
         let eid = if let Ok(eid) = EndpointID::try_from(e.clone()) {
             // TODO: add check if non-local ID that service name is non-singleton ('~') for naming scheme dtn
@@ -151,8 +174,10 @@ pub async fn start_dtnd(cfg: DtnConfig) -> anyhow::Result<()> {
                 .expect("Error constructing new endpoint")
         };
 
-        (*DTNCORE.lock()).register_application_agent(SimpleApplicationAgent::with(eid).into());
+        (*DTNCORE.lock()).register_application_agent(application_agent::new(eid));
     }
+    let registrations = CONFIG.lock().workdir.join("registrations.json");
+    (*DTNCORE.lock()).load_registrations(registrations)?;
     start_convergencylayers().await;
     if CONFIG.lock().janitor_interval.as_micros() != 0 {
         janitor::spawn_janitor();
@@ -0,0 +1,117 @@
+//! Live dashboard of the web interface.
+//!
+//! The page itself is static and talks to the [`api`](super::api), the websocket at
+//! `/ws/dashboard` keeps it live by pushing a snapshot of the node state every second. Snapshots
+//! are cached and shared by all clients, so more clients do not cost more store scans.
+
+use super::api::{self, NodeInfo, PeerInfo};
+use crate::core::helpers::get_complete_digest;
+use crate::STATS;
+use axum::extract::ws::{Message, WebSocket};
+use axum::response::Html;
+use lazy_static::lazy_static;
+use log::debug;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+
+/// How often snapshots are pushed to dashboard clients
+pub const DASHBOARD_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Latest snapshot as sent to clients and when it was taken
+    static ref LATEST: Mutex<Option<(Instant, Arc<String>)>> = Mutex::new(None);
+}
+
+/// Bundle counters of the node, the dashboard derives throughput from their changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Counters {
+    pub incoming: u64,
+    pub dups: u64,
+    pub outgoing: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub broken: u64,
+    pub created: u64,
+    pub forward_pending: u64,
+}
+
+/// State of the node pushed to dashboard clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// unix time as milliseconds
+    pub time: u64,
+    pub node: NodeInfo,
+    pub peers: Vec<PeerInfo>,
+    pub counters: Counters,
+    /// digest of all bundle IDs in the store, changes whenever bundles come or go
+    pub digest: String,
+}
+
+pub fn snapshot() -> Snapshot {
+    let counters = {
+        let mut stats = STATS.lock();
+        stats.update_node_stats();
+        Counters {
+            incoming: stats.incoming,
+            dups: stats.dups,
+            outgoing: stats.outgoing,
+            delivered: stats.delivered,
+            failed: stats.failed,
+            broken: stats.broken,
+            created: stats.node.bundles.bundles_created,
+            forward_pending: stats.node.bundles.forward_pending_bundle_count,
+        }
+    };
+    Snapshot {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        node: api::node_info(),
+        peers: api::peer_infos(),
+        counters,
+        digest: get_complete_digest(),
+    }
+}
+
+/// Encoded snapshot shared by all clients.
+///
+/// Snapshots younger than half the interval are reused, clients ticking at different times
+/// still get a new one on each tick.
+pub fn latest() -> Arc<String> {
+    let mut latest = LATEST.lock();
+    match latest.as_ref() {
+        Some((taken, update)) if taken.elapsed() < DASHBOARD_INTERVAL / 2 => update.clone(),
+        _ => {
+            let update = Arc::new(serde_json::to_string(&snapshot()).unwrap());
+            *latest = Some((Instant::now(), update.clone()));
+            update
+        }
+    }
+}
+
+pub async fn page() -> Html<&'static str> {
+    Html(include_str!("../../webroot/dashboard.html"))
+}
+
+pub async fn handle_socket(mut socket: WebSocket) {
+    let mut ticker = interval(DASHBOARD_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let update = latest().as_ref().clone();
+                if socket.send(Message::Text(update)).await.is_err() {
+                    break;
+                }
+            }
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => {}
+            }
+        }
+    }
+    debug!("dashboard client disconnected");
+}
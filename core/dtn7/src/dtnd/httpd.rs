@@ -1,29 +1,45 @@
+use super::api;
+use super::dashboard;
 use crate::core::application_agent::ApplicationAgent;
-use crate::core::application_agent::SimpleApplicationAgent;
+use crate::core::application_agent::{
+    self, ApplicationAgentEnum, GroupMemberInfo, SimpleApplicationAgent,
+};
 use crate::core::bundlepack::Constraint;
+use crate::core::encoding::Encoding;
+use crate::core::events::{self, Event, EventFilter};
 use crate::core::helpers::get_complete_digest;
 use crate::core::helpers::get_digest_of_bids;
 use crate::core::helpers::is_valid_service_name;
 use crate::core::helpers::rnd_peer;
 use crate::core::peer::PeerType;
+use crate::core::reconciliation::{answer_ranges, local_bids, RangeDigest, SyncRequest};
+use crate::core::rpc;
 use crate::core::store::BundleStore;
+use crate::core::topics;
+use crate::core::transfer::{self, TransferProgress, Upload};
 use crate::peers_add;
 use crate::peers_remove;
 use crate::routing_cmd;
 use crate::routing_get_data;
+use crate::status_reports_get;
 use crate::store_remove;
 use crate::CONFIG;
 use crate::DTNCORE;
 use crate::PEERS;
 use crate::STATS;
 use crate::STORE;
+use crate::UPLOADS;
 use crate::{cla_names, peers_count};
+use crate::{store_check, store_repair};
+use crate::{store_get_bundle, EXTENSION_BLOCKS};
 use crate::{DtnConfig, PeerAddress};
 use anyhow::Result;
 use async_trait::async_trait;
-use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::extract::DefaultBodyLimit;
 use axum::extract::Query;
+use axum::handler::Handler;
+use axum::response::sse::{self, KeepAlive, Sse};
 use axum::response::Html;
 use axum::{
     extract::{self, connect_info::ConnectInfo, RequestParts},
@@ -36,15 +52,18 @@ use bp7::flags::BlockControlFlags;
 use bp7::flags::BundleControlFlags;
 use bp7::helpers::rnd_bundle;
 use bp7::EndpointID;
+use futures::StreamExt;
 use http::StatusCode;
 use humansize::format_size;
 use humansize::DECIMAL;
-use log::{trace, debug, info, warn};
+use log::{debug, info, trace, warn};
 use serde::Serialize;
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::{Infallible, TryFrom, TryInto};
 use std::fmt::Write;
+use std::io::Read;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Instant;
 use tinytemplate::TinyTemplate;
 use tower_http::cors::Any;
@@ -288,6 +307,53 @@ async fn status_bundles_verbose() -> String {
 async fn status_bundles_digest() -> String {
     get_complete_digest()
 }
+
+//#[get("/store/check", guard = "fn_guard_localhost")]
+async fn http_store_check() -> String {
+    let report = tokio::task::spawn_blocking(store_check).await.unwrap();
+    serde_json::to_string_pretty(&report).unwrap()
+}
+//#[post("/store/repair", guard = "fn_guard_localhost")]
+async fn http_store_repair() -> Result<String, (StatusCode, String)> {
+    match tokio::task::spawn_blocking(store_repair).await.unwrap() {
+        Ok(report) => Ok(serde_json::to_string_pretty(&report).unwrap()),
+        Err(err) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("store repair failed: {}", err),
+        )),
+    }
+}
+
+/// Maximum number of ranges or bundles a single sync request may ask for.
+const MAX_SYNC_ITEMS: usize = 4096;
+
+//#[post("/sync/ranges")]
+async fn sync_ranges(
+    extract::Json(req): extract::Json<SyncRequest>,
+) -> Result<extract::Json<Vec<RangeDigest>>, (StatusCode, &'static str)> {
+    if req.ranges.len() > MAX_SYNC_ITEMS {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Too many ranges"));
+    }
+    let bids = local_bids(&req.filter);
+    Ok(extract::Json(answer_ranges(&bids, &req.ranges)))
+}
+
+//#[post("/sync/bundles")]
+async fn sync_bundles(
+    extract::Json(bids): extract::Json<Vec<String>>,
+) -> Result<Vec<u8>, (StatusCode, &'static str)> {
+    if bids.len() > MAX_SYNC_ITEMS {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Too many bundles"));
+    }
+    // bundles no longer available are silently skipped
+    let buffers: Vec<serde_bytes::ByteBuf> = bids
+        .iter()
+        .filter_map(|bid| (*STORE.lock()).get_bundle(bid))
+        .map(|mut bundle| serde_bytes::ByteBuf::from(bundle.to_cbor()))
+        .collect();
+    serde_cbor::to_vec(&buffers)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Error encoding bundles"))
+}
 //#[get("/status/store", guard = "fn_guard_localhost")]
 async fn status_store() -> String {
     serde_json::to_string_pretty(&(*STORE.lock()).bundles_status()).unwrap()
@@ -297,6 +363,18 @@ async fn status_peers() -> String {
     let peers = &(*PEERS.lock()).clone();
     serde_json::to_string_pretty(&peers).unwrap()
 }
+//#[get("/status/bundle/<bid>/reports")]
+async fn status_bundle_reports(extract::Path(bid): extract::Path<String>) -> String {
+    serde_json::to_string_pretty(&status_reports_get(&bid)).unwrap()
+}
+//#[get("/status/bundle/<bid>/blocks")]
+async fn status_bundle_blocks(
+    extract::Path(bid): extract::Path<String>,
+) -> Result<String, (StatusCode, &'static str)> {
+    let bundle = store_get_bundle(&bid).ok_or((StatusCode::NOT_FOUND, "bundle not found"))?;
+    let blocks = (*EXTENSION_BLOCKS.lock()).describe(&bundle);
+    Ok(serde_json::to_string_pretty(&blocks).unwrap())
+}
 //#[get("/status/info")]
 async fn status_info() -> String {
     STATS.lock().update_node_stats();
@@ -426,7 +504,7 @@ async fn insert_get(extract::RawQuery(query): extract::RawQuery) -> Result<Strin
                     bndl.id(),
                     bndl.primary.destination
                 );
-                if bndl.primary.source.node() == (*CONFIG.lock()).host_eid.node() {
+                if bndl.primary.source.node() == CONFIG.lock().host_eid.node() {
                     STATS.lock().node.bundles.bundles_created += 1;
                 }
                 crate::core::processing::send_bundle(bndl).await;
@@ -454,7 +532,7 @@ async fn insert_post(body: bytes::Bytes) -> Result<String, (StatusCode, &'static
             bndl.primary.destination
         );
 
-        if bndl.primary.source.node() == (*CONFIG.lock()).host_eid.node() {
+        if bndl.primary.source.node() == CONFIG.lock().host_eid.node() {
             STATS.lock().node.bundles.bundles_created += 1;
         }
 
@@ -534,6 +612,318 @@ async fn send_post(
     Ok(format!("Sent ADU in bundle {} with {} bytes", bid, b_len))
 }
 
+/// Parameters of `/send/stream` to resume a parked upload or to start a new one.
+fn stream_upload(params: &HashMap<String, String>) -> Result<Upload, (StatusCode, String)> {
+    let offset: u64 = params
+        .get("offset")
+        .map(|o| o.parse())
+        .transpose()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid offset!".to_string()))?
+        .unwrap_or(0);
+    if let Some(id) = params.get("transfer") {
+        if !transfer::is_valid_transfer_id(id) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid transfer id!".into()));
+        }
+        let mut uploads = UPLOADS.lock();
+        if let Some(upload) = uploads.remove(id) {
+            if upload.offset() != offset {
+                let msg = format!("Transfer {} continues at offset {}", id, upload.offset());
+                uploads.insert(id.clone(), upload);
+                return Err((StatusCode::CONFLICT, msg));
+            }
+            return Ok(upload);
+        }
+    }
+    if offset != 0 {
+        return Err((StatusCode::NOT_FOUND, "Transfer not found".into()));
+    }
+    let dst = params
+        .get("dst")
+        .and_then(|dst| EndpointID::try_from(dst.as_str()).ok())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Missing destination endpoint id!".to_string(),
+        ))?;
+    let lifetime = params
+        .get("lifetime")
+        .and_then(|l| humantime::parse_duration(l).ok())
+        .unwrap_or(std::time::Duration::from_secs(60 * 60));
+    let chunk_size = params
+        .get("chunk_size")
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(transfer::TRANSFER_CHUNK_SIZE_DEFAULT);
+    let id = params
+        .get("transfer")
+        .cloned()
+        .unwrap_or_else(transfer::new_transfer_id);
+    let src = CONFIG.lock().host_eid.clone();
+    Ok(Upload::new(
+        id,
+        src,
+        dst,
+        lifetime,
+        chunk_size,
+        params.get("name").cloned(),
+    ))
+}
+
+//#[post("/send/stream")]
+async fn send_stream(
+    Query(params): Query<HashMap<String, String>>,
+    mut body: extract::BodyStream,
+) -> Result<String, (StatusCode, String)> {
+    let mut upload = stream_upload(&params)?;
+    while let Some(data) = body.next().await {
+        let data = match data {
+            Ok(data) => data,
+            Err(err) => {
+                warn!(
+                    "Upload {} interrupted at offset {}: {}",
+                    upload.id(),
+                    upload.offset(),
+                    err
+                );
+                let status = upload.status(false);
+                UPLOADS.lock().insert(upload.id().to_string(), upload);
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    serde_json::to_string_pretty(&status).unwrap(),
+                ));
+            }
+        };
+        for bndl in upload.write(&data) {
+            transfer::send_chunk(bndl)
+                .await
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+        }
+    }
+    let status = if params.get("more").map(|m| m == "true").unwrap_or(false) {
+        let status = upload.status(false);
+        UPLOADS.lock().insert(upload.id().to_string(), upload);
+        status
+    } else {
+        let mut status = upload.status(true);
+        status.bundles += 1;
+        transfer::send_chunk(upload.finish())
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+        status
+    };
+    debug!(
+        "Streamed {} bytes of transfer {} in {} bundles",
+        status.offset, status.transfer, status.bundles
+    );
+    Ok(serde_json::to_string_pretty(&status).unwrap())
+}
+
+/// State of a parked upload
+async fn send_stream_status(
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<String, (StatusCode, &'static str)> {
+    let id = params
+        .get("transfer")
+        .ok_or((StatusCode::BAD_REQUEST, "Transfer id not specified"))?;
+    match UPLOADS.lock().get(id) {
+        Some(upload) => Ok(serde_json::to_string_pretty(&upload.status(false)).unwrap()),
+        None => Err((StatusCode::NOT_FOUND, "Transfer not found")),
+    }
+}
+
+/// Progress of incoming transfers, optionally only those for one destination
+async fn transfers(extract::RawQuery(query): extract::RawQuery) -> String {
+    let transfers: Vec<TransferProgress> = transfer::transfers(&transfer::transfer_dir())
+        .into_iter()
+        .filter(|t| query.as_ref().is_none_or(|dst| &t.dst == dst))
+        .collect();
+    serde_json::to_string_pretty(&transfers).unwrap()
+}
+
+/// Finds the transfer given as `?<ID>` or `?id=<ID>&src=<EID>`, the source is only needed
+/// if several sources use the same transfer ID
+fn find_transfer(query: Option<String>) -> Result<TransferProgress, (StatusCode, &'static str)> {
+    let query = query.ok_or((StatusCode::BAD_REQUEST, "Transfer id not specified"))?;
+    let (id, src) = if query.contains('=') {
+        let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let id = params
+            .get("id")
+            .cloned()
+            .ok_or((StatusCode::BAD_REQUEST, "Transfer id not specified"))?;
+        (id, params.get("src").cloned())
+    } else {
+        (query, None)
+    };
+    let mut found: Vec<TransferProgress> = transfer::transfers(&transfer::transfer_dir())
+        .into_iter()
+        .filter(|t| t.id == id && src.as_ref().is_none_or(|src| &t.src == src))
+        .collect();
+    match found.len() {
+        0 => Err((StatusCode::NOT_FOUND, "Transfer not found")),
+        1 => Ok(found.remove(0)),
+        _ => Err((
+            StatusCode::CONFLICT,
+            "Transfer id is used by several sources, specify src",
+        )),
+    }
+}
+
+/// Streams the reassembled ADU of a complete transfer
+async fn transfer_download(
+    extract::RawQuery(query): extract::RawQuery,
+) -> Result<axum::response::Response, (StatusCode, &'static str)> {
+    let progress = find_transfer(query)?;
+    if !progress.complete {
+        return Err((StatusCode::CONFLICT, "Transfer is incomplete"));
+    }
+    let dir = transfer::transfer_dir();
+    let file = std::fs::File::open(transfer::adu_path(&dir, &progress.src, &progress.id))
+        .map_err(|_| (StatusCode::NOT_FOUND, "Transfer not found"))?;
+    let file = Arc::new(parking_lot::Mutex::new(file));
+    let stream = futures::stream::unfold(file, |file| async move {
+        let reader = file.clone();
+        let block = tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0; 64 * 1024];
+            let n = reader.lock().read(&mut buf)?;
+            buf.truncate(n);
+            Ok::<_, std::io::Error>(bytes::Bytes::from(buf))
+        })
+        .await
+        .unwrap_or_else(|err| Err(std::io::Error::other(err)));
+        match block {
+            Ok(block) if block.is_empty() => None,
+            block => Some((block, file)),
+        }
+    });
+    Ok(axum::response::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            http::header::CONTENT_LENGTH,
+            progress.total_bytes.unwrap_or(0),
+        )
+        .body(axum::body::boxed(axum::body::StreamBody::new(stream)))
+        .unwrap())
+}
+
+async fn transfer_delete(
+    extract::RawQuery(query): extract::RawQuery,
+) -> Result<String, (StatusCode, String)> {
+    let progress = find_transfer(query).map_err(|(code, msg)| (code, msg.to_string()))?;
+    let id = progress.id;
+    match transfer::remove(&transfer::transfer_dir(), &progress.src, &id) {
+        Ok(true) => Ok(format!("Deleted transfer {}", id)),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Transfer not found".into())),
+        Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    }
+}
+
+/// Sends a request and waits for the response, answers with the response payload
+async fn rpc_request(
+    Query(params): Query<HashMap<String, String>>,
+    body: bytes::Bytes,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let dst = params
+        .get("dst")
+        .and_then(|dst| EndpointID::try_from(dst.as_str()).ok())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Missing destination endpoint id!".to_string(),
+        ))?;
+    let timeout = match params.get("timeout") {
+        Some(t) => humantime::parse_duration(t)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid timeout!".to_string()))?,
+        None => rpc::RPC_TIMEOUT_DEFAULT,
+    };
+    let reply_to = params
+        .get("reply_to")
+        .map(|r| EndpointID::try_from(r.as_str()))
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Invalid reply-to endpoint!".to_string(),
+            )
+        })?;
+    let request = rpc::request(dst, body.to_vec(), timeout, reply_to)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let id = request.id.clone();
+    let bid = request.bid.clone();
+    let response = request.wait().await.ok_or((
+        StatusCode::GATEWAY_TIMEOUT,
+        format!("Request {} in {} timed out", id, bid),
+    ))?;
+    Ok(axum::response::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/octet-stream")
+        .header("X-Dtn-Bundle-Id", response.id())
+        .header("X-Dtn-Source", response.primary.source.to_string())
+        .header("X-Dtn-Correlation-Id", id)
+        .body(axum::body::boxed(axum::body::Full::from(
+            response.payload().cloned().unwrap_or_default(),
+        )))
+        .unwrap())
+}
+
+/// Answers a received request, the query is the bundle ID of the request
+async fn rpc_reply(
+    extract::RawQuery(query): extract::RawQuery,
+    body: bytes::Bytes,
+) -> Result<String, (StatusCode, String)> {
+    let bid = query.ok_or((
+        StatusCode::BAD_REQUEST,
+        "Request bundle id not specified".to_string(),
+    ))?;
+    let response = rpc::reply(&bid, body.to_vec())
+        .await
+        .map_err(|err| (StatusCode::NOT_FOUND, err.to_string()))?;
+    Ok(format!("Sent response to {} in bundle {}", bid, response))
+}
+
+/// Requests waiting for a response and received requests not answered yet
+async fn rpc_pending() -> String {
+    serde_json::to_string_pretty(&rpc::status()).unwrap()
+}
+
+/// Streams node events as server-sent events, optionally filtered by `types` and `bid`.
+async fn events_sse(
+    Query(params): Query<HashMap<String, String>>,
+) -> Sse<impl futures::Stream<Item = Result<sse::Event, Infallible>>> {
+    let filter = EventFilter::from_params(&params);
+    let rx = crate::EVENTS.subscribe();
+    let stream = futures::stream::unfold((rx, filter), |(mut rx, filter)| async move {
+        let record = events::next_matching(&mut rx, &filter).await?;
+        let event = sse::Event::default()
+            .event(record.event.name())
+            .id(record.seq.to_string())
+            .json_data(&record)
+            .expect("event serialization failed");
+        Some((Ok(event), (rx, filter)))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn events_socket(mut socket: WebSocket, filter: EventFilter) {
+    let mut rx = crate::EVENTS.subscribe();
+    loop {
+        tokio::select! {
+            record = events::next_matching(&mut rx, &filter) => {
+                let Some(record) = record else {
+                    break;
+                };
+                let msg = serde_json::to_string(&record).expect("event serialization failed");
+                if socket.send(Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => {}
+            }
+        }
+    }
+    debug!("event subscriber disconnected");
+}
+
 //#[post("/push")]
 async fn push_post(body: bytes::Bytes) -> Result<String, (StatusCode, String)> {
     let b_len = body.len();
@@ -541,6 +931,7 @@ async fn push_post(body: bytes::Bytes) -> Result<String, (StatusCode, String)> {
     if let Ok(bndl) = bp7::Bundle::try_from(body.as_ref()) {
         //trace!("received bundle {}", bndl.id());
         info!("Received bundle: {}", bndl.id());
+        crate::peers_report_receive(&bndl, "http", b_len);
         let bid = bndl.id();
         //tokio::spawn(async move {
         let now = Instant::now();
@@ -574,13 +965,11 @@ async fn register(
         let eid = host_eid
             .new_endpoint(&path)
             .expect("Error constructing new endpoint");
-        (*DTNCORE.lock())
-            .register_application_agent(SimpleApplicationAgent::with(eid.clone()).into());
+        (*DTNCORE.lock()).register_application_agent(application_agent::new(eid.clone()));
         Ok(format!("Registered {}", eid))
     } else if let Ok(eid) = EndpointID::try_from(path) {
         // fully qualified EID, can be non-singleton endpoint
-        (*DTNCORE.lock())
-            .register_application_agent(SimpleApplicationAgent::with(eid.clone()).into());
+        (*DTNCORE.lock()).register_application_agent(application_agent::new(eid.clone()));
         Ok(format!("Registered URI: {}", eid))
     } else {
         Err((
@@ -590,6 +979,27 @@ async fn register(
     }
 }
 
+/// Local and advertised topics with the topics of peers
+async fn http_topics() -> String {
+    serde_json::to_string_pretty(&topics::status()).unwrap()
+}
+
+/// Members of all registered group endpoints with their delivery state
+async fn groups() -> String {
+    let groups: BTreeMap<String, Vec<GroupMemberInfo>> = DTNCORE
+        .lock()
+        .endpoints
+        .iter()
+        .filter_map(|aa| match aa {
+            ApplicationAgentEnum::GroupApplicationAgent(group) => {
+                Some((group.eid().to_string(), group.members()))
+            }
+            _ => None,
+        })
+        .collect();
+    serde_json::to_string_pretty(&groups).unwrap()
+}
+
 //#[get("/unregister", guard = "fn_guard_localhost")]
 async fn unregister(
     extract::RawQuery(query): extract::RawQuery,
@@ -709,6 +1119,10 @@ async fn delete(
     if let Some(bid) = query {
         info!("Requested deleting of bundle {}", bid);
         if store_remove(&bid).is_ok() {
+            events::emit(Event::BundleDeleted {
+                bid: bid.clone(),
+                reason: "removed by request".into(),
+            });
             Ok(format!("Deleted {}", bid).as_bytes().to_vec())
         } else {
             Err((StatusCode::NOT_FOUND, "Bundle not found"))
@@ -748,6 +1162,17 @@ async fn download_hex(
     }
 }
 
+/// Packet encoding requested by external modules with the `encoding` query parameter.
+fn encoding_param(
+    params: &HashMap<String, String>,
+) -> Result<Option<Encoding>, (StatusCode, String)> {
+    params
+        .get("encoding")
+        .map(|e| e.parse::<Encoding>())
+        .transpose()
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
 pub async fn spawn_httpd() -> Result<()> {
     let cors = CorsLayer::new()
         // allow `GET` and `POST` when accessing the resource
@@ -762,14 +1187,28 @@ pub async fn spawn_httpd() -> Result<()> {
         .route("/routing/getdata", get(http_routing_getdata))
         .route("/send", post(send_post))
         .layer(DefaultBodyLimit::disable())
+        .route("/send/stream", get(send_stream_status).post(send_stream))
+        .route("/transfers", get(transfers))
+        .route("/rpc/request", post(rpc_request))
+        .route("/rpc/reply", post(rpc_reply))
+        .route("/rpc/pending", get(rpc_pending))
+        .route("/transfers/download", get(transfer_download))
+        .route(
+            "/transfers/delete",
+            get(transfer_delete).delete(transfer_delete),
+        )
         .route("/delete", get(delete).delete(delete))
         .route("/register", get(register))
         .route("/unregister", get(unregister))
+        .route("/groups", get(groups))
+        .route("/topics", get(http_topics))
         .route("/endpoint", get(endpoint))
         .route("/insert", get(insert_get).post(insert_post))
         .layer(DefaultBodyLimit::disable())
         .route("/endpoint.hex", get(endpoint_hex))
         .route("/cts", get(get_creation_timestamp))
+        .route("/store/check", get(http_store_check))
+        .route("/store/repair", post(http_store_repair))
         .route(
             "/ws",
             get(|ws: WebSocketUpgrade| async move {
@@ -778,26 +1217,52 @@ pub async fn spawn_httpd() -> Result<()> {
                     .on_upgrade(super::ws::handle_socket)
             }),
         )
+        .route("/dashboard", get(dashboard::page))
+        .route("/events", get(events_sse))
+        .route(
+            "/ws/events",
+            get(
+                |ws: WebSocketUpgrade, Query(params): Query<HashMap<String, String>>| async move {
+                    let filter = EventFilter::from_params(&params);
+                    ws.on_upgrade(move |socket| events_socket(socket, filter))
+                },
+            ),
+        )
+        .route(
+            "/ws/dashboard",
+            get(|ws: WebSocketUpgrade| async move { ws.on_upgrade(dashboard::handle_socket) }),
+        )
         .route("/debug/rnd_bundle", get(debug_rnd_bundle))
         .route("/debug/rnd_peer", get(debug_rnd_peer))
+        .nest(api::API_PREFIX, api::router())
         .layer(from_extractor::<RequireLocalhost>())
         .layer(cors.clone());
 
     if CONFIG.lock().routing == "external" {
         app_local_only = app_local_only.route(
             "/ws/erouting",
-            get(|ws: WebSocketUpgrade| async move {
-                ws.on_upgrade(crate::routing::erouting::processing::handle_connection)
-            }),
+            get(
+                |ws: WebSocketUpgrade, Query(params): Query<HashMap<String, String>>| async move {
+                    let encoding = encoding_param(&params)?.unwrap_or_default();
+                    Ok::<_, (StatusCode, String)>(ws.on_upgrade(move |socket| {
+                        crate::routing::erouting::processing::handle_connection(socket, encoding)
+                    }))
+                },
+            ),
         )
     }
 
     if CONFIG.lock().ecla_enable {
         app_local_only = app_local_only.route(
             "/ws/ecla",
-            get(|ws: WebSocketUpgrade| async move {
-                ws.on_upgrade(crate::cla::ecla::ws::handle_connection)
-            }),
+            get(
+                |ws: WebSocketUpgrade, Query(params): Query<HashMap<String, String>>| async move {
+                    let encoding = encoding_param(&params)?;
+                    Ok::<_, (StatusCode, String)>(ws.on_upgrade(move |socket| {
+                        crate::cla::ecla::ws::handle_connection(socket, encoding)
+                    }))
+                },
+            ),
         )
     }
 
@@ -819,9 +1284,14 @@ pub async fn spawn_httpd() -> Result<()> {
         )
         .route("/status/bundles/verbose", get(status_bundles_verbose))
         .route("/status/bundles/digest", get(status_bundles_digest))
+        .route("/sync/ranges", post(sync_ranges))
+        .route("/sync/bundles", post(sync_bundles))
         .route("/status/store", get(status_store))
         .route("/status/peers", get(status_peers))
+        .route("/status/bundle/:bid/reports", get(status_bundle_reports))
+        .route("/status/bundle/:bid/blocks", get(status_bundle_blocks))
         .route("/status/info", get(status_info))
+        .fallback(api::fallback.into_service())
         .layer(cors.clone());
 
     let port = CONFIG.lock().webport;
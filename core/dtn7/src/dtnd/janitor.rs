@@ -1,4 +1,4 @@
-use log::{debug, trace};
+use log::{debug, error, info, trace};
 
 async fn janitor() {
     debug!("running janitor");
@@ -15,6 +15,58 @@ async fn janitor() {
         error!("Processing bundles failed: {}", err);
     }*/
     crate::core::process_bundles().await;
+
+    trace!("maintaining groups");
+    crate::core::process_groups();
+
+    trace!("expiring requests");
+    let expired = crate::core::rpc::expire();
+    if expired > 0 {
+        debug!("Removed {} expired requests", expired);
+    }
+
+    trace!("expiring transfers");
+    expire_transfers().await;
+}
+
+async fn expire_transfers() {
+    let max_age = crate::CONFIG.lock().transfer_expiry;
+    if max_age.is_zero() {
+        return;
+    }
+    crate::UPLOADS.lock().retain(|id, upload| {
+        let keep = upload.idle() <= max_age;
+        if !keep {
+            info!("Dropping interrupted upload {}", id);
+        }
+        keep
+    });
+    let dir = crate::core::transfer::transfer_dir();
+    match tokio::task::spawn_blocking(move || crate::core::transfer::remove_stale(&dir, max_age))
+        .await
+    {
+        Ok(0) => {}
+        Ok(removed) => info!("Removed {} incomplete transfers", removed),
+        Err(err) => error!("Expiring transfers failed: {}", err),
+    }
+}
+
+async fn store_maintenance() {
+    debug!("checking bundle store");
+    match tokio::task::spawn_blocking(crate::store_repair)
+        .await
+        .unwrap_or_else(|err| Err(err.into()))
+    {
+        Ok(report) if !report.is_clean() => info!(
+            "Store maintenance: {} expired tombstones, {} missing, {} orphaned, {} corrupt entries removed",
+            report.expired_tombstones.len(),
+            report.missing_bundles.len(),
+            report.orphaned_bundles.len(),
+            report.corrupt_metadata.len() + report.corrupt_bundles.len()
+        ),
+        Ok(_) => trace!("bundle store is clean"),
+        Err(err) => error!("Store maintenance failed: {}", err),
+    }
 }
 
 pub fn spawn_janitor() {
@@ -22,4 +74,11 @@ pub fn spawn_janitor() {
         crate::CONFIG.lock().janitor_interval,
         janitor,
     ));
+    let store_check_interval = crate::CONFIG.lock().store_check_interval;
+    if !store_check_interval.is_zero() {
+        tokio::spawn(crate::dtnd::cron::spawn_timer(
+            store_check_interval,
+            store_maintenance,
+        ));
+    }
 }
@@ -1,5 +1,7 @@
+pub mod api;
 pub mod cron;
 pub mod daemon;
+pub mod dashboard;
 pub mod httpd;
 pub mod janitor;
 pub mod ws;
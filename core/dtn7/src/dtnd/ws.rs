@@ -1,7 +1,12 @@
-use crate::core::application_agent::ApplicationAgent;
+use crate::core::application_agent::{self, ApplicationAgent, GROUP_DEFAULT_MEMBER};
+use crate::core::rpc;
+use crate::core::status_reports::StatusReportNotification;
+use crate::core::topics;
+use crate::core::transfer::{self, Upload};
 use crate::CONFIG;
 use crate::DTNCORE;
 use crate::STATS;
+use crate::UPLOADS;
 
 use anyhow::{bail, Result};
 use axum::extract::ws::{Message, WebSocket};
@@ -11,7 +16,7 @@ use bp7::{Bundle, CreationTimestamp, EndpointID};
 use dtn7_plus::client::{WsRecvData, WsSendData};
 use futures::{sink::SinkExt, stream::StreamExt};
 use log::{debug, warn};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::{
     convert::TryFrom,
@@ -41,6 +46,14 @@ pub enum DataReceiveFormat {
     JSON,
 }
 
+/// Command whose payload is the next binary message
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PendingBinary {
+    Publish(String),
+    Request(EndpointID, Duration),
+    Reply(String),
+}
+
 /// WebSocket Applicatin Agent Session
 #[derive(Debug, Clone)]
 pub struct WsAASession {
@@ -49,13 +62,21 @@ pub struct WsAASession {
     hb: Instant,
     /// list of endpoints subscribed to
     endpoints: Option<HashSet<EndpointID>>,
+    /// named memberships of subscribed group endpoints
+    members: HashMap<EndpointID, String>,
     /// receive either complete bundles or data and construct bundle server side
     mode: WsReceiveMode,
+    /// active upload of a large ADU, binary messages are its data
+    upload: Option<Upload>,
+    /// command the next binary message is the payload of
+    pending_binary: Option<PendingBinary>,
     tx: mpsc::Sender<BundleDelivery>,
+    /// status reports for bundles sent by this session
+    sr_tx: mpsc::Sender<StatusReportNotification>,
 }
 
 pub async fn handle_socket(socket: WebSocket) {
-    let (session, mut rx_bd) = WsAASession::new();
+    let (session, mut rx_bd, mut rx_sr) = WsAASession::new();
     let (mut sender, mut receiver) = socket.split();
 
     let session = Arc::new(Mutex::new(session));
@@ -137,25 +158,59 @@ pub async fn handle_socket(socket: WebSocket) {
         }
     });
 
+    let tx2 = tx.clone();
+    let mut sr_task = tokio::spawn(async move {
+        while let Some(notification) = rx_sr.recv().await {
+            debug!("Received status report for {}", notification.bid);
+            let msg = format!(
+                "210 status report: {}",
+                serde_json::to_string(&notification).unwrap()
+            );
+            if tx2.send(Message::Text(msg)).await.is_err() {
+                break;
+            }
+        }
+    });
+
     // TODO: maybe add legacy fetch_new_bundles call periodically again?
     tokio::select! {
-        _ = (&mut send_task) => {recv_task.abort(); hb_task.abort();br_task.abort();reflush_task.abort();},
-        _ = (&mut reflush_task) => {send_task.abort(); recv_task.abort(); hb_task.abort(); br_task.abort();},
-        _ = (&mut recv_task) => {send_task.abort(); hb_task.abort();br_task.abort();reflush_task.abort();},
-        _ = (&mut hb_task) => {send_task.abort(); recv_task.abort(); br_task.abort();reflush_task.abort();},
-        _ = (&mut br_task) => {hb_task.abort(); recv_task.abort(); send_task.abort();reflush_task.abort();},
+        _ = (&mut send_task) => {recv_task.abort(); hb_task.abort();br_task.abort();reflush_task.abort();sr_task.abort();},
+        _ = (&mut reflush_task) => {send_task.abort(); recv_task.abort(); hb_task.abort(); br_task.abort();sr_task.abort();},
+        _ = (&mut recv_task) => {send_task.abort(); hb_task.abort();br_task.abort();reflush_task.abort();sr_task.abort();},
+        _ = (&mut hb_task) => {send_task.abort(); recv_task.abort(); br_task.abort();reflush_task.abort();sr_task.abort();},
+        _ = (&mut br_task) => {hb_task.abort(); recv_task.abort(); send_task.abort();reflush_task.abort();sr_task.abort();},
+        _ = (&mut sr_task) => {hb_task.abort(); recv_task.abort(); send_task.abort();reflush_task.abort();br_task.abort();},
     };
 
-    if let Some(endpoints) = &session.lock().await.endpoints {
+    let mut session = session.lock().await;
+    if let Some(upload) = session.upload.take() {
+        debug!("connection ended, parked upload {}", upload.id());
+        UPLOADS.lock().insert(upload.id().to_string(), upload);
+    }
+    if let Some(endpoints) = &session.endpoints {
         for eid in endpoints {
-            if let Some(ep) = (*DTNCORE.lock()).get_endpoint_mut(eid) {
-                ep.clear_delivery_addr();
-            }
+            session.release(eid);
             debug!("connection ended, unsubscribed endpoint: {}", eid);
         }
     };
 }
 
+/// Publishes a message to a topic and returns the reply for the client
+async fn publish(topic: &str, data: Vec<u8>) -> String {
+    match topics::publish(topic, data, Duration::from_secs(60 * 60)).await {
+        Ok(bid) => format!("200 published {} to {}", bid, topic),
+        Err(err) => format!("400 {}", err),
+    }
+}
+
+/// Answers a received request and returns the reply for the client
+async fn reply(request_bid: &str, data: Vec<u8>) -> String {
+    match rpc::reply(request_bid, data).await {
+        Ok(bid) => format!("200 replied {} to {}", bid, request_bid),
+        Err(err) => format!("404 {}", err),
+    }
+}
+
 macro_rules! ws_reply_text {
     ($sock:expr,$msg:expr) => {
         if let Err(err) = $sock.send(Message::Text($msg.to_string())).await {
@@ -165,16 +220,26 @@ macro_rules! ws_reply_text {
 }
 
 impl WsAASession {
-    pub fn new() -> (WsAASession, mpsc::Receiver<BundleDelivery>) {
+    pub fn new() -> (
+        WsAASession,
+        mpsc::Receiver<BundleDelivery>,
+        mpsc::Receiver<StatusReportNotification>,
+    ) {
         let (tx, rx) = mpsc::channel(32);
+        let (sr_tx, sr_rx) = mpsc::channel(32);
         (
             WsAASession {
                 hb: Instant::now(),
                 endpoints: None,
+                members: HashMap::new(),
                 mode: WsReceiveMode::Data(DataReceiveFormat::JSON),
+                upload: None,
+                pending_binary: None,
                 tx,
+                sr_tx,
             },
             rx,
+            sr_rx,
         )
     }
     pub async fn handle_bundle_delivery(
@@ -252,9 +317,8 @@ impl WsAASession {
                                 if let Ok(eid) = EndpointID::try_from(v[1]) {
                                     if let Some(endpoints) = &mut self.endpoints {
                                         endpoints.remove(&eid);
-                                        if let Some(ep) = (*DTNCORE.lock()).get_endpoint_mut(&eid) {
-                                            ep.clear_delivery_addr();
-                                        }
+                                        self.release(&eid);
+                                        self.members.remove(&eid);
                                         debug!("unsubscribed endpoint: {}", eid);
 
                                         ws_reply_text!(socket, "200 unsubscribed");
@@ -266,6 +330,48 @@ impl WsAASession {
                                 }
                             }
                         }
+                        "/leave" => {
+                            if let Some(eid) = v.get(1).and_then(|e| EndpointID::try_from(*e).ok())
+                            {
+                                if let Some(member) = self.members.remove(&eid) {
+                                    if let Some(group) = (*DTNCORE.lock()).get_group_mut(&eid) {
+                                        group.leave(&member);
+                                    }
+                                    if let Some(endpoints) = &mut self.endpoints {
+                                        endpoints.remove(&eid);
+                                    }
+                                    ws_reply_text!(socket, "200 left");
+                                } else {
+                                    ws_reply_text!(socket, "404 no group membership");
+                                }
+                            } else {
+                                ws_reply_text!(socket, "400 invalid endpoint");
+                            }
+                        }
+                        "/ack" => {
+                            if v.len() == 2 {
+                                let acked = {
+                                    let mut core = DTNCORE.lock();
+                                    self.members.iter().fold(false, |acked, (eid, member)| {
+                                        core.get_group_mut(eid)
+                                            .is_some_and(|group| group.ack(member, v[1]))
+                                            || acked
+                                    })
+                                };
+                                if acked {
+                                    ws_reply_text!(socket, "200 acked");
+                                } else {
+                                    ws_reply_text!(socket, "404 unknown bundle");
+                                }
+                            } else {
+                                ws_reply_text!(socket, "400 bundle id is missing");
+                            }
+                        }
+                        "/subscribe" if v.len() == 2 && v[1].split_whitespace().count() > 1 => {
+                            let args: Vec<&str> = v[1].split_whitespace().collect();
+                            let reply = self.subscribe_member(&args);
+                            ws_reply_text!(socket, reply);
+                        }
                         "/subscribe" => {
                             if v.len() == 2 {
                                 if let Ok(eid) = EndpointID::try_from(v[1]) {
@@ -330,6 +436,76 @@ impl WsAASession {
                                 ws_reply_text!(socket, "400 endpoint is missing");
                             }
                         }
+                        "/subscribe-topic" => {
+                            let reply = self.subscribe_topic(v.get(1).copied().unwrap_or(""));
+                            let subscribed = reply.starts_with("200");
+                            ws_reply_text!(socket, reply);
+                            if subscribed {
+                                self.fetch_new_bundles(socket.clone()).await;
+                            }
+                        }
+                        "/publish" => {
+                            let args: Vec<&str> = v
+                                .get(1)
+                                .map(|a| a.splitn(2, ' ').collect())
+                                .unwrap_or_default();
+                            let reply = match args.as_slice() {
+                                [] => "400 topic is missing".to_string(),
+                                [topic] if topics::is_valid_topic(topic) => {
+                                    self.pending_binary =
+                                        Some(PendingBinary::Publish(topic.to_string()));
+                                    format!("200 publishing next binary message to {}", topic)
+                                }
+                                [topic, message] => {
+                                    publish(topic, message.as_bytes().to_vec()).await
+                                }
+                                _ => "400 invalid topic".to_string(),
+                            };
+                            ws_reply_text!(socket, reply);
+                        }
+                        "/request" => {
+                            let args: Vec<&str> = v
+                                .get(1)
+                                .map(|a| a.splitn(3, ' ').collect())
+                                .unwrap_or_default();
+                            let target = args.first().and_then(|d| EndpointID::try_from(*d).ok());
+                            let timeout =
+                                args.get(1).and_then(|t| humantime::parse_duration(t).ok());
+                            let reply = match (target, timeout, args.get(2)) {
+                                (Some(dst), Some(timeout), Some(message)) => {
+                                    self.request(socket.clone(), dst, timeout, message.as_bytes())
+                                        .await
+                                }
+                                (Some(dst), Some(timeout), None) => {
+                                    self.pending_binary =
+                                        Some(PendingBinary::Request(dst.clone(), timeout));
+                                    format!("200 requesting next binary message from {}", dst)
+                                }
+                                (None, ..) => "400 invalid endpoint".to_string(),
+                                (_, None, _) => "400 invalid timeout".to_string(),
+                            };
+                            ws_reply_text!(socket, reply);
+                        }
+                        "/reply" => {
+                            let args: Vec<&str> = v
+                                .get(1)
+                                .map(|a| a.splitn(2, ' ').collect())
+                                .unwrap_or_default();
+                            let reply = match args.as_slice() {
+                                [] => "400 bundle id is missing".to_string(),
+                                [bid] => {
+                                    self.pending_binary =
+                                        Some(PendingBinary::Reply(bid.to_string()));
+                                    format!("200 replying next binary message to {}", bid)
+                                }
+                                [bid, message, ..] => reply(bid, message.as_bytes().to_vec()).await,
+                            };
+                            ws_reply_text!(socket, reply);
+                        }
+                        "/stream" => {
+                            let reply = self.stream_command(v.get(1).copied().unwrap_or("")).await;
+                            ws_reply_text!(socket, reply);
+                        }
                         _ => {
                             ws_reply_text!(socket, format!("501 unknown command: {:?}", m));
                         }
@@ -337,6 +513,27 @@ impl WsAASession {
                 }
             }
 
+            Message::Binary(bin) if self.upload.is_some() => {
+                let upload = self.upload.as_mut().unwrap();
+                for bndl in upload.write(&bin) {
+                    if let Err(err) = transfer::send_chunk(bndl).await {
+                        ws_reply_text!(socket, format!("500 {}", err));
+                        return Ok(());
+                    }
+                }
+                let offset = upload.offset();
+                ws_reply_text!(socket, format!("200 streamed {}", offset));
+            }
+            Message::Binary(bin) if self.pending_binary.is_some() => {
+                let reply = match self.pending_binary.take().unwrap() {
+                    PendingBinary::Publish(topic) => publish(&topic, bin).await,
+                    PendingBinary::Request(dst, timeout) => {
+                        self.request(socket.clone(), dst, timeout, &bin).await
+                    }
+                    PendingBinary::Reply(bid) => reply(&bid, bin).await,
+                };
+                ws_reply_text!(socket, reply);
+            }
             Message::Binary(bin) => {
                 match self.mode {
                     WsReceiveMode::Bundle => {
@@ -346,6 +543,7 @@ impl WsAASession {
                                 bndl.id(),
                                 bndl.primary.destination
                             );
+                            crate::status_reports_watch(&bndl.id(), self.sr_tx.clone());
                             // TODO: turn into channel
                             //                            crate::core::processing::send_bundle(bndl);
                             //crate::core::processing::send_through_task(bndl);
@@ -413,6 +611,7 @@ impl WsAASession {
 
                             bndl.set_crc(bp7::crc::CRC_NO);
                             let bid = bndl.id();
+                            crate::status_reports_watch(&bid, self.sr_tx.clone());
                             debug!(
                                 "Sending bundle {} from data frame to {} from WS",
                                 bid, bndl.primary.destination
@@ -460,11 +659,176 @@ impl WsAASession {
 
         Ok(())
     }
+    /// Sends a request, the response or a timeout is reported to the client later on
+    async fn request(
+        &self,
+        socket: mpsc::Sender<Message>,
+        dst: EndpointID,
+        timeout: Duration,
+        data: &[u8],
+    ) -> String {
+        let request = match rpc::request(dst, data.to_vec(), timeout, None).await {
+            Ok(request) => request,
+            Err(err) => return format!("400 {}", err),
+        };
+        let reply = format!("200 request {} sent as {}", request.id, request.bid);
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let id = request.id.clone();
+            let msg = match request.wait().await {
+                Some(response) => {
+                    let msg = format!("220 response {} {}", id, response.id());
+                    if socket.send(Message::Text(msg)).await.is_ok() {
+                        tx.send(BundleDelivery(response)).await.ok();
+                    }
+                    return;
+                }
+                None => format!("408 request {} timed out", id),
+            };
+            socket.send(Message::Text(msg)).await.ok();
+        });
+        reply
+    }
+    /// Controls the upload of a large ADU: `<endpoint> [name]`, `end`, `pause` or `resume <transfer>`
+    async fn stream_command(&mut self, args: &str) -> String {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        match args.as_slice() {
+            [] => "400 endpoint is missing".into(),
+            ["end"] => {
+                let Some(upload) = self.upload.take() else {
+                    return "404 no active stream".into();
+                };
+                let id = upload.id().to_string();
+                let (offset, bundles) = (upload.offset(), upload.bundles() + 1);
+                if let Err(err) = transfer::send_chunk(upload.finish()).await {
+                    return format!("500 {}", err);
+                }
+                format!(
+                    "200 stream {} complete with {} bytes in {} bundles",
+                    id, offset, bundles
+                )
+            }
+            ["pause"] => {
+                let Some(upload) = self.upload.take() else {
+                    return "404 no active stream".into();
+                };
+                let reply = format!("200 stream {} paused at {}", upload.id(), upload.offset());
+                UPLOADS.lock().insert(upload.id().to_string(), upload);
+                reply
+            }
+            _ if self.upload.is_some() => "409 stream already active".into(),
+            ["resume", id] => match UPLOADS.lock().remove(*id) {
+                Some(upload) => {
+                    let reply =
+                        format!("200 stream {} resumed at {}", upload.id(), upload.offset());
+                    self.upload = Some(upload);
+                    reply
+                }
+                None => "404 unknown stream".into(),
+            },
+            [dst, name @ ..] => {
+                let Ok(dst) = EndpointID::try_from(*dst) else {
+                    return "400 invalid endpoint".into();
+                };
+                let src = CONFIG.lock().host_eid.clone();
+                let upload = Upload::new(
+                    transfer::new_transfer_id(),
+                    src,
+                    dst,
+                    Duration::from_secs(60 * 60),
+                    transfer::TRANSFER_CHUNK_SIZE_DEFAULT,
+                    name.first().map(|n| n.to_string()),
+                );
+                let reply = format!("200 stream {} started", upload.id());
+                self.upload = Some(upload);
+                reply
+            }
+        }
+    }
+    /// Subscribes as named member to a group endpoint: `<endpoint> <member> [lease]`
+    fn subscribe_member(&mut self, args: &[&str]) -> &'static str {
+        let Ok(eid) = EndpointID::try_from(args[0]) else {
+            return "400 invalid endpoint";
+        };
+        let member = args[1];
+        let lease = match args
+            .get(2)
+            .map(|l| humantime::parse_duration(l))
+            .transpose()
+        {
+            Ok(lease) => lease,
+            Err(_) => return "400 invalid lease",
+        };
+        let subscribed = self
+            .endpoints
+            .as_ref()
+            .is_some_and(|endpoints| endpoints.contains(&eid));
+        let mut core = DTNCORE.lock();
+        let Some(group) = core.get_group_mut(&eid) else {
+            return "404 unknown group endpoint";
+        };
+        match self.members.get(&eid) {
+            Some(previous) if previous != member => group.detach(previous),
+            None if subscribed => group.detach(GROUP_DEFAULT_MEMBER),
+            _ => {}
+        }
+        group.join(member, lease);
+        group.attach(member, self.tx.clone());
+        drop(core);
+
+        debug!("subscribed to group {} as {}", eid, member);
+        self.members.insert(eid.clone(), member.to_string());
+        self.endpoints.get_or_insert_with(HashSet::new).insert(eid);
+        "200 subscribed"
+    }
+    /// Subscribes to a topic, registering its group endpoint if needed: `<topic> [member [lease]]`
+    fn subscribe_topic(&mut self, args: &str) -> String {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let Some(topic) = args.first() else {
+            return "400 topic is missing".into();
+        };
+        let Ok(eid) = topics::topic_eid(topic) else {
+            return "400 invalid topic".into();
+        };
+        DTNCORE
+            .lock()
+            .register_application_agent(application_agent::new(eid.clone()));
+        if args.len() > 1 {
+            let eid = eid.to_string();
+            let mut member_args = vec![eid.as_str()];
+            member_args.extend(&args[1..]);
+            return self.subscribe_member(&member_args).into();
+        }
+        if let Some(ep) = DTNCORE.lock().get_endpoint_mut(&eid) {
+            ep.set_delivery_addr(self.tx.clone());
+        }
+        debug!("subscribed to topic {}", topic);
+        self.endpoints.get_or_insert_with(HashSet::new).insert(eid);
+        "200 subscribed".into()
+    }
+    /// Stops deliveries of `eid` to this session, named group members keep their membership
+    fn release(&self, eid: &EndpointID) {
+        let mut core = DTNCORE.lock();
+        if let Some(member) = self.members.get(eid) {
+            if let Some(group) = core.get_group_mut(eid) {
+                group.detach(member);
+            }
+        } else if let Some(ep) = core.get_endpoint_mut(eid) {
+            ep.clear_delivery_addr();
+        }
+    }
     pub async fn fetch_new_bundles(&mut self, socket: mpsc::Sender<Message>) {
         debug!("delivering bundles for endpoint(s)");
         let mut senders = Vec::new();
         if let Some(endpoints) = self.endpoints.clone() {
             for eid in endpoints {
+                if let Some(member) = self.members.get(&eid) {
+                    // named group members are served through the delivery channel
+                    if let Some(group) = (*DTNCORE.lock()).get_group_mut(&eid) {
+                        group.flush(member);
+                    }
+                    continue;
+                }
                 if let Some(aa) = (*DTNCORE.lock()).get_endpoint_mut(&eid) {
                     while let Some(mut bundle) = aa.pop() {
                         let recv_data = match self.mode {
@@ -1,9 +1,10 @@
 use crate::cla::ConvergenceLayerAgent;
+use crate::core::topics;
 use crate::core::{DtnPeer, PeerType};
 use crate::ipnd::{beacon::Beacon, services::*};
 use crate::routing::RoutingNotifcation;
 use crate::{peers_add, routing_notify, CLAS, CONFIG};
-use crate::{peers_touch, DTNCORE};
+use crate::{peers_touch, peers_update_services, DTNCORE};
 use anyhow::Result;
 use log::{debug, error, info, trace};
 use socket2::{Domain, Socket, Type};
@@ -58,6 +59,11 @@ async fn receiver(socket: UdpSocket) -> Result<(), io::Error> {
                 if let Err(e) = peers_touch(deserialized.eid().node().unwrap().as_ref()) {
                     error!("Failed to touch peer: {}", e);
                 }
+                // advertised topics change over time
+                peers_update_services(
+                    &deserialized.eid().node().unwrap(),
+                    deserialized.service_block().convert_services(),
+                );
             }
             trace!("{}", deserialized);
             if let Err(err) = routing_notify(RoutingNotifcation::EncounteredPeer(
@@ -94,6 +100,10 @@ async fn announcer(socket: UdpSocket, _v6: bool) {
             .service_list
             .iter()
             .for_each(|(tag, payload)| pkt.add_custom_service(*tag, payload.clone()));
+        pkt.add_custom_service(
+            Service::TOPICS,
+            topics::format_interest(&topics::advertisement()),
+        );
 
         //let nodeid = format!("dtn://{}", (*DTNCORE.lock()).nodeid);
         //let addr = "127.0.0.1:3003".parse().unwrap();
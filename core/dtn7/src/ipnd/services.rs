@@ -66,6 +66,9 @@ impl ServiceBlock {
                         .expect("Couldn't parse byte array into string");
                     convert.insert(*tag, message);
                 }
+                Service::TOPICS => {
+                    convert.insert(*tag, String::from_utf8_lossy(payload).to_string());
+                }
                 _ => {
                     warn!("Unknown service encountered. Compare senders IPND version with this one to check for incompatibilities.");
                 }
@@ -155,6 +158,20 @@ impl ServiceBlock {
                     Err(String::from("Can not derive address from provided arguments. Argument order is: Street HouseNumber PostalNumber City CountryCode"))
                 }
             }
+            // Topics expects space separated `<topic>:<hops>` entries
+            Service::TOPICS => {
+                if payload.len() > crate::core::topics::TOPIC_ADVERTISEMENT_MAX {
+                    Err(String::from("The provided topic advertisement is too big"))
+                } else if crate::core::topics::parse_interest(payload).len()
+                    != payload.split_whitespace().count()
+                {
+                    Err(String::from(
+                        "Can not derive topics from provided arguments. Expected format is: topic:hops ...",
+                    ))
+                } else {
+                    Ok((tag, payload.as_bytes().to_vec()))
+                }
+            }
             // Undefined tags
             _ => Err(String::from(
                 "This custom tag is not yet defined. Please refrain from using it until added.",
@@ -221,6 +238,12 @@ impl std::fmt::Display for ServiceBlock {
                     format!("{}. Tag = {} Address service. Street {}; House Number {}; Postal Number {}; City {}; Country Code {}\n",
                             counter, tag, address[0],address[1],address[2],address[3],address[4])
                 }
+                Service::TOPICS => format!(
+                    "{}. Tag = {} Topics service. Subscribed topics: {}\n",
+                    counter,
+                    tag,
+                    String::from_utf8_lossy(payload)
+                ),
                 _ => {
                     warn!("Unknown service encountered. Compare senders IPND version with this one to check for incompatibilities.");
                     String::new()
@@ -290,9 +313,10 @@ impl<'de> Deserialize<'de> for ServiceBlock {
 }
 
 /// Enum struct for defining services
-struct Service;
+pub struct Service;
 
 impl Service {
+    pub const TOPICS: u8 = 31;
     pub const CUSTOM_STRING: u8 = 63;
     pub const GEO_LOCATION: u8 = 127;
     pub const BATTERY: u8 = 191;
@@ -7,8 +7,12 @@ pub mod ipnd;
 pub mod routing;
 
 use crate::cla::CLAsAvailable;
-use crate::core::bundlepack::BundlePack;
+use crate::core::bundlepack::{BundlePack, Constraint};
+use crate::core::events::{Event, EventBus, EvictionReason};
+use crate::core::extension_blocks::{ExtensionBlockHandler, ExtensionBlockRegistry};
+use crate::core::rpc::RpcState;
 use crate::core::store::{BundleStore, InMemoryBundleStore};
+use crate::core::transfer::Upload;
 use crate::core::DtnStatistics;
 use crate::routing::{RoutingAgent, RoutingCmd};
 use bp7::{Bundle, EndpointID};
@@ -20,8 +24,10 @@ pub use crate::core::{DtnCore, DtnPeer};
 pub use crate::routing::RoutingNotifcation;
 
 use crate::cla::ConvergenceLayerAgent;
-use crate::core::bundlepack::Constraint;
-use crate::core::peer::PeerAddress;
+use crate::core::linkstats::LinkStats;
+use crate::core::peer::{PeerAddress, PeerType};
+use crate::core::status_reports::{StatusReportEntry, StatusReportLog, StatusReportNotification};
+use crate::core::store::maintenance::{self, StoreCheckReport};
 use crate::core::store::BundleStoresEnum;
 use anyhow::{bail, Context, Result};
 use lazy_static::*;
@@ -38,8 +44,23 @@ lazy_static! {
     pub static ref SENDERTASK: Mutex<Option<Sender<Bundle>>> = Mutex::new(None);
     pub static ref STORE: Mutex<BundleStoresEnum> = Mutex::new(InMemoryBundleStore::new().into());
     pub static ref CLAS: Mutex<Vec<CLAEnum>> = Mutex::new(Vec::new());
+    /// Link statistics of peers that were dropped, restored once they are seen again
+    pub static ref PEER_HISTORY: Mutex<BTreeMap<String, LinkStats>> = Mutex::new(BTreeMap::new());
+    /// Status reports received for bundles, keyed by the referenced bundle ID
+    pub static ref STATUS_REPORTS: Mutex<StatusReportLog> = Mutex::new(StatusReportLog::default());
+    /// Handlers of known extension block types
+    pub static ref EXTENSION_BLOCKS: Mutex<ExtensionBlockRegistry> = Mutex::new(ExtensionBlockRegistry::default());
+    /// Interrupted uploads of large ADUs, keyed by transfer ID
+    pub static ref UPLOADS: Mutex<HashMap<String, Upload>> = Mutex::new(HashMap::new());
+    /// Requests waiting for a response and received requests not answered yet
+    pub static ref RPC: Mutex<RpcState> = Mutex::new(RpcState::default());
+    /// Structured events of node activity
+    pub static ref EVENTS: EventBus = EventBus::new();
 }
 
+/// Maximum number of dropped peers whose link statistics are kept
+const MAX_PEER_HISTORY: usize = 256;
+
 pub type BundleID = String;
 
 pub fn cla_add(cla: CLAEnum) {
@@ -107,16 +128,56 @@ pub fn get_sequence(destination: &str) -> u32 {
 /// adds a new peer to the DTN core
 /// return true if peer was seen first time
 /// return false if peer was already known
-pub fn peers_add(peer: DtnPeer) -> bool {
+pub fn peers_add(mut peer: DtnPeer) -> bool {
     debug!("Adding peer eid={}", peer.eid.node().unwrap());
-    (*PEERS.lock())
-        .insert(peer.eid.node().unwrap(), peer)
-        .is_none()
+    let node_name = peer.eid.node().unwrap();
+    let mut peers = PEERS.lock();
+    if let Some(known) = peers.get(&node_name) {
+        peer.link_stats = known.link_stats.clone();
+    } else if let Some(history) = PEER_HISTORY.lock().remove(&node_name) {
+        peer.link_stats = history;
+    }
+    if peer.con_type == PeerType::Dynamic {
+        peer.link_stats.contact_started();
+    }
+    let addr = peer.addr.to_string();
+    let new = peers.insert(node_name.clone(), peer).is_none();
+    drop(peers);
+    if new {
+        EVENTS.publish(Event::PeerEncountered {
+            peer: node_name,
+            addr,
+        });
+    }
+    new
+}
+
+/// Keeps the link statistics of a peer that is no longer known
+pub fn peers_retire(mut peer: DtnPeer) {
+    EVENTS.publish(Event::PeerDropped {
+        peer: peer.node_name(),
+    });
+    peer.link_stats.contact_ended();
+    let mut history = PEER_HISTORY.lock();
+    if history.len() >= MAX_PEER_HISTORY {
+        // forget the peer that has been out of contact the longest
+        let oldest = history
+            .iter()
+            .min_by_key(|(_, stats)| stats.contacts.back().map_or(0, |c| c.end))
+            .map(|(name, _)| name.clone());
+        if let Some(oldest) = oldest {
+            history.remove(&oldest);
+        }
+    }
+    history.insert(peer.node_name(), peer.link_stats);
 }
 
 pub fn peers_remove(peer_id: &str) {
     debug!("Removing peer eid={}", peer_id);
-    (*PEERS.lock()).remove(peer_id);
+    let removed = (*PEERS.lock()).remove(peer_id);
+    if let Some(peer) = removed {
+        peers_retire(peer);
+    }
 }
 
 pub fn peers_count() -> usize {
@@ -136,6 +197,12 @@ pub fn peers_touch(peer: &str) -> Result<()> {
         .touch();
     Ok(())
 }
+/// Replaces the services of a peer with those of its latest announcement
+pub fn peers_update_services(peer: &str, services: HashMap<u8, String>) {
+    if let Some(peer) = (*PEERS.lock()).get_mut(peer) {
+        peer.services = services;
+    }
+}
 pub fn peers_get_for_node(eid: &EndpointID) -> Option<DtnPeer> {
     for (_, p) in (*PEERS.lock()).iter() {
         if p.node_name() == eid.node().unwrap_or_default() {
@@ -144,6 +211,14 @@ pub fn peers_get_for_node(eid: &EndpointID) -> Option<DtnPeer> {
     }
     None
 }
+/// Records a bundle received via the given CLA for the bundle's previous node
+pub fn peers_report_receive(bndl: &Bundle, cla: &str, bytes: usize) {
+    if let Some(node_name) = bndl.previous_node().and_then(|eid| eid.node()) {
+        if let Some(peer) = (*PEERS.lock()).get_mut(&node_name) {
+            peer.report_receive(cla, bytes as u64);
+        }
+    }
+}
 pub fn is_local_node_id(eid: &EndpointID) -> bool {
     eid.node_id() == CONFIG.lock().host_eid.node_id()
 }
@@ -185,13 +260,28 @@ pub fn store_remove(bid: &str) -> Result<()> {
     Ok(())
 }
 
-/// Removes a bundle from the store if its destination is a singleton endpoint
-fn store_remove_if_singleton_bundle(bundle: &Bundle) {
-    if !bundle.primary.destination.is_non_singleton() {
-        debug!("Removing bundle with singleton destination from store");
-        if let Err(e) = store_remove(&bundle.id()) {
-            error!("Error while removing bundle from store: {:?}", e);
-        }
+/// Marks a bundle as delivered to its local endpoint.
+///
+/// Bundles with a singleton destination are removed from the store, others are kept
+/// for forwarding but are no longer pending local delivery.
+fn store_mark_delivered(bid: &str) {
+    let Some(mut bp) = store_get_metadata(bid) else {
+        return;
+    };
+    bp.remove_constraint(Constraint::LocalEndpoint);
+    let res = if !bp.destination.is_non_singleton() || !bp.has_constraints() {
+        debug!("Removing delivered bundle {} from store", bid);
+        store_remove(bid).map(|_| {
+            EVENTS.publish(Event::BundleDeleted {
+                bid: bid.to_string(),
+                reason: "delivered".into(),
+            });
+        })
+    } else {
+        store_update_metadata(&bp)
+    };
+    if let Err(e) = res {
+        error!("Error while marking bundle {} as delivered: {:?}", bid, e);
     }
 }
 
@@ -208,22 +298,102 @@ pub fn store_get_metadata(bpid: &str) -> Option<BundlePack> {
     (*STORE.lock()).get_metadata(bpid)
 }
 
-pub fn store_delete_expired() {
-    let all_bids = (*STORE.lock()).bundles();
+/// Registers a handler for a custom canonical block type, replacing a previous one.
+pub fn extension_block_register(handler: impl ExtensionBlockHandler + 'static) {
+    (*EXTENSION_BLOCKS.lock()).register(std::sync::Arc::new(handler));
+}
+pub fn extension_block_unregister(block_type: u64) -> bool {
+    (*EXTENSION_BLOCKS.lock()).unregister(block_type)
+}
 
-    let all_but_deleted: Vec<&BundlePack> = all_bids
-        .iter()
-        .filter(|bp| !bp.has_constraint(Constraint::Deleted))
-        .collect();
+/// Returns the status reports received for a bundle, oldest first
+pub fn status_reports_get(bid: &str) -> Vec<StatusReportEntry> {
+    (*STATUS_REPORTS.lock())
+        .get(bid)
+        .cloned()
+        .unwrap_or_default()
+}
+/// Forwards future status reports for a bundle to an application
+pub fn status_reports_watch(bid: &str, tx: Sender<StatusReportNotification>) {
+    (*STATUS_REPORTS.lock()).watch(bid, tx);
+}
 
-    for meta in all_but_deleted {
-        if meta.has_expired() {
-            debug!("Bundle {} is too old, deleting it", meta.id);
-            if store_remove(&meta.id).is_err() {
-                error!("Error while deleting expired bundle {}", meta.id);
+pub fn store_delete_expired() {
+    let now = bp7::CreationTimestamp::now().dtntime();
+    let expired = (*STORE.lock()).query_expiring_before(now);
+
+    for bid in expired {
+        debug!("Bundle {} is too old, deleting it", bid);
+        if store_remove(&bid).is_err() {
+            error!("Error while deleting expired bundle {}", bid);
+        } else {
+            EVENTS.publish(Event::StoreEvicted {
+                bid,
+                reason: EvictionReason::Expired,
+            });
+        }
+    }
+}
+/// Checks the integrity of the bundle store
+///
+/// The store is only locked for fetching single entries, bundles are decoded and verified
+/// without holding the lock. Blocks for a while on large stores, async callers should use
+/// `spawn_blocking`.
+pub fn store_check() -> StoreCheckReport {
+    let horizon = CONFIG.lock().tombstone_horizon;
+    let (meta_ids, blob_ids) = {
+        let store = STORE.lock();
+        (store.metadata_ids(), store.blob_ids())
+    };
+    let mut checker = maintenance::StoreChecker::new(&blob_ids, horizon);
+    for bid in meta_ids {
+        let meta = STORE.lock().get_metadata(&bid);
+        checker.check_metadata(&bid, meta);
+    }
+    for bid in blob_ids {
+        checker.check_blob(&bid, || STORE.lock().get_raw(&bid));
+    }
+    checker.finish()
+}
+/// Checks the bundle store and purges expired tombstones and broken entries
+///
+/// Like [`store_check`], this only locks the store for single entries. Each finding is
+/// confirmed under the lock before purging, as the store may have changed in the meantime.
+/// The returned report only contains the purged entries.
+pub fn store_repair() -> Result<StoreCheckReport> {
+    let horizon = CONFIG.lock().tombstone_horizon;
+    let mut report = store_check();
+    let evicted = [
+        (&mut report.expired_tombstones, EvictionReason::TombstoneExpired),
+        (&mut report.missing_bundles, EvictionReason::MissingBundle),
+        (&mut report.orphaned_bundles, EvictionReason::OrphanedBundle),
+        (&mut report.corrupt_metadata, EvictionReason::Corrupt),
+        (&mut report.corrupt_bundles, EvictionReason::Corrupt),
+    ];
+    for (bids, reason) in evicted {
+        let mut purged = Vec::new();
+        for bid in bids.drain(..) {
+            {
+                let mut store = STORE.lock();
+                if !maintenance::confirm(&*store, &bid, reason, horizon) {
+                    debug!("Store entry {} changed during the check, keeping it", bid);
+                    continue;
+                }
+                store.purge(&bid)?;
             }
+            EVENTS.publish(Event::StoreEvicted {
+                bid: bid.clone(),
+                reason,
+            });
+            purged.push(bid);
         }
+        *bids = purged;
+    }
+    if !report.is_clean() {
+        info!("Store repair purged {} entries", report.affected().len());
+        STORE.lock().compact()?;
     }
+    Ok(report)
 }
 pub async fn routing_cmd(cmd: String) -> Result<()> {
     let chan = DTNCORE.lock().routing_agent.channel();
@@ -1,4 +1,6 @@
+use crate::core::bundlepack::Constraint;
 use crate::{peers_get_for_node, BundleID, BundlePack, DtnPeer, PeerAddress, RoutingNotifcation};
+use bp7::administrative_record::StatusReportReason;
 use bp7::{Bundle, EndpointID};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
@@ -48,6 +50,23 @@ pub enum Packet {
     ServiceState(ServiceState),
     /// Packet that contains the full initial service state of dtnd at the point of connection.
     ServiceAdd(AddService),
+    /// Packet that tells a router whether it is the primary, which answers the
+    /// RequestSenderForBundle packets, or a standby that only follows the state.
+    AgentRole(AgentRole),
+    /// Packet that requests the metadata of all stored bundles matching a filter.
+    QueryBundles(QueryBundles),
+    /// Packet response to a QueryBundles packet with the matching bundles.
+    BundleList(BundleList),
+    /// Packet that triggers the forwarding of a stored bundle.
+    ForwardBundle(ForwardBundle),
+    /// Packet that deletes a stored bundle.
+    DeleteBundle(DeleteBundle),
+    /// Packet that schedules a timer, replacing a pending timer with the same id.
+    ScheduleTimer(ScheduleTimer),
+    /// Packet that cancels a pending timer.
+    CancelTimer(CancelTimer),
+    /// Packet that signals that a scheduled timer expired.
+    TimerExpired(TimerExpired),
 }
 
 impl From<RoutingNotifcation> for Packet {
@@ -165,3 +184,139 @@ pub struct AddService {
 pub struct ServiceState {
     pub service_list: HashMap<u8, String>,
 }
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AgentRole {
+    pub primary: bool,
+}
+
+/// Criteria for a QueryBundles request, all given criteria have to match.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BundleFilter {
+    /// Only the bundles with these IDs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bids: Vec<String>,
+    /// Bundles with this retention constraint, e.g. `ForwardPending`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<Constraint>,
+    /// Bundles with exactly this destination.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Bundles with a source or destination containing this string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Bundles whose lifetime ends before this DTN time in ms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiring_before: Option<u64>,
+    /// Also return the tombstones of deleted bundles.
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Skips this many bundles, ordered by creation time.
+    #[serde(default)]
+    pub offset: usize,
+    /// Returns at most this many bundles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl BundleFilter {
+    /// Checks a bundle against all criteria except offset and limit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bp7::helpers::rnd_bundle;
+    /// use bp7::CreationTimestamp;
+    /// use dtn7::core::bundlepack::{BundlePack, Constraint};
+    /// use dtn7::routing::erouting::BundleFilter;
+    ///
+    /// let mut bp = BundlePack::from(rnd_bundle(CreationTimestamp::now()));
+    /// bp.add_constraint(Constraint::ForwardPending);
+    /// let filter = BundleFilter {
+    ///     constraint: Some(Constraint::ForwardPending),
+    ///     destination: Some(bp.destination.to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert!(filter.matches(&bp));
+    ///
+    /// bp.add_constraint(Constraint::Deleted);
+    /// assert!(!filter.matches(&bp));
+    /// ```
+    pub fn matches(&self, bp: &BundlePack) -> bool {
+        if !self.include_deleted && bp.has_constraint(Constraint::Deleted) {
+            return false;
+        }
+        if !self.bids.is_empty() && !self.bids.contains(&bp.id) {
+            return false;
+        }
+        if let Some(constraint) = self.constraint {
+            if !bp.has_constraint(constraint) {
+                return false;
+            }
+        }
+        if let Some(dst) = &self.destination {
+            if bp.destination.to_string() != *dst {
+                return false;
+            }
+        }
+        if let Some(addr) = &self.address {
+            if !bp.source.to_string().contains(addr.as_str())
+                && !bp.destination.to_string().contains(addr.as_str())
+            {
+                return false;
+            }
+        }
+        if let Some(time) = self.expiring_before {
+            if bp.lifetime == 0 || bp.creation_time + bp.lifetime >= time {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueryBundles {
+    /// Chosen by the router and returned in the BundleList.
+    pub id: u64,
+    #[serde(default)]
+    pub filter: BundleFilter,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BundleList {
+    pub id: u64,
+    pub bundles: Vec<BundlePack>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ForwardBundle {
+    pub bid: BundleID,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeleteBundle {
+    pub bid: BundleID,
+    /// Reason code for the deletion status report, if one was requested by the source.
+    #[serde(default)]
+    pub reason: StatusReportReason,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScheduleTimer {
+    pub id: String,
+    pub delay_ms: u64,
+    /// Fire every `delay_ms` until cancelled.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CancelTimer {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TimerExpired {
+    pub id: String,
+}
@@ -1,107 +1,189 @@
-use super::{Packet, PeerState, RequestSenderForBundle, ResponseSenderForBundle, ServiceState};
+use super::{
+    AgentRole, BundleFilter, BundleList, Error, Packet, PeerState, RequestSenderForBundle,
+    ResponseSenderForBundle, ScheduleTimer, ServiceState, TimerExpired,
+};
 use crate::cla::ConvergenceLayerAgent;
-use crate::routing::erouting::Error;
+use crate::core::bundlepack::Constraint;
+use crate::core::encoding::Encoding;
+use crate::core::processing::{delete, forward};
+use crate::core::store::BundleStore;
 use crate::{
-    cla_names, lazy_static, service_add, BundlePack, ClaSenderTask, RoutingNotifcation, CLAS,
-    DTNCORE, PEERS,
+    cla_names, lazy_static, service_add, store_get_metadata, BundlePack, ClaSenderTask,
+    RoutingNotifcation, CLAS, DTNCORE, PEERS, STORE,
 };
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::{future, SinkExt, StreamExt, TryStreamExt};
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
+use tokio::task::AbortHandle;
 use tokio::time::timeout;
 
 /// Maximum timeout for a sender_for_bundle response packet.
 const EROUTING_RESPONSE_TIMEOUT_MS: u64 = 250;
+/// Maximum number of bundles returned by a single QueryBundles, also used if no limit is given.
+pub const EROUTING_MAX_QUERY_BUNDLES: usize = 1000;
+/// Shortest delay of a scheduled timer, shorter delays are raised to this.
+pub const EROUTING_MIN_TIMER_DELAY_MS: u64 = 100;
+/// Maximum number of pending timers per router.
+pub const EROUTING_MAX_TIMERS: usize = 256;
+
+/// Default number of consecutive timeouts after which the primary router hands over to a standby.
+pub const EROUTING_FAILOVER_TIMEOUTS: u32 = 3;
 
-/// Holds the channel to send messages to the connected router.
+/// Holds the channel to send messages to a connected router.
 struct Connection {
+    id: u64,
     tx: Sender<Message>,
+    encoding: Encoding,
+    /// Consecutive sender_for_bundle requests the router did not answer in time.
+    timeouts: u32,
+    /// Pending timers of the router by their id, with a generation to tell replaced timers apart.
+    timers: HashMap<String, (u64, AbortHandle)>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for (_, timer) in self.timers.values() {
+            timer.abort();
+        }
+    }
 }
 
-type ResponseMap = Arc<Mutex<HashMap<String, oneshot::Sender<Packet>>>>;
+/// Status of a connected router as reported by the external routing agent.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentInfo {
+    pub id: u64,
+    pub primary: bool,
+    pub encoding: Encoding,
+    pub timeouts: u32,
+}
+
+/// Response channels by bundle id, together with the id of the router the request was sent to.
+type ResponseMap = Arc<Mutex<HashMap<String, (u64, oneshot::Sender<Packet>)>>>;
 
 lazy_static! {
-    /// Keeps track of the single router that can be connected.
-    static ref CONNECTION: Arc<Mutex<Option<Connection>>> = Arc::new(Mutex::new(None));
+    /// Keeps track of the connected routers. The first one is the primary that answers
+    /// the routing requests, the others are standbys in order of their connection.
+    static ref CONNECTIONS: Arc<Mutex<Vec<Connection>>> = Arc::new(Mutex::new(Vec::new()));
     /// Tracks the response channels for SenderForBundle requests.
     static ref RESPONSES: ResponseMap = ResponseMap::new(Mutex::new(HashMap::new()));
 }
 
-fn send_peer_state() {
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_TIMER: AtomicU64 = AtomicU64::new(1);
+static FAILOVER_TIMEOUTS: AtomicU32 = AtomicU32::new(EROUTING_FAILOVER_TIMEOUTS);
+
+/// Sets the number of consecutive timeouts after which the primary router is moved behind the standbys.
+pub fn set_failover_timeouts(timeouts: u32) {
+    FAILOVER_TIMEOUTS.store(timeouts.max(1), Ordering::Relaxed);
+}
+
+/// Returns the connected routers, the primary first.
+pub fn agents() -> Vec<AgentInfo> {
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .map(|(i, con)| AgentInfo {
+            id: con.id,
+            primary: i == 0,
+            encoding: con.encoding,
+            timeouts: con.timeouts,
+        })
+        .collect()
+}
+
+fn send_peer_state(id: u64) {
     let peer_state: Packet = Packet::PeerState(PeerState {
         peers: PEERS.lock().clone(),
     });
-    send_packet(&peer_state);
+    send_packet_to(id, &peer_state);
 }
 
-fn send_service_state() {
+fn send_service_state(id: u64) {
     let service_state: Packet = Packet::ServiceState(ServiceState {
         service_list: DTNCORE.lock().service_list.clone(),
     });
-    send_packet(&service_state);
+    send_packet_to(id, &service_state);
 }
 
-pub async fn handle_connection(ws: WebSocket) {
-    let (tx, mut rx) = mpsc::channel(100);
-    let (mut outgoing, incoming) = ws.split();
+fn send_role(con: &Connection, primary: bool) {
+    send_on(con, &Packet::AgentRole(AgentRole { primary }));
+}
 
-    if CONNECTION.lock().unwrap().is_some() {
-        info!("Websocket connection closed because external routing agent is already connected");
+/// Encodes a packet as text message for JSON or as binary message for CBOR.
+fn encode_message(p: &Packet, encoding: Encoding) -> Option<Message> {
+    match encoding {
+        Encoding::Json => serde_json::to_string(p).map(Message::Text).ok(),
+        Encoding::Cbor => serde_cbor::to_vec(p).map(Message::Binary).ok(),
+    }
+}
 
-        if let Ok(data) = serde_json::to_string(&Packet::Error(Error {
-            reason: String::from("external routing agent already registered"),
-        })) {
-            if let Err(err) = outgoing.send(Message::Text(data)).await {
-                error!("Error while sending closing reason: {}", err);
-            }
-        }
+/// Handles the websocket connection of a router, `encoding` is the encoding requested on connect.
+pub async fn handle_connection(ws: WebSocket, encoding: Encoding) {
+    let (tx, mut rx) = mpsc::channel(100);
+    let (mut outgoing, incoming) = ws.split();
 
-        if let Err(err) = outgoing.close().await {
-            error!("Error while closing websocket: {}", err);
-        }
-        return;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut connections = CONNECTIONS.lock().unwrap();
+        let con = Connection {
+            id,
+            tx,
+            encoding,
+            timeouts: 0,
+            timers: HashMap::new(),
+        };
+        let primary = connections.is_empty();
+        info!(
+            "External routing agent {} connected as {}",
+            id,
+            if primary { "primary" } else { "standby" }
+        );
+        send_role(&con, primary);
+        connections.push(con);
     }
 
-    *CONNECTION.lock().unwrap() = Some(Connection { tx });
-
     // Send initial states to the router
-    send_peer_state();
-    send_service_state();
+    send_peer_state(id);
+    send_service_state(id);
 
     let broadcast_incoming = incoming.try_for_each(|msg| {
-        trace!(
-            "Received a external routing message: {}",
-            msg.to_text().unwrap().trim()
-        );
-
-        let packet: serde_json::Result<Packet> = serde_json::from_str(msg.to_text().unwrap());
+        // Text messages carry JSON, binary messages CBOR
+        let packet: anyhow::Result<Packet> = match &msg {
+            Message::Text(text) => {
+                trace!("Received a external routing message: {}", text.trim());
+                Encoding::Json.decode(text.as_bytes())
+            }
+            Message::Binary(data) => {
+                trace!(
+                    "Received a binary external routing message: {} bytes",
+                    data.len()
+                );
+                Encoding::Cbor.decode(data)
+            }
+            _ => return future::ok(()),
+        };
 
         match packet {
             Ok(packet) => match packet {
                 // When a ResponseSenderForBundle is received we check if a response channel for that
                 // bundle id exists and send the response on that channel.
                 Packet::ResponseSenderForBundle(packet) => {
-                    trace!(
-                        "sender_for_bundle response: {}",
-                        msg.to_text().unwrap().trim()
-                    );
+                    trace!("sender_for_bundle response: {}", packet.bp);
 
-                    if let Some(tx) = RESPONSES
-                        .lock()
-                        .unwrap()
-                        .remove(packet.bp.to_string().as_str())
-                    {
+                    if let Some(tx) = take_response_channel(id, &packet.bp.id) {
                         if tx.send(Packet::ResponseSenderForBundle(packet)).is_err() {
                             error!("sender_for_bundle response could not be passed to channel")
                         }
-                    } else {
-                        info!("sender_for_bundle no response channel available")
                     }
                 }
                 // Add a service on packet
@@ -113,10 +195,10 @@ pub async fn handle_connection(ws: WebSocket) {
 
                     service_add(packet.tag, packet.service);
                 }
-                _ => {}
+                packet => handle_command(id, packet),
             },
             Err(err) => {
-                info!("err decoding external routing packet: {}", err);
+                warn!("err decoding external routing packet: {}", err);
             }
         }
 
@@ -133,37 +215,294 @@ pub async fn handle_connection(ws: WebSocket) {
 
     future::select(broadcast_incoming, receive_from_others).await;
 
-    info!("External routing disconnected");
-    disconnect();
+    info!("External routing agent {} disconnected", id);
+    disconnect(id);
+}
+
+fn send_error(id: u64, reason: String) {
+    warn!("external routing agent {}: {}", id, reason);
+    send_packet_to(id, &Packet::Error(Error { reason }));
 }
 
-fn disconnect() {
-    (*CONNECTION.lock().unwrap()) = None;
+fn is_primary(id: u64) -> bool {
+    CONNECTIONS.lock().unwrap().first().map(|con| con.id) == Some(id)
 }
 
-/// Sends a JSON encoded packet to the connected router.
-fn send_packet(p: &Packet) {
-    if let Ok(data) = serde_json::to_string(p) {
-        if let Some(con) = CONNECTION.lock().unwrap().as_ref() {
-            if let Err(err) = con.tx.try_send(Message::Text(data)) {
-                error!("couldn't send packet {}", err)
+/// Handles the store and timer commands of the router with the given connection id.
+/// Only the primary may forward or delete bundles.
+fn handle_command(id: u64, packet: Packet) {
+    match packet {
+        Packet::QueryBundles(query) => {
+            let bundles = query_bundles(&query.filter);
+            trace!(
+                "query {} of router {}: {} bundles",
+                query.id,
+                id,
+                bundles.len()
+            );
+            send_packet_to(
+                id,
+                &Packet::BundleList(BundleList {
+                    id: query.id,
+                    bundles,
+                }),
+            );
+        }
+        Packet::ForwardBundle(cmd) => {
+            if !is_primary(id) {
+                return send_error(id, format!("standby can not forward {}", cmd.bid));
+            }
+            match store_get_metadata(&cmd.bid) {
+                Some(bp) if !bp.has_constraint(Constraint::Deleted) => {
+                    info!("forwarding {} on request of external routing", cmd.bid);
+                    // forward asks the router for senders, so it must not block this connection
+                    tokio::spawn(async move {
+                        if let Err(err) = forward(bp).await {
+                            send_error(id, format!("forwarding {} failed: {}", cmd.bid, err));
+                        }
+                    });
+                }
+                _ => send_error(id, format!("bundle {} not found", cmd.bid)),
             }
         }
+        Packet::DeleteBundle(cmd) => {
+            if !is_primary(id) {
+                return send_error(id, format!("standby can not delete {}", cmd.bid));
+            }
+            match store_get_metadata(&cmd.bid) {
+                Some(bp) if !bp.has_constraint(Constraint::Deleted) => {
+                    info!("deleting {} on request of external routing", cmd.bid);
+                    tokio::spawn(async move {
+                        if let Err(err) = delete(bp, cmd.reason).await {
+                            send_error(id, format!("deleting {} failed: {}", cmd.bid, err));
+                        }
+                    });
+                }
+                _ => send_error(id, format!("bundle {} not found", cmd.bid)),
+            }
+        }
+        Packet::ScheduleTimer(timer) => schedule_timer(id, timer),
+        Packet::CancelTimer(timer) => {
+            if let Some(con) = CONNECTIONS
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .find(|con| con.id == id)
+            {
+                if let Some((_, handle)) = con.timers.remove(&timer.id) {
+                    handle.abort();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collects the metadata of the stored bundles matching the filter, ordered by creation time.
+///
+/// The store is only locked to look up the candidates and for each single bundle. At most
+/// `EROUTING_MAX_QUERY_BUNDLES` are returned.
+fn query_bundles(filter: &BundleFilter) -> Vec<BundlePack> {
+    let candidates = {
+        let store = STORE.lock();
+        // narrow down the candidates with the store's indexed queries where possible
+        if !filter.bids.is_empty() {
+            filter.bids.clone()
+        } else if let Some(constraint) = filter.constraint {
+            store.query_constraint(constraint)
+        } else if filter.include_deleted {
+            store.metadata_ids()
+        } else if let Some(dst) = &filter.destination {
+            store.query_destination(dst)
+        } else if let Some(time) = filter.expiring_before {
+            store.query_expiring_before(time)
+        } else {
+            store.metadata_ids()
+        }
+    };
+    let mut bundles: Vec<BundlePack> = candidates
+        .iter()
+        .filter_map(|bid| STORE.lock().get_metadata(bid))
+        .filter(|bp| filter.matches(bp))
+        .collect();
+    bundles.sort_unstable_by(|a, b| (a.creation_time, &a.id).cmp(&(b.creation_time, &b.id)));
+    let limit = filter
+        .limit
+        .unwrap_or(EROUTING_MAX_QUERY_BUNDLES)
+        .min(EROUTING_MAX_QUERY_BUNDLES);
+    bundles
+        .into_iter()
+        .skip(filter.offset)
+        .take(limit)
+        .collect()
+}
+
+/// Starts a timer that sends TimerExpired packets to the router, replacing one with the same id.
+/// Timers are cancelled when the router disconnects.
+fn schedule_timer(id: u64, timer: ScheduleTimer) {
+    let generation = NEXT_TIMER.fetch_add(1, Ordering::Relaxed);
+    let delay = time::Duration::from_millis(timer.delay_ms.max(EROUTING_MIN_TIMER_DELAY_MS));
+    let name = timer.id.clone();
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let Some(con) = connections.iter_mut().find(|con| con.id == id) else {
+        return;
+    };
+    if con.timers.len() >= EROUTING_MAX_TIMERS && !con.timers.contains_key(&name) {
+        drop(connections);
+        return send_error(
+            id,
+            format!(
+                "timer {} rejected, at most {} timers can be pending",
+                name, EROUTING_MAX_TIMERS
+            ),
+        );
+    }
+
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(delay).await;
+            send_packet_to(
+                id,
+                &Packet::TimerExpired(TimerExpired {
+                    id: timer.id.clone(),
+                }),
+            );
+            if !timer.repeat {
+                break;
+            }
+        }
+        if let Some(con) = CONNECTIONS
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|con| con.id == id)
+        {
+            if con.timers.get(&timer.id).map(|(gen, _)| *gen) == Some(generation) {
+                con.timers.remove(&timer.id);
+            }
+        }
+    });
+    if let Some((_, old)) = con.timers.insert(name, (generation, task.abort_handle())) {
+        old.abort();
+    }
+}
+
+/// Removes a router, if it was the primary the first standby takes over.
+fn disconnect(id: u64) {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let was_primary = connections.first().map(|con| con.id) == Some(id);
+    connections.retain(|con| con.id != id);
+
+    if was_primary {
+        if let Some(con) = connections.first_mut() {
+            info!("Failover to standby external routing agent {}", con.id);
+            con.timeouts = 0;
+            send_role(con, true);
+        } else {
+            info!("No external routing agent left");
+        }
+    }
+}
+
+/// Sends a packet in the requested encoding on a connection.
+fn send_on(con: &Connection, p: &Packet) {
+    if let Some(msg) = encode_message(p, con.encoding) {
+        if let Err(err) = con.tx.try_send(msg) {
+            error!("couldn't send packet {}", err)
+        }
+    }
+}
+
+/// Sends a packet to the router with the given id.
+fn send_packet_to(id: u64, p: &Packet) {
+    if let Some(con) = CONNECTIONS.lock().unwrap().iter().find(|con| con.id == id) {
+        send_on(con, p);
+    }
+}
+
+/// Sends a packet to all connected routers, so standbys follow the same state as the primary.
+fn broadcast(p: &Packet) {
+    for con in CONNECTIONS.lock().unwrap().iter() {
+        send_on(con, p);
+    }
+}
+
+/// Counts a missed response of the primary. After too many consecutive timeouts it is moved
+/// behind the standbys, so a hanging router does not block forwarding.
+fn primary_timed_out(id: u64) {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let Some(con) = connections.first_mut().filter(|con| con.id == id) else {
+        return;
+    };
+    con.timeouts += 1;
+    if con.timeouts < FAILOVER_TIMEOUTS.load(Ordering::Relaxed) || connections.len() < 2 {
+        return;
+    }
+
+    let mut con = connections.remove(0);
+    con.timeouts = 0;
+    send_role(&con, false);
+    connections.push(con);
+    info!(
+        "External routing agent {} timed out repeatedly, failover to standby {}",
+        id, connections[0].id
+    );
+    send_role(&connections[0], true);
+}
+
+fn primary_responded(id: u64) {
+    if let Some(con) = CONNECTIONS
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|con| con.id == id)
+    {
+        con.timeouts = 0;
     }
 }
 
 /// Takes the RoutingNotification's, encodes them to serializable structs and then sends them
-/// to the external router if one is connected.
+/// to all connected external routers.
 pub fn notify(notification: RoutingNotifcation) {
-    send_packet(&notification.into());
+    if CONNECTIONS.lock().unwrap().is_empty() {
+        return;
+    }
+    broadcast(&notification.into());
 }
 
 fn remove_response_channel(id: &str) {
     RESPONSES.lock().unwrap().remove(id);
 }
 
-fn create_response_channel(id: &str, tx: oneshot::Sender<Packet>) {
-    RESPONSES.lock().unwrap().insert(id.to_string(), tx);
+fn create_response_channel(id: &str, agent: u64, tx: oneshot::Sender<Packet>) {
+    RESPONSES
+        .lock()
+        .unwrap()
+        .insert(id.to_string(), (agent, tx));
+}
+
+/// Takes the response channel for a bundle if the request was sent to the answering router,
+/// responses of other routers are rejected.
+fn take_response_channel(agent: u64, id: &str) -> Option<oneshot::Sender<Packet>> {
+    let mut responses = RESPONSES.lock().unwrap();
+    match responses.get(id) {
+        Some((requested, _)) if *requested == agent => responses.remove(id).map(|(_, tx)| tx),
+        Some(_) => {
+            drop(responses);
+            send_error(
+                agent,
+                format!(
+                    "sender_for_bundle {} was not requested from this router",
+                    id
+                ),
+            );
+            None
+        }
+        None => {
+            info!("sender_for_bundle no response channel available");
+            None
+        }
+    }
 }
 
 // Builds a list of ClaSenderTask from the information contained in the ResponseSenderForBundle packet.
@@ -197,25 +536,24 @@ fn unpack_sender_for_bundle(packet: ResponseSenderForBundle) -> (Vec<ClaSenderTa
     )
 }
 
-/// Tries to send a routing requests to the external router and waits for the response.
-/// The wait will be limited to a timeout of 250ms.
-pub async fn sender_for_bundle(bp: &BundlePack) -> (Vec<ClaSenderTask>, bool) {
+/// Tries to send a routing requests to the primary external router and waits for the response.
+/// The wait will be limited to a timeout of 250ms. Returns `None` if no router is connected
+/// or it did not answer in time.
+pub async fn sender_for_bundle(bp: &BundlePack) -> Option<(Vec<ClaSenderTask>, bool)> {
     trace!("external sender_for_bundle initiated: {}", bp);
 
-    if CONNECTION.lock().unwrap().is_none() {
-        return (vec![], false);
-    }
+    let primary = CONNECTIONS.lock().unwrap().first().map(|con| con.id)?;
 
     // Register a response channel for the request
     let (tx, rx) = oneshot::channel();
-    create_response_channel(bp.to_string().as_str(), tx);
+    create_response_channel(&bp.id, primary, tx);
 
     // Send out the SenderForBundle packet
     let packet: Packet = Packet::RequestSenderForBundle(RequestSenderForBundle {
         clas: cla_names(),
         bp: bp.clone(),
     });
-    send_packet(&packet);
+    send_packet_to(primary, &packet);
 
     let res = timeout(
         time::Duration::from_millis(EROUTING_RESPONSE_TIMEOUT_MS),
@@ -223,23 +561,25 @@ pub async fn sender_for_bundle(bp: &BundlePack) -> (Vec<ClaSenderTask>, bool) {
     )
     .await;
     if let Ok(Ok(Packet::ResponseSenderForBundle(packet))) = res {
-        remove_response_channel(bp.to_string().as_str());
+        remove_response_channel(&bp.id);
+        primary_responded(primary);
 
-        if packet.bp.to_string() != bp.to_string() {
+        if packet.bp.id != bp.id {
             error!("got a wrong bundle pack! {} != {}", bp, packet.bp);
-            return (vec![], false);
+            return Some((vec![], false));
         }
 
-        return unpack_sender_for_bundle(packet);
+        return Some(unpack_sender_for_bundle(packet));
     }
 
     // Signal to the external router that the timeout was reached and no ResponseSenderForBundle was processed.
     // This is needed in case that the response arrived later than the timeout and the connected router thinks
     // it successfully sends its response. Otherwise, there is no way for the router to know if its response has
     // failed.
-    send_packet(&Packet::Timeout(super::Timeout { bp: bp.clone() }));
+    send_packet_to(primary, &Packet::Timeout(super::Timeout { bp: bp.clone() }));
 
     info!("timeout while waiting for sender_for_bundle");
-    remove_response_channel(bp.to_string().as_str());
-    (vec![], false)
+    remove_response_channel(&bp.id);
+    primary_timed_out(primary);
+    None
 }
@@ -1,7 +1,7 @@
 use super::Packet;
+use crate::core::encoding::Encoding;
 use futures_util::{future, pin_mut, SinkExt, StreamExt};
 use log::{error, info};
-use serde_json::Result;
 use std::str::FromStr;
 use tokio::sync::mpsc;
 use tokio_tungstenite::connect_async;
@@ -18,6 +18,7 @@ pub enum Command {
 pub struct Client {
     ip: String,
     port: u16,
+    encoding: Encoding,
     cmd_receiver: mpsc::Receiver<Command>,
     cmd_sender: mpsc::Sender<Command>,
     packet_out: mpsc::Sender<Packet>,
@@ -45,6 +46,7 @@ pub fn new(addr: &str, packet_out: mpsc::Sender<Packet>) -> std::io::Result<Clie
     Ok(Client {
         ip: parts[0].to_string(),
         port: u16::from_str(parts[1]).expect("could not parse port"),
+        encoding: Encoding::default(),
         cmd_receiver,
         cmd_sender,
         packet_out,
@@ -54,8 +56,12 @@ pub fn new(addr: &str, packet_out: mpsc::Sender<Packet>) -> std::io::Result<Clie
 impl Client {
     /// Connects and starts to handle packets. Will block until a severe error is encountered or the client is closed.
     pub async fn serve(&mut self) -> anyhow::Result<()> {
-        let (ws_stream, _) =
-            connect_async(format!("ws://{}:{}/ws/erouting", self.ip, self.port)).await?;
+        let encoding = self.encoding;
+        let (ws_stream, _) = connect_async(format!(
+            "ws://{}:{}/ws/erouting?encoding={}",
+            self.ip, self.port, encoding
+        ))
+        .await?;
 
         info!("WebSocket handshake has been successfully completed");
 
@@ -67,8 +73,17 @@ impl Client {
             while let Some(command) = cmd_receiver.recv().await {
                 match command {
                     Command::SendPacket(packet) => {
-                        let data = serde_json::to_string(&packet);
-                        if write.send(Message::Text(data.unwrap())).await.is_err() {
+                        let msg = match encoding {
+                            Encoding::Json => {
+                                serde_json::to_string(&packet).map(Message::Text).ok()
+                            }
+                            Encoding::Cbor => serde_cbor::to_vec(&packet).map(Message::Binary).ok(),
+                        };
+                        let sent = match msg {
+                            Some(msg) => write.send(msg).await.is_ok(),
+                            None => false,
+                        };
+                        if !sent {
                             error!("Error while sending packet");
                         }
                     }
@@ -87,9 +102,11 @@ impl Client {
                     return;
                 }
 
-                let data = message.unwrap().into_text();
-
-                let packet: Result<Packet> = serde_json::from_str(data.unwrap().as_str());
+                let packet: anyhow::Result<Packet> = match message.unwrap() {
+                    Message::Text(text) => Encoding::Json.decode(text.as_bytes()),
+                    Message::Binary(data) => Encoding::Cbor.decode(&data),
+                    _ => return,
+                };
                 if let Ok(packet) = packet {
                     if let Err(err) = self.packet_out.send(packet).await {
                         error!("Error while sending packet to channel: {}", err);
@@ -106,6 +123,11 @@ impl Client {
         Ok(())
     }
 
+    /// Sets the encoding of the packets, CBOR carries bundles as raw byte strings.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
     pub fn command_channel(&self) -> mpsc::Sender<Command> {
         self.cmd_sender.clone()
     }
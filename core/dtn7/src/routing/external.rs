@@ -1,10 +1,20 @@
-use super::erouting::processing::{notify, sender_for_bundle};
+use super::erouting::processing::{
+    agents, notify, sender_for_bundle, set_failover_timeouts, EROUTING_FAILOVER_TIMEOUTS,
+};
 use super::RoutingAgent;
 use crate::routing::RoutingCmd;
+use crate::CONFIG;
 use async_trait::async_trait;
-use tokio::sync::mpsc;
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, HashMap};
 use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc, oneshot};
 
+/// Routing through external routing agents connected via the erouting websocket.
+///
+/// The first connected agent is the primary, later ones are standbys that take over if the primary
+/// disconnects or repeatedly fails to answer. While no agent answers, the built-in agent configured
+/// with `external.fallback` routes the bundles.
 #[derive(Debug)]
 pub struct ExternalRoutingAgent {
     tx: mpsc::Sender<super::RoutingCmd>,
@@ -16,25 +26,129 @@ impl Default for ExternalRoutingAgent {
     }
 }
 
+/// Checks that `external.fallback` names a built-in routing agent.
+///
+/// An invalid fallback is replaced by `none`, so external routing starts without a fallback
+/// instead of failing.
+pub fn validate_settings(routing_settings: &mut BTreeMap<String, HashMap<String, String>>) {
+    let Some(fallback) = routing_settings
+        .get_mut("external")
+        .and_then(|settings| settings.get_mut("fallback"))
+    else {
+        return;
+    };
+    if fallback == "none" {
+        return;
+    }
+    if fallback == "external" {
+        warn!("external routing can not be its own fallback, not using a fallback");
+    } else if !super::routing_algorithms().contains(&fallback.as_str()) {
+        warn!(
+            "unknown external.fallback routing agent {}, not using a fallback",
+            fallback
+        );
+    } else {
+        return;
+    }
+    *fallback = "none".into();
+}
+
+/// Creates the fallback agent from the `external.fallback` routing setting.
+fn fallback_agent() -> Option<(String, Sender<RoutingCmd>)> {
+    let settings = CONFIG.lock().routing_settings.get("external").cloned()?;
+
+    if let Some(timeouts) = settings.get("failover_timeouts") {
+        match timeouts.parse::<u32>() {
+            Ok(timeouts) => set_failover_timeouts(timeouts),
+            Err(_) => warn!(
+                "invalid external.failover_timeouts {}, using {}",
+                timeouts, EROUTING_FAILOVER_TIMEOUTS
+            ),
+        }
+    }
+
+    match settings.get("fallback").map(|f| f.as_str()) {
+        None | Some("none") => None,
+        Some(fallback)
+            if fallback == "external" || !super::routing_algorithms().contains(&fallback) =>
+        {
+            warn!(
+                "invalid external.fallback {}, not using a fallback",
+                fallback
+            );
+            None
+        }
+        Some(fallback) => {
+            info!("external routing falls back to {}", fallback);
+            Some((fallback.to_string(), super::new(fallback).channel()))
+        }
+    }
+}
+
+async fn fallback_sender_for_bundle(
+    fallback: Option<Sender<RoutingCmd>>,
+    bp: crate::BundlePack,
+) -> (Vec<crate::ClaSenderTask>, bool) {
+    let Some(fallback) = fallback else {
+        return (vec![], false);
+    };
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if fallback
+        .send(RoutingCmd::SenderForBundle(bp, reply_tx))
+        .await
+        .is_err()
+    {
+        return (vec![], false);
+    }
+    reply_rx.await.unwrap_or_default()
+}
+
 impl ExternalRoutingAgent {
     pub fn new() -> Self {
         let (tx, mut rx) = mpsc::channel(100);
+        let (fallback_name, fallback) = fallback_agent().unzip();
         tokio::spawn(async move {
             while let Some(cmd) = rx.recv().await {
                 match cmd {
                     super::RoutingCmd::SenderForBundle(bp, reply) => {
+                        let fallback = fallback.clone();
                         tokio::spawn(async move {
-                            reply.send(sender_for_bundle(&bp).await).unwrap();
+                            let bid = bp.id().to_string();
+                            let senders = match sender_for_bundle(&bp).await {
+                                Some(senders) => senders,
+                                None => fallback_sender_for_bundle(fallback, bp).await,
+                            };
+                            if reply.send(senders).is_err() {
+                                debug!("requester of senders for {} is gone", bid);
+                            }
                         });
                     }
                     super::RoutingCmd::Shutdown => {
+                        if let Some(fallback) = &fallback {
+                            fallback.send(RoutingCmd::Shutdown).await.ok();
+                        }
                         break;
                     }
-                    super::RoutingCmd::Command(_cmd) => {}
+                    super::RoutingCmd::Command(cmd) => {
+                        if let Some(fallback) = &fallback {
+                            fallback.send(RoutingCmd::Command(cmd)).await.ok();
+                        }
+                    }
                     super::RoutingCmd::GetData(_, tx) => {
-                        tx.send("unimplemented!".to_string()).unwrap();
+                        let data = serde_json::json!({
+                            "agents": agents(),
+                            "fallback": fallback_name,
+                        });
+                        tx.send(data.to_string()).ok();
                     }
                     super::RoutingCmd::Notify(notification) => {
+                        // the fallback keeps its own state up to date to take over at any time
+                        if let Some(fallback) = &fallback {
+                            fallback
+                                .send(RoutingCmd::Notify(notification.clone()))
+                                .await
+                                .ok();
+                        }
                         notify(notification);
                     }
                 }
@@ -24,7 +24,7 @@ use std::fmt::Display;
 use log::debug;
 use tokio::sync::{mpsc, oneshot};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RoutingNotifcation {
     SendingFailed(BundleID, String),
     SendingSucceeded(BundleID, String),
@@ -85,7 +85,12 @@ pub fn routing_algorithms() -> Vec<&'static str> {
 }
 
 pub fn routing_options() -> Vec<&'static str> {
-    vec!["sprayandwait.num_copies=<int>", "static.routes=<file>"]
+    vec![
+        "sprayandwait.num_copies=<int>",
+        "static.routes=<file>",
+        "external.fallback=<algorithm>",
+        "external.failover_timeouts=<int>",
+    ]
 }
 
 pub fn new(routingagent: &str) -> RoutingAgentsEnum {
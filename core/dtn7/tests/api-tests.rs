@@ -0,0 +1,134 @@
+use bp7::EndpointID;
+use dtn7::core::bundlepack::{BundlePack, Constraint};
+use dtn7::dtnd::api::{self, BundleQuery, Location};
+use dtn7::dtnd::dashboard;
+use std::sync::Arc;
+
+/// Pages bundles in the order of the store, by creation time and ID.
+fn page(
+    bundles: &[BundlePack],
+    query: &BundleQuery,
+) -> Result<api::Page<api::BundleSummary>, api::ApiError> {
+    let mut sorted = bundles.to_vec();
+    sorted.sort_unstable_by(|a, b| (a.creation_time, &a.id).cmp(&(b.creation_time, &b.id)));
+    api::bundle_page(&sorted, query)
+}
+
+fn pack(src: &str, dst: &str, received_time: u64) -> BundlePack {
+    let bndl = bp7::bundle::new_std_payload_bundle(
+        EndpointID::try_from(src).unwrap(),
+        EndpointID::try_from(dst).unwrap(),
+        b"data".to_vec(),
+    );
+    let mut bp = BundlePack::from(bndl);
+    bp.received_time = received_time;
+    bp
+}
+
+#[test]
+fn bundles_are_filtered_and_paged() {
+    let mut bundles: Vec<BundlePack> = (0..10)
+        .map(|i| {
+            pack(
+                "dtn://node1/",
+                &format!("dtn://node{}/in", i % 2 + 2),
+                100 - i,
+            )
+        })
+        .collect();
+    bundles[0].add_constraint(Constraint::Deleted);
+    bundles[1].add_constraint(Constraint::ForwardPending);
+
+    let all = page(&bundles, &BundleQuery::default()).unwrap();
+    assert_eq!(all.total, 9);
+    // oldest first
+    assert!(all
+        .items
+        .windows(2)
+        .all(|w| w[0].creation_time <= w[1].creation_time));
+
+    let query = BundleQuery {
+        dst: Some("dtn://node2/".into()),
+        offset: Some(1),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let paged = page(&bundles, &query).unwrap();
+    assert_eq!((paged.total, paged.items.len()), (4, 2));
+    assert!(paged
+        .items
+        .iter()
+        .all(|b| b.destination == "dtn://node2/in"));
+
+    let query = BundleQuery {
+        constraint: Some("ForwardPending".into()),
+        ..Default::default()
+    };
+    assert_eq!(page(&bundles, &query).unwrap().total, 1);
+
+    let query = BundleQuery {
+        deleted: true,
+        ..Default::default()
+    };
+    assert_eq!(page(&bundles, &query).unwrap().total, 10);
+
+    let query = BundleQuery {
+        limit: Some(api::PAGE_LIMIT_MAX + 1),
+        ..Default::default()
+    };
+    let err = page(&bundles, &query).unwrap_err();
+    assert_eq!(err.status, 400);
+
+    // pages far into the store
+    let many: Vec<BundlePack> = (0..2500)
+        .map(|i| pack("dtn://node1/", "dtn://node2/in", i))
+        .collect();
+    let query = BundleQuery {
+        offset: Some(2495),
+        limit: Some(10),
+        ..Default::default()
+    };
+    let paged = page(&many, &query).unwrap();
+    assert_eq!((paged.total, paged.items.len()), (2500, 5));
+}
+
+#[test]
+fn openapi_document_is_valid_json() {
+    let doc: serde_json::Value = serde_json::from_str(api::OPENAPI).unwrap();
+    assert_eq!(doc["servers"][0]["url"], api::API_PREFIX);
+    for path in [
+        "/bundles",
+        "/bundles/{bid}",
+        "/endpoints",
+        "/peers",
+        "/routing",
+        "/stats",
+    ] {
+        assert!(
+            doc["paths"].get(path).is_some(),
+            "{} is not described",
+            path
+        );
+    }
+}
+
+#[test]
+fn locations_are_parsed_from_geolocation_service() {
+    assert_eq!(
+        Location::from_service("52.32 24.42"),
+        Some(Location {
+            latitude: 52.32,
+            longitude: 24.42
+        })
+    );
+    assert_eq!(Location::from_service("52.32"), None);
+    assert_eq!(Location::from_service("north east"), None);
+}
+
+#[tokio::test]
+async fn dashboard_snapshots_are_shared() {
+    let first = dashboard::latest();
+    assert!(Arc::ptr_eq(&first, &dashboard::latest()));
+    tokio::time::sleep(dashboard::DASHBOARD_INTERVAL / 2).await;
+    assert!(!Arc::ptr_eq(&first, &dashboard::latest()));
+}
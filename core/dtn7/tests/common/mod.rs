@@ -0,0 +1,16 @@
+use bp7::helpers::rnd_bundle;
+use bp7::{Bundle, CreationTimestamp, EndpointID};
+use dtn7::core::bundlepack::BundlePack;
+use dtn7::core::processing;
+use dtn7::store_push_bundle;
+
+/// Receives a random bundle for `dst` created at `time` and passes it through local delivery.
+pub async fn deliver(dst: &EndpointID, time: u64) -> Bundle {
+    let mut bndl = rnd_bundle(CreationTimestamp::with_time_and_seq(time, 0));
+    bndl.primary.destination = dst.clone();
+    store_push_bundle(&bndl).unwrap();
+    processing::local_delivery(BundlePack::from(&bndl))
+        .await
+        .unwrap();
+    bndl
+}
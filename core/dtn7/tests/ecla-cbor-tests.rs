@@ -0,0 +1,87 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use bytes::Bytes;
+use dtn7::cla::ecla::processing::{scheduled_submission, start_ecla};
+use dtn7::cla::ecla::{Capabilities, ForwardData, Packet, Register, ECLA_PROTOCOL_VERSION};
+use dtn7::cla::TransferResult;
+use dtn7::core::encoding::Encoding;
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn cbor_forward_data_uses_byte_strings() {
+    let data: Vec<u8> = (0..=255).collect();
+    let packet = Packet::ForwardData(ForwardData {
+        src: "a".into(),
+        dst: "b".into(),
+        bundle_id: "dtn://node1/-1-0".into(),
+        data: data.clone(),
+        transfer_id: Some(3),
+    });
+
+    let cbor = Encoding::Cbor.encode(&packet).unwrap();
+    let json = Encoding::Json.encode(&packet).unwrap();
+    assert!(contains(&cbor, &data));
+    assert!(cbor.len() < json.len());
+    assert_eq!(Encoding::detect(&cbor), Encoding::Cbor);
+    assert_eq!(Encoding::detect(&json), Encoding::Json);
+
+    for (encoding, buf) in [(Encoding::Cbor, cbor), (Encoding::Json, json)] {
+        let Packet::ForwardData(fwd) = encoding.decode(&buf).unwrap() else {
+            panic!("wrong packet type");
+        };
+        assert_eq!(fwd.data, data);
+        assert_eq!(fwd.transfer_id, Some(3));
+    }
+}
+
+#[tokio::test]
+async fn ecla_tcp_cbor_session() {
+    start_ecla(26264).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let stream = TcpStream::connect("127.0.0.1:26264").await.unwrap();
+    let mut conn = Framed::new(stream, LengthDelimitedCodec::new());
+
+    let register = Packet::Register(Register {
+        name: "cbor".into(),
+        enable_beacon: false,
+        port: None,
+        version: ECLA_PROTOCOL_VERSION,
+        capabilities: Capabilities::default(),
+    });
+    let frame = Encoding::Cbor.encode(&register).unwrap();
+    conn.send(Bytes::from(frame)).await.unwrap();
+
+    // dtnd answers in the encoding of the first frame
+    let frame = conn.next().await.unwrap().unwrap();
+    assert_eq!(Encoding::detect(&frame), Encoding::Cbor);
+    let Packet::Registered(reg) = Encoding::Cbor.decode(&frame).unwrap() else {
+        panic!("not registered");
+    };
+    assert_eq!(reg.version, ECLA_PROTOCOL_VERSION);
+
+    let mut bndl = rnd_bundle(CreationTimestamp::now());
+    let data = bndl.to_cbor();
+    let result = scheduled_submission("cbor".into(), "peer".into(), &data).await;
+    assert_eq!(result, TransferResult::Successful);
+
+    let frame = loop {
+        let frame = conn.next().await.unwrap().unwrap();
+        match Encoding::Cbor.decode(&frame).unwrap() {
+            Packet::Beacon(_) => continue,
+            _ => break frame,
+        }
+    };
+    assert!(contains(&frame, &data));
+    let Packet::ForwardData(fwd) = Encoding::Cbor.decode(&frame).unwrap() else {
+        panic!("no data");
+    };
+    assert_eq!(fwd.bundle_id, bndl.id());
+    assert_eq!(fwd.data, data);
+}
@@ -0,0 +1,118 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use bytes::Bytes;
+use dtn7::cla::ecla::processing::{scheduled_submission, start_ecla};
+use dtn7::cla::ecla::{Capabilities, Credit, Packet, Register, TransferAck, ECLA_PROTOCOL_VERSION};
+use dtn7::cla::TransferResult;
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+type Conn = Framed<TcpStream, LengthDelimitedCodec>;
+
+async fn send(conn: &mut Conn, packet: &Packet) {
+    let data = serde_json::to_vec(packet).unwrap();
+    conn.send(Bytes::from(data)).await.unwrap();
+}
+
+async fn recv(conn: &mut Conn) -> Packet {
+    loop {
+        let frame = conn.next().await.unwrap().unwrap();
+        match serde_json::from_slice(&frame).unwrap() {
+            // beacons are sent periodically
+            Packet::Beacon(_) => continue,
+            packet => return packet,
+        }
+    }
+}
+
+fn submit(name: &str, data: Vec<u8>) -> tokio::task::JoinHandle<TransferResult> {
+    let name = name.to_string();
+    tokio::spawn(async move { scheduled_submission(name, "peer".into(), &data).await })
+}
+
+#[tokio::test]
+async fn ecla_transfer_ack_and_credits() {
+    start_ecla(26263).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let stream = TcpStream::connect("127.0.0.1:26263").await.unwrap();
+    let mut conn = Framed::new(stream, LengthDelimitedCodec::new());
+
+    send(
+        &mut conn,
+        &Packet::Register(Register {
+            name: "lora".into(),
+            enable_beacon: false,
+            port: None,
+            version: ECLA_PROTOCOL_VERSION,
+            capabilities: Capabilities {
+                transfer_ack: true,
+                credits: Some(1),
+                max_bundle_size: Some(1024),
+            },
+        }),
+    )
+    .await;
+    let Packet::Registered(reg) = recv(&mut conn).await else {
+        panic!("not registered");
+    };
+    assert_eq!(reg.version, ECLA_PROTOCOL_VERSION);
+    assert_eq!(reg.capabilities.credits, Some(1));
+
+    let mut bndl = rnd_bundle(CreationTimestamp::now());
+    let data = bndl.to_cbor();
+
+    // too large bundles are refused right away
+    let result = submit("lora", vec![0; 2048]).await.unwrap();
+    assert_eq!(result, TransferResult::Failure);
+
+    // the transfer is only successful once acknowledged
+    let first = submit("lora", data.clone());
+    let Packet::ForwardData(fwd) = recv(&mut conn).await else {
+        panic!("no data");
+    };
+    assert_eq!(fwd.bundle_id, bndl.id());
+    let first_id = fwd.transfer_id.unwrap();
+
+    // without credits the second transfer has to wait
+    let second = submit("lora", data.clone());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!second.is_finished());
+
+    send(
+        &mut conn,
+        &Packet::TransferAck(TransferAck {
+            transfer_id: first_id,
+            success: true,
+            reason: None,
+        }),
+    )
+    .await;
+    assert_eq!(first.await.unwrap(), TransferResult::Successful);
+
+    send(&mut conn, &Packet::Credit(Credit { credits: 1 })).await;
+    let Packet::ForwardData(fwd) = recv(&mut conn).await else {
+        panic!("no data");
+    };
+    assert_ne!(fwd.transfer_id, Some(first_id));
+    send(
+        &mut conn,
+        &Packet::TransferAck(TransferAck {
+            transfer_id: fwd.transfer_id.unwrap(),
+            success: false,
+            reason: Some("channel busy".into()),
+        }),
+    )
+    .await;
+    assert_eq!(second.await.unwrap(), TransferResult::Failure);
+
+    // pending transfers fail when the module disconnects
+    send(&mut conn, &Packet::Credit(Credit { credits: 1 })).await;
+    let third = submit("lora", data);
+    let Packet::ForwardData(_) = recv(&mut conn).await else {
+        panic!("no data");
+    };
+    drop(conn);
+    assert_eq!(third.await.unwrap(), TransferResult::Failure);
+}
@@ -0,0 +1,156 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::core::bundlepack::{BundlePack, Constraint};
+use dtn7::routing::erouting::ws_client::{self, Command};
+use dtn7::routing::erouting::{
+    BundleFilter, CancelTimer, DeleteBundle, ForwardBundle, Packet, QueryBundles,
+    ResponseSenderForBundle, ScheduleTimer,
+};
+use dtn7::routing::external::ExternalRoutingAgent;
+use dtn7::{store_push_bundle, store_update_metadata, CONFIG, DTNCORE};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+struct Router {
+    cmd: mpsc::Sender<Command>,
+    packets: mpsc::Receiver<Packet>,
+}
+
+impl Router {
+    async fn send(&self, packet: Packet) {
+        self.cmd
+            .send(Command::SendPacket(Box::new(packet)))
+            .await
+            .unwrap();
+    }
+
+    /// Waits for the next packet that is not a state update or notification.
+    async fn next(&mut self) -> Packet {
+        loop {
+            let packet = tokio::time::timeout(Duration::from_secs(2), self.packets.recv())
+                .await
+                .expect("no packet received")
+                .unwrap();
+            match packet {
+                Packet::PeerState(_) | Packet::ServiceState(_) | Packet::AgentRole(_) => continue,
+                packet => return packet,
+            }
+        }
+    }
+
+    async fn query(&mut self, id: u64, filter: BundleFilter) -> Vec<BundlePack> {
+        self.send(Packet::QueryBundles(QueryBundles { id, filter }))
+            .await;
+        match self.next().await {
+            Packet::BundleList(list) if list.id == id => list.bundles,
+            _ => panic!("expected a bundle list"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn erouting_store_commands_and_timers() {
+    {
+        let mut cfg = CONFIG.lock();
+        cfg.routing = "external".into();
+        cfg.webport = 33002;
+        cfg.v4 = true;
+        cfg.v6 = false;
+        cfg.generate_status_reports = false;
+    }
+    DTNCORE.lock().routing_agent = ExternalRoutingAgent::new().into();
+    tokio::spawn(dtn7::dtnd::httpd::spawn_httpd());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut bids = Vec::new();
+    for i in 0..3 {
+        let bndl = rnd_bundle(CreationTimestamp::with_time_and_seq(1_000_000 + i, 0));
+        store_push_bundle(&bndl).unwrap();
+        let mut bp = BundlePack::from(&bndl);
+        bp.add_constraint(if i == 0 {
+            Constraint::Contraindicated
+        } else {
+            Constraint::ForwardPending
+        });
+        store_update_metadata(&bp).unwrap();
+        bids.push(bndl.id());
+    }
+
+    let (tx, packets) = mpsc::channel(100);
+    let mut client = ws_client::new("127.0.0.1:33002", tx).unwrap();
+    let cmd = client.command_channel();
+    tokio::spawn(async move { client.serve().await });
+    let mut router = Router { cmd, packets };
+
+    let all = router.query(1, BundleFilter::default()).await;
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].id, bids[0]);
+
+    let filter = BundleFilter {
+        constraint: Some(Constraint::ForwardPending),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let pending = router.query(2, filter).await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, bids[1]);
+
+    // forwarding a bundle asks the router for its senders
+    router
+        .send(Packet::ForwardBundle(ForwardBundle {
+            bid: bids[0].clone(),
+        }))
+        .await;
+    let Packet::RequestSenderForBundle(req) = router.next().await else {
+        panic!("expected a request");
+    };
+    assert_eq!(req.bp.id, bids[0]);
+    router
+        .send(Packet::ResponseSenderForBundle(ResponseSenderForBundle {
+            bp: req.bp,
+            clas: vec![],
+            delete_afterwards: false,
+        }))
+        .await;
+
+    router
+        .send(Packet::DeleteBundle(DeleteBundle {
+            bid: bids[2].clone(),
+            reason: 0,
+        }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let remaining = router.query(3, BundleFilter::default()).await;
+    assert_eq!(remaining.len(), 2);
+    assert!(remaining.iter().all(|bp| bp.id != bids[2]));
+
+    router
+        .send(Packet::DeleteBundle(DeleteBundle {
+            bid: bids[2].clone(),
+            reason: 0,
+        }))
+        .await;
+    assert!(matches!(router.next().await, Packet::Error(_)));
+
+    // a repeating timer fires until it is cancelled
+    router
+        .send(Packet::ScheduleTimer(ScheduleTimer {
+            id: "tick".into(),
+            delay_ms: 50,
+            repeat: true,
+        }))
+        .await;
+    for _ in 0..2 {
+        let Packet::TimerExpired(timer) = router.next().await else {
+            panic!("expected a timer");
+        };
+        assert_eq!(timer.id, "tick");
+    }
+    router
+        .send(Packet::CancelTimer(CancelTimer { id: "tick".into() }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    while router.packets.try_recv().is_ok() {}
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(router.packets.try_recv().is_err());
+}
@@ -0,0 +1,153 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::core::bundlepack::BundlePack;
+use dtn7::core::encoding::Encoding;
+use dtn7::routing::erouting::processing::{agents, sender_for_bundle, set_failover_timeouts};
+use dtn7::routing::erouting::ws_client::{self, Command};
+use dtn7::routing::erouting::{Packet, ResponseSenderForBundle};
+use dtn7::routing::external::validate_settings;
+use dtn7::CONFIG;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+struct Router {
+    cmd: mpsc::Sender<Command>,
+    packets: mpsc::Receiver<Packet>,
+}
+
+impl Router {
+    async fn connect(encoding: Encoding) -> Router {
+        let (tx, packets) = mpsc::channel(100);
+        let mut client = ws_client::new("127.0.0.1:33001", tx).unwrap();
+        client.set_encoding(encoding);
+        let cmd = client.command_channel();
+        tokio::spawn(async move { client.serve().await });
+        Router { cmd, packets }
+    }
+
+    /// Waits for the next role or routing request, skipping state updates.
+    async fn next(&mut self) -> Packet {
+        loop {
+            let packet = tokio::time::timeout(Duration::from_secs(2), self.packets.recv())
+                .await
+                .expect("no packet received")
+                .unwrap();
+            if matches!(
+                packet,
+                Packet::AgentRole(_) | Packet::RequestSenderForBundle(_)
+            ) {
+                return packet;
+            }
+        }
+    }
+
+    async fn role(&mut self) -> bool {
+        match self.next().await {
+            Packet::AgentRole(role) => role.primary,
+            _ => panic!("expected a role"),
+        }
+    }
+
+    async fn close(self) {
+        self.cmd.send(Command::Close).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn erouting_primary_standby_failover() {
+    {
+        let mut cfg = CONFIG.lock();
+        cfg.routing = "external".into();
+        cfg.webport = 33001;
+        cfg.v4 = true;
+        cfg.v6 = false;
+    }
+    set_failover_timeouts(2);
+    tokio::spawn(dtn7::dtnd::httpd::spawn_httpd());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let bp = BundlePack::from(rnd_bundle(CreationTimestamp::now()));
+    assert!(sender_for_bundle(&bp).await.is_none());
+
+    let mut first = Router::connect(Encoding::Json).await;
+    assert!(first.role().await);
+    let mut second = Router::connect(Encoding::Cbor).await;
+    assert!(!second.role().await);
+    assert_eq!(agents().len(), 2);
+
+    // only the primary is asked
+    let request = {
+        let bp = bp.clone();
+        tokio::spawn(async move { sender_for_bundle(&bp).await })
+    };
+    let Packet::RequestSenderForBundle(req) = first.next().await else {
+        panic!("expected a request");
+    };
+    // answers of routers the request was not sent to are rejected
+    let standby_response = Packet::ResponseSenderForBundle(ResponseSenderForBundle {
+        bp: req.bp.clone(),
+        clas: vec![],
+        delete_afterwards: false,
+    });
+    second
+        .cmd
+        .send(Command::SendPacket(Box::new(standby_response)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let response = Packet::ResponseSenderForBundle(ResponseSenderForBundle {
+        bp: req.bp,
+        clas: vec![],
+        delete_afterwards: true,
+    });
+    first
+        .cmd
+        .send(Command::SendPacket(Box::new(response)))
+        .await
+        .unwrap();
+    let (senders, delete_afterwards) = request.await.unwrap().unwrap();
+    assert!(senders.is_empty());
+    assert!(delete_afterwards);
+
+    // a primary that stops answering hands over to the standby
+    assert!(sender_for_bundle(&bp).await.is_none());
+    assert!(sender_for_bundle(&bp).await.is_none());
+    assert!(matches!(
+        first.next().await,
+        Packet::RequestSenderForBundle(_)
+    ));
+    assert!(matches!(
+        first.next().await,
+        Packet::RequestSenderForBundle(_)
+    ));
+    assert!(!first.role().await);
+    assert!(second.role().await);
+    assert!(agents()[0].primary);
+
+    // a disconnected primary is replaced right away
+    second.close().await;
+    assert!(first.role().await);
+    assert_eq!(agents().len(), 1);
+
+    first.close().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(agents().is_empty());
+    assert!(sender_for_bundle(&bp).await.is_none());
+}
+
+#[test]
+fn erouting_invalid_fallback_is_disabled() {
+    let fallback = |name: &str| {
+        let mut settings = BTreeMap::new();
+        settings.insert(
+            "external".to_string(),
+            HashMap::from([("fallback".to_string(), name.to_string())]),
+        );
+        validate_settings(&mut settings);
+        settings["external"]["fallback"].clone()
+    };
+    assert_eq!(fallback("epidemic"), "epidemic");
+    assert_eq!(fallback("epidemik"), "none");
+    assert_eq!(fallback("external"), "none");
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::deliver;
+use dtn7::core::application_agent::{ApplicationAgent, SimpleApplicationAgent};
+use dtn7::core::events::{self, Event, EventBus, EventFilter, EventRecord, EvictionReason};
+use dtn7::{CONFIG, EVENTS};
+use std::collections::HashMap;
+
+fn delivered(bid: &str) -> Event {
+    Event::BundleDelivered {
+        bid: bid.into(),
+        endpoint: "dtn://node1/incoming".into(),
+    }
+}
+
+#[tokio::test]
+async fn subscribers_receive_numbered_events() {
+    let bus = EventBus::new();
+    bus.set_node("dtn://node1/");
+    let mut rx = bus.subscribe();
+
+    bus.publish(Event::PeerEncountered {
+        peer: "node2".into(),
+        addr: "192.168.2.1".into(),
+    });
+    bus.publish(delivered("dtn://node2/-1-0"));
+
+    let first = rx.recv().await.unwrap();
+    let second = rx.recv().await.unwrap();
+    assert_eq!((first.seq, second.seq), (0, 1));
+    assert_eq!(first.node, "dtn://node1/");
+    assert_eq!(first.event.name(), "peer_encountered");
+    assert_eq!(second.event, delivered("dtn://node2/-1-0"));
+}
+
+#[test]
+fn events_are_filtered_by_type_and_bundle() {
+    let bus = EventBus::new();
+    let params: HashMap<String, String> = [
+        (
+            "types".to_string(),
+            "bundle_delivered, store_evicted".to_string(),
+        ),
+        ("bid".to_string(), "dtn://node2/-1-0".to_string()),
+    ]
+    .into();
+    let filter = EventFilter::from_params(&params);
+    assert_eq!(filter.types, vec!["bundle_delivered", "store_evicted"]);
+
+    assert!(filter.matches(&bus.publish(delivered("dtn://node2/-1-0"))));
+    assert!(!filter.matches(&bus.publish(delivered("dtn://node2/-2-0"))));
+    assert!(!filter.matches(&bus.publish(Event::BundleDeleted {
+        bid: "dtn://node2/-1-0".into(),
+        reason: "lifetime expired".into(),
+    })));
+    assert!(!filter.matches(&bus.publish(Event::PeerDropped {
+        peer: "node2".into()
+    })));
+    assert!(
+        EventFilter::default().matches(&bus.publish(Event::PeerDropped {
+            peer: "node2".into()
+        }))
+    );
+}
+
+#[test]
+fn event_log_is_newline_delimited_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let bus = EventBus::new();
+    bus.publish(Event::PeerDropped {
+        peer: "node0".into(),
+    });
+    bus.open_log(&path).unwrap();
+    let published = vec![
+        bus.publish(Event::StoreEvicted {
+            bid: "dtn://node2/-1-0".into(),
+            reason: EvictionReason::Expired,
+        }),
+        bus.publish(Event::ClaSessionUp {
+            cla: "mtcp".into(),
+            addr: "192.168.2.1:16162".into(),
+            peer: None,
+        }),
+    ];
+    bus.flush_log();
+
+    let logged: Vec<EventRecord> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(logged, published);
+}
+
+#[tokio::test]
+async fn delivered_bundles_are_reported_deleted() {
+    let eid = CONFIG.lock().host_eid.new_endpoint("incoming").unwrap();
+    let bid = deliver(&eid, 4_000_000).await.id();
+    let mut rx = EVENTS.subscribe();
+
+    let mut aa = SimpleApplicationAgent::with(eid);
+    aa.restore();
+    assert_eq!(aa.pop().unwrap().id(), bid);
+
+    let filter = EventFilter {
+        bid: Some(bid.clone()),
+        ..Default::default()
+    };
+    let record = events::next_matching(&mut rx, &filter).await.unwrap();
+    assert_eq!(
+        record.event,
+        Event::BundleDeleted {
+            bid,
+            reason: "delivered".into()
+        }
+    );
+}
@@ -0,0 +1,83 @@
+use bp7::administrative_record::BLOCK_UNSUPPORTED;
+use bp7::canonical::{new_canonical_block, CanonicalBlock, CanonicalBlockType};
+use bp7::flags::BlockControlFlags;
+use bp7::helpers::rnd_bundle;
+use bp7::{Bundle, CanonicalData, CreationTimestamp};
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::extension_blocks::{BlockAction, ExtensionBlockHandler};
+use dtn7::core::processing::receive;
+use dtn7::{extension_block_register, store_get_bundle, store_get_metadata, EXTENSION_BLOCKS};
+
+const METADATA_BLOCK: CanonicalBlockType = 192;
+
+/// Application metadata as UTF-8 text, empty metadata is invalid.
+struct MetadataBlock;
+
+impl ExtensionBlockHandler for MetadataBlock {
+    fn block_type(&self) -> CanonicalBlockType {
+        METADATA_BLOCK
+    }
+    fn name(&self) -> &str {
+        "metadata"
+    }
+    fn on_receive(&self, _bundle: &Bundle, block: &CanonicalBlock) -> BlockAction {
+        match block.data() {
+            CanonicalData::Unknown(data) if !data.is_empty() => BlockAction::Keep,
+            _ => BlockAction::DeleteBundle(BLOCK_UNSUPPORTED),
+        }
+    }
+    fn describe(&self, block: &CanonicalBlock) -> serde_json::Value {
+        match block.data() {
+            CanonicalData::Unknown(data) => {
+                serde_json::json!(String::from_utf8_lossy(data).to_string())
+            }
+            _ => serde_json::Value::Null,
+        }
+    }
+}
+
+fn bundle_with_metadata(data: &[u8]) -> Bundle {
+    let mut bndl = rnd_bundle(CreationTimestamp::now());
+    let flags = BlockControlFlags::BLOCK_REMOVE | BlockControlFlags::BLOCK_STATUS_REPORT;
+    bndl.add_canonical_block(new_canonical_block(
+        METADATA_BLOCK,
+        0,
+        flags.bits(),
+        CanonicalData::Unknown(data.to_vec()),
+    ));
+    bndl
+}
+
+fn has_metadata(bid: &str) -> bool {
+    store_get_bundle(bid)
+        .unwrap()
+        .extension_block_by_type(METADATA_BLOCK)
+        .is_some()
+}
+
+#[tokio::test]
+async fn registered_blocks_are_processed() {
+    // unknown blocks are removed as requested by their flags
+    let unknown = bundle_with_metadata(b"sensor=3");
+    receive(unknown.clone()).await.unwrap();
+    assert!(!has_metadata(&unknown.id()));
+
+    extension_block_register(MetadataBlock);
+    let known = bundle_with_metadata(b"sensor=3");
+    receive(known.clone()).await.unwrap();
+    assert!(has_metadata(&known.id()));
+
+    let blocks = (*EXTENSION_BLOCKS.lock()).describe(&store_get_bundle(&known.id()).unwrap());
+    let metadata = blocks
+        .iter()
+        .find(|b| b.block_type == METADATA_BLOCK)
+        .unwrap();
+    assert_eq!(metadata.name.as_deref(), Some("metadata"));
+    assert_eq!(metadata.data, serde_json::json!("sensor=3"));
+
+    let invalid = bundle_with_metadata(b"");
+    receive(invalid.clone()).await.unwrap();
+    assert!(store_get_metadata(&invalid.id())
+        .unwrap()
+        .has_constraint(Constraint::Deleted));
+}
@@ -0,0 +1,142 @@
+use bp7::helpers::rnd_bundle;
+use bp7::{CreationTimestamp, EndpointID};
+use dtn7::cla::file::{
+    complete_import, export_bundle, filter_senders, pending_imports, read_import,
+    FileConvergenceLayer, Manifest, BUNDLE_DIR,
+};
+use dtn7::cla::ClaSenderTask;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+fn set_local_node(name: &str) {
+    dtn7::CONFIG.lock().host_eid = EndpointID::try_from(format!("dtn://{}/", name)).unwrap();
+}
+
+#[test]
+fn sneakernet_export_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut bundles = [
+        rnd_bundle(CreationTimestamp::now()),
+        rnd_bundle(CreationTimestamp::now()),
+        rnd_bundle(CreationTimestamp::now()),
+    ];
+
+    set_local_node("site1");
+    for b in bundles.iter_mut() {
+        let entry = export_bundle(dir.path(), &b.to_cbor()).unwrap();
+        assert!(dir.path().join(BUNDLE_DIR).join(entry.file).exists());
+    }
+    let manifest = Manifest::load(dir.path()).unwrap();
+    assert_eq!(manifest.bundles.len(), 3);
+    assert!(manifest.bundles.iter().all(|e| e.exported_by == "site1"));
+
+    // own exports are never imported again
+    assert!(pending_imports(dir.path(), false).unwrap().is_empty());
+
+    // bundles already in the local store are skipped
+    set_local_node("site2");
+    dtn7::store_push_bundle(&bundles[0]).unwrap();
+    let imported = pending_imports(dir.path(), false).unwrap();
+    assert_eq!(imported.len(), 2);
+    assert!(imported.iter().all(|e| e.id != bundles[0].id()));
+    for entry in &imported {
+        let bndl = read_import(dir.path(), entry, false).unwrap().unwrap();
+        assert_eq!(bndl.id(), entry.id);
+        assert_eq!(bndl.clone().to_cbor().len() as u64, entry.size);
+    }
+
+    // nothing is marked before the bundles were received
+    let manifest = Manifest::load(dir.path()).unwrap();
+    assert_eq!(
+        manifest
+            .bundles
+            .iter()
+            .filter(|e| e.imported_by.is_empty())
+            .count(),
+        2
+    );
+    assert_eq!(pending_imports(dir.path(), false).unwrap().len(), 2);
+    for b in &imported {
+        complete_import(dir.path(), &b.id, false).unwrap();
+    }
+
+    // files are kept and marked, so a second import yields nothing
+    let manifest = Manifest::load(dir.path()).unwrap();
+    assert!(manifest
+        .bundles
+        .iter()
+        .all(|e| e.imported_by == vec!["site2".to_string()]));
+    assert!(pending_imports(dir.path(), false).unwrap().is_empty());
+
+    // another site removes the files after importing
+    set_local_node("site3");
+    let imported = pending_imports(dir.path(), true).unwrap();
+    assert_eq!(imported.len(), 2);
+    // only the entry of the locally known bundle is completed right away
+    assert_eq!(Manifest::load(dir.path()).unwrap().bundles.len(), 2);
+    for b in &imported {
+        complete_import(dir.path(), &b.id, true).unwrap();
+    }
+    assert!(Manifest::load(dir.path()).unwrap().bundles.is_empty());
+    assert_eq!(
+        std::fs::read_dir(dir.path().join(BUNDLE_DIR))
+            .unwrap()
+            .count(),
+        0
+    );
+
+    // broken files are completed without being received
+    let mut b = rnd_bundle(CreationTimestamp::now());
+    let entry = export_bundle(dir.path(), &b.to_cbor()).unwrap();
+    std::fs::write(dir.path().join(BUNDLE_DIR).join(&entry.file), b"broken").unwrap();
+    set_local_node("site4");
+    let pending = pending_imports(dir.path(), true).unwrap();
+    assert_eq!(pending, vec![entry]);
+    assert!(read_import(dir.path(), &pending[0], true)
+        .unwrap()
+        .is_none());
+    assert!(Manifest::load(dir.path()).unwrap().bundles.is_empty());
+}
+
+#[test]
+fn sneakernet_missing_medium() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("not-mounted");
+    let mut b = rnd_bundle(CreationTimestamp::now());
+    assert!(export_bundle(&missing, &b.to_cbor()).is_err());
+    assert!(!missing.exists());
+}
+
+#[tokio::test]
+async fn sneakernet_restricted_destinations() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut settings = HashMap::new();
+    settings.insert("path".to_string(), dir.path().display().to_string());
+    settings.insert("peer".to_string(), "usbstick".to_string());
+    settings.insert("destinations".to_string(), "site2".to_string());
+    FileConvergenceLayer::new(Some(&settings))
+        .spawn_listener()
+        .await
+        .unwrap();
+
+    let (tx, _rx) = tokio::sync::mpsc::channel(1);
+    let sender = |cla: &str, peer: &str| ClaSenderTask {
+        tx: tx.clone(),
+        dest: String::new(),
+        cla_name: cla.into(),
+        next_hop: EndpointID::try_from(format!("dtn://{}/", peer)).unwrap(),
+    };
+    let mut senders = vec![sender("file", "usbstick"), sender("mtcp", "node2")];
+    filter_senders(
+        &EndpointID::try_from("dtn://site2/incoming").unwrap(),
+        &mut senders,
+    );
+    assert_eq!(senders.len(), 2);
+    // other destinations are not offered to the pseudo-peer at all instead of failing
+    filter_senders(
+        &EndpointID::try_from("dtn://site3/incoming").unwrap(),
+        &mut senders,
+    );
+    assert_eq!(senders.len(), 1);
+    assert_eq!(senders[0].cla_name, "mtcp");
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use bp7::EndpointID;
+use common::deliver;
+use dtn7::core::application_agent::{self, ApplicationAgent, GroupApplicationAgent};
+use dtn7::core::DtnCore;
+use dtn7::dtnd::ws::BundleDelivery;
+use dtn7::store_remove;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+fn group() -> EndpointID {
+    EndpointID::try_from("dtn://sensors/~telemetry").unwrap()
+}
+
+fn received(rx: &mut mpsc::Receiver<BundleDelivery>) -> Vec<String> {
+    let mut bids = Vec::new();
+    while let Ok(delivery) = rx.try_recv() {
+        bids.push(delivery.0.id());
+    }
+    bids
+}
+
+#[test]
+fn group_endpoints_get_group_agents() {
+    assert!(matches!(
+        application_agent::new(group()),
+        application_agent::ApplicationAgentEnum::GroupApplicationAgent(_)
+    ));
+    assert!(matches!(
+        application_agent::new(EndpointID::try_from("dtn://node1/incoming").unwrap()),
+        application_agent::ApplicationAgentEnum::SimpleApplicationAgent(_)
+    ));
+}
+
+#[tokio::test]
+async fn every_member_receives_every_bundle() {
+    let mut aa = GroupApplicationAgent::with(group());
+    let (tx_a, mut rx_a) = mpsc::channel(10);
+    let (tx_b, mut rx_b) = mpsc::channel(10);
+    aa.attach("a", tx_a.clone());
+    aa.attach("b", tx_b);
+
+    let first = deliver(&group(), 1_000_000).await;
+    aa.push(&first);
+    let second = deliver(&group(), 2_000_000).await;
+    aa.push(&second);
+    let expected = vec![first.id(), second.id()];
+    assert_eq!(received(&mut rx_a), expected);
+    assert_eq!(received(&mut rx_b), expected);
+
+    // anonymous consumers share the default member
+    assert_eq!(aa.pop().unwrap().id(), first.id());
+    assert_eq!(aa.pop().unwrap().id(), second.id());
+    assert!(aa.pop().is_none());
+
+    // unacknowledged bundles are delivered again after reconnecting
+    assert!(aa.ack("a", &first.id()));
+    aa.detach("a");
+    aa.attach("a", tx_a);
+    assert_eq!(received(&mut rx_a), vec![second.id()]);
+    assert!(aa.ack("a", &second.id()));
+    aa.detach("a");
+    let members = aa.members();
+    assert!(!members[0].connected && members[1].connected);
+    assert_eq!(members[0].unacked, 0);
+    assert_eq!(members[1].unacked, 2);
+
+    // late joiners get all bundles still in the store
+    store_remove(&first.id()).unwrap();
+    aa.maintain();
+    let (tx_c, mut rx_c) = mpsc::channel(10);
+    aa.attach("c", tx_c);
+    assert_eq!(received(&mut rx_c), vec![second.id()]);
+}
+
+#[tokio::test]
+async fn disconnected_members_expire() {
+    let mut aa = GroupApplicationAgent::with(group());
+    let (tx, _rx) = mpsc::channel(10);
+    aa.join("short", Some(Duration::from_millis(10)));
+    aa.join("long", None);
+    aa.attach("connected", tx);
+    std::thread::sleep(Duration::from_millis(20));
+
+    aa.maintain();
+    let members: Vec<String> = aa.members().into_iter().map(|m| m.member).collect();
+    assert_eq!(members, vec!["connected".to_string(), "long".to_string()]);
+    assert!(!aa.leave("short"));
+    assert!(aa.leave("long"));
+}
+
+#[tokio::test]
+async fn group_state_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registrations.json");
+    let eid = EndpointID::try_from("dtn://sensors/~restart").unwrap();
+
+    let mut core = DtnCore::new();
+    core.load_registrations(path.clone()).unwrap();
+    core.register_application_agent(application_agent::new(eid.clone()));
+    let first = deliver(&eid, 1_000_000).await;
+    let second = deliver(&eid, 2_000_000).await;
+    let aa = core.get_group_mut(&eid).unwrap();
+    aa.push(&first);
+    aa.push(&second);
+    let (tx, mut rx) = mpsc::channel(10);
+    aa.attach("a", tx);
+    assert_eq!(received(&mut rx), vec![first.id(), second.id()]);
+    assert!(aa.ack("a", &first.id()));
+    aa.detach("a");
+    core.save_groups();
+
+    // after a restart the member resumes after its last acknowledged bundle
+    let mut core = DtnCore::new();
+    core.load_registrations(path).unwrap();
+    let aa = core.get_group_mut(&eid).unwrap();
+    assert_eq!(aa.members()[0].unacked, 1);
+    let (tx, mut rx) = mpsc::channel(10);
+    aa.attach("a", tx);
+    assert_eq!(received(&mut rx), vec![second.id()]);
+}
+
+#[tokio::test]
+async fn full_members_are_served_by_maintenance() {
+    let eid = EndpointID::try_from("dtn://sensors/~slow").unwrap();
+    let mut aa = GroupApplicationAgent::with(eid.clone());
+    let (tx, mut rx) = mpsc::channel(1);
+    aa.attach("slow", tx);
+    let first = deliver(&eid, 1_000_000).await;
+    aa.push(&first);
+    let second = deliver(&eid, 2_000_000).await;
+    aa.push(&second);
+    assert_eq!(received(&mut rx), vec![first.id()]);
+
+    aa.maintain();
+    assert_eq!(received(&mut rx), vec![second.id()]);
+}
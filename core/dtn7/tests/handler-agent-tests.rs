@@ -0,0 +1,139 @@
+mod common;
+
+use bp7::EndpointID;
+use common::deliver;
+use dtn7::core::application_agent::ApplicationAgent;
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::handler_agent::{AgentHandler, HandlerApplicationAgent, RetryPolicy};
+use dtn7::core::stats::FailureAction;
+use dtn7::{store_get_metadata, store_has_item, CONFIG};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn local(name: &str) -> EndpointID {
+    CONFIG.lock().host_eid.new_endpoint(name).unwrap()
+}
+
+fn exec(command: &str) -> AgentHandler {
+    AgentHandler::Exec {
+        command: vec![command.into()],
+        timeout: Duration::from_secs(10),
+    }
+}
+
+fn retry(failure_action: FailureAction) -> RetryPolicy {
+    RetryPolicy {
+        retries: 1,
+        delay: Duration::from_millis(10),
+        failure_action,
+        defer_delay: Duration::from_secs(60),
+    }
+}
+
+async fn wait_until(cond: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if cond() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("condition not reached");
+}
+
+#[tokio::test]
+async fn file_drop_writes_payloads() {
+    let dir = tempfile::tempdir().unwrap();
+    let eid = local("spool");
+    let handler = AgentHandler::FileDrop {
+        dir: dir.path().join("in"),
+    };
+    let mut aa = HandlerApplicationAgent::new(eid.clone(), handler, RetryPolicy::default());
+
+    let bndl = deliver(&eid, bp7::dtn_time_now()).await;
+    aa.push(&bndl);
+    wait_until(|| !store_has_item(&bndl.id())).await;
+
+    let files: Vec<_> = std::fs::read_dir(dir.path().join("in"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        std::fs::read(&files[0]).unwrap(),
+        bndl.payload().unwrap().to_vec()
+    );
+}
+
+#[tokio::test]
+async fn failed_commands_follow_failure_action() {
+    let ok = local("ok");
+    let mut aa =
+        HandlerApplicationAgent::new(ok.clone(), exec("true"), retry(FailureAction::Abandon));
+    let bndl = deliver(&ok, bp7::dtn_time_now()).await;
+    aa.push(&bndl);
+    wait_until(|| !store_has_item(&bndl.id())).await;
+
+    let abandon = local("abandon");
+    let mut aa = HandlerApplicationAgent::new(
+        abandon.clone(),
+        exec("false"),
+        retry(FailureAction::Abandon),
+    );
+    let bndl = deliver(&abandon, bp7::dtn_time_now()).await;
+    aa.push(&bndl);
+    wait_until(|| !store_has_item(&bndl.id())).await;
+
+    // deferred bundles stay until a later attempt succeeds
+    let defer = local("defer");
+    let mut aa =
+        HandlerApplicationAgent::new(defer.clone(), exec("false"), retry(FailureAction::Defer));
+    let bndl = deliver(&defer, bp7::dtn_time_now()).await;
+    aa.push(&bndl);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(store_get_metadata(&bndl.id())
+        .unwrap()
+        .has_constraint(Constraint::LocalEndpoint));
+}
+
+#[tokio::test]
+async fn failing_bundles_do_not_block_others() {
+    let eid = local("picky");
+    let failing = deliver(&eid, bp7::dtn_time_now()).await;
+    let other = deliver(&eid, bp7::dtn_time_now()).await;
+    let settings: HashMap<String, String> = [
+        ("type", "exec"),
+        ("endpoint", "picky"),
+        (
+            "command",
+            &format!("sh -c 'test \"$DTN_BUNDLE_ID\" != \"{}\"'", failing.id()),
+        ),
+        ("retry_delay", "1h"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let mut aa = HandlerApplicationAgent::from_settings(&settings).unwrap();
+    aa.push(&failing);
+    aa.push(&other);
+    wait_until(|| !store_has_item(&other.id())).await;
+    assert!(store_has_item(&failing.id()));
+}
+
+#[tokio::test]
+async fn hanging_commands_are_killed() {
+    let eid = local("hanging");
+    let mut aa = HandlerApplicationAgent::new(
+        eid.clone(),
+        AgentHandler::Exec {
+            command: vec!["sh".into(), "-c".into(), "sleep 30".into()],
+            timeout: Duration::from_millis(100),
+        },
+        RetryPolicy {
+            retries: 0,
+            ..retry(FailureAction::Abandon)
+        },
+    );
+    let bndl = deliver(&eid, bp7::dtn_time_now()).await;
+    aa.push(&bndl);
+    wait_until(|| !store_has_item(&bndl.id())).await;
+}
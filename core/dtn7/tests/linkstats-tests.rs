@@ -0,0 +1,81 @@
+use bp7::EndpointID;
+use dtn7::core::linkstats::{LinkStats, MAX_TRANSFER_HISTORY};
+use dtn7::core::{DtnPeer, PeerType};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn peer() -> DtnPeer {
+    DtnPeer::new(
+        EndpointID::try_from("dtn://node2/").unwrap(),
+        "127.0.0.1".parse::<std::net::IpAddr>().unwrap().into(),
+        PeerType::Dynamic,
+        None,
+        Vec::new(),
+        HashMap::new(),
+    )
+}
+
+fn transfer(stats: &mut LinkStats, success: bool) {
+    stats.record_transfer("mtcp", 100, Duration::from_millis(10), success);
+}
+
+#[test]
+fn transfer_history_is_bounded() {
+    let mut stats = LinkStats::default();
+    assert_eq!(stats.success_ratio(), None);
+    for i in 0..MAX_TRANSFER_HISTORY * 2 {
+        transfer(&mut stats, i < MAX_TRANSFER_HISTORY);
+    }
+    assert_eq!(stats.transfers.len(), MAX_TRANSFER_HISTORY);
+    // only the failed half is left in the history, the counters keep everything
+    assert_eq!(stats.success_ratio(), Some(0.0));
+    assert_eq!(stats.clas["mtcp"].bundles_sent, MAX_TRANSFER_HISTORY as u64);
+    assert_eq!(
+        stats.clas["mtcp"].transfers_failed,
+        MAX_TRANSFER_HISTORY as u64
+    );
+    assert_eq!(stats.consecutive_failures(), MAX_TRANSFER_HISTORY);
+}
+
+#[test]
+fn links_with_failing_transfers_are_unreliable() {
+    let mut stats = LinkStats::default();
+    for _ in 0..3 {
+        transfer(&mut stats, false);
+    }
+    assert!(!stats.unreliable());
+    transfer(&mut stats, false);
+    assert!(stats.unreliable());
+    transfer(&mut stats, true);
+    assert!(!stats.unreliable());
+
+    // occasional successes do not keep a flaky link alive
+    let mut flaky = LinkStats::default();
+    for round in 0..3 {
+        assert!(!flaky.unreliable(), "round {}", round);
+        transfer(&mut flaky, true);
+        transfer(&mut flaky, false);
+        transfer(&mut flaky, false);
+    }
+    assert_eq!(flaky.consecutive_failures(), 2);
+    assert!(flaky.recent_success_ratio().unwrap() < 0.5);
+    assert!(flaky.unreliable());
+    transfer(&mut flaky, true);
+    assert!(!flaky.unreliable());
+}
+
+#[test]
+fn peers_fail_by_their_link_stats() {
+    let mut peer = peer();
+    for _ in 0..4 {
+        assert!(!peer.failed_too_much());
+        peer.report_transfer("mtcp", 100, Duration::from_millis(10), false);
+    }
+    assert!(peer.failed_too_much());
+    assert!(!peer.link_stats.in_contact());
+
+    peer.report_transfer("mtcp", 100, Duration::from_millis(10), true);
+    assert!(!peer.failed_too_much());
+    assert_eq!(peer.fails, 0);
+    assert!(peer.link_stats.in_contact());
+}
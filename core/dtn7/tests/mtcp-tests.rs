@@ -42,3 +42,128 @@ fn mpdu_encoding() {
 
     assert_eq!(mpdu_encoded, expected_bytes);
 }
+
+/// Random bundle forwarded by the neighbour `node`.
+fn forwarded_by(node: &str) -> bundle::Bundle {
+    let mut b = bp7::helpers::rnd_bundle(dtntime::CreationTimestamp::now());
+    b.add_canonical_block(bp7::canonical::new_previous_node_block(
+        0,
+        bp7::flags::BlockControlFlags::empty(),
+        format!("dtn://{}/", node).try_into().unwrap(),
+    ));
+    b
+}
+
+#[tokio::test]
+async fn mtcp_bidirectional_session() {
+    use bp7::helpers::rnd_bundle;
+    use dtn7::cla::ConvergenceLayerAgent;
+    use futures::{SinkExt, StreamExt};
+    use std::collections::HashMap;
+    use tokio_util::codec::Framed;
+
+    // run the janitor with a short peer timeout while the session is open
+    dtn7::CONFIG.lock().peer_timeout = std::time::Duration::from_secs(1);
+    let janitor = tokio::spawn(async {
+        loop {
+            dtn7::core::process_peers().await;
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    });
+
+    let mut settings = HashMap::new();
+    settings.insert("port".to_string(), "26162".to_string());
+    settings.insert("bind".to_string(), "127.0.0.1".to_string());
+    let mut cla = mtcp::MtcpConvergenceLayer::new(Some(&settings));
+    cla.setup().await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // a node behind NAT connects and sends a bundle
+    let socket = tokio::net::TcpStream::connect("127.0.0.1:26162")
+        .await
+        .unwrap();
+    let local_addr = socket.local_addr().unwrap();
+    let mut client = Framed::new(socket, mtcp::MPDUCodec::new());
+    // the source of a bundle without previous node may be many hops away
+    let b = rnd_bundle(dtntime::CreationTimestamp::now());
+    client.send(mtcp::MPDU::new(&b)).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!dtn7::peers_known(&b.primary.source.node().unwrap()));
+
+    let node = "natnode1".to_string();
+    client
+        .send(mtcp::MPDU::new(&forwarded_by(&node)))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let peer = dtn7::PEERS.lock().get(&node).cloned().unwrap();
+    assert_eq!(
+        peer.cla_list,
+        vec![("mtcp".into(), Some(local_addr.port()))]
+    );
+
+    // the peer outlives the peer timeout as long as the session is open
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    assert!(dtn7::peers_known(&node));
+
+    // bundles for that node are sent back over the same connection
+    let reply = rnd_bundle(dtntime::CreationTimestamp::now());
+    let res = mtcp::mtcp_send_bundles(
+        local_addr,
+        vec![reply.clone().to_cbor()],
+        mtcp::MtcpSettings::default(),
+    )
+    .await;
+    assert_eq!(res, dtn7::cla::TransferResult::Successful);
+    let received = client.next().await.unwrap().unwrap();
+    let received: bundle::Bundle = received.try_into().unwrap();
+    assert_eq!(received.id(), reply.id());
+
+    // closing the connection forgets the peer again
+    drop(client);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!dtn7::peers_known(&node));
+
+    // a peer replaced while the session was open, e.g., by discovery, is kept
+    let socket = tokio::net::TcpStream::connect("127.0.0.1:26162")
+        .await
+        .unwrap();
+    let mut client = Framed::new(socket, mtcp::MPDUCodec::new());
+    let node = "natnode2".to_string();
+    client
+        .send(mtcp::MPDU::new(&forwarded_by(&node)))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let mut discovered = dtn7::PEERS.lock().get(&node).cloned().unwrap();
+    discovered.cla_list = vec![("mtcp".into(), Some(16162))];
+    dtn7::peers_add(discovered);
+    drop(client);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(dtn7::peers_known(&node));
+    janitor.abort();
+}
+
+#[tokio::test]
+async fn mtcp_idle_timeout() {
+    use dtn7::cla::ConvergenceLayerAgent;
+    use std::collections::HashMap;
+    use tokio::io::AsyncReadExt;
+
+    let mut settings = HashMap::new();
+    settings.insert("port".to_string(), "26163".to_string());
+    settings.insert("bind".to_string(), "127.0.0.1".to_string());
+    settings.insert("idle".to_string(), "300ms".to_string());
+    let mut cla = mtcp::MtcpConvergenceLayer::new(Some(&settings));
+    cla.setup().await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let mut socket = tokio::net::TcpStream::connect("127.0.0.1:26163")
+        .await
+        .unwrap();
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(std::time::Duration::from_secs(2), socket.read(&mut buf))
+        .await
+        .expect("idle connection was not closed");
+    assert_eq!(read.unwrap(), 0);
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "cla_quic")]
+
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::cla::quic::{quic_send_bundles, QuicConvergenceLayer};
+use dtn7::cla::{ConvergenceLayerAgent, TransferResult};
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn quic_loopback_transfer() {
+    let mut settings = HashMap::new();
+    settings.insert("port".to_string(), "24560".to_string());
+    settings.insert("bind".to_string(), "127.0.0.1".to_string());
+    settings.insert("insecure".to_string(), "true".to_string());
+    let mut cla = QuicConvergenceLayer::new(Some(&settings));
+    cla.setup().await;
+
+    let addr = "127.0.0.1:24560".parse().unwrap();
+    let bundles = vec![
+        rnd_bundle(CreationTimestamp::now()).to_cbor(),
+        rnd_bundle(CreationTimestamp::now()).to_cbor(),
+    ];
+    assert_eq!(
+        quic_send_bundles(addr, bundles).await,
+        TransferResult::Successful
+    );
+    // second transfer reuses the established connection
+    let bundles = vec![rnd_bundle(CreationTimestamp::now()).to_cbor()];
+    assert_eq!(
+        quic_send_bundles(addr, bundles).await,
+        TransferResult::Successful
+    );
+}
+
+#[tokio::test]
+async fn quic_requires_ca_or_insecure() {
+    let mut settings = HashMap::new();
+    settings.insert("port".to_string(), "24561".to_string());
+    settings.insert("bind".to_string(), "127.0.0.1".to_string());
+    let cla = QuicConvergenceLayer::new(Some(&settings));
+    assert!(cla.spawn_listener().await.is_err());
+}
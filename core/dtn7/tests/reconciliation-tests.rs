@@ -0,0 +1,87 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::core::reconciliation::*;
+use dtn7::{store_push_bundle, store_remove};
+use std::collections::BTreeSet;
+
+fn bids(range: std::ops::Range<usize>) -> BTreeSet<String> {
+    range
+        .map(|i| format!("dtn://node1/-{:012}-0", i * 7))
+        .collect()
+}
+
+/// Runs the puller side of the reconciliation and returns the bundles missing locally.
+fn reconcile(local: &[String], remote: &[String]) -> (Vec<String>, usize) {
+    let mut ranges = vec![full_range_digest(local)];
+    let mut missing = Vec::new();
+    let mut rounds = 0;
+    while !ranges.is_empty() {
+        rounds += 1;
+        let mut next = Vec::new();
+        for reply in answer_ranges(remote, &ranges) {
+            if let Some(bids) = reply.bids {
+                missing.extend(bids.into_iter().filter(|b| local.binary_search(b).is_err()));
+            } else {
+                let own = range_digest(local, &reply.lo, reply.hi.as_deref());
+                if own.count != reply.count || own.digest != reply.digest {
+                    next.push(own);
+                }
+            }
+        }
+        ranges = next;
+    }
+    (missing, rounds)
+}
+
+#[test]
+fn reconcile_equal_sets() {
+    let set: Vec<String> = bids(0..5000).into_iter().collect();
+    let (missing, rounds) = reconcile(&set, &set);
+    assert!(missing.is_empty());
+    assert_eq!(rounds, 1);
+}
+
+#[test]
+fn reconcile_differences_on_both_sides() {
+    let mut local = bids(0..5000);
+    let mut remote = local.clone();
+    let mut expected = Vec::new();
+    for i in [0, 17, 2500, 2501, 4999] {
+        let bid = format!("dtn://node1/-{:012}-0", i * 7);
+        local.remove(&bid);
+        expected.push(bid);
+    }
+    // bundles only known locally do not show up as missing
+    for i in [3, 1200] {
+        remote.remove(&format!("dtn://node1/-{:012}-0", i * 7));
+    }
+    remote.insert("dtn://node2/-000000000001-0".into());
+    expected.push("dtn://node2/-000000000001-0".into());
+    expected.sort();
+
+    let local: Vec<String> = local.into_iter().collect();
+    let remote: Vec<String> = remote.into_iter().collect();
+    let (mut missing, _) = reconcile(&local, &remote);
+    missing.sort();
+    assert_eq!(missing, expected);
+}
+
+#[test]
+fn reconcile_empty_local_store() {
+    let remote: Vec<String> = bids(0..100).into_iter().collect();
+    let (missing, _) = reconcile(&[], &remote);
+    assert_eq!(missing, remote);
+}
+
+#[test]
+fn deleted_bundles_are_not_offered() {
+    let kept = rnd_bundle(CreationTimestamp::now());
+    let deleted = rnd_bundle(CreationTimestamp::now());
+    store_push_bundle(&kept).unwrap();
+    store_push_bundle(&deleted).unwrap();
+    store_remove(&deleted.id()).unwrap();
+
+    let local = local_bids(&SyncFilter::default());
+    assert!(local.contains(&kept.id()));
+    assert!(!local.contains(&deleted.id()));
+}
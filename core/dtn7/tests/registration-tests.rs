@@ -0,0 +1,65 @@
+mod common;
+
+use bp7::EndpointID;
+use common::deliver;
+use dtn7::core::application_agent::{ApplicationAgent, SimpleApplicationAgent};
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::handler_agent::{AgentHandler, HandlerApplicationAgent, RetryPolicy};
+use dtn7::core::DtnCore;
+use dtn7::{store_get_metadata, store_has_item, CONFIG};
+
+#[tokio::test]
+async fn registrations_survive_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registrations.json");
+    let eid = CONFIG.lock().host_eid.new_endpoint("incoming").unwrap();
+    let group = EndpointID::try_from("dtn://group/~news").unwrap();
+
+    let mut core = DtnCore::new();
+    core.load_registrations(path.clone()).unwrap();
+    core.register_application_agent(SimpleApplicationAgent::with(eid.clone()).into());
+    core.register_application_agent(SimpleApplicationAgent::with(group.clone()).into());
+    core.unregister_application_agent(SimpleApplicationAgent::with(group.clone()).into());
+    // handlers come from the configuration and are not persisted
+    let spool = CONFIG.lock().host_eid.new_endpoint("spool").unwrap();
+    let handler = AgentHandler::FileDrop {
+        dir: dir.path().join("spool"),
+    };
+    core.register_application_agent(
+        HandlerApplicationAgent::new(spool, handler, RetryPolicy::default()).into(),
+    );
+    let saved: Vec<String> = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(saved, vec![eid.to_string()]);
+
+    // bundles that arrived before the restart are queued again in creation order
+    let second = deliver(&eid, 2_000_000).await.id();
+    let first = deliver(&eid, 1_000_000).await.id();
+    let mut core = DtnCore::new();
+    core.load_registrations(path).unwrap();
+    assert_eq!(core.eids(), vec![eid.to_string()]);
+
+    let aa = core.get_endpoint_mut(&eid).unwrap();
+    assert_eq!(aa.pop().unwrap().id(), first);
+    assert_eq!(aa.pop().unwrap().id(), second);
+    assert!(aa.pop().is_none());
+    assert!(!store_has_item(&first));
+}
+
+#[tokio::test]
+async fn delivered_group_bundles_stay_for_forwarding() {
+    let group = EndpointID::try_from("dtn://group/~weather").unwrap();
+    let bid = deliver(&group, 3_000_000).await.id();
+
+    let mut aa = SimpleApplicationAgent::with(group);
+    aa.restore();
+    assert_eq!(aa.pop().unwrap().id(), bid);
+    assert!(aa.pop().is_none());
+
+    let bp = store_get_metadata(&bid).unwrap();
+    assert!(!bp.has_constraint(Constraint::LocalEndpoint));
+    assert!(bp.has_constraint(Constraint::ForwardPending));
+
+    // not queued again after a restart
+    aa.restore();
+    assert!(aa.pop().is_none());
+}
@@ -0,0 +1,35 @@
+use bp7::EndpointID;
+use dtn7::core::rpc::{self, Correlation, MessageKind};
+
+fn request_bundle(id: usize) -> bp7::Bundle {
+    let mut bndl = bp7::bundle::new_std_payload_bundle(
+        EndpointID::try_from("dtn://node1/").unwrap(),
+        EndpointID::try_from("dtn://node2/echo").unwrap(),
+        format!("ping {}", id).into_bytes(),
+    );
+    // a long lifetime does not keep requests forever
+    bndl.primary.lifetime = std::time::Duration::from_secs(365 * 24 * 3600);
+    let correlation = Correlation {
+        id: format!("{:016x}", id),
+        kind: MessageKind::Request,
+        reply_to: "dtn://node1/replies".into(),
+    };
+    bndl.add_canonical_block(correlation.to_block());
+    bndl
+}
+
+#[test]
+fn received_requests_are_limited() {
+    let bids: Vec<String> = (0..=rpc::RPC_MAX_RECEIVED)
+        .map(|i| {
+            let bndl = request_bundle(i);
+            rpc::on_delivery(&bndl);
+            bndl.id()
+        })
+        .collect();
+    let received: Vec<String> = rpc::status().received.into_iter().map(|r| r.bid).collect();
+    assert_eq!(received.len(), rpc::RPC_MAX_RECEIVED);
+    // the oldest request is dropped first
+    assert!(!received.contains(&bids[0]));
+    assert!(received.contains(&bids[rpc::RPC_MAX_RECEIVED]));
+}
@@ -0,0 +1,113 @@
+use bp7::EndpointID;
+use dtn7::core::extension_blocks::{BlockAction, ExtensionBlockHandler};
+use dtn7::core::rpc::{self, Correlation, CorrelationBlock, MessageKind, ReceivedRequest};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn request_bundle(id: &str) -> bp7::Bundle {
+    let mut bndl = bp7::bundle::new_std_payload_bundle(
+        EndpointID::try_from("dtn://node1/").unwrap(),
+        EndpointID::try_from("dtn://node2/echo").unwrap(),
+        b"ping".to_vec(),
+    );
+    let correlation = Correlation {
+        id: id.into(),
+        kind: MessageKind::Request,
+        reply_to: "dtn://node1/replies".into(),
+    };
+    bndl.add_canonical_block(correlation.to_block());
+    bndl
+}
+
+#[tokio::test]
+async fn responses_are_matched_to_pending_requests() {
+    let dst = EndpointID::try_from("dtn://node2/echo").unwrap();
+    let request = rpc::request(dst, b"ping".to_vec(), Duration::from_secs(5), None)
+        .await
+        .unwrap();
+    assert!(rpc::status().pending.iter().any(|p| p.id == request.id));
+
+    let expires = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+    let received = ReceivedRequest {
+        bid: request.bid.clone(),
+        correlation: Correlation {
+            id: request.id.clone(),
+            kind: MessageKind::Request,
+            reply_to: "dtn://node1/".into(),
+        },
+        destination: "dtn://node2/echo".into(),
+        expires,
+    };
+    let response = received.response(b"pong".to_vec()).unwrap();
+    assert_eq!(response.primary.source.to_string(), "dtn://node2/echo");
+    assert_eq!(response.primary.destination.to_string(), "dtn://node1/");
+    assert!(rpc::on_delivery(&response));
+    // a second response to the same request is delivered normally
+    assert!(!rpc::on_delivery(&response));
+
+    let id = request.id.clone();
+    let answer = request.wait().await.unwrap();
+    assert_eq!(answer.payload().unwrap(), b"pong");
+    assert!(!rpc::status().pending.iter().any(|p| p.id == id));
+}
+
+#[tokio::test]
+async fn requests_time_out() {
+    let dst = EndpointID::try_from("dtn://node3/echo").unwrap();
+    let request = rpc::request(dst, Vec::new(), Duration::from_millis(50), None)
+        .await
+        .unwrap();
+    let id = request.id.clone();
+    assert!(request.wait().await.is_none());
+    assert!(!rpc::status().pending.iter().any(|p| p.id == id));
+    assert!(
+        rpc::request(EndpointID::none(), Vec::new(), Duration::from_secs(1), None)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn abandoned_requests_are_forgotten() {
+    let dst = EndpointID::try_from("dtn://node3/echo").unwrap();
+    let request = rpc::request(dst, Vec::new(), Duration::from_secs(30), None)
+        .await
+        .unwrap();
+    let id = request.id.clone();
+    assert!(rpc::status().pending.iter().any(|p| p.id == id));
+    // the client goes away before the response arrives
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), request.wait())
+            .await
+            .is_err()
+    );
+    assert!(!rpc::status().pending.iter().any(|p| p.id == id));
+}
+
+#[tokio::test]
+async fn received_requests_are_answered_by_bundle_id() {
+    let bndl = request_bundle("c0ffee");
+    assert!(!rpc::on_delivery(&bndl));
+    let status = rpc::status();
+    let received = status.received.iter().find(|r| r.bid == bndl.id()).unwrap();
+    assert_eq!(received.correlation.reply_to, "dtn://node1/replies");
+
+    assert!(rpc::reply(&bndl.id(), b"pong".to_vec()).await.is_ok());
+    // each request is answered once
+    assert!(rpc::reply(&bndl.id(), b"pong".to_vec()).await.is_err());
+
+    let broken = request_bundle("broken");
+    let block = bp7::canonical::new_canonical_block(
+        rpc::CORRELATION_BLOCK,
+        0,
+        0,
+        bp7::CanonicalData::Unknown(vec![0xff]),
+    );
+    assert_eq!(
+        CorrelationBlock.on_receive(&broken, &block),
+        BlockAction::RemoveBlock
+    );
+}
@@ -0,0 +1,174 @@
+#![cfg(all(feature = "cla_serial", unix))]
+
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::cla::serial::{
+    cobs_decode, cobs_encode, fragment, FrameType, Reassembler, SerialConvergenceLayer,
+    SerialFrame, SerialFrameCodec,
+};
+use dtn7::cla::{ClaCmd, ConvergenceLayerAgent, TransferResult};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::oneshot;
+use tokio_serial::{SerialPort, SerialStream};
+use tokio_util::codec::{Decoder, Framed};
+
+#[test]
+fn cobs_roundtrip() {
+    let inputs: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![0],
+        vec![0, 0, 1, 0],
+        (0..=255).collect(),
+        vec![0xaa; 254],
+        vec![0xaa; 600],
+    ];
+    for input in inputs {
+        let encoded = cobs_encode(&input);
+        assert!(!encoded.contains(&0));
+        assert_eq!(cobs_decode(&encoded), Some(input));
+    }
+}
+
+#[test]
+fn corrupted_frames_are_skipped() {
+    let mut codec = SerialFrameCodec;
+    let mut buf = bytes::BytesMut::new();
+    let frames = fragment(7, &[1, 2, 3, 0, 4, 5], 4, false).unwrap();
+    for f in &frames {
+        tokio_util::codec::Encoder::encode(&mut codec, f.clone(), &mut buf).unwrap();
+    }
+    // flip a bit in the first frame
+    buf[3] ^= 0x10;
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(frames[1].clone()));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+}
+
+#[test]
+fn fragment_limits() {
+    let data = vec![0xaa; 70000];
+    // frames never exceed the maximum frame size, even with a larger mtu
+    let frames = fragment(3, &data, 100_000, false).unwrap();
+    assert_eq!(frames.len(), 2);
+    let mut buf = bytes::BytesMut::new();
+    tokio_util::codec::Encoder::encode(&mut SerialFrameCodec, frames[0].clone(), &mut buf).unwrap();
+    assert!(buf.len() <= 65535);
+    // more than u16::MAX fragments can not be numbered
+    assert!(fragment(3, &data, 1, false).is_err());
+}
+
+#[test]
+fn reassembly_out_of_order() {
+    let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let mut frames = fragment(1, &data, 100, true).unwrap();
+    frames.reverse();
+    let mut reassembler = Reassembler::new();
+    let last = frames.pop().unwrap();
+    for f in frames.iter().cloned() {
+        assert_eq!(reassembler.push(f), None);
+    }
+    // duplicate fragment must not complete the bundle
+    assert_eq!(reassembler.push(frames[0].clone()), None);
+    assert_eq!(reassembler.push(last.clone()), Some(data));
+    // retransmission after completion is ignored
+    assert_eq!(reassembler.push(last), None);
+}
+
+#[test]
+fn oversized_bundles_are_not_buffered() {
+    let mut reassembler = Reassembler::with_max_size(1000);
+    let frame = |index: u16, count: u16, len: usize| SerialFrame {
+        frame_type: FrameType::Data,
+        seq: 5,
+        index,
+        count,
+        payload: vec![0xaa; len],
+    };
+    // the header announces more data than allowed
+    assert_eq!(reassembler.push(frame(0, u16::MAX, 100)), None);
+    assert_eq!(reassembler.push(frame(u16::MAX - 1, u16::MAX, 1)), None);
+    assert_eq!(reassembler.pending(), 0);
+
+    // fragments adding up to more than allowed drop the bundle
+    assert_eq!(reassembler.push(frame(0, 4, 250)), None);
+    assert_eq!(reassembler.push(frame(1, 4, 250)), None);
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.push(frame(3, 4, 600)), None);
+    assert_eq!(reassembler.pending(), 0);
+
+    let data = vec![0x55; 1000];
+    for f in fragment(6, &data, 250, false).unwrap() {
+        if let Some(bundle) = reassembler.push(f) {
+            assert_eq!(bundle, data);
+        }
+    }
+    assert_eq!(reassembler.pending(), 0);
+}
+
+/// Creates a pseudo-terminal pair, returns the master side and the path of the slave device.
+fn pty() -> (Framed<SerialStream, SerialFrameCodec>, String) {
+    let (master, slave) = SerialStream::pair().expect("failed to create pty pair");
+    let path = slave.name().unwrap();
+    drop(slave);
+    (Framed::new(master, SerialFrameCodec), path)
+}
+
+#[tokio::test]
+async fn serial_transfer_with_ack() {
+    let (mut remote, path) = pty();
+    let mut settings = HashMap::new();
+    settings.insert("path".to_string(), path);
+    settings.insert("mtu".to_string(), "64".to_string());
+    settings.insert("ack".to_string(), "true".to_string());
+    let mut cla = SerialConvergenceLayer::new(Some(&settings));
+    cla.setup().await;
+
+    let bundle = rnd_bundle(CreationTimestamp::now()).to_cbor();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    cla.channel()
+        .send(ClaCmd::Transfer("".into(), bundle.clone(), reply_tx))
+        .await
+        .unwrap();
+
+    let mut reassembler = Reassembler::new();
+    let mut dropped_one = false;
+    let received = loop {
+        let frame = remote.next().await.unwrap().unwrap();
+        assert_eq!(frame.frame_type, FrameType::DataAckReq);
+        // do not ack the second fragment the first time to force a retransmission
+        if frame.index == 1 && !dropped_one {
+            dropped_one = true;
+            continue;
+        }
+        remote
+            .send(SerialFrame::ack(frame.seq, frame.index))
+            .await
+            .unwrap();
+        if let Some(data) = reassembler.push(frame) {
+            break data;
+        }
+    };
+    assert!(dropped_one);
+    assert_eq!(received, bundle);
+    assert_eq!(reply_rx.await.unwrap(), TransferResult::Successful);
+}
+
+#[tokio::test]
+async fn serial_receive_acks_fragments() {
+    let (mut remote, path) = pty();
+    let mut settings = HashMap::new();
+    settings.insert("path".to_string(), path);
+    let mut cla = SerialConvergenceLayer::new(Some(&settings));
+    cla.setup().await;
+
+    let bundle = rnd_bundle(CreationTimestamp::now()).to_cbor();
+    let frames = fragment(42, &bundle, 32, true).unwrap();
+    let count = frames.len() as u16;
+    for f in frames {
+        remote.send(f).await.unwrap();
+    }
+    for index in 0..count {
+        let ack = remote.next().await.unwrap().unwrap();
+        assert_eq!(ack, SerialFrame::ack(42, index));
+    }
+}
@@ -0,0 +1,80 @@
+use bp7::helpers::rnd_bundle;
+use bp7::{CreationTimestamp, EndpointID};
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::store::maintenance::check;
+use dtn7::core::store::{BundleStore, SqliteBundleStore};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+#[test]
+fn sqlite_store_queries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.sqlite3");
+    let mut store = SqliteBundleStore::open(&path).unwrap();
+
+    let mut bundles = Vec::new();
+    for i in 0..5u64 {
+        let mut b = rnd_bundle(CreationTimestamp::with_time_and_seq(1_000_000 + i, 0));
+        b.primary.destination = EndpointID::try_from(format!("dtn://node{}/inbox", i % 2)).unwrap();
+        b.primary.lifetime = Duration::from_secs(10 * (i + 1));
+        store.push(&b).unwrap();
+        bundles.push(b);
+    }
+    let bids: Vec<String> = bundles.iter().map(|b| b.id()).collect();
+    assert_eq!(store.count(), 5);
+    assert_eq!(store.get_bundle(&bids[0]), Some(bundles[0].clone()));
+
+    for bid in &bids[..3] {
+        let mut bp = store.get_metadata(bid).unwrap();
+        bp.add_constraint(Constraint::ForwardPending);
+        store.update_metadata(&bp).unwrap();
+    }
+    let mut bp = store.get_metadata(&bids[2]).unwrap();
+    bp.add_constraint(Constraint::ReassemblyPending);
+    store.update_metadata(&bp).unwrap();
+    store.remove(&bids[4]).unwrap();
+
+    let sorted = |mut v: Vec<String>| {
+        v.sort();
+        v
+    };
+    assert_eq!(sorted(store.forwarding()), sorted(bids[..3].to_vec()));
+    assert_eq!(sorted(store.pending()), sorted(bids[..2].to_vec()));
+    let criteria: HashSet<Constraint> = [Constraint::ForwardPending, Constraint::ReassemblyPending]
+        .into_iter()
+        .collect();
+    assert_eq!(store.filter(&criteria), vec![bids[2].clone()]);
+    assert_eq!(
+        store.query_constraint(Constraint::Deleted),
+        vec![bids[4].clone()]
+    );
+
+    // deleted bundles are left out
+    assert_eq!(
+        sorted(store.query_destination("dtn://node0/inbox")),
+        sorted(vec![bids[0].clone(), bids[2].clone()])
+    );
+    assert_eq!(store.filter_addr("node1/").len(), 2);
+
+    // lifetimes end at creation time + 10s, 20s, ...
+    assert_eq!(
+        sorted(store.query_expiring_before(1_000_000 + 25_000)),
+        sorted(bids[..2].to_vec())
+    );
+
+    let page: Vec<String> = store.query_page(1, 2).into_iter().map(|bp| bp.id).collect();
+    assert_eq!(page, bids[1..3].to_vec());
+
+    assert!(check(&store, Duration::ZERO).is_clean());
+
+    // metadata and constraints persist
+    drop(store);
+    let store = SqliteBundleStore::open(&path).unwrap();
+    assert_eq!(store.count(), 4);
+    assert!(store
+        .get_metadata(&bids[4])
+        .unwrap()
+        .has_constraint(Constraint::Deleted));
+    assert_eq!(store.bundles().len(), 5);
+}
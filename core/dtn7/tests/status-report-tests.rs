@@ -0,0 +1,42 @@
+use dtn7::core::status_reports::*;
+use tokio::sync::mpsc;
+
+#[test]
+fn status_report_watchers() {
+    let mut log = StatusReportLog::default();
+    let bid = "dtn://node1/-683555464000-0";
+    assert!(!log.is_tracked(bid));
+
+    let (tx, mut rx) = mpsc::channel(8);
+    log.watch(bid, tx);
+    assert!(log.is_tracked(bid));
+
+    let report = StatusReportEntry::new(
+        "dtn://node2/".into(),
+        BundleStatus::Deleted,
+        0,
+        bp7::administrative_record::LIFETIME_EXPIRED,
+    );
+    log.add(bid, report.clone());
+    let notification = rx.try_recv().unwrap();
+    assert_eq!(notification.bid, bid);
+    assert_eq!(notification.report, report);
+    assert_eq!(notification.report.reason_text, "lifetime expired");
+
+    // reports for other bundles are not pushed
+    log.add(
+        "dtn://node1/-683555464000-1",
+        StatusReportEntry::new("dtn://node2/".into(), BundleStatus::Received, 0, 0),
+    );
+    assert!(rx.try_recv().is_err());
+
+    // closed sessions are forgotten, the timeline is kept
+    drop(rx);
+    log.add(
+        bid,
+        StatusReportEntry::new("dtn://node3/".into(), BundleStatus::Delivered, 0, 0),
+    );
+    assert_eq!(log.get(bid).unwrap().len(), 2);
+    let json = serde_json::to_value(&log.get(bid).unwrap()[1]).unwrap();
+    assert_eq!(json["status"], "delivered");
+}
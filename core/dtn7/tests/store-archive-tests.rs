@@ -0,0 +1,73 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::store::archive::{import_store, migrate_store};
+use dtn7::core::store::{BundleStore, InMemoryBundleStore, SneakersBundleStore};
+
+fn populated_store() -> (InMemoryBundleStore, Vec<String>) {
+    let mut store = InMemoryBundleStore::new();
+    let mut bids = Vec::new();
+    for _ in 0..4 {
+        let b = rnd_bundle(CreationTimestamp::now());
+        store.push(&b).unwrap();
+        bids.push(b.id());
+    }
+    let mut meta = store.get_metadata(&bids[1]).unwrap();
+    meta.add_constraint(Constraint::ForwardPending);
+    store.update_metadata(&meta).unwrap();
+    store.remove(&bids[3]).unwrap();
+    (store, bids)
+}
+
+#[test]
+fn store_archive_roundtrip() {
+    let (store, bids) = populated_store();
+    let mut archive = Vec::new();
+    let summary = store.export(&mut archive, "dtn://node1/").unwrap();
+    assert_eq!((summary.bundles, summary.tombstones), (3, 1));
+
+    let mut target = InMemoryBundleStore::new();
+    let (header, summary) = import_store(&mut target, &mut archive.as_slice()).unwrap();
+    assert_eq!(header.node, "dtn://node1/");
+    assert_eq!((summary.bundles, summary.tombstones), (3, 1));
+    for bid in &bids {
+        assert_eq!(target.get_metadata(bid), store.get_metadata(bid));
+        assert_eq!(target.get_bundle(bid), store.get_bundle(bid));
+    }
+    assert!(target
+        .get_metadata(&bids[1])
+        .unwrap()
+        .has_constraint(Constraint::ForwardPending));
+    assert!(target
+        .get_metadata(&bids[3])
+        .unwrap()
+        .has_constraint(Constraint::Deleted));
+
+    // importing again leaves everything untouched
+    let summary = target.import(&mut archive.as_slice()).unwrap();
+    assert_eq!(summary.skipped, 4);
+
+    assert!(target.import(&mut &b"no archive"[..]).is_err());
+}
+
+#[test]
+fn store_migrate_to_sneakers() {
+    let dir = tempfile::tempdir().unwrap();
+    dtn7::CONFIG.lock().workdir = dir.path().to_path_buf();
+    let mut target = SneakersBundleStore::new();
+
+    let (store, bids) = populated_store();
+    let summary = migrate_store(&store, &mut target).unwrap();
+    assert_eq!(summary.bundles, 3);
+    // sneakers keeps no tombstones
+    assert_eq!((summary.tombstones, summary.skipped), (0, 1));
+    assert!(target.get_metadata(&bids[3]).is_none());
+
+    for bid in &bids[..3] {
+        assert_eq!(target.get_bundle(bid), store.get_bundle(bid));
+    }
+    assert!(target
+        .get_metadata(&bids[1])
+        .unwrap()
+        .has_constraint(Constraint::ForwardPending));
+}
@@ -0,0 +1,192 @@
+use bp7::helpers::rnd_bundle;
+use bp7::CreationTimestamp;
+use dtn7::core::bundlepack::Constraint;
+use dtn7::core::store::{
+    BundleStore, BundleStoresEnum, EncryptedBundleStore, InMemoryBundleStore, SqliteBundleStore,
+    StoreKeys,
+};
+
+fn bundles(n: u64) -> Vec<bp7::Bundle> {
+    (0..n)
+        .map(|i| rnd_bundle(CreationTimestamp::with_time_and_seq(1_000_000 + i, 0)))
+        .collect()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn encrypted_store_roundtrip() {
+    let key = StoreKeys::generate_entry(1).unwrap();
+    let inner: BundleStoresEnum = InMemoryBundleStore::new().into();
+    let mut store = EncryptedBundleStore::new(inner, StoreKeys::parse(&key).unwrap()).unwrap();
+
+    let bundles = bundles(3);
+    for b in &bundles {
+        store.push(b).unwrap();
+    }
+    let bids: Vec<String> = bundles.iter().map(|b| b.id()).collect();
+    let mut bp = store.get_metadata(&bids[0]).unwrap();
+    bp.add_constraint(Constraint::ForwardPending);
+    store.update_metadata(&bp).unwrap();
+    store.remove(&bids[2]).unwrap();
+
+    assert_eq!(store.count(), 2);
+    assert_eq!(store.get_bundle(&bids[1]), Some(bundles[1].clone()));
+    assert_eq!(store.forwarding(), vec![bids[0].clone()]);
+    assert!(!store.has_item(&bids[2]));
+
+    // the inner store only holds opaque carriers, two per bundle and one per tombstone
+    let inner = store.into_inner();
+    assert_eq!(inner.metadata_ids().len(), 5);
+    for id in inner.metadata_ids() {
+        let raw = inner.get_raw(&id).unwrap();
+        for b in &bundles {
+            assert!(!contains(
+                &raw,
+                b.primary.destination.to_string().as_bytes()
+            ));
+            assert!(!contains(&raw, b.primary.source.to_string().as_bytes()));
+        }
+    }
+
+    // reopening restores metadata, constraints and tombstones
+    let store = EncryptedBundleStore::new(inner, StoreKeys::parse(&key).unwrap()).unwrap();
+    assert_eq!(store.count(), 2);
+    assert_eq!(store.get_bundle(&bids[0]), Some(bundles[0].clone()));
+    assert_eq!(store.forwarding(), vec![bids[0].clone()]);
+    assert_eq!(
+        store.query_constraint(Constraint::Deleted),
+        vec![bids[2].clone()]
+    );
+    assert_eq!(store.get_bundle(&bids[2]), None);
+
+    // a wrong key opens nothing and leaves the carriers alone
+    let other = StoreKeys::parse(&StoreKeys::generate_entry(1).unwrap()).unwrap();
+    let store = EncryptedBundleStore::new(store.into_inner(), other).unwrap();
+    assert!(store.bundles().is_empty());
+    assert_eq!(store.into_inner().metadata_ids().len(), 5);
+}
+
+#[test]
+fn encrypted_store_key_rotation() {
+    let old_key = StoreKeys::generate_entry(1).unwrap();
+    let new_key = StoreKeys::generate_entry(2).unwrap();
+    let inner: BundleStoresEnum = InMemoryBundleStore::new().into();
+    let mut store = EncryptedBundleStore::new(inner, StoreKeys::parse(&old_key).unwrap()).unwrap();
+    let bundles = bundles(4);
+    for b in &bundles {
+        store.push(b).unwrap();
+    }
+    store.remove(&bundles[3].id()).unwrap();
+
+    let keys = StoreKeys::parse(&format!("{}\n{}", new_key, old_key)).unwrap();
+    let mut store = EncryptedBundleStore::new(store.into_inner(), keys).unwrap();
+    assert_eq!(store.stale_count(), 4);
+    assert_eq!(store.rotate().unwrap(), 4);
+    assert_eq!(store.stale_count(), 0);
+    assert_eq!(store.rotate().unwrap(), 0);
+
+    // the old key is no longer needed
+    let store =
+        EncryptedBundleStore::new(store.into_inner(), StoreKeys::parse(&new_key).unwrap()).unwrap();
+    assert_eq!(store.bundles().len(), 4);
+    for b in &bundles[..3] {
+        assert_eq!(store.get_bundle(&b.id()), Some(b.clone()));
+    }
+    assert_eq!(store.into_inner().metadata_ids().len(), 7);
+}
+
+#[test]
+fn encrypted_store_encrypts_plaintext_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.sqlite3");
+    let mut plain = SqliteBundleStore::open(&path).unwrap();
+    let bundles = bundles(3);
+    for b in &bundles {
+        plain.push(b).unwrap();
+    }
+    plain.remove(&bundles[0].id()).unwrap();
+
+    let keys = StoreKeys::parse(&StoreKeys::generate_entry(7).unwrap()).unwrap();
+    let store = EncryptedBundleStore::new(plain.into(), keys).unwrap();
+    assert_eq!(store.count(), 2);
+    assert_eq!(store.get_bundle(&bundles[1].id()), Some(bundles[1].clone()));
+    assert!(store
+        .get_metadata(&bundles[0].id())
+        .unwrap()
+        .has_constraint(Constraint::Deleted));
+
+    let inner = store.into_inner();
+    for b in &bundles {
+        assert!(inner.get_metadata(&b.id()).is_none());
+    }
+    assert_eq!(inner.metadata_ids().len(), 5);
+}
+
+#[test]
+fn read_only_store_is_left_as_it_is() {
+    let old_key = StoreKeys::generate_entry(1).unwrap();
+    let new_key = StoreKeys::generate_entry(2).unwrap();
+    let mut plain: BundleStoresEnum = InMemoryBundleStore::new().into();
+    let bundles = bundles(3);
+    plain.push(&bundles[0]).unwrap();
+
+    let mut store = EncryptedBundleStore::new(plain, StoreKeys::parse(&old_key).unwrap()).unwrap();
+    store.push(&bundles[1]).unwrap();
+    let mut inner = store.into_inner();
+    inner.push(&bundles[2]).unwrap();
+    let mut ids = inner.metadata_ids();
+    ids.sort_unstable();
+
+    let keys = StoreKeys::parse(&format!("{}\n{}", new_key, old_key)).unwrap();
+    let store = EncryptedBundleStore::read_only(inner, keys).unwrap();
+    assert_eq!(store.stale_count(), 2);
+    // the plaintext bundle is skipped, not encrypted
+    assert!(store.get_bundle(&bundles[2].id()).is_none());
+    assert_eq!(store.get_bundle(&bundles[1].id()), Some(bundles[1].clone()));
+    let mut unchanged = store.into_inner().metadata_ids();
+    unchanged.sort_unstable();
+    assert_eq!(unchanged, ids);
+}
+
+#[test]
+fn superseded_carriers_are_purged_in_batches() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.sqlite3");
+    let key = StoreKeys::generate_entry(1).unwrap();
+    let inner = SqliteBundleStore::open(&path).unwrap();
+    let mut store =
+        EncryptedBundleStore::new(inner.into(), StoreKeys::parse(&key).unwrap()).unwrap();
+    let bundles = bundles(2);
+    for b in &bundles {
+        store.push(b).unwrap();
+    }
+    let mut bp = store.get_metadata(&bundles[0].id()).unwrap();
+    for i in 0..100 {
+        bp.received_time = i;
+        store.update_metadata(&bp).unwrap();
+    }
+    // the store goes away without purging the last superseded carriers
+    drop(store);
+
+    let inner = SqliteBundleStore::open(&path).unwrap();
+    assert!(inner.metadata_ids().len() > 4);
+    let mut store =
+        EncryptedBundleStore::new(inner.into(), StoreKeys::parse(&key).unwrap()).unwrap();
+    assert_eq!(
+        store.get_metadata(&bundles[0].id()).unwrap().received_time,
+        99
+    );
+    assert_eq!(store.get_bundle(&bundles[0].id()), Some(bundles[0].clone()));
+
+    // superseded carriers must not restore a purged bundle
+    store.update_metadata(&bp).unwrap();
+    store.purge(&bundles[0].id()).unwrap();
+    drop(store);
+    let inner = SqliteBundleStore::open(&path).unwrap();
+    assert_eq!(inner.metadata_ids().len(), 2);
+    let store = EncryptedBundleStore::new(inner.into(), StoreKeys::parse(&key).unwrap()).unwrap();
+    assert_eq!(store.metadata_ids(), vec![bundles[1].id()]);
+}
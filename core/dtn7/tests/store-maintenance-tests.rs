@@ -0,0 +1,144 @@
+use bp7::helpers::rnd_bundle;
+use bp7::{Bundle, CreationTimestamp};
+use dtn7::core::events::EvictionReason;
+use dtn7::core::store::maintenance::{check, confirm, repair, verify_bundle, StoreChecker};
+use dtn7::core::store::{BundleStore, InMemoryBundleStore, SneakersBundleStore};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// Finds the file a bundle is stored in.
+fn find_file(dir: &std::path::Path, bid: &str) -> Option<std::path::PathBuf> {
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(found) = find_file(&path, bid) {
+                return Some(found);
+            }
+        } else if std::fs::read(&path)
+            .ok()
+            .and_then(|buf| Bundle::try_from(buf).ok())
+            .is_some_and(|b| b.id() == bid)
+        {
+            return Some(path);
+        }
+    }
+    None
+}
+
+#[test]
+fn store_tombstone_expiry() {
+    let mut store = InMemoryBundleStore::new();
+    let bundles = [
+        rnd_bundle(CreationTimestamp::now()),
+        rnd_bundle(CreationTimestamp::now()),
+    ];
+    for b in bundles.iter() {
+        store.push(b).unwrap();
+    }
+    store.remove(&bundles[0].id()).unwrap();
+
+    // tombstones outlive the bundle lifetime
+    assert!(check(&store, Duration::from_millis(1)).is_clean());
+    // and are kept forever without a horizon
+    assert!(check(&store, Duration::ZERO).is_clean());
+
+    // bundles with a lifetime that ended long ago
+    let mut old = rnd_bundle(CreationTimestamp::with_time_and_seq(1_000, 0));
+    old.primary.lifetime = Duration::from_secs(1);
+    store.push(&old).unwrap();
+    store.remove(&old.id()).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+
+    let report = check(&store, Duration::from_millis(1));
+    assert_eq!(report.expired_tombstones, vec![old.id()]);
+    assert_eq!(repair(&mut store, &report).unwrap(), 1);
+    assert!(store.get_metadata(&old.id()).is_none());
+    assert!(store.get_metadata(&bundles[0].id()).is_some());
+}
+
+#[test]
+fn store_check_and_repair_sneakers() {
+    let dir = tempfile::tempdir().unwrap();
+    dtn7::CONFIG.lock().workdir = dir.path().to_path_buf();
+    let mut store = SneakersBundleStore::new();
+
+    let bundles = [
+        rnd_bundle(CreationTimestamp::now()),
+        rnd_bundle(CreationTimestamp::now()),
+        rnd_bundle(CreationTimestamp::now()),
+    ];
+    for b in bundles.iter() {
+        store.push(b).unwrap();
+    }
+    let report = check(&store, Duration::from_secs(3600));
+    assert!(report.is_clean());
+    assert_eq!(report.checked, 3);
+
+    // bundle file lost during power loss
+    let lost = find_file(dir.path(), &bundles[0].id()).unwrap();
+    std::fs::remove_file(lost).unwrap();
+    // bundle file partially written
+    let broken = find_file(dir.path(), &bundles[1].id()).unwrap();
+    let buf = std::fs::read(&broken).unwrap();
+    std::fs::write(&broken, &buf[..buf.len() / 2]).unwrap();
+
+    // bundle files without database entries, e.g., left behind by a crash
+    let orphan = rnd_bundle(CreationTimestamp::now());
+    store.push(&orphan).unwrap();
+    let orphan_file = find_file(dir.path(), &orphan.id()).unwrap();
+    let buf = std::fs::read(&orphan_file).unwrap();
+    store.purge(&orphan.id()).unwrap();
+    std::fs::write(&orphan_file, buf).unwrap();
+    let garbage_file = dir.path().join("files/single").join("dtn_garbage.bundle");
+    std::fs::write(&garbage_file, b"garbage").unwrap();
+
+    let report = check(&store, Duration::from_secs(3600));
+    assert_eq!(report.missing_bundles, vec![bundles[0].id()]);
+    assert_eq!(report.corrupt_bundles, vec![bundles[1].id()]);
+    let mut orphaned = report.orphaned_bundles.clone();
+    orphaned.sort();
+    assert_eq!(orphaned, vec![orphan.id(), "dtn_garbage".to_string()]);
+
+    assert_eq!(repair(&mut store, &report).unwrap(), 4);
+    assert!(!orphan_file.exists());
+    assert!(!garbage_file.exists());
+    assert!(check(&store, Duration::from_secs(3600)).is_clean());
+    assert!(!store.has_item(&bundles[0].id()));
+    assert!(!store.has_item(&bundles[1].id()));
+    assert!(store.get_bundle(&bundles[2].id()).is_some());
+}
+
+#[test]
+fn store_verify_bundle_crc() {
+    let mut b = rnd_bundle(CreationTimestamp::now());
+    b.set_crc(bp7::crc::CRC_32);
+    let mut buf = b.to_cbor();
+    assert!(verify_bundle(&b.id(), buf.clone()).is_ok());
+    assert!(verify_bundle("dtn://other/-1-0", buf.clone()).is_err());
+
+    // flip a bit within the payload
+    let len = buf.len();
+    buf[len - 8] ^= 0x01;
+    assert!(verify_bundle(&b.id(), buf).is_err());
+}
+
+#[test]
+fn bundles_deleted_during_a_check_are_not_corrupt() {
+    let mut store = InMemoryBundleStore::new();
+    let bndl = rnd_bundle(CreationTimestamp::now());
+    store.push(&bndl).unwrap();
+    let bid = bndl.id();
+
+    // the bundle is deleted between listing and reading it
+    let mut checker = StoreChecker::new(&store.blob_ids(), Duration::ZERO);
+    checker.check_metadata(&bid, store.get_metadata(&bid));
+    store.remove(&bid).unwrap();
+    checker.check_blob(&bid, || store.get_raw(&bid));
+    assert!(checker.finish().is_clean());
+
+    // findings that do not apply anymore are not purged
+    let applies = |reason| confirm(&store, &bid, reason, Duration::ZERO);
+    assert!(!applies(EvictionReason::Corrupt));
+    assert!(!applies(EvictionReason::MissingBundle));
+    assert!(store.get_metadata(&bid).is_some());
+}
@@ -0,0 +1,64 @@
+use bp7::EndpointID;
+use dtn7::core::application_agent;
+use dtn7::core::topics::{self, TOPIC_MAX_HOPS};
+use dtn7::core::{DtnPeer, PeerType};
+use dtn7::ipnd::services::Service;
+use dtn7::{peers_add, DTNCORE};
+use std::collections::HashMap;
+
+fn peer(name: &str, topics: Option<&str>) -> DtnPeer {
+    let mut services = HashMap::new();
+    if let Some(topics) = topics {
+        services.insert(Service::TOPICS, topics.to_string());
+    }
+    DtnPeer::new(
+        EndpointID::try_from(format!("dtn://{}/", name)).unwrap(),
+        "127.0.0.1".parse::<std::net::IpAddr>().unwrap().into(),
+        PeerType::Dynamic,
+        None,
+        Vec::new(),
+        services,
+    )
+}
+
+#[test]
+fn topics_are_parsed_and_formatted() {
+    assert!(topics::topic_eid("../etc").is_err());
+    assert!(topics::topic_eid("sensors/").is_err());
+    let other = EndpointID::try_from("dtn://node1/~sensors").unwrap();
+    assert_eq!(topics::topic_of(&other), None);
+
+    let interest = topics::parse_interest("a:0 b/c:3 invalid d:x");
+    assert_eq!(interest.len(), 2);
+    assert_eq!(topics::format_interest(&interest), "a:0 b/c:3");
+}
+
+#[tokio::test]
+async fn topics_are_relayed_and_filtered() {
+    let local = topics::topic_eid("fleet/position").unwrap();
+    DTNCORE
+        .lock()
+        .register_application_agent(application_agent::new(local));
+    peers_add(peer("relay1", Some("fleet/images:0 fleet/position:2")));
+    peers_add(peer(
+        "relay2",
+        Some(&format!("fleet/images:2 far:{}", TOPIC_MAX_HOPS)),
+    ));
+    peers_add(peer("legacy", None));
+
+    let advertised = topics::advertisement();
+    assert_eq!(advertised.get("fleet/position"), Some(&0));
+    assert_eq!(advertised.get("fleet/images"), Some(&1));
+    assert_eq!(advertised.get("far"), None);
+
+    let relay2 = EndpointID::try_from("dtn://relay2/").unwrap();
+    assert!(topics::is_interested(&relay2, "fleet/images"));
+    assert!(!topics::is_interested(&relay2, "fleet/position"));
+    // peers without topic advertisement get everything
+    let legacy = EndpointID::try_from("dtn://legacy/").unwrap();
+    assert!(topics::is_interested(&legacy, "fleet/position"));
+
+    let status = topics::status();
+    assert!(status.local.contains("fleet/position"));
+    assert_eq!(status.peers.len(), 2);
+}
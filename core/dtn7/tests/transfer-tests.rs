@@ -0,0 +1,188 @@
+use bp7::{Bundle, EndpointID};
+use dtn7::core::transfer::{self, TransferInfo, Upload};
+use std::time::Duration;
+
+const SRC: &str = "dtn://node1/";
+
+fn upload(id: &str, data: &[u8], chunk_size: usize) -> Vec<Bundle> {
+    upload_from(SRC, id, data, chunk_size)
+}
+
+fn upload_from(src: &str, id: &str, data: &[u8], chunk_size: usize) -> Vec<Bundle> {
+    let src = EndpointID::try_from(src).unwrap();
+    let dst = EndpointID::try_from("dtn://node2/incoming").unwrap();
+    let mut upload = Upload::new(
+        id.into(),
+        src,
+        dst,
+        Duration::from_secs(60),
+        chunk_size,
+        Some("image.tif".into()),
+    );
+    // uneven writes must not change the chunking
+    let mut bundles: Vec<Bundle> = data.chunks(7).flat_map(|d| upload.write(d)).collect();
+    assert_eq!(upload.offset(), data.len() as u64);
+    bundles.push(upload.finish());
+    bundles
+}
+
+#[test]
+fn uploads_are_split_into_chunks() {
+    let data: Vec<u8> = (0..100u8).collect();
+    let bundles = upload("t1", &data, 32);
+    assert_eq!(bundles.len(), 4);
+    for (i, bndl) in bundles.iter().enumerate() {
+        let info = TransferInfo::from_bundle(bndl).unwrap();
+        assert_eq!(info.index, i as u64);
+        assert_eq!(info.offset, 32 * i as u64);
+        assert_eq!(info.last, i == 3);
+        assert_eq!(info.name.as_deref(), Some("image.tif"));
+    }
+    assert_eq!(bundles[3].payload().unwrap().len(), 4);
+}
+
+#[test]
+fn chunks_are_reassembled_in_any_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+    let mut bundles = upload("t2", &data, 128);
+    bundles.reverse();
+    let (last, rest) = bundles.split_first().unwrap();
+
+    let info = TransferInfo::from_bundle(last).unwrap();
+    let progress = transfer::receive_chunk(dir.path(), last, &info).unwrap();
+    assert_eq!(progress.total_bytes, Some(1000));
+    assert_eq!(progress.total_chunks, Some(8));
+    assert!(!progress.complete);
+
+    for bndl in rest.iter().chain(rest.iter().take(2)) {
+        let info = TransferInfo::from_bundle(bndl).unwrap();
+        transfer::receive_chunk(dir.path(), bndl, &info).unwrap();
+    }
+    // state is read back from disk, duplicates are not counted twice
+    let progress = transfer::progress(dir.path(), SRC, "t2").unwrap();
+    assert!(progress.complete);
+    assert_eq!((progress.chunks, progress.bytes), (8, 1000));
+    assert_eq!(
+        std::fs::read(transfer::adu_path(dir.path(), SRC, "t2")).unwrap(),
+        data
+    );
+
+    assert_eq!(transfer::transfers(dir.path()), vec![progress]);
+    assert!(transfer::remove(dir.path(), SRC, "t2").unwrap());
+    assert!(transfer::transfers(dir.path()).is_empty());
+}
+
+#[test]
+fn chunks_received_at_the_same_time_are_all_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+    let bundles = upload("t9", &data, 64);
+    let start = std::sync::Barrier::new(bundles.len());
+    std::thread::scope(|scope| {
+        for bndl in &bundles {
+            let (dir, start) = (dir.path(), &start);
+            scope.spawn(move || {
+                let info = TransferInfo::from_bundle(bndl).unwrap();
+                start.wait();
+                transfer::receive_chunk(dir, bndl, &info).unwrap();
+            });
+        }
+    });
+    let progress = transfer::progress(dir.path(), SRC, "t9").unwrap();
+    assert!(progress.complete);
+    assert_eq!(
+        (progress.chunks, progress.bytes),
+        (bundles.len() as u64, 4096)
+    );
+    assert_eq!(
+        std::fs::read(transfer::adu_path(dir.path(), SRC, "t9")).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn unsafe_transfer_ids_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let bundles = upload("../escape", b"data", 16);
+    let info = TransferInfo::from_bundle(&bundles[0]).unwrap();
+    assert!(transfer::receive_chunk(dir.path(), &bundles[0], &info).is_err());
+}
+
+fn receive_all(dir: &std::path::Path, bundles: &[Bundle]) {
+    for bndl in bundles {
+        let info = TransferInfo::from_bundle(bndl).unwrap();
+        transfer::receive_chunk(dir, bndl, &info).unwrap();
+    }
+}
+
+#[test]
+fn transfers_of_different_sources_are_kept_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    receive_all(
+        dir.path(),
+        &upload_from("dtn://node1/", "same", b"first", 4),
+    );
+    receive_all(
+        dir.path(),
+        &upload_from("dtn://node3/", "same", b"second", 4),
+    );
+
+    assert_eq!(transfer::transfers(dir.path()).len(), 2);
+    for (src, data) in [("dtn://node1/", "first"), ("dtn://node3/", "second")] {
+        assert!(
+            transfer::progress(dir.path(), src, "same")
+                .unwrap()
+                .complete
+        );
+        assert_eq!(
+            std::fs::read(transfer::adu_path(dir.path(), src, "same")).unwrap(),
+            data.as_bytes()
+        );
+    }
+}
+
+#[test]
+fn chunks_beyond_the_transfer_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let bundles = upload("bounds", &[0; 100], 32);
+    receive_all(dir.path(), &bundles[3..]);
+
+    // a chunk claiming an offset past the end of the transfer
+    let mut info = TransferInfo::from_bundle(&bundles[1]).unwrap();
+    info.offset = 90;
+    assert!(transfer::receive_chunk(dir.path(), &bundles[1], &info).is_err());
+    info.offset = u64::MAX - 8;
+    assert!(transfer::receive_chunk(dir.path(), &bundles[1], &info).is_err());
+    info.offset = transfer::TRANSFER_SIZE_MAX;
+    info.id = "huge".into();
+    assert!(transfer::receive_chunk(dir.path(), &bundles[1], &info).is_err());
+
+    receive_all(dir.path(), &bundles[..3]);
+    let progress = transfer::progress(dir.path(), SRC, "bounds").unwrap();
+    assert!(progress.complete);
+    assert_eq!(progress.bytes, 100);
+}
+
+#[test]
+fn stale_transfers_are_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    receive_all(dir.path(), &upload("done", b"data", 16));
+    let partial = upload("partial", &[0; 40], 16);
+    receive_all(dir.path(), &partial[..1]);
+
+    assert_eq!(
+        transfer::remove_stale(dir.path(), Duration::from_secs(60)),
+        0
+    );
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(
+        transfer::remove_stale(dir.path(), Duration::from_millis(10)),
+        1
+    );
+    let ids: Vec<String> = transfer::transfers(dir.path())
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+    assert_eq!(ids, vec!["done".to_string()]);
+}
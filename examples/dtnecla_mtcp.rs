@@ -2,7 +2,7 @@ use anyhow::Result;
 use bp7::Bundle;
 use clap::{crate_authors, crate_version, value_parser, Arg, ArgAction, Command as ClapCommand};
 use dtn7::cla::mtcp::{MPDUCodec, MPDU};
-use dtn7::client::ecla::{ws_client, Command, ForwardData, Packet};
+use dtn7::client::ecla::{ws_client, Capabilities, Command, ForwardData, Packet, TransferAck};
 use futures_util::future::Either;
 use futures_util::{future, pin_mut, StreamExt};
 use lazy_static::lazy_static;
@@ -45,6 +45,7 @@ async fn handle_connection(
                                 dst: addr.clone(),
                                 bundle_id: bndl.id(),
                                 data: bndl.to_cbor(),
+                                transfer_id: None,
                             }))
                             .await
                         {
@@ -174,6 +175,10 @@ async fn main() -> Result<()> {
             let mut c = ws_client::new("mtcp", addr.as_str(), "", tx, false)
                 .expect("couldn't create client");
             c.set_ecla_port(port);
+            c.set_capabilities(Capabilities {
+                transfer_ack: true,
+                ..Default::default()
+            });
 
             let cmd_chan = c.command_channel();
             let read = tokio::spawn(async move {
@@ -203,20 +208,34 @@ async fn main() -> Result<()> {
     }
 
     // Read from Packet Stream
+    let ack_tx = ctx.clone();
     let read = tokio::spawn(async move {
         while let Some(packet) = rx.recv().await {
             match packet {
                 Packet::ForwardData(fwd) => {
                     info!("Got ForwardData {} -> {}", fwd.src, fwd.dst);
 
+                    let mut success = false;
                     if let Ok(bndl) = Bundle::try_from(fwd.data) {
                         let mpdu = MPDU::new(&bndl);
                         if let Ok(buf) = serde_cbor::to_vec(&mpdu) {
-                            send_bundle(fwd.dst, buf);
+                            success = send_bundle(fwd.dst, buf);
                         } else {
                             error!("MPDU encoding error!");
                         }
                     }
+
+                    // Report the outcome, so dtnd can keep the bundle on failure
+                    if let Some(transfer_id) = fwd.transfer_id {
+                        let ack = Packet::TransferAck(TransferAck {
+                            transfer_id,
+                            success,
+                            reason: None,
+                        });
+                        if let Err(err) = ack_tx.send(ack).await {
+                            error!("couldn't pass ack to client: {}", err);
+                        }
+                    }
                 }
                 Packet::Beacon(_) => {
                     // Beacon is not needed with MTCP
@@ -174,6 +174,13 @@ async fn serve(strategy: String, addr: &str) -> Result<()> {
 
         while let Some(packet) = rx.recv().await {
             match packet {
+                // Only the primary router receives RequestSenderForBundle packets.
+                Packet::AgentRole(role) => {
+                    info!(
+                        "Running as {} router",
+                        if role.primary { "primary" } else { "standby" }
+                    );
+                }
                 // Overwrite own peer map with the initial state of dtnd.
                 Packet::PeerState(packet) => {
                     info!("Got information about {} peers", packet.peers.len());
@@ -195,34 +202,24 @@ async fn serve(strategy: String, addr: &str) -> Result<()> {
                         .remove(packet.eid.node().unwrap().as_str());
                     info!("Peer Dropped: {}", packet.eid.node().unwrap());
                 }
-                Packet::SendingFailed(packet) => {
-                    if strategy == "epidemic" {
-                        epidemic_router
-                            .sending_failed(packet.bid.as_str(), packet.cla_sender.as_str());
-                    }
+                Packet::SendingFailed(packet) if strategy == "epidemic" => {
+                    epidemic_router.sending_failed(packet.bid.as_str(), packet.cla_sender.as_str());
                 }
                 Packet::Error(error) => {
                     error!("Error received: {}", error.reason);
                 }
-                Packet::Timeout(packet) => {
-                    if strategy == "epidemic" {
-                        epidemic_router.sending_timeout(packet.bp.id.as_str());
-                    }
+                Packet::Timeout(packet) if strategy == "epidemic" => {
+                    epidemic_router.sending_timeout(packet.bp.id.as_str());
                 }
-                Packet::IncomingBundle(packet) => {
-                    if strategy == "epidemic" {
-                        if let Some(eid) = packet.bndl.previous_node() {
-                            if let Some(node_name) = eid.node() {
-                                epidemic_router.incoming_bundle(&packet.bndl.id(), &node_name);
-                            }
-                        };
-                    }
+                Packet::IncomingBundle(packet) if strategy == "epidemic" => {
+                    if let Some(eid) = packet.bndl.previous_node() {
+                        if let Some(node_name) = eid.node() {
+                            epidemic_router.incoming_bundle(&packet.bndl.id(), &node_name);
+                        }
+                    };
                 }
-                Packet::IncomingBundleWithoutPreviousNode(packet) => {
-                    if strategy == "epidemic" {
-                        epidemic_router
-                            .incoming_bundle(packet.bid.as_str(), packet.node_name.as_str());
-                    }
+                Packet::IncomingBundleWithoutPreviousNode(packet) if strategy == "epidemic" => {
+                    epidemic_router.incoming_bundle(packet.bid.as_str(), packet.node_name.as_str());
                 }
                 Packet::RequestSenderForBundle(packet) => {
                     info!("got bundle pack: {}", packet.bp);